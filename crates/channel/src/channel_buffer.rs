@@ -175,22 +175,28 @@ impl ChannelBuffer {
         cx: &mut ModelContext<Self>,
     ) {
         match event {
-            language::Event::Operation(operation) => {
-                if *ZED_ALWAYS_ACTIVE {
-                    match operation {
-                        language::Operation::UpdateSelections { selections, .. } => {
-                            if selections.is_empty() {
-                                return;
+            language::Event::Operations(operations) => {
+                let operations = operations
+                    .iter()
+                    .filter(|operation| {
+                        if *ZED_ALWAYS_ACTIVE {
+                            if let language::Operation::UpdateSelections { selections, .. } =
+                                operation
+                            {
+                                return !selections.is_empty();
                             }
                         }
-                        _ => {}
-                    }
+                        true
+                    })
+                    .map(language::proto::serialize_operation)
+                    .collect::<Vec<_>>();
+                if operations.is_empty() {
+                    return;
                 }
-                let operation = language::proto::serialize_operation(operation);
                 self.client
                     .send(proto::UpdateChannelBuffer {
                         channel_id: self.channel_id.0,
-                        operations: vec![operation],
+                        operations,
                     })
                     .log_err();
             }
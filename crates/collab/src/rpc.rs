@@ -470,6 +470,12 @@ impl Server {
             .add_request_handler(user_handler(
                 forward_read_only_project_request::<proto::GetDocumentHighlights>,
             ))
+            .add_request_handler(user_handler(
+                forward_read_only_project_request::<proto::GetDocumentColors>,
+            ))
+            .add_request_handler(user_handler(
+                forward_read_only_project_request::<proto::GetColorPresentations>,
+            ))
             .add_request_handler(user_handler(
                 forward_read_only_project_request::<proto::GetProjectSymbols>,
             ))
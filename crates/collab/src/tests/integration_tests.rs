@@ -2368,13 +2368,13 @@ async fn test_propagate_saves_and_fs_changes(
 
     // Edit the buffer as the host and concurrently save as guest B.
     let save_b = project_b.update(cx_b, |project, cx| {
-        project.save_buffer(buffer_b.clone(), cx)
+        project.save_buffer(buffer_b.clone(), false, cx)
     });
     buffer_a.update(cx_a, |buf, cx| buf.edit([(0..0, "hi-a, ")], None, cx));
     save_b.await.unwrap();
     assert_eq!(
         client_a.fs().load("/a/file1.rs".as_ref()).await.unwrap(),
-        "hi-a, i-am-c, i-am-b, i-am-a"
+        "hi-a, i-am-c, i-am-b, i-am-a\n"
     );
 
     executor.run_until_parked();
@@ -3458,7 +3458,7 @@ async fn test_buffer_conflict_after_save(
 
     project_b
         .update(cx_b, |project, cx| {
-            project.save_buffer(buffer_b.clone(), cx)
+            project.save_buffer(buffer_b.clone(), false, cx)
         })
         .await
         .unwrap();
@@ -781,8 +781,9 @@ impl RandomizedTest for ProjectCollaborationTest {
 
                 ensure_project_shared(&project, client, cx).await;
                 let requested_version = buffer.read_with(cx, |buffer, _| buffer.version());
-                let save =
-                    project.update(cx, |project, cx| project.save_buffer(buffer.clone(), cx));
+                let save = project.update(cx, |project, cx| {
+                    project.save_buffer(buffer.clone(), true, cx)
+                });
                 let save = cx.spawn(|cx| async move {
                     save.await
                         .map_err(|err| anyhow!("save request failed: {:?}", err))?;
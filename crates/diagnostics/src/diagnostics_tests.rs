@@ -954,6 +954,8 @@ fn random_diagnostic(
             is_primary,
             is_disk_based: false,
             is_unnecessary: false,
+            is_deprecated: false,
+            data: None,
         },
     }
 }
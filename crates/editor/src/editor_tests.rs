@@ -5528,6 +5528,50 @@ async fn test_autoclose_with_overrides(cx: &mut gpui::TestAppContext) {
     );
 }
 
+#[gpui::test]
+async fn test_autoclose_quote_not_in_string(cx: &mut gpui::TestAppContext) {
+    init_test(cx, |_| {});
+
+    let mut cx = EditorTestContext::new(cx).await;
+
+    let rust_language = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "Rust".into(),
+                brackets: serde_json::from_value(json!([
+                    { "start": "'", "end": "'", "close": true, "newline": false, "not_in": ["string"] },
+                ]))
+                .unwrap(),
+                autoclose_before: ";\"".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_rust::language()),
+        )
+        .with_override_query("(string_literal) @string")
+        .unwrap(),
+    );
+
+    cx.language_registry().add(rust_language.clone());
+    cx.update_buffer(|buffer, cx| {
+        buffer.set_language(Some(rust_language), cx);
+    });
+
+    // In code, an apostrophe is automatically closed.
+    cx.set_state("let x = ˇ;");
+    cx.update_editor(|editor, cx| editor.handle_input("'", cx));
+    cx.assert_editor_state("let x = 'ˇ';");
+
+    // Inside of a string literal, it is not.
+    cx.set_state("let x = \"a ˇ\";");
+    cx.update_editor(|editor, cx| editor.handle_input("'", cx));
+    cx.assert_editor_state("let x = \"a 'ˇ\";");
+
+    // Selections inside of a string literal are replaced rather than surrounded.
+    cx.set_state("let x = \"«aˇ»\";");
+    cx.update_editor(|editor, cx| editor.handle_input("'", cx));
+    cx.assert_editor_state("let x = \"'ˇ\";");
+}
+
 #[gpui::test]
 async fn test_surround_with_pair(cx: &mut gpui::TestAppContext) {
     init_test(cx, |_| {});
@@ -711,10 +711,14 @@ impl Item for Editor {
                 .await?;
             }
 
+            // The workspace has already asked whether to overwrite any buffers
+            // that changed on disk, so force the save.
             if buffers.len() == 1 {
                 // Apply full save routine for singleton buffers, to allow to `touch` the file via the editor.
                 project
-                    .update(&mut cx, |project, cx| project.save_buffers(buffers, cx))?
+                    .update(&mut cx, |project, cx| {
+                        project.save_buffers(buffers, true, cx)
+                    })?
                     .await?;
             } else {
                 // For multi-buffers, only format and save the buffers with changes.
@@ -730,7 +734,7 @@ impl Item for Editor {
 
                 project
                     .update(&mut cx, |project, cx| {
-                        project.save_buffers(dirty_buffers, cx)
+                        project.save_buffers(dirty_buffers, true, cx)
                     })?
                     .await?;
                 for buffer in clean_buffers {
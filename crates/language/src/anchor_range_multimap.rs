@@ -0,0 +1,253 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    iter,
+    ops::Range,
+};
+use sum_tree::{self, Bias, SeekTarget, SumTree};
+use text::{Anchor, FromAnchor, ToOffset};
+
+/// A collection of values associated with ranges of a buffer, where several
+/// values may share the same range.
+///
+/// Like a [`DiagnosticSet`](crate::DiagnosticSet), the entries are stored as
+/// [`Anchor`] ranges in a [`SumTree`], so they follow edits to the buffer, can
+/// be cheaply cloned, and can be efficiently queried by range.
+#[derive(Clone, Debug)]
+pub struct AnchorRangeMultimap<T: Clone> {
+    entries: SumTree<AnchorRangeMultimapEntry<T>>,
+}
+
+/// A single value in an [`AnchorRangeMultimap`], along with its range.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AnchorRangeMultimapEntry<T> {
+    /// The range of the buffer the value is associated with.
+    pub range: Range<Anchor>,
+    /// The value.
+    pub value: T,
+}
+
+#[derive(Clone, Debug)]
+pub struct Summary {
+    start: Anchor,
+    end: Anchor,
+    min_start: Anchor,
+    max_end: Anchor,
+    count: usize,
+}
+
+impl<T: Clone> Default for AnchorRangeMultimap<T> {
+    fn default() -> Self {
+        Self {
+            entries: SumTree::new(),
+        }
+    }
+}
+
+impl<T: Clone> AnchorRangeMultimap<T> {
+    /// Constructs a map from a sequence of offset ranges and values in an arbitrary order.
+    pub fn from_offset_ranges<I, O>(iter: I, buffer: &text::BufferSnapshot) -> Self
+    where
+        I: IntoIterator<Item = (Range<O>, T)>,
+        O: ToOffset,
+    {
+        let mut entries = iter
+            .into_iter()
+            .map(|(range, value)| {
+                (
+                    range.start.to_offset(buffer)..range.end.to_offset(buffer),
+                    value,
+                )
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by_key(|(range, _)| (range.start, Reverse(range.end)));
+        Self {
+            entries: SumTree::from_iter(
+                entries
+                    .into_iter()
+                    .map(|(range, value)| AnchorRangeMultimapEntry {
+                        range: buffer.anchor_before(range.start)..buffer.anchor_before(range.end),
+                        value,
+                    }),
+                buffer,
+            ),
+        }
+    }
+
+    /// Constructs a map from a sequence of anchor ranges and values in an arbitrary order.
+    pub fn from_anchor_ranges<I>(iter: I, buffer: &text::BufferSnapshot) -> Self
+    where
+        I: IntoIterator<Item = (Range<Anchor>, T)>,
+    {
+        let mut entries = iter
+            .into_iter()
+            .map(|(range, value)| AnchorRangeMultimapEntry { range, value })
+            .collect::<Vec<_>>();
+        entries.sort_by(|a, b| {
+            a.range
+                .start
+                .cmp(&b.range.start, buffer)
+                .then_with(|| b.range.end.cmp(&a.range.end, buffer))
+        });
+        Self {
+            entries: SumTree::from_iter(entries, buffer),
+        }
+    }
+
+    /// Returns the number of entries in the map.
+    pub fn len(&self) -> usize {
+        self.entries.summary().count
+    }
+
+    /// Returns whether the map contains no entries.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns an iterator over all entries, ordered by their position in the buffer.
+    pub fn iter(&self) -> impl Iterator<Item = &AnchorRangeMultimapEntry<T>> {
+        self.entries.iter()
+    }
+
+    /// Associates the given value with the given range of the buffer.
+    pub fn insert(&mut self, range: Range<Anchor>, value: T, buffer: &text::BufferSnapshot) {
+        let mut cursor = self.entries.cursor::<Summary>();
+        let mut new_entries = cursor.slice(&RangeTarget(&range), Bias::Left, buffer);
+        new_entries.push(AnchorRangeMultimapEntry { range, value }, buffer);
+        new_entries.append(cursor.suffix(buffer), buffer);
+        drop(cursor);
+        self.entries = new_entries;
+    }
+
+    /// Removes all entries for which the predicate returns `true`, returning them
+    /// in order. The map is left untouched if no entries match.
+    pub fn remove_matching(
+        &mut self,
+        buffer: &text::BufferSnapshot,
+        mut predicate: impl FnMut(&AnchorRangeMultimapEntry<T>) -> bool,
+    ) -> Vec<AnchorRangeMultimapEntry<T>> {
+        let mut removed = Vec::new();
+        let mut kept = Vec::new();
+        for entry in self.entries.iter() {
+            if predicate(entry) {
+                removed.push(entry.clone());
+            } else {
+                kept.push(entry.clone());
+            }
+        }
+        if !removed.is_empty() {
+            // Removing entries preserves the order of the rest, so they can be
+            // reassembled without searching.
+            self.entries = SumTree::from_iter(kept, buffer);
+        }
+        removed
+    }
+
+    /// Removes all entries for which the predicate returns `false`.
+    pub fn retain(
+        &mut self,
+        buffer: &text::BufferSnapshot,
+        mut predicate: impl FnMut(&AnchorRangeMultimapEntry<T>) -> bool,
+    ) {
+        self.remove_matching(buffer, |entry| !predicate(entry));
+    }
+
+    /// Removes all entries.
+    pub fn clear(&mut self) {
+        self.entries = SumTree::new();
+    }
+
+    /// Returns an iterator over the entries that intersect the given range of
+    /// the buffer, resolving their ranges to the given coordinate type.
+    pub fn range<'a, P, O>(
+        &'a self,
+        range: Range<P>,
+        buffer: &'a text::BufferSnapshot,
+        inclusive: bool,
+    ) -> impl 'a + Iterator<Item = (Range<O>, &'a T)>
+    where
+        P: 'a + ToOffset,
+        O: FromAnchor,
+    {
+        let end_bias = if inclusive { Bias::Right } else { Bias::Left };
+        let range = buffer.anchor_before(range.start)..buffer.anchor_at(range.end, end_bias);
+        let mut cursor = self.entries.filter::<_, ()>({
+            move |summary: &Summary| {
+                let start_cmp = range.start.cmp(&summary.max_end, buffer);
+                let end_cmp = range.end.cmp(&summary.min_start, buffer);
+                if inclusive {
+                    start_cmp <= Ordering::Equal && end_cmp >= Ordering::Equal
+                } else {
+                    start_cmp == Ordering::Less && end_cmp == Ordering::Greater
+                }
+            }
+        });
+
+        cursor.next(buffer);
+        iter::from_fn(move || {
+            let entry = cursor.item()?;
+            cursor.next(buffer);
+            let range = O::from_anchor(&entry.range.start, buffer)
+                ..O::from_anchor(&entry.range.end, buffer);
+            Some((range, &entry.value))
+        })
+    }
+}
+
+impl<T: Clone> sum_tree::Item for AnchorRangeMultimapEntry<T> {
+    type Summary = Summary;
+
+    fn summary(&self) -> Self::Summary {
+        Summary {
+            start: self.range.start,
+            end: self.range.end,
+            min_start: self.range.start,
+            max_end: self.range.end,
+            count: 1,
+        }
+    }
+}
+
+impl Default for Summary {
+    fn default() -> Self {
+        Self {
+            start: Anchor::MIN,
+            end: Anchor::MAX,
+            min_start: Anchor::MAX,
+            max_end: Anchor::MIN,
+            count: 0,
+        }
+    }
+}
+
+impl sum_tree::Summary for Summary {
+    type Context = text::BufferSnapshot;
+
+    fn add_summary(&mut self, other: &Self, buffer: &Self::Context) {
+        if other.min_start.cmp(&self.min_start, buffer).is_lt() {
+            self.min_start = other.min_start;
+        }
+        if other.max_end.cmp(&self.max_end, buffer).is_gt() {
+            self.max_end = other.max_end;
+        }
+        self.start = other.start;
+        self.end = other.end;
+        self.count += other.count;
+    }
+}
+
+/// Seeks to the position where an entry with the given range would be inserted,
+/// keeping entries ordered by start, and then by descending end.
+#[derive(Debug)]
+struct RangeTarget<'a>(&'a Range<Anchor>);
+
+impl<'a> SeekTarget<'a, Summary, Summary> for RangeTarget<'_> {
+    fn cmp(&self, cursor_location: &Summary, buffer: &text::BufferSnapshot) -> Ordering {
+        if cursor_location.count == 0 {
+            return Ordering::Greater;
+        }
+        self.0
+            .start
+            .cmp(&cursor_location.start, buffer)
+            .then_with(|| cursor_location.end.cmp(&self.0.end, buffer))
+    }
+}
@@ -10,14 +10,15 @@ use crate::{
     markdown::parse_markdown,
     outline::OutlineItem,
     syntax_map::{
-        SyntaxLayer, SyntaxMap, SyntaxMapCapture, SyntaxMapCaptures, SyntaxMapMatches,
-        SyntaxSnapshot, ToTreeSitterPoint,
+        SyntaxLayer, SyntaxMap, SyntaxMapCaptures, SyntaxMapMatches, SyntaxSnapshot,
+        ToTreeSitterPoint,
     },
     task_context::RunnableRange,
-    LanguageScope, Outline, RunnableCapture, RunnableTag,
+    AnchorRangeMultimap, LanguageScope, Outline, RunnableCapture, RunnableTag,
 };
 use anyhow::{anyhow, Context, Result};
 pub use clock::ReplicaId;
+use collections::{HashMap, HashSet};
 use futures::channel::oneshot;
 use gpui::{
     AnyElement, AppContext, EventEmitter, HighlightStyle, ModelContext, Task, TaskLabel,
@@ -25,12 +26,13 @@ use gpui::{
 };
 use lazy_static::lazy_static;
 use lsp::LanguageServerId;
-use parking_lot::Mutex;
+use parking_lot::{Mutex, RwLock};
+use regex::Regex;
 use similar::{ChangeTag, TextDiff};
 use smallvec::SmallVec;
 use smol::future::yield_now;
 use std::{
-    any::Any,
+    any::{Any, TypeId},
     cell::Cell,
     cmp::{self, Ordering},
     collections::BTreeMap,
@@ -39,10 +41,14 @@ use std::{
     future::Future,
     iter::{self, Iterator, Peekable},
     mem,
+    num::NonZeroU32,
     ops::{Deref, Range},
     path::{Path, PathBuf},
     str,
-    sync::Arc,
+    sync::{
+        atomic::{self, AtomicUsize},
+        Arc,
+    },
     time::{Duration, Instant, SystemTime},
     vec,
 };
@@ -53,12 +59,12 @@ pub use text::{
     Anchor, Bias, Buffer as TextBuffer, BufferId, BufferSnapshot as TextBufferSnapshot, Edit,
     OffsetRangeExt, OffsetUtf16, Patch, Point, PointUtf16, Rope, Selection, SelectionGoal,
     Subscription, TextDimension, TextSummary, ToOffset, ToOffsetUtf16, ToPoint, ToPointUtf16,
-    Transaction, TransactionId, Unclipped,
+    Transaction, TransactionId, TransactionSummary, Unclipped,
 };
 use theme::SyntaxTheme;
 #[cfg(any(test, feature = "test-support"))]
 use util::RandomCharIter;
-use util::RangeExt;
+use util::{post_inc, RangeExt};
 
 #[cfg(any(test, feature = "test-support"))]
 pub use {tree_sitter_rust, tree_sitter_typescript};
@@ -69,8 +75,31 @@ lazy_static! {
     /// A label for the background task spawned by the buffer to compute
     /// a diff against the contents of its file.
     pub static ref BUFFER_DIFF_TASK: TaskLabel = TaskLabel::new();
+
+    /// Matches comments, strings and numbers in text whose language is unknown. Each
+    /// alternative's capture group corresponds to an entry in [`FALLBACK_CAPTURE_NAMES`].
+    static ref FALLBACK_HIGHLIGHTS_REGEX: Regex = Regex::new(concat!(
+        r#"(?m)(//.*$|^[ \t]*#.*$|/\*(?s:.*?)\*/)"#,
+        r#"|("(?:[^"\\\n]|\\.)*"|'(?:[^'\\\n]|\\.)*')"#,
+        r#"|(\b\d+(?:\.\d+)?\b)"#,
+    ))
+    .unwrap();
+
+    /// Maps the captures of the heuristic highlighter to the current theme. Like each
+    /// grammar's highlight map, it is updated by [`LanguageRegistry::set_theme`].
+    static ref FALLBACK_HIGHLIGHT_MAP: RwLock<HighlightMap> = Default::default();
+}
+
+/// Maps the captures of the heuristic highlighter that is enabled with
+/// [`Buffer::set_fallback_highlighting`] to the given theme.
+pub(crate) fn set_fallback_highlight_theme(theme: &SyntaxTheme) {
+    *FALLBACK_HIGHLIGHT_MAP.write() = HighlightMap::new(&FALLBACK_CAPTURE_NAMES, theme);
 }
 
+/// The names of the captures produced by the heuristic highlighter that is
+/// enabled with [`Buffer::set_fallback_highlighting`].
+const FALLBACK_CAPTURE_NAMES: [&str; 3] = ["comment", "string", "number"];
+
 /// Indicate whether a [Buffer] has permissions to edit.
 #[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Capability {
@@ -97,23 +126,55 @@ pub struct Buffer {
     saved_version: clock::Global,
     transaction_depth: usize,
     was_dirty_before_starting_transaction: Option<bool>,
+    /// Operations produced during the current transaction, which are
+    /// sent together once the outermost transaction ends.
+    pending_operations: Vec<Operation>,
     reload_task: Option<Task<Result<()>>>,
     language: Option<Arc<Language>>,
     autoindent_requests: Vec<Arc<AutoindentRequest>>,
     pending_autoindent: Option<Task<()>>,
+    autoindent_timeout: Duration,
     sync_parse_timeout: Duration,
     syntax_map: Mutex<SyntaxMap>,
     parsing_in_background: bool,
+    #[cfg(feature = "profile")]
+    parse_metrics: ParseMetrics,
     non_text_state_update_count: usize,
     diagnostics: SmallVec<[(LanguageServerId, DiagnosticSet); 2]>,
+    fixable_diagnostic_groups: TreeMap<usize, bool>,
+    /// The diagnostic group whose message is currently expanded inline.
+    active_diagnostic_group: Option<usize>,
     remote_selections: TreeMap<ReplicaId, SelectionSet>,
     diagnostics_timestamp: clock::Lamport,
+    /// Language servers or other providers whose diagnostics were produced on this
+    /// replica and are not shared with collaborators.
+    local_diagnostics_server_ids: HashSet<LanguageServerId>,
+    /// Positioned metadata attached to the buffer, keyed by the type of its values.
+    metadata_stores: HashMap<TypeId, Box<dyn Any>>,
+    next_annotation_set_id: usize,
     completion_triggers: Vec<String>,
     completion_triggers_timestamp: clock::Lamport,
     deferred_ops: OperationQueue<Operation>,
     capability: Capability,
     has_conflict: bool,
     diff_base_version: usize,
+    /// For each selection id, the stack of ranges that the selection occupied
+    /// before being expanded to an enclosing syntax node, paired with the range
+    /// it was expanded to.
+    syntax_selection_history: HashMap<usize, Vec<(Range<Anchor>, Range<Anchor>)>>,
+    pre_save_hooks: Vec<Box<dyn Fn(&mut Buffer, &mut ModelContext<Buffer>)>>,
+    /// A line ending chosen by the user, to be used the next time the buffer is saved.
+    line_ending_override: Option<LineEnding>,
+    line_ending_override_timestamp: clock::Lamport,
+    /// The character encoding used to read and write the buffer's file.
+    encoding: Encoding,
+    /// Whether the encoding was chosen by the user, in which case it is kept
+    /// instead of being detected again when the buffer is reloaded.
+    encoding_chosen: bool,
+    highlight_cache: Arc<RwLock<HighlightCache>>,
+    /// Whether the heuristic highlighter has been enabled with
+    /// [`Buffer::set_fallback_highlighting`].
+    fallback_highlighting: bool,
     /// Memoize calls to has_changes_since(saved_version).
     /// The contents of a cell are (self.version, has_changes) at the time of a last call.
     has_unsaved_edits: Cell<(clock::Global, bool)>,
@@ -123,13 +184,18 @@ pub struct Buffer {
 /// state of a buffer.
 pub struct BufferSnapshot {
     text: text::BufferSnapshot,
+    diff_base: Option<Rope>,
     git_diff: git::diff::BufferDiff,
     pub(crate) syntax: SyntaxSnapshot,
     file: Option<Arc<dyn File>>,
     diagnostics: SmallVec<[(LanguageServerId, DiagnosticSet); 2]>,
+    fixable_diagnostic_groups: TreeMap<usize, bool>,
+    active_diagnostic_group: Option<usize>,
     remote_selections: TreeMap<ReplicaId, SelectionSet>,
     language: Option<Arc<Language>>,
     non_text_state_update_count: usize,
+    highlight_cache: Arc<RwLock<HighlightCache>>,
+    fallback_highlighting: bool,
 }
 
 /// The kind and amount of indentation in a particular line. For now,
@@ -205,8 +271,53 @@ pub struct Diagnostic {
     pub is_disk_based: bool,
     /// Whether this diagnostic marks unnecessary code.
     pub is_unnecessary: bool,
+    /// Whether this diagnostic marks the use of a deprecated symbol.
+    pub is_deprecated: bool,
+    /// Data that the language server attached to this diagnostic, to be passed back
+    /// to it when requesting code actions. This isn't sent to collaborators, whose
+    /// code action requests are resolved by the host.
+    pub data: Option<serde_json::Value>,
+}
+
+/// The number of diagnostics of each severity within a band of rows,
+/// as returned by [`BufferSnapshot::diagnostic_density`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DiagnosticCounts {
+    pub errors: usize,
+    pub warnings: usize,
+    pub infos: usize,
+    pub hints: usize,
 }
 
+/// Identifies a set of ranges added with [`Buffer::add_annotation_set`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AnnotationSetId(usize);
+
+/// The value stored in the buffer's metadata for each range of an annotation set.
+#[derive(Clone, Copy, Debug)]
+struct Annotation {
+    key: TypeId,
+    set_id: AnnotationSetId,
+}
+
+/// Timing information about a buffer's parses, as returned by [`Buffer::parse_metrics`].
+#[cfg(feature = "profile")]
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ParseMetrics {
+    /// How long the most recent parse took.
+    pub last_parse_duration: Option<Duration>,
+    /// The number of parses that completed before the synchronous parse timeout.
+    pub sync_parse_count: usize,
+    /// The number of parses that outlasted the synchronous parse timeout, and
+    /// were completed in the background.
+    pub async_parse_count: usize,
+}
+
+#[cfg(feature = "profile")]
+type ParseDuration = Duration;
+#[cfg(not(feature = "profile"))]
+type ParseDuration = ();
+
 /// TODO - move this into the `project` crate and make it private.
 pub async fn prepare_completion_documentation(
     documentation: &lsp::Documentation,
@@ -288,14 +399,24 @@ pub enum Operation {
         /// The buffer's lamport timestamp.
         lamport_timestamp: clock::Lamport,
     },
+
+    /// An update to the line ending that will be used the next time the buffer
+    /// is saved.
+    UpdateLineEndingOverride {
+        /// The line ending chosen by the user, if it differs from the buffer's.
+        line_ending: Option<LineEnding>,
+        /// The buffer's lamport timestamp.
+        lamport_timestamp: clock::Lamport,
+    },
 }
 
 /// An event that occurs in a buffer.
 #[derive(Clone, Debug, PartialEq)]
 pub enum Event {
     /// The buffer was changed in a way that must be
-    /// propagated to its other replicas.
-    Operation(Operation),
+    /// propagated to its other replicas. Operations produced within
+    /// a transaction are batched into a single event.
+    Operations(Vec<Operation>),
     /// The buffer was edited.
     Edited,
     /// The buffer's `dirty` bit changed.
@@ -304,6 +425,9 @@ pub enum Event {
     Saved,
     /// The buffer's file was changed on disk.
     FileHandleChanged,
+    /// The buffer's file was changed on disk while the buffer had unsaved
+    /// changes, so the buffer now conflicts with its file.
+    Conflict,
     /// The buffer was reloaded.
     Reloaded,
     /// The buffer's diff_base changed.
@@ -315,7 +439,14 @@ pub enum Event {
     /// The buffer's syntax trees were updated.
     Reparsed,
     /// The buffer's diagnostics were updated.
-    DiagnosticsUpdated,
+    DiagnosticsUpdated {
+        /// The ids of the diagnostic groups that no longer have any diagnostics.
+        removed_group_ids: Vec<usize>,
+    },
+    /// One of the buffer's metadata stores was updated.
+    MetadataChanged,
+    /// The diagnostic group whose message is expanded inline was changed.
+    ActiveDiagnosticGroupChanged,
     /// The buffer gained or lost editing capabilities.
     CapabilityChanged,
     /// The buffer was explicitly requested to close.
@@ -375,8 +506,10 @@ pub trait LocalFile: File {
     /// Returns the absolute path of this file.
     fn abs_path(&self, cx: &AppContext) -> PathBuf;
 
-    /// Loads the file's contents from disk.
-    fn load(&self, cx: &AppContext) -> Task<Result<String>>;
+    /// Loads the file's contents from disk, decoding them with the given encoding,
+    /// or with the encoding detected from the file's contents if none is given.
+    fn load(&self, encoding: Option<Encoding>, cx: &AppContext)
+        -> Task<Result<(Encoding, String)>>;
 
     /// Returns true if the file should not be shared with collaborators.
     fn is_private(&self, _: &AppContext) -> bool {
@@ -419,6 +552,8 @@ struct AutoindentRequestEntry {
     first_line_is_new: bool,
     indent_size: IndentSize,
     original_indent_column: Option<u32>,
+    /// The offset at which the text was inserted, in the buffer before the edit.
+    old_offset: usize,
 }
 
 #[derive(Debug)]
@@ -429,12 +564,65 @@ struct IndentSuggestion {
 }
 
 struct BufferChunkHighlights<'a> {
-    captures: SyntaxMapCaptures<'a>,
-    next_capture: Option<SyntaxMapCapture<'a>>,
+    captures: BufferChunkCaptures<'a>,
+    next_capture: Option<HighlightCapture>,
     stack: Vec<(usize, HighlightId)>,
     highlight_maps: Vec<HighlightMap>,
 }
 
+/// The source of the syntax highlight captures for a [`BufferChunks`] iterator.
+enum BufferChunkCaptures<'a> {
+    /// Captures produced by running the highlights query as the iterator advances.
+    Live(SyntaxMapCaptures<'a>),
+    /// Captures that were computed ahead of time and stored in a [`HighlightCache`].
+    Cached {
+        captures: Arc<[HighlightCapture]>,
+        range: Range<usize>,
+        ix: usize,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct HighlightCapture {
+    range: Range<usize>,
+    grammar_index: usize,
+    capture_index: u32,
+}
+
+/// The maximum length of a range whose highlight captures are stored in a [`HighlightCache`].
+const MAX_CACHED_HIGHLIGHT_RANGE_LEN: usize = 16 * 1024;
+
+/// The number of ranges whose highlight captures are stored in a [`HighlightCache`].
+const HIGHLIGHT_CACHE_CAPACITY: usize = 8;
+
+/// The highlight captures for the ranges most recently requested via
+/// [`BufferSnapshot::chunks`], shared between a buffer and its snapshots so that
+/// repeatedly rendering the same range doesn't re-run the highlights query.
+///
+/// Lookups only take a read lock, so that snapshots rendered concurrently don't
+/// contend for the cache. Recency is tracked with atomic stamps instead of by
+/// reordering the entries.
+#[derive(Default)]
+struct HighlightCache {
+    entries: Vec<HighlightCacheEntry>,
+    /// The stamp of the most recent lookup or insertion.
+    last_used: AtomicUsize,
+}
+
+struct HighlightCacheEntry {
+    text_version: clock::Global,
+    parsed_version: clock::Global,
+    interpolated_version: clock::Global,
+    language_registry_version: usize,
+    non_text_state_update_count: usize,
+    /// Whether the captures come from the heuristic highlighter.
+    fallback: bool,
+    range: Range<usize>,
+    captures: Arc<[HighlightCapture]>,
+    /// The cache's stamp when this entry was last used.
+    last_used: AtomicUsize,
+}
+
 /// An iterator that yields chunks of a buffer's text, along with their
 /// syntax highlights and diagnostic status.
 pub struct BufferChunks<'a> {
@@ -447,6 +635,9 @@ pub struct BufferChunks<'a> {
     hint_depth: usize,
     unnecessary_depth: usize,
     highlights: Option<BufferChunkHighlights<'a>>,
+    overlay_highlights: Vec<(Range<usize>, HighlightStyle)>,
+    overlay_highlight_ix: usize,
+    render_whitespace: bool,
 }
 
 /// A chunk of a buffer's text, along with its syntax highlight and
@@ -466,10 +657,32 @@ pub struct Chunk<'a> {
     pub is_unnecessary: bool,
     /// Whether this chunk of text was originally a tab character.
     pub is_tab: bool,
+    /// The kind of whitespace that this chunk consists of, if the chunks were
+    /// requested via [`BufferSnapshot::chunks_with_whitespace`].
+    pub whitespace: Option<WhitespaceKind>,
     /// An optional recipe for how the chunk should be presented.
     pub renderer: Option<ChunkRenderer>,
 }
 
+/// A kind of whitespace character, used to identify runs of whitespace in a [`Chunk`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WhitespaceKind {
+    /// A run of ASCII space characters.
+    Space,
+    /// A run of ASCII tab characters.
+    Tab,
+}
+
+impl WhitespaceKind {
+    fn for_char(c: char) -> Option<Self> {
+        match c {
+            ' ' => Some(Self::Space),
+            '\t' => Some(Self::Tab),
+            _ => None,
+        }
+    }
+}
+
 /// A recipe for how the chunk should be presented.
 #[derive(Clone)]
 pub struct ChunkRenderer {
@@ -503,6 +716,17 @@ pub(crate) struct DiagnosticEndpoint {
     is_unnecessary: bool,
 }
 
+/// A coarse classification of the syntax at a position in a buffer.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum SyntaxClass {
+    /// Inside of a comment.
+    Comment,
+    /// Inside of a string literal.
+    String,
+    /// Anywhere else.
+    Code,
+}
+
 /// A class of characters, used for characterizing a run of text.
 #[derive(Copy, Clone, Eq, PartialEq, PartialOrd, Ord, Debug)]
 pub enum CharKind {
@@ -598,6 +822,14 @@ impl Buffer {
             rpc::proto::LineEnding::from_i32(message.line_ending)
                 .ok_or_else(|| anyhow!("missing line_ending"))?,
         ));
+        this.line_ending_override = message
+            .line_ending_override
+            .map(|line_ending| {
+                rpc::proto::LineEnding::from_i32(line_ending)
+                    .map(proto::deserialize_line_ending)
+                    .ok_or_else(|| anyhow!("invalid line_ending_override"))
+            })
+            .transpose()?;
         this.saved_version = proto::deserialize_version(&message.saved_version);
         this.saved_mtime = message.saved_mtime.map(|time| time.into());
         Ok(this)
@@ -613,6 +845,9 @@ impl Buffer {
             line_ending: proto::serialize_line_ending(self.line_ending()) as i32,
             saved_version: proto::serialize_version(&self.saved_version),
             saved_mtime: self.saved_mtime.map(|time| time.into()),
+            line_ending_override: self
+                .line_ending_override
+                .map(|line_ending| proto::serialize_line_ending(line_ending) as i32),
         }
     }
 
@@ -622,45 +857,79 @@ impl Buffer {
         since: Option<clock::Global>,
         cx: &AppContext,
     ) -> Task<Vec<proto::Operation>> {
+        let mut operations = self
+            .non_text_operations()
+            .iter()
+            .map(proto::serialize_operation)
+            .collect::<Vec<_>>();
+        let text_operations = self.text.operations().clone();
+        cx.background_executor().spawn(async move {
+            let since = since.unwrap_or_default();
+            operations.extend(
+                text_operations
+                    .iter()
+                    .filter(|(_, op)| !since.observed(op.timestamp()))
+                    .map(|(_, op)| proto::serialize_operation(&Operation::Buffer(op.clone()))),
+            );
+            operations.sort_unstable_by_key(proto::lamport_timestamp_for_operation);
+            operations
+        })
+    }
+
+    /// Returns the operations that bring a replica of this buffer at the given version
+    /// up to date, ordered by their lamport timestamps. Besides the text operations
+    /// that the replica hasn't observed, this includes the current selections,
+    /// diagnostics and completion triggers, which replicas ignore if they're stale.
+    pub fn operations_since(&self, version: &clock::Global) -> Vec<Operation> {
+        let mut operations = self.non_text_operations();
+        operations.extend(
+            self.text
+                .operations()
+                .iter()
+                .filter(|(_, operation)| !version.observed(operation.timestamp()))
+                .map(|(_, operation)| Operation::Buffer(operation.clone())),
+        );
+        operations.sort_unstable_by_key(|operation| match operation {
+            Operation::Buffer(operation) => operation.timestamp(),
+            operation => operation_queue::Operation::lamport_timestamp(operation),
+        });
+        operations
+    }
+
+    /// Returns operations describing the buffer's current state other than its text.
+    fn non_text_operations(&self) -> Vec<Operation> {
         let mut operations = Vec::new();
-        operations.extend(self.deferred_ops.iter().map(proto::serialize_operation));
+        operations.extend(self.deferred_ops.iter().cloned());
 
         operations.extend(self.remote_selections.iter().map(|(_, set)| {
-            proto::serialize_operation(&Operation::UpdateSelections {
+            Operation::UpdateSelections {
                 selections: set.selections.clone(),
                 lamport_timestamp: set.lamport_timestamp,
                 line_mode: set.line_mode,
                 cursor_shape: set.cursor_shape,
-            })
+            }
         }));
 
         for (server_id, diagnostics) in &self.diagnostics {
-            operations.push(proto::serialize_operation(&Operation::UpdateDiagnostics {
+            if self.local_diagnostics_server_ids.contains(server_id) {
+                continue;
+            }
+            operations.push(Operation::UpdateDiagnostics {
                 lamport_timestamp: self.diagnostics_timestamp,
                 server_id: *server_id,
                 diagnostics: diagnostics.iter().cloned().collect(),
-            }));
+            });
         }
 
-        operations.push(proto::serialize_operation(
-            &Operation::UpdateCompletionTriggers {
-                triggers: self.completion_triggers.clone(),
-                lamport_timestamp: self.completion_triggers_timestamp,
-            },
-        ));
-
-        let text_operations = self.text.operations().clone();
-        cx.background_executor().spawn(async move {
-            let since = since.unwrap_or_default();
-            operations.extend(
-                text_operations
-                    .iter()
-                    .filter(|(_, op)| !since.observed(op.timestamp()))
-                    .map(|(_, op)| proto::serialize_operation(&Operation::Buffer(op.clone()))),
-            );
-            operations.sort_unstable_by_key(proto::lamport_timestamp_for_operation);
-            operations
-        })
+        operations.push(Operation::UpdateCompletionTriggers {
+            triggers: self.completion_triggers.clone(),
+            lamport_timestamp: self.completion_triggers_timestamp,
+        });
+        operations.push(Operation::UpdateLineEndingOverride {
+            line_ending: self.line_ending_override,
+            lamport_timestamp: self.line_ending_override_timestamp,
+        });
+        operations
     }
 
     /// Assign a language to the buffer, returning the buffer.
@@ -694,6 +963,7 @@ impl Buffer {
             reload_task: None,
             transaction_depth: 0,
             was_dirty_before_starting_transaction: None,
+            pending_operations: Vec::new(),
             has_unsaved_edits: Cell::new((buffer.version(), false)),
             text: buffer,
             diff_base: diff_base
@@ -710,16 +980,32 @@ impl Buffer {
             parsing_in_background: false,
             non_text_state_update_count: 0,
             sync_parse_timeout: Duration::from_millis(1),
+            #[cfg(feature = "profile")]
+            parse_metrics: ParseMetrics::default(),
+            autoindent_timeout: Duration::from_micros(500),
             autoindent_requests: Default::default(),
             pending_autoindent: Default::default(),
             language: None,
             remote_selections: Default::default(),
             diagnostics: Default::default(),
+            fixable_diagnostic_groups: Default::default(),
+            active_diagnostic_group: None,
             diagnostics_timestamp: Default::default(),
+            local_diagnostics_server_ids: Default::default(),
+            metadata_stores: Default::default(),
+            next_annotation_set_id: 0,
             completion_triggers: Default::default(),
             completion_triggers_timestamp: Default::default(),
             deferred_ops: OperationQueue::new(),
             has_conflict: false,
+            syntax_selection_history: Default::default(),
+            pre_save_hooks: vec![Box::new(Self::format_whitespace)],
+            line_ending_override: None,
+            line_ending_override_timestamp: Default::default(),
+            encoding: Encoding::Utf8,
+            encoding_chosen: false,
+            highlight_cache: Default::default(),
+            fallback_highlighting: false,
         }
     }
 
@@ -734,12 +1020,17 @@ impl Buffer {
         BufferSnapshot {
             text,
             syntax,
+            diff_base: self.diff_base.clone(),
             git_diff: self.git_diff.clone(),
             file: self.file.clone(),
             remote_selections: self.remote_selections.clone(),
             diagnostics: self.diagnostics.clone(),
+            fixable_diagnostic_groups: self.fixable_diagnostic_groups.clone(),
+            active_diagnostic_group: self.active_diagnostic_group,
             language: self.language.clone(),
             non_text_state_update_count: self.non_text_state_update_count,
+            highlight_cache: self.highlight_cache.clone(),
+            fallback_highlighting: self.fallback_highlighting,
         }
     }
 
@@ -796,6 +1087,96 @@ impl Buffer {
         cx.emit(Event::CapabilityChanged)
     }
 
+    /// Enables or disables a heuristic highlighter for buffers whose language has no
+    /// grammar, which colors comments, strings and numbers using the theme set on the
+    /// [`LanguageRegistry`].
+    pub fn set_fallback_highlighting(&mut self, enabled: bool, cx: &mut ModelContext<Self>) {
+        if self.fallback_highlighting != enabled {
+            self.fallback_highlighting = enabled;
+            self.non_text_state_update_count += 1;
+            cx.notify();
+        }
+    }
+
+    /// Registers a hook that transforms the buffer's contents each time it is saved,
+    /// before it is written to disk. Hooks run in the order they were added, after
+    /// the built-in hook, [`Buffer::format_whitespace`].
+    pub fn add_pre_save_hook(&mut self, hook: Box<dyn Fn(&mut Buffer, &mut ModelContext<Buffer>)>) {
+        self.pre_save_hooks.push(hook);
+    }
+
+    /// Runs the buffer's pre-save hooks within a single transaction. This method
+    /// is called before the buffer's contents are written during a save.
+    pub fn run_pre_save_hooks(&mut self, cx: &mut ModelContext<Self>) -> Option<TransactionId> {
+        let hooks = mem::take(&mut self.pre_save_hooks);
+        self.start_transaction();
+        for hook in &hooks {
+            hook(self, cx);
+        }
+        let added_hooks = mem::replace(&mut self.pre_save_hooks, hooks);
+        self.pre_save_hooks.extend(added_hooks);
+        self.end_transaction(cx)
+    }
+
+    /// Sets the line ending that will be used the next time the buffer is saved,
+    /// regardless of the line ending that was detected when the buffer was loaded.
+    /// The buffer is considered dirty until it is saved, and the choice is shared
+    /// with the buffer's other replicas so that any of them can save it.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding, cx: &mut ModelContext<Self>) {
+        let line_ending = if line_ending == self.line_ending() {
+            None
+        } else {
+            Some(line_ending)
+        };
+        self.line_ending_override_timestamp = self.text.lamport_clock.tick();
+        self.send_operation(
+            Operation::UpdateLineEndingOverride {
+                line_ending,
+                lamport_timestamp: self.line_ending_override_timestamp,
+            },
+            cx,
+        );
+        self.set_line_ending_override(line_ending, cx);
+    }
+
+    fn set_line_ending_override(
+        &mut self,
+        line_ending: Option<LineEnding>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let was_dirty = self.is_dirty();
+        self.line_ending_override = line_ending;
+        if was_dirty != self.is_dirty() {
+            cx.emit(Event::DirtyChanged);
+        }
+        cx.notify();
+    }
+
+    /// The line ending that should be used when writing the buffer's contents to disk.
+    pub fn line_ending_at_save(&self) -> LineEnding {
+        self.line_ending_override
+            .unwrap_or_else(|| self.line_ending())
+    }
+
+    /// The character encoding used to read and write the buffer's file.
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+
+    /// Sets the character encoding that will be used when the buffer is saved,
+    /// such as when it was detected while loading the file.
+    pub fn set_encoding(&mut self, encoding: Encoding, cx: &mut ModelContext<Self>) {
+        self.encoding = encoding;
+        cx.notify();
+    }
+
+    /// Sets the character encoding chosen by the user. Unlike a detected encoding,
+    /// it is used to decode the file when the buffer is reloaded.
+    pub fn choose_encoding(&mut self, encoding: Encoding, cx: &mut ModelContext<Self>) {
+        self.encoding_chosen = true;
+        self.set_encoding(encoding, cx);
+    }
+
     /// This method is called to signal that the buffer has been saved.
     pub fn did_save(
         &mut self,
@@ -803,6 +1184,9 @@ impl Buffer {
         mtime: Option<SystemTime>,
         cx: &mut ModelContext<Self>,
     ) {
+        if let Some(line_ending) = self.line_ending_override.take() {
+            self.text.set_line_ending(line_ending);
+        }
         self.saved_version = version;
         self.has_unsaved_edits
             .set((self.saved_version().clone(), false));
@@ -812,44 +1196,90 @@ impl Buffer {
         cx.notify();
     }
 
-    /// Reloads the contents of the buffer from disk.
+    /// Reloads the contents of the buffer from disk, merging in any unsaved changes.
+    ///
+    /// The changes made on disk since the buffer was last saved or reloaded are
+    /// rebased onto the buffer's current contents. Changes that overlap unsaved
+    /// edits are discarded, leaving the buffer in a conflicted state. The buffer is
+    /// only considered saved if its contents end up matching the file on disk.
     pub fn reload(
         &mut self,
         cx: &mut ModelContext<Self>,
+    ) -> oneshot::Receiver<Option<Transaction>> {
+        self.reload_internal(false, cx)
+    }
+
+    /// Reloads the contents of the buffer from disk, discarding any unsaved
+    /// changes, including a pending line ending change. The new contents are
+    /// applied as a diff, so that as few operations as possible are sent to
+    /// collaborators.
+    pub fn reload_discarding_changes(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+    ) -> oneshot::Receiver<Option<Transaction>> {
+        self.reload_internal(true, cx)
+    }
+
+    fn reload_internal(
+        &mut self,
+        discard_changes: bool,
+        cx: &mut ModelContext<Self>,
     ) -> oneshot::Receiver<Option<Transaction>> {
         let (tx, rx) = futures::channel::oneshot::channel();
-        let prev_version = self.text.version();
         self.reload_task = Some(cx.spawn(|this, mut cx| async move {
             let Some((new_mtime, new_text)) = this.update(&mut cx, |this, cx| {
                 let file = this.file.as_ref()?.as_local()?;
-                Some((file.mtime(), file.load(cx)))
+                let encoding = this.encoding_chosen.then_some(this.encoding);
+                Some((file.mtime(), file.load(encoding, cx)))
             })?
             else {
                 return Ok(());
             };
 
-            let new_text = new_text.await?;
+            let (encoding, mut new_text) = new_text.await?;
             let diff = this
-                .update(&mut cx, |this, cx| this.diff(new_text.clone(), cx))?
+                .update(&mut cx, |this, cx| {
+                    if discard_changes {
+                        this.diff(new_text.clone(), cx)
+                    } else {
+                        // Diff the file against the contents it had when the buffer was
+                        // last saved, so that the unsaved edits can be preserved.
+                        let base_version = this.saved_version.clone();
+                        let base_text = this.text.rope_for_version(&base_version);
+                        Self::diff_texts(base_text, base_version, new_text.clone(), cx)
+                    }
+                })?
                 .await;
+            LineEnding::normalize(&mut new_text);
             this.update(&mut cx, |this, cx| {
-                if this.version() == diff.base_version {
-                    this.finalize_last_transaction();
-                    this.apply_diff(diff, cx);
-                    tx.send(this.finalize_last_transaction().cloned()).ok();
+                let had_conflict = this.has_conflict;
+                this.finalize_last_transaction();
+                let (_, conflicts) = this.merge_diff(diff, cx);
+                tx.send(this.finalize_last_transaction().cloned()).ok();
+                this.encoding = encoding;
+                if discard_changes {
+                    this.line_ending_override = None;
+                }
+
+                let matches_disk =
+                    this.len() == new_text.len() && this.as_rope().to_string() == new_text;
+                if matches_disk {
                     this.has_conflict = false;
                     this.did_reload(this.version(), this.line_ending(), new_mtime, cx);
+                } else if conflicts.is_empty() {
+                    // The buffer still has unsaved edits, so it remains dirty relative
+                    // to the version it was last saved at.
+                    this.has_conflict = false;
+                    let saved_version = this.saved_version.clone();
+                    this.did_reload(saved_version, this.line_ending(), new_mtime, cx);
                 } else {
-                    if !diff.edits.is_empty()
-                        || this
-                            .edits_since::<usize>(&diff.base_version)
-                            .next()
-                            .is_some()
-                    {
-                        this.has_conflict = true;
+                    // Leave the saved mtime as it was, so that collaborators also see
+                    // that the buffer conflicts with the file.
+                    this.has_conflict = true;
+                    if !had_conflict {
+                        cx.emit(Event::Conflict);
                     }
-
-                    this.did_reload(prev_version, this.line_ending(), this.saved_mtime, cx);
+                    cx.notify();
                 }
 
                 this.reload_task.take();
@@ -879,6 +1309,7 @@ impl Buffer {
     /// the file has changed or has been deleted.
     pub fn file_updated(&mut self, new_file: Arc<dyn File>, cx: &mut ModelContext<Self>) {
         let mut file_changed = false;
+        let mut conflict_started = false;
 
         if let Some(old_file) = self.file.as_ref() {
             if new_file.path() != old_file.path() {
@@ -897,8 +1328,12 @@ impl Buffer {
                 if new_mtime != old_file.mtime() {
                     file_changed = true;
 
-                    if !self.is_dirty() {
+                    // Local files are reloaded even when dirty, merging in the unsaved
+                    // edits. Otherwise, it's up to the host to reload the file.
+                    if new_file.as_local().is_some() || !self.is_dirty() {
                         self.reload(cx).close();
+                    } else {
+                        conflict_started = !self.has_conflict();
                     }
                 }
             }
@@ -912,6 +1347,9 @@ impl Buffer {
             cx.emit(Event::FileHandleChanged);
             cx.notify();
         }
+        if conflict_started && self.has_conflict() {
+            cx.emit(Event::Conflict);
+        }
     }
 
     /// Returns the current diff base, see [Buffer::set_diff_base].
@@ -949,8 +1387,8 @@ impl Buffer {
 
     /// Recomputes the Git diff status.
     pub fn git_diff_recalc(&mut self, cx: &mut ModelContext<Self>) -> Option<Task<()>> {
-        let diff_base = self.diff_base.clone()?;
         let snapshot = self.snapshot();
+        let diff_base = snapshot.diff_base_text()?.clone();
 
         let mut diff = self.git_diff.clone();
         let diff = cx.background_executor().spawn(async move {
@@ -1008,6 +1446,12 @@ impl Buffer {
         self.sync_parse_timeout = timeout;
     }
 
+    /// Sets how long an edit waits for autoindentation to be computed before
+    /// applying it asynchronously. Defaults to 500 microseconds.
+    pub fn set_autoindent_timeout(&mut self, timeout: Duration) {
+        self.autoindent_timeout = timeout;
+    }
+
     /// Called after an edit to synchronize the buffer's main parse tree with
     /// the buffer's new underlying state.
     ///
@@ -1054,8 +1498,10 @@ impl Buffer {
             let language = language.clone();
             let language_registry = language_registry.clone();
             async move {
-                syntax_snapshot.reparse(&text, language_registry, language);
-                syntax_snapshot
+                let parse_duration = time_parse(|| {
+                    syntax_snapshot.reparse(&text, language_registry, language);
+                });
+                (syntax_snapshot, parse_duration)
             }
         });
 
@@ -1063,27 +1509,37 @@ impl Buffer {
             .background_executor()
             .block_with_timeout(self.sync_parse_timeout, parse_task)
         {
-            Ok(new_syntax_snapshot) => {
+            Ok((new_syntax_snapshot, parse_duration)) => {
+                self.record_parse(parse_duration, true);
                 self.did_finish_parsing(new_syntax_snapshot, cx);
                 return;
             }
             Err(parse_task) => {
                 self.parsing_in_background = true;
                 cx.spawn(move |this, mut cx| async move {
-                    let new_syntax_map = parse_task.await;
+                    let (new_syntax_map, parse_duration) = parse_task.await;
                     this.update(&mut cx, move |this, cx| {
                         let grammar_changed =
                             this.language.as_ref().map_or(true, |current_language| {
                                 !Arc::ptr_eq(&language, current_language)
                             });
+                        if grammar_changed {
+                            // The syntax map was cleared when the language changed, so
+                            // discard this parse instead of installing layers from the old
+                            // grammar, and parse again from scratch with the new one.
+                            this.parsing_in_background = false;
+                            this.reparse(cx);
+                            return;
+                        }
+
                         let language_registry_changed = new_syntax_map
                             .contains_unknown_injections()
                             && language_registry.map_or(false, |registry| {
                                 registry.version() != new_syntax_map.language_registry_version()
                             });
                         let parse_again = language_registry_changed
-                            || grammar_changed
                             || this.version.changed_since(&parsed_version);
+                        this.record_parse(parse_duration, false);
                         this.did_finish_parsing(new_syntax_map, cx);
                         this.parsing_in_background = false;
                         if parse_again {
@@ -1097,6 +1553,31 @@ impl Buffer {
         }
     }
 
+    #[cfg(feature = "profile")]
+    fn record_parse(&mut self, duration: ParseDuration, sync: bool) {
+        self.parse_metrics.last_parse_duration = Some(duration);
+        if sync {
+            self.parse_metrics.sync_parse_count += 1;
+        } else {
+            self.parse_metrics.async_parse_count += 1;
+        }
+    }
+
+    #[cfg(not(feature = "profile"))]
+    fn record_parse(&mut self, _: ParseDuration, _: bool) {}
+
+    /// Returns how long the most recent parse of the buffer took.
+    #[cfg(feature = "profile")]
+    pub fn last_parse_duration(&self) -> Option<Duration> {
+        self.parse_metrics.last_parse_duration
+    }
+
+    /// Returns timing information about the buffer's parses so far.
+    #[cfg(feature = "profile")]
+    pub fn parse_metrics(&self) -> ParseMetrics {
+        self.parse_metrics
+    }
+
     fn did_finish_parsing(&mut self, syntax_snapshot: SyntaxSnapshot, cx: &mut ModelContext<Self>) {
         self.non_text_state_update_count += 1;
         self.syntax_map.lock().did_parse(syntax_snapshot);
@@ -1122,12 +1603,163 @@ impl Buffer {
         self.send_operation(op, cx);
     }
 
+    /// Assign to the buffer a set of diagnostics created on this replica, such as by a
+    /// spell checker running on a guest. Unlike [`Buffer::update_diagnostics`], these
+    /// diagnostics aren't shared with collaborators, and they coexist with any
+    /// diagnostics received from the host as long as their server ids differ.
+    pub fn update_local_diagnostics(
+        &mut self,
+        server_id: LanguageServerId,
+        diagnostics: DiagnosticSet,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if diagnostics.len() == 0 {
+            self.local_diagnostics_server_ids.remove(&server_id);
+        } else {
+            self.local_diagnostics_server_ids.insert(server_id);
+        }
+        self.set_diagnostics(server_id, diagnostics, cx);
+    }
+
+    /// Removes all of the buffer's diagnostics, such as when its language servers
+    /// have shut down. Collaborators are sent operations to remove the diagnostics
+    /// that were shared with them.
+    pub fn clear_diagnostics(&mut self, cx: &mut ModelContext<Self>) {
+        let server_ids = self
+            .diagnostics
+            .iter()
+            .map(|(server_id, _)| *server_id)
+            .collect::<Vec<_>>();
+        for server_id in server_ids {
+            if self.local_diagnostics_server_ids.contains(&server_id) {
+                self.update_local_diagnostics(server_id, DiagnosticSet::default(), cx);
+            } else {
+                self.update_diagnostics(server_id, DiagnosticSet::default(), cx);
+            }
+        }
+    }
+
+    /// Returns the buffer's store of positioned metadata with values of type `T`,
+    /// such as test coverage or review comments. The entries' ranges follow
+    /// subsequent edits to the buffer.
+    pub fn metadata_store<T: 'static + Clone>(&self) -> AnchorRangeMultimap<T> {
+        self.metadata_store_ref().cloned().unwrap_or_default()
+    }
+
+    fn metadata_store_ref<T: 'static + Clone>(&self) -> Option<&AnchorRangeMultimap<T>> {
+        self.metadata_stores
+            .get(&TypeId::of::<T>())
+            .and_then(|store| store.downcast_ref::<AnchorRangeMultimap<T>>())
+    }
+
+    /// Updates the buffer's store of positioned metadata with values of type `T`,
+    /// emitting [`Event::MetadataChanged`].
+    pub fn update_metadata_store<T: 'static + Clone, R>(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+        update: impl FnOnce(&mut AnchorRangeMultimap<T>, &text::BufferSnapshot) -> R,
+    ) -> R {
+        let store = self
+            .metadata_stores
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::<AnchorRangeMultimap<T>>::default())
+            .downcast_mut::<AnchorRangeMultimap<T>>()
+            .unwrap();
+        let result = update(store, &self.text);
+        cx.emit(Event::MetadataChanged);
+        cx.notify();
+        result
+    }
+
+    /// Attaches a set of ranges to the buffer under the given key, such as the
+    /// [`TypeId`] of the feature that owns them. Like diagnostics, the ranges
+    /// follow subsequent edits, but they are local to this replica and are never
+    /// sent to collaborators. The ranges are kept in one of the buffer's metadata
+    /// stores.
+    pub fn add_annotation_set(
+        &mut self,
+        ranges: impl IntoIterator<Item = Range<Anchor>>,
+        key: TypeId,
+        cx: &mut ModelContext<Self>,
+    ) -> AnnotationSetId {
+        let set_id = AnnotationSetId(post_inc(&mut self.next_annotation_set_id));
+        self.update_metadata_store(cx, |annotations, buffer| {
+            for range in ranges {
+                annotations.insert(range, Annotation { key, set_id }, buffer);
+            }
+        });
+        set_id
+    }
+
+    /// Removes all of the ranges in the given annotation set.
+    pub fn remove_annotation_set(&mut self, id: AnnotationSetId, cx: &mut ModelContext<Self>) {
+        self.update_metadata_store::<Annotation, _>(cx, |annotations, buffer| {
+            annotations.remove_matching(buffer, |entry| entry.value.set_id == id);
+        });
+    }
+
+    /// Returns the annotated ranges with the given key that intersect the given
+    /// range, along with the sets they belong to, ordered by position.
+    pub fn annotations_in_range<'a, T, O>(
+        &'a self,
+        key: TypeId,
+        range: Range<T>,
+    ) -> impl 'a + Iterator<Item = (Range<O>, AnnotationSetId)>
+    where
+        T: 'a + ToOffset,
+        O: 'a + FromAnchor,
+    {
+        self.metadata_store_ref::<Annotation>()
+            .into_iter()
+            .flat_map(move |annotations| {
+                annotations
+                    .range(
+                        range.start.to_offset(self)..range.end.to_offset(self),
+                        &self.text,
+                        true,
+                    )
+                    .filter(move |(_, annotation)| annotation.key == key)
+                    .map(|(range, annotation)| (range, annotation.set_id))
+            })
+    }
+
+    /// Records whether any code actions are available for the diagnostic group
+    /// with the given id, so that this doesn't need to be requested again while
+    /// the group exists.
+    pub fn set_diagnostic_group_fixable(
+        &mut self,
+        group_id: usize,
+        fixable: bool,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if self.fixable_diagnostic_groups.get(&group_id) != Some(&fixable) {
+            self.fixable_diagnostic_groups.insert(group_id, fixable);
+            self.non_text_state_update_count += 1;
+            cx.notify();
+        }
+    }
+
+    /// Sets the diagnostic group whose message is expanded inline, such as
+    /// when the cursor enters one of its diagnostics.
+    pub fn set_active_diagnostic_group(
+        &mut self,
+        group_id: Option<usize>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        if self.active_diagnostic_group != group_id {
+            self.active_diagnostic_group = group_id;
+            self.non_text_state_update_count += 1;
+            cx.notify();
+            cx.emit(Event::ActiveDiagnosticGroupChanged);
+        }
+    }
+
     fn request_autoindent(&mut self, cx: &mut ModelContext<Self>) {
         if let Some(indent_sizes) = self.compute_autoindents() {
             let indent_sizes = cx.background_executor().spawn(indent_sizes);
             match cx
                 .background_executor()
-                .block_with_timeout(Duration::from_micros(500), indent_sizes)
+                .block_with_timeout(self.autoindent_timeout, indent_sizes)
             {
                 Ok(indent_sizes) => self.apply_autoindents(indent_sizes, cx),
                 Err(indent_sizes) => {
@@ -1219,16 +1851,41 @@ impl Buffer {
                     yield_now().await;
                 }
 
+                // In languages whose blocks are delimited by indentation, indent the first
+                // line of each pasted block like the block that it was pasted into.
+                let mut rows_indented_by_scope = Vec::new();
+                if request.is_block_mode {
+                    for entry in &request.entries {
+                        let before_edit = &request.before_edit;
+                        let indent_pastes_by_scope = before_edit
+                            .language_at(entry.old_offset)
+                            .map_or(false, |language| language.config.indent_pastes_by_scope);
+                        if !indent_pastes_by_scope {
+                            continue;
+                        }
+                        if let Some(indent) =
+                            before_edit.indent_for_scope_at(entry.old_offset, entry.indent_size)
+                        {
+                            let new_row = entry.range.start.to_point(&snapshot).row;
+                            indent_sizes.insert(new_row, indent);
+                            rows_indented_by_scope.push(new_row);
+                        }
+                    }
+                }
+
                 // In block mode, only compute indentation suggestions for the first line
                 // of each insertion. Otherwise, compute suggestions for every inserted line.
                 let new_edited_row_ranges = contiguous_ranges(
-                    row_ranges.iter().flat_map(|(range, _)| {
-                        if request.is_block_mode {
-                            range.start..range.start + 1
-                        } else {
-                            range.clone()
-                        }
-                    }),
+                    row_ranges
+                        .iter()
+                        .flat_map(|(range, _)| {
+                            if request.is_block_mode {
+                                range.start..range.start + 1
+                            } else {
+                                range.clone()
+                            }
+                        })
+                        .filter(|row| !rows_indented_by_scope.contains(row)),
                     max_rows_between_yields,
                 );
 
@@ -1335,17 +1992,46 @@ impl Buffer {
         self.edit(edits, None, cx);
     }
 
-    /// Create a minimal edit that will cause the given row to be indented
-    /// with the given size. After applying this edit, the length of the line
-    /// will always be at least `new_size.len`.
-    pub fn edit_for_indent_size_adjustment(
-        row: u32,
-        current_size: IndentSize,
-        new_size: IndentSize,
-    ) -> Option<(Range<Point>, String)> {
-        if new_size.kind == current_size.kind {
-            match new_size.len.cmp(&current_size.len) {
-                Ordering::Greater => {
+    /// Recomputes the indentation of each of the given rows using the language's
+    /// indentation rules, rewriting their leading whitespace in a single transaction.
+    /// Blank rows and rows that are already indented correctly are left unchanged.
+    pub fn reindent_rows(
+        &mut self,
+        rows: Range<u32>,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<TransactionId> {
+        let snapshot = self.snapshot();
+        let rows = rows.start..rows.end.min(snapshot.max_point().row + 1);
+        let single_indent_size = snapshot.language_indent_size_at(Point::new(rows.start, 0), cx);
+        let edits = snapshot
+            .suggested_indents(rows, single_indent_size)
+            .into_iter()
+            .filter(|(row, _)| !snapshot.is_line_blank(*row))
+            .filter_map(|(row, indent_size)| {
+                let current_size = snapshot.indent_size_for_line(row);
+                Self::edit_for_indent_size_adjustment(row, current_size, indent_size)
+            })
+            .collect::<Vec<_>>();
+        if edits.is_empty() {
+            return None;
+        }
+
+        self.start_transaction();
+        self.edit(edits, None, cx);
+        self.end_transaction(cx)
+    }
+
+    /// Create a minimal edit that will cause the given row to be indented
+    /// with the given size. After applying this edit, the length of the line
+    /// will always be at least `new_size.len`.
+    pub fn edit_for_indent_size_adjustment(
+        row: u32,
+        current_size: IndentSize,
+        new_size: IndentSize,
+    ) -> Option<(Range<Point>, String)> {
+        if new_size.kind == current_size.kind {
+            match new_size.len.cmp(&current_size.len) {
+                Ordering::Greater => {
                     let point = Point::new(row, 0);
                     Some((
                         point..point,
@@ -1374,9 +2060,22 @@ impl Buffer {
 
     /// Spawns a background task that asynchronously computes a `Diff` between the buffer's text
     /// and the given new text.
-    pub fn diff(&self, mut new_text: String, cx: &AppContext) -> Task<Diff> {
-        let old_text = self.as_rope().clone();
-        let base_version = self.version();
+    ///
+    /// The texts are compared character by character, so that small changes within a line
+    /// produce correspondingly small edits when the diff is applied, rather than replacing
+    /// whole lines.
+    pub fn diff(&self, new_text: String, cx: &AppContext) -> Task<Diff> {
+        Self::diff_texts(self.as_rope().clone(), self.version(), new_text, cx)
+    }
+
+    /// Computes a `Diff` between the given text of the buffer at `base_version`
+    /// and the given new text.
+    fn diff_texts(
+        old_text: Rope,
+        base_version: clock::Global,
+        mut new_text: String,
+        cx: &AppContext,
+    ) -> Task<Diff> {
         cx.background_executor()
             .spawn_labeled(*BUFFER_DIFF_TASK, async move {
                 let old_text = old_text.to_string();
@@ -1447,24 +2146,61 @@ impl Buffer {
 
     /// Spawns a background task that searches the buffer for any whitespace
     /// at the ends of a lines, and returns a `Diff` that removes that whitespace.
-    pub fn remove_trailing_whitespace(&self, cx: &AppContext) -> Task<Diff> {
-        let old_text = self.as_rope().clone();
+    ///
+    /// Whitespace inside of string literals is left intact, as is whitespace on
+    /// the lines containing this replica's cursors if `preserve_cursor_lines` is set.
+    pub fn remove_trailing_whitespace(
+        &self,
+        preserve_cursor_lines: bool,
+        cx: &AppContext,
+    ) -> Task<Diff> {
+        let snapshot = self.snapshot();
         let line_ending = self.line_ending();
         let base_version = self.version();
+        let cursor_rows = self.cursor_rows(preserve_cursor_lines, &snapshot);
         cx.background_executor().spawn(async move {
-            let ranges = trailing_whitespace_ranges(&old_text);
-            let empty = Arc::<str>::from("");
             Diff {
                 base_version,
                 line_ending,
-                edits: ranges
-                    .into_iter()
-                    .map(|range| (range, empty.clone()))
-                    .collect(),
+                edits: removable_trailing_whitespace(&snapshot, &cursor_rows),
             }
         })
     }
 
+    /// Removes trailing whitespace and ensures a final newline, as enabled by the
+    /// buffer's language settings. This is the built-in pre-save hook, and it also
+    /// runs when the buffer is formatted.
+    pub fn format_whitespace(&mut self, cx: &mut ModelContext<Self>) {
+        let settings = language_settings(self.language(), self.file(), cx);
+        let preserve_cursor_lines = settings.preserve_trailing_whitespace_on_cursor_lines;
+        let ensure_final_newline = settings.ensure_final_newline_on_save;
+        if settings.remove_trailing_whitespace_on_save {
+            let snapshot = self.snapshot();
+            let cursor_rows = self.cursor_rows(preserve_cursor_lines, &snapshot);
+            let edits = removable_trailing_whitespace(&snapshot, &cursor_rows);
+            self.edit(edits, None, cx);
+        }
+        if ensure_final_newline {
+            self.ensure_final_newline(cx);
+        }
+    }
+
+    /// Returns the rows containing this replica's cursors, if `include` is set.
+    fn cursor_rows(&self, include: bool, snapshot: &BufferSnapshot) -> HashSet<u32> {
+        let mut cursor_rows = HashSet::default();
+        if include {
+            if let Some(selections) = self.remote_selections.get(&self.text.replica_id()) {
+                cursor_rows.extend(
+                    selections
+                        .selections
+                        .iter()
+                        .map(|selection| selection.head().to_point(snapshot).row),
+                );
+            }
+        }
+        cursor_rows
+    }
+
     /// Ensures that the buffer ends with a single newline character, and
     /// no other whitespace.
     pub fn ensure_final_newline(&mut self, cx: &mut ModelContext<Self>) {
@@ -1486,14 +2222,63 @@ impl Buffer {
         self.edit([(offset..len, "\n")], None, cx);
     }
 
+    /// Replaces each of the given ranges with its replacement text in a single edit,
+    /// which is undone as one transaction, returning the number of replaced ranges. When
+    /// `preserve_case` is set, each replacement is adapted to the case style of
+    /// the text it replaces, using [`preserve_case`]. The ranges must be sorted
+    /// and must not overlap.
+    pub fn replace_ranges(
+        &mut self,
+        replacements: Vec<(Range<Anchor>, String)>,
+        preserve_case: bool,
+        cx: &mut ModelContext<Self>,
+    ) -> usize {
+        if replacements.is_empty() {
+            return 0;
+        }
+
+        let count = replacements.len();
+        let edits = replacements
+            .into_iter()
+            .map(|(range, new_text)| {
+                if preserve_case {
+                    let old_text = self.text_for_range(range.clone()).collect::<String>();
+                    (range, self::preserve_case(&old_text, &new_text))
+                } else {
+                    (range, new_text)
+                }
+            })
+            .collect::<Vec<_>>();
+        self.finalize_last_transaction();
+        self.edit(edits, None, cx);
+        self.finalize_last_transaction();
+        count
+    }
+
     /// Applies a diff to the buffer. If the buffer has changed since the given diff was
     /// calculated, then adjust the diff to account for those changes, and discard any
     /// parts of the diff that conflict with those changes.
     pub fn apply_diff(&mut self, diff: Diff, cx: &mut ModelContext<Self>) -> Option<TransactionId> {
+        self.merge_diff(diff, cx).0
+    }
+
+    /// Applies a diff to the buffer like [`Buffer::apply_diff`], rebasing it onto any
+    /// changes made since it was calculated. Also returns the ranges of the buffer where
+    /// parts of the diff were discarded because they overlapped those changes. Parts of
+    /// the diff that those changes already made are skipped without a conflict.
+    pub fn merge_diff(
+        &mut self,
+        diff: Diff,
+        cx: &mut ModelContext<Self>,
+    ) -> (Option<TransactionId>, Vec<Range<Anchor>>) {
         // Check for any edits to the buffer that have occurred since this diff
         // was computed.
         let snapshot = self.snapshot();
-        let mut edits_since = snapshot.edits_since::<usize>(&diff.base_version).peekable();
+        let edits_since = snapshot
+            .edits_since::<usize>(&diff.base_version)
+            .collect::<Vec<_>>();
+        let mut edits_since = edits_since.iter().peekable();
+        let mut conflicts = Vec::new();
         let mut delta = 0;
         let adjusted_edits = diff.edits.into_iter().filter_map(|(range, new_text)| {
             while let Some(edit_since) = edits_since.peek() {
@@ -1508,8 +2293,37 @@ impl Buffer {
                     delta += edit_since.new_len() as i64 - edit_since.old_len() as i64;
                     edits_since.next();
                 }
-                // If the edit intersects a diff hunk, then discard that hunk.
+                // If the edit made the same change as the diff hunk, such as when
+                // the hunk was merged by an earlier reload, then skip the hunk.
+                else if edit_since.old == range
+                    && edit_since.new_len() == new_text.len()
+                    && snapshot
+                        .text_for_range(edit_since.new.clone())
+                        .collect::<String>()
+                        == *new_text
+                {
+                    delta += edit_since.new_len() as i64 - edit_since.old_len() as i64;
+                    edits_since.next();
+                    return None;
+                }
+                // If the edit intersects a diff hunk, then discard that hunk, and
+                // report the region spanned by the hunk and the edits it overlaps.
                 else {
+                    let start = edit_since
+                        .new
+                        .start
+                        .min((range.start as i64 + delta) as usize);
+                    let mut end = start;
+                    let mut end_delta = delta;
+                    for edit_since in edits_since
+                        .clone()
+                        .take_while(|edit_since| edit_since.old.start <= range.end)
+                    {
+                        end = edit_since.new.end;
+                        end_delta += edit_since.new_len() as i64 - edit_since.old_len() as i64;
+                    }
+                    end = end.max((range.end as i64 + end_delta) as usize);
+                    conflicts.push(snapshot.anchor_before(start)..snapshot.anchor_after(end));
                     return None;
                 }
             }
@@ -1522,7 +2336,7 @@ impl Buffer {
         self.start_transaction();
         self.text.set_line_ending(diff.line_ending);
         self.edit(adjusted_edits, None, cx);
-        self.end_transaction(cx)
+        (self.end_transaction(cx), conflicts)
     }
 
     fn has_unsaved_edits(&self) -> bool {
@@ -1544,6 +2358,7 @@ impl Buffer {
     pub fn is_dirty(&self) -> bool {
         self.has_conflict
             || self.has_unsaved_edits()
+            || self.line_ending_override.is_some()
             || self
                 .file
                 .as_ref()
@@ -1571,6 +2386,12 @@ impl Buffer {
         self.start_transaction_at(Instant::now())
     }
 
+    /// Sets the buffer's undo grouping duration. Transactions that end within
+    /// this interval of the previous one are undone and redone together.
+    pub fn set_group_interval(&mut self, group_interval: Duration) {
+        self.text.set_group_interval(group_interval);
+    }
+
     /// Starts a transaction, providing the current time. Subsequent transactions
     /// that occur within a short period of time will be grouped together. This
     /// is controlled by the buffer's undo grouping duration.
@@ -1602,12 +2423,51 @@ impl Buffer {
         } else {
             false
         };
-        if let Some((transaction_id, start_version)) = self.text.end_transaction_at(now) {
-            self.did_edit(&start_version, was_dirty, cx);
-            Some(transaction_id)
-        } else {
-            None
+        let transaction_id =
+            if let Some((transaction_id, start_version)) = self.text.end_transaction_at(now) {
+                self.did_edit(&start_version, was_dirty, cx);
+                Some(transaction_id)
+            } else {
+                None
+            };
+        if self.transaction_depth == 0 {
+            let operations = mem::take(&mut self.pending_operations);
+            self.send_operations(operations, cx);
         }
+        transaction_id
+    }
+
+    /// Runs the given closure within a transaction, so that the edits it makes
+    /// are undone and redone together. The transaction is ended even if the
+    /// closure panics.
+    pub fn transact(
+        &mut self,
+        cx: &mut ModelContext<Self>,
+        update: impl FnOnce(&mut Self, &mut ModelContext<Self>),
+    ) -> Option<TransactionId> {
+        struct EndTransactionOnDrop<'a, 'b> {
+            buffer: &'a mut Buffer,
+            cx: &'a mut ModelContext<'b, Buffer>,
+            ended: bool,
+        }
+
+        impl Drop for EndTransactionOnDrop<'_, '_> {
+            fn drop(&mut self) {
+                if !self.ended {
+                    self.buffer.end_transaction(self.cx);
+                }
+            }
+        }
+
+        self.start_transaction();
+        let mut guard = EndTransactionOnDrop {
+            buffer: self,
+            cx,
+            ended: false,
+        };
+        update(&mut *guard.buffer, &mut *guard.cx);
+        guard.ended = true;
+        guard.buffer.end_transaction(guard.cx)
     }
 
     /// Manually add a transaction to the buffer's undo history.
@@ -1694,6 +2554,123 @@ impl Buffer {
         cx.notify();
     }
 
+    /// Replaces this replica's selections with a single selection covering the given rows
+    /// in their entirety, including the newline at the end of the last row, if there is one.
+    pub fn select_rows(&mut self, rows: Range<u32>, cx: &mut ModelContext<Self>) {
+        let range = self.anchor_range_for_rows(rows);
+        let selection = Selection {
+            id: 0,
+            start: range.start,
+            end: range.end,
+            reversed: false,
+            goal: SelectionGoal::None,
+        };
+        self.set_active_selections_preserving_mode(Arc::from([selection]), cx);
+    }
+
+    /// Expands each of this replica's selections to cover the rows it touches in their
+    /// entirety, including their trailing newlines, merging selections that end up
+    /// overlapping. This is the basis of linewise operations.
+    pub fn select_current_line(&mut self, cx: &mut ModelContext<Self>) {
+        let Some(set) = self.remote_selections.get(&self.text.replica_id()) else {
+            return;
+        };
+
+        let mut selections: Vec<Selection<Anchor>> = Vec::new();
+        for selection in set.selections.iter() {
+            let start_row = selection.start.to_point(self).row;
+            let end_row = selection.end.to_point(self).row;
+            let range = self.anchor_range_for_rows(start_row..end_row + 1);
+            if let Some(last) = selections.last_mut() {
+                if range.start.cmp(&last.end, self).is_le() {
+                    last.end = range.end;
+                    continue;
+                }
+            }
+            selections.push(Selection {
+                id: selection.id,
+                start: range.start,
+                end: range.end,
+                reversed: false,
+                goal: SelectionGoal::None,
+            });
+        }
+        self.set_active_selections_preserving_mode(selections.into(), cx);
+    }
+
+    /// Replaces each of this replica's selections with the result of calling the given
+    /// function on it, and broadcasts the new selections once. Points returned by the
+    /// function are clipped to the buffer, and selections whose end precedes their start
+    /// are flipped to be reversed. Selections that end up overlapping or touching are
+    /// merged, as they are when set by the editor.
+    pub fn map_selections(
+        &mut self,
+        f: impl Fn(Selection<Point>) -> Selection<Point>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let Some(set) = self.remote_selections.get(&self.text.replica_id()) else {
+            return;
+        };
+
+        let mut mapped = set
+            .selections
+            .iter()
+            .map(|selection| {
+                let mut selection = f(selection.map(|anchor| anchor.to_point(self)))
+                    .map(|point| self.clip_point(point, Bias::Left));
+                if selection.start > selection.end {
+                    mem::swap(&mut selection.start, &mut selection.end);
+                    selection.reversed = !selection.reversed;
+                }
+                selection
+            })
+            .collect::<Vec<_>>();
+        mapped.sort_unstable_by_key(|selection| (selection.start, selection.end));
+
+        let mut selections: Vec<Selection<Point>> = Vec::with_capacity(mapped.len());
+        for selection in mapped {
+            if let Some(last) = selections.last_mut() {
+                if selection.start <= last.end {
+                    last.end = last.end.max(selection.end);
+                    last.goal = SelectionGoal::None;
+                    continue;
+                }
+            }
+            selections.push(selection);
+        }
+
+        let selections = selections
+            .into_iter()
+            .map(|selection| selection.map(|point| self.anchor_before(point)))
+            .collect::<Vec<_>>();
+        self.set_active_selections_preserving_mode(selections.into(), cx);
+    }
+
+    fn anchor_range_for_rows(&self, rows: Range<u32>) -> Range<Anchor> {
+        let max_point = self.max_point();
+        let start = Point::new(rows.start.min(max_point.row), 0);
+        let end = if rows.end > max_point.row {
+            max_point
+        } else {
+            Point::new(rows.end, 0)
+        };
+        self.anchor_before(start)..self.anchor_before(end)
+    }
+
+    fn set_active_selections_preserving_mode(
+        &mut self,
+        selections: Arc<[Selection<Anchor>]>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let (line_mode, cursor_shape) = self
+            .remote_selections
+            .get(&self.text.replica_id())
+            .map_or((false, CursorShape::default()), |set| {
+                (set.line_mode, set.cursor_shape)
+            });
+        self.set_active_selections(selections, line_mode, cursor_shape, cx);
+    }
+
     /// Clears the selections, so that other replicas of the buffer do not see any selections for
     /// this replica.
     pub fn remove_active_selections(&mut self, cx: &mut ModelContext<Self>) {
@@ -1735,27 +2712,104 @@ impl Buffer {
         S: ToOffset,
         T: Into<Arc<str>>,
     {
-        // Skip invalid edits and coalesce contiguous ones.
-        let mut edits: Vec<(Range<usize>, Arc<str>)> = Vec::new();
+        self.edit_internal(edits_iter, autoindent_mode, true, cx)
+    }
+
+    /// Applies the given edits to the buffer like [`Buffer::edit`], but without emitting
+    /// an [`Event::Operations`], so that the edits are never sent to other replicas.
+    ///
+    /// This is only intended for ephemeral edits in buffers that aren't shared, such as
+    /// buffers used for previews. Other replicas can't apply any subsequent operation
+    /// that depends on these edits, so the edits are refused if this buffer is a remote
+    /// replica or has seen another replica's operations or selections.
+    pub fn edit_local_only<I, S, T>(
+        &mut self,
+        edits_iter: I,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<clock::Lamport>
+    where
+        I: IntoIterator<Item = (Range<S>, T)>,
+        S: ToOffset,
+        T: Into<Arc<str>>,
+    {
+        if self.has_other_replicas() {
+            log::error!("refusing to make local-only edits to a shared buffer");
+            return None;
+        }
+        self.edit_internal(edits_iter, None, false, cx)
+    }
+
+    fn has_other_replicas(&self) -> bool {
+        let replica_id = self.replica_id();
+        replica_id != 0
+            || self
+                .version
+                .iter()
+                .any(|timestamp| timestamp.replica_id != replica_id)
+            || self
+                .remote_selections
+                .iter()
+                .any(|(selections_replica_id, _)| *selections_replica_id != replica_id)
+    }
+
+    /// Resolves the given edits to offsets, skipping ones that have no effect.
+    fn resolve_edits<I, S, T>(&self, edits_iter: I) -> Vec<(Range<usize>, Arc<str>)>
+    where
+        I: IntoIterator<Item = (Range<S>, T)>,
+        S: ToOffset,
+        T: Into<Arc<str>>,
+    {
+        let mut edits = Vec::new();
         for (range, new_text) in edits_iter {
             let mut range = range.start.to_offset(self)..range.end.to_offset(self);
             if range.start > range.end {
                 mem::swap(&mut range.start, &mut range.end);
             }
-            let new_text = new_text.into();
+            // Offsets that come from outside the editor may not lie on character
+            // boundaries. Rather than splitting a character, extend the range to
+            // cover it, or move an insertion to the character's start.
+            range.start = self.clip_offset(range.start, Bias::Left);
+            range.end = if range.end > range.start {
+                self.clip_offset(range.end, Bias::Right)
+            } else {
+                range.start
+            };
+            let new_text: Arc<str> = new_text.into();
             if !new_text.is_empty() || !range.is_empty() {
-                if let Some((prev_range, prev_text)) = edits.last_mut() {
-                    if prev_range.end >= range.start {
-                        prev_range.end = cmp::max(prev_range.end, range.end);
-                        *prev_text = format!("{prev_text}{new_text}").into();
-                    } else {
-                        edits.push((range, new_text));
-                    }
-                } else {
-                    edits.push((range, new_text));
-                }
+                edits.push((range, new_text));
             }
         }
+        edits
+    }
+
+    /// Resolves the given edits to offsets like [`Buffer::edit`] does, and also sorts
+    /// them, so that edits can be given in any order. Any that overlap or are adjacent
+    /// are coalesced.
+    fn normalize_edits<I, S, T>(&self, edits_iter: I) -> Vec<(Range<usize>, Arc<str>)>
+    where
+        I: IntoIterator<Item = (Range<S>, T)>,
+        S: ToOffset,
+        T: Into<Arc<str>>,
+    {
+        let mut edits = self.resolve_edits(edits_iter);
+        edits.sort_by_key(|(range, _)| range.start);
+        coalesce_edits(edits)
+    }
+
+    fn edit_internal<I, S, T>(
+        &mut self,
+        edits_iter: I,
+        autoindent_mode: Option<AutoindentMode>,
+        send_operation: bool,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<clock::Lamport>
+    where
+        I: IntoIterator<Item = (Range<S>, T)>,
+        S: ToOffset,
+        T: Into<Arc<str>>,
+    {
+        // Skip invalid edits and coalesce contiguous ones.
+        let edits = coalesce_edits(self.resolve_edits(edits_iter));
         if edits.is_empty() {
             return None;
         }
@@ -1806,10 +2860,9 @@ impl Buffer {
                     {
                         original_indent_column =
                             Some(original_indent_columns.get(ix).copied().unwrap_or_else(|| {
-                                indent_size_for_text(
-                                    new_text[range_of_insertion_to_indent.clone()].chars(),
+                                common_leading_indent(
+                                    &new_text[range_of_insertion_to_indent.clone()],
                                 )
-                                .len
                             }));
                         if new_text[range_of_insertion_to_indent.clone()].ends_with('\n') {
                             range_of_insertion_to_indent.end -= 1;
@@ -1819,6 +2872,7 @@ impl Buffer {
                     AutoindentRequestEntry {
                         first_line_is_new,
                         original_indent_column,
+                        old_offset: range.start,
                         indent_size: before_edit.language_indent_size_at(range.start, cx),
                         range: self.anchor_before(new_start + range_of_insertion_to_indent.start)
                             ..self.anchor_after(new_start + range_of_insertion_to_indent.end),
@@ -1833,11 +2887,309 @@ impl Buffer {
             }));
         }
 
+        // Send the operation before ending the transaction, so that it's batched
+        // with any edits made by synchronously applied autoindents.
+        if send_operation {
+            self.send_operation(Operation::Buffer(edit_operation), cx);
+        }
         self.end_transaction(cx);
-        self.send_operation(Operation::Buffer(edit_operation), cx);
         Some(edit_id)
     }
 
+    /// Applies the given edits to the buffer like [`Buffer::edit`], returning the range
+    /// of text inserted by each edit that was applied, in buffer order. Unlike `edit`,
+    /// this accepts edits in any order. Edits that have no effect are omitted, and ones
+    /// that overlap or are adjacent are coalesced into a single edit, whose range is
+    /// reported once.
+    pub fn edit_reporting<I, S, T>(
+        &mut self,
+        edits_iter: I,
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<Range<Anchor>>
+    where
+        I: IntoIterator<Item = (Range<S>, T)>,
+        S: ToOffset,
+        T: Into<Arc<str>>,
+    {
+        let edits = self.normalize_edits(edits_iter);
+        let version = self.version();
+        self.edit(edits, None, cx);
+        self.edits_since::<usize>(&version)
+            .map(|edit| self.anchor_before(edit.new.start)..self.anchor_after(edit.new.end))
+            .collect()
+    }
+
+    /// Swaps the character before each of the given offsets with the character after
+    /// it, as a single transaction. When an offset is at the end of a line, the two
+    /// characters preceding it are swapped instead. Newlines are never transposed.
+    ///
+    /// Returns the new offset for each of the given offsets, which is advanced past
+    /// the transposed characters.
+    pub fn transpose_chars(
+        &mut self,
+        offsets: &[usize],
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<usize> {
+        let snapshot = self.snapshot();
+        let mut edits = Vec::new();
+        let mut new_offsets = Vec::with_capacity(offsets.len());
+        for &offset in offsets {
+            let offset = snapshot.clip_offset(offset, Bias::Left);
+            let mut prev_chars = snapshot.reversed_chars_at(offset);
+            let (first, second, new_offset) = match snapshot.chars_at(offset).next() {
+                Some(next) if next != '\n' => {
+                    (prev_chars.next(), Some(next), offset + next.len_utf8())
+                }
+                _ => {
+                    let second = prev_chars.next();
+                    (prev_chars.next(), second, offset)
+                }
+            };
+
+            match (first, second) {
+                (Some(first), Some(second)) if first != '\n' && second != '\n' => {
+                    let start = new_offset - first.len_utf8() - second.len_utf8();
+                    edits.push((start..new_offset, format!("{second}{first}")));
+                    new_offsets.push(new_offset);
+                }
+                _ => new_offsets.push(offset),
+            }
+        }
+
+        self.edit_disjoint(edits, cx);
+        new_offsets
+    }
+
+    /// Swaps the word before each of the given offsets with the word after it, as a
+    /// single transaction. When an offset is inside of a word, that word is treated
+    /// as the word before the offset.
+    ///
+    /// Returns the new offset for each of the given offsets, which is placed at the
+    /// end of the transposed words.
+    pub fn transpose_words(
+        &mut self,
+        offsets: &[usize],
+        cx: &mut ModelContext<Self>,
+    ) -> Vec<usize> {
+        let snapshot = self.snapshot();
+        let mut edits = Vec::new();
+        let mut new_offsets = Vec::with_capacity(offsets.len());
+        for &offset in offsets {
+            let offset = snapshot.clip_offset(offset, Bias::Left);
+            let scope = snapshot.language_scope_at(offset);
+            let is_word = |c| char_kind(&scope, c) == CharKind::Word;
+
+            // Move to the end of the word containing the offset, if any.
+            let mut position = offset;
+            if snapshot
+                .reversed_chars_at(offset)
+                .next()
+                .map_or(false, is_word)
+            {
+                for c in snapshot.chars_at(offset) {
+                    if !is_word(c) {
+                        break;
+                    }
+                    position += c.len_utf8();
+                }
+            }
+
+            let mut first_end = position;
+            let mut prev_chars = snapshot.reversed_chars_at(position).peekable();
+            while let Some(c) = prev_chars.next_if(|c| !is_word(*c)) {
+                first_end -= c.len_utf8();
+            }
+            let mut first_start = first_end;
+            while let Some(c) = prev_chars.next_if(|c| is_word(*c)) {
+                first_start -= c.len_utf8();
+            }
+
+            let mut second_start = position;
+            let mut next_chars = snapshot.chars_at(position).peekable();
+            while let Some(c) = next_chars.next_if(|c| !is_word(*c)) {
+                second_start += c.len_utf8();
+            }
+            let mut second_end = second_start;
+            while let Some(c) = next_chars.next_if(|c| is_word(*c)) {
+                second_end += c.len_utf8();
+            }
+
+            if first_start == first_end || second_start == second_end {
+                new_offsets.push(offset);
+                continue;
+            }
+
+            let mut new_text = snapshot
+                .text_for_range(second_start..second_end)
+                .collect::<String>();
+            new_text.extend(snapshot.text_for_range(first_end..second_start));
+            new_text.extend(snapshot.text_for_range(first_start..first_end));
+            edits.push((first_start..second_end, new_text));
+            new_offsets.push(second_end);
+        }
+
+        self.edit_disjoint(edits, cx);
+        new_offsets
+    }
+
+    /// Inserts the given text at the given position, automatically pairing brackets.
+    ///
+    /// Only [bracket pairs](crate::BracketPair) that auto-close and are enabled in the
+    /// language scope at the position are considered, and only when the position is in
+    /// code, rather than in a string or comment. When the text is the start of such a
+    /// pair, and the position is followed by whitespace, the end of the buffer, or one
+    /// of the language's `autoclose_before` characters, the closing bracket is inserted
+    /// after it. When the text is the end of such a pair and that text already follows
+    /// the position, nothing is inserted, so that the existing closing bracket is typed
+    /// over.
+    ///
+    /// Returns true if the text was handled in either of these ways, in which case
+    /// the caller should place the cursor right after the typed text, between the
+    /// brackets. Otherwise, the text is inserted as-is and false is returned.
+    pub fn handle_input(
+        &mut self,
+        position: Anchor,
+        text: &str,
+        cx: &mut ModelContext<Self>,
+    ) -> bool {
+        let snapshot = self.snapshot();
+        let offset = position.to_offset(&snapshot);
+        if let Some(scope) = snapshot.language_scope_at(offset) {
+            if snapshot.syntax_class_at(offset) == SyntaxClass::Code {
+                let pairs = scope
+                    .brackets()
+                    .filter(|(pair, enabled)| *enabled && pair.close)
+                    .map(|(pair, _)| pair)
+                    .collect::<Vec<_>>();
+
+                if pairs.iter().any(|pair| pair.end == text)
+                    && snapshot.contains_str_at(offset, text)
+                {
+                    return true;
+                }
+
+                if let Some(pair) = pairs.iter().find(|pair| pair.start == text) {
+                    let should_autoclose = snapshot
+                        .chars_at(offset)
+                        .next()
+                        .map_or(true, |next_char| scope.should_autoclose_before(next_char));
+                    if should_autoclose {
+                        let new_text = format!("{}{}", pair.start, pair.end);
+                        self.edit([(offset..offset, new_text)], None, cx);
+                        return true;
+                    }
+                }
+            }
+        }
+
+        self.edit([(offset..offset, text)], None, cx);
+        false
+    }
+
+    /// Inserts a newline at the given position, automatically indenting the new line.
+    ///
+    /// When the position is between an empty pair of brackets, such as `{}`, a blank
+    /// line is inserted between them as well, so that the closing bracket moves down
+    /// and is outdented by the language's indentation rules. Returns true in this
+    /// case, where the caller should place the cursor at the end of the blank line.
+    pub fn handle_newline(&mut self, position: Anchor, cx: &mut ModelContext<Self>) -> bool {
+        let snapshot = self.snapshot();
+        let offset = position.to_offset(&snapshot);
+        let is_between_brackets = snapshot
+            .enclosing_bracket_ranges(offset..offset)
+            .any(|(open, close)| open.end == offset && close.start == offset);
+
+        let new_text = if is_between_brackets { "\n\n" } else { "\n" };
+        self.edit(
+            [(offset..offset, new_text)],
+            Some(AutoindentMode::EachLine),
+            cx,
+        );
+        is_between_brackets
+    }
+
+    /// Inserts indentation at each of the given offsets, as a single transaction. When
+    /// hard tabs are enabled, a tab character is inserted. Otherwise, enough spaces are
+    /// inserted to advance the offset's visual column, where tabs expand to the tab
+    /// size, to the next multiple of the tab size.
+    ///
+    /// Returns the new offset for each of the given offsets, which is placed after the
+    /// inserted indentation.
+    pub fn insert_tab(&mut self, offsets: &[usize], cx: &mut ModelContext<Self>) -> Vec<usize> {
+        let snapshot = self.snapshot();
+        let mut edits: Vec<(Range<usize>, String)> = Vec::new();
+        let mut anchors = Vec::with_capacity(offsets.len());
+        for &offset in offsets {
+            let offset = snapshot.clip_offset(offset, Bias::Left);
+            anchors.push(snapshot.anchor_after(offset));
+            if edits.iter().any(|(range, _)| range.start == offset) {
+                continue;
+            }
+
+            let settings = snapshot.settings_at(offset, cx);
+            if settings.hard_tabs {
+                edits.push((offset..offset, "\t".to_string()));
+                continue;
+            }
+
+            let tab_size = settings.tab_size.get();
+            let line_start =
+                snapshot.point_to_offset(Point::new(snapshot.offset_to_point(offset).row, 0));
+            let column = snapshot
+                .chars_for_range(line_start..offset)
+                .fold(0, |column, c| {
+                    if c == '\t' {
+                        column + tab_size - column % tab_size
+                    } else {
+                        column + 1
+                    }
+                });
+            let len = tab_size - column % tab_size;
+            edits.push((offset..offset, " ".repeat(len as usize)));
+        }
+
+        self.edit_disjoint(edits, cx);
+        anchors
+            .into_iter()
+            .map(|anchor| anchor.to_offset(self))
+            .collect()
+    }
+
+    /// Removes the innermost pair of brackets enclosing the given position, keeping
+    /// the text between them, as a single transaction.
+    pub fn delete_enclosing_brackets<T: ToOffset>(
+        &mut self,
+        position: T,
+        cx: &mut ModelContext<Self>,
+    ) -> Option<TransactionId> {
+        let (open, close) = self.snapshot().innermost_brackets_at(position)?;
+        self.start_transaction();
+        self.edit([(open, ""), (close, "")], None, cx);
+        self.end_transaction(cx)
+    }
+
+    /// Applies the given edits in a single transaction, discarding any edit
+    /// that overlaps with a preceding one.
+    fn edit_disjoint(
+        &mut self,
+        mut edits: Vec<(Range<usize>, String)>,
+        cx: &mut ModelContext<Self>,
+    ) {
+        edits.sort_by_key(|(range, _)| range.start);
+        let mut last_end = None;
+        edits.retain(|(range, _)| {
+            if last_end.map_or(false, |last_end| range.start < last_end) {
+                false
+            } else {
+                last_end = Some(range.end);
+                true
+            }
+        });
+        self.start_transaction();
+        self.edit(edits, None, cx);
+        self.end_transaction(cx);
+    }
+
     fn did_edit(
         &mut self,
         old_version: &clock::Global,
@@ -1919,6 +3271,7 @@ impl Buffer {
                 .iter()
                 .all(|s| self.can_resolve(&s.start) && self.can_resolve(&s.end)),
             Operation::UpdateCompletionTriggers { .. } => true,
+            Operation::UpdateLineEndingOverride { .. } => true,
         }
     }
 
@@ -1971,6 +3324,16 @@ impl Buffer {
                 self.completion_triggers = triggers;
                 self.text.lamport_clock.observe(lamport_timestamp);
             }
+            Operation::UpdateLineEndingOverride {
+                line_ending,
+                lamport_timestamp,
+            } => {
+                if lamport_timestamp > self.line_ending_override_timestamp {
+                    self.line_ending_override_timestamp = lamport_timestamp;
+                    self.text.lamport_clock.observe(lamport_timestamp);
+                    self.set_line_ending_override(line_ending, cx);
+                }
+            }
         }
     }
 
@@ -1982,27 +3345,70 @@ impl Buffer {
         cx: &mut ModelContext<Self>,
     ) {
         if lamport_timestamp > self.diagnostics_timestamp {
-            let ix = self.diagnostics.binary_search_by_key(&server_id, |e| e.0);
-            if diagnostics.len() == 0 {
-                if let Ok(ix) = ix {
-                    self.diagnostics.remove(ix);
-                }
-            } else {
-                match ix {
-                    Err(ix) => self.diagnostics.insert(ix, (server_id, diagnostics)),
-                    Ok(ix) => self.diagnostics[ix].1 = diagnostics,
-                };
-            }
+            self.local_diagnostics_server_ids.remove(&server_id);
             self.diagnostics_timestamp = lamport_timestamp;
-            self.non_text_state_update_count += 1;
             self.text.lamport_clock.observe(lamport_timestamp);
-            cx.notify();
-            cx.emit(Event::DiagnosticsUpdated);
+            self.set_diagnostics(server_id, diagnostics, cx);
+        }
+    }
+
+    fn set_diagnostics(
+        &mut self,
+        server_id: LanguageServerId,
+        diagnostics: DiagnosticSet,
+        cx: &mut ModelContext<Self>,
+    ) {
+        let old_group_ids = self.diagnostic_group_ids();
+        let ix = self.diagnostics.binary_search_by_key(&server_id, |e| e.0);
+        if diagnostics.len() == 0 {
+            if let Ok(ix) = ix {
+                self.diagnostics.remove(ix);
+            }
+        } else {
+            match ix {
+                Err(ix) => self.diagnostics.insert(ix, (server_id, diagnostics)),
+                Ok(ix) => self.diagnostics[ix].1 = diagnostics,
+            };
+        }
+        let group_ids = self.diagnostic_group_ids();
+        let mut removed_group_ids = old_group_ids
+            .difference(&group_ids)
+            .copied()
+            .collect::<Vec<_>>();
+        removed_group_ids.sort_unstable();
+        self.fixable_diagnostic_groups
+            .retain(|group_id, _| group_ids.contains(group_id));
+        if self
+            .active_diagnostic_group
+            .is_some_and(|group_id| !group_ids.contains(&group_id))
+        {
+            self.active_diagnostic_group = None;
+            cx.emit(Event::ActiveDiagnosticGroupChanged);
         }
+        self.non_text_state_update_count += 1;
+        cx.notify();
+        cx.emit(Event::DiagnosticsUpdated { removed_group_ids });
+    }
+
+    fn diagnostic_group_ids(&self) -> HashSet<usize> {
+        self.diagnostics
+            .iter()
+            .flat_map(|(_, set)| set.iter().map(|entry| entry.diagnostic.group_id))
+            .collect()
     }
 
     fn send_operation(&mut self, operation: Operation, cx: &mut ModelContext<Self>) {
-        cx.emit(Event::Operation(operation));
+        if self.transaction_depth > 0 {
+            self.pending_operations.push(operation);
+        } else {
+            self.send_operations(vec![operation], cx);
+        }
+    }
+
+    fn send_operations(&mut self, operations: Vec<Operation>, cx: &mut ModelContext<Self>) {
+        if !operations.is_empty() {
+            cx.emit(Event::Operations(operations));
+        }
     }
 
     /// Removes the selections for a given peer.
@@ -2042,7 +3448,11 @@ impl Buffer {
         }
     }
 
-    /// Manually undoes all changes after a given transaction in the buffer's undo history.
+    /// Undoes the given transaction along with every transaction made after it,
+    /// moving them all onto the redo stack so they can be redone in order.
+    ///
+    /// Does nothing if the transaction isn't in the undo stack, either because
+    /// it has already been undone or because it doesn't exist yet.
     pub fn undo_to_transaction(
         &mut self,
         transaction_id: TransactionId,
@@ -2115,6 +3525,64 @@ impl Buffer {
     pub fn completion_triggers(&self) -> &[String] {
         &self.completion_triggers
     }
+
+    /// Expands the given selection to the closest syntax node enclosing it, recording
+    /// the original range so that [`Buffer::shrink_syntax_selection`] can retrace it.
+    pub fn expand_syntax_selection(
+        &mut self,
+        selection_id: usize,
+        range: Range<usize>,
+    ) -> Range<usize> {
+        let snapshot = self.snapshot();
+        let new_range = snapshot.expand_selection(range.clone());
+        if new_range != range {
+            self.syntax_selection_history
+                .entry(selection_id)
+                .or_default()
+                .push((
+                    snapshot.anchor_before(range.start)..snapshot.anchor_after(range.end),
+                    snapshot.anchor_before(new_range.start)..snapshot.anchor_after(new_range.end),
+                ));
+        }
+        new_range
+    }
+
+    /// Shrinks the given selection to the range it occupied before it was last expanded
+    /// via [`Buffer::expand_syntax_selection`]. If the selection has changed since then,
+    /// falls back to the syntax node containing the start of the selection.
+    pub fn shrink_syntax_selection(
+        &mut self,
+        selection_id: usize,
+        range: Range<usize>,
+    ) -> Range<usize> {
+        let snapshot = self.snapshot();
+        if let Some(history) = self.syntax_selection_history.get_mut(&selection_id) {
+            if let Some((previous_range, expanded_range)) = history.pop() {
+                if history.is_empty() {
+                    self.syntax_selection_history.remove(&selection_id);
+                }
+                if expanded_range.to_offset(&snapshot) == range {
+                    return previous_range.to_offset(&snapshot);
+                }
+                self.syntax_selection_history.remove(&selection_id);
+            }
+        }
+        snapshot.shrink_selection(range.clone(), range.start)
+    }
+
+    /// Returns the ranges that the given selection occupied before each of its
+    /// syntax-aware expansions, from outermost to innermost.
+    pub fn syntax_selection_history(&self, selection_id: usize) -> Vec<Range<usize>> {
+        self.syntax_selection_history
+            .get(&selection_id)
+            .map_or(Vec::new(), |history| {
+                history
+                    .iter()
+                    .rev()
+                    .map(|(previous_range, _)| previous_range.to_offset(self))
+                    .collect()
+            })
+    }
 }
 
 #[doc(hidden)]
@@ -2130,10 +3598,6 @@ impl Buffer {
         self.edit(edits, autoindent_mode, cx);
     }
 
-    pub fn set_group_interval(&mut self, group_interval: Duration) {
-        self.text.set_group_interval(group_interval);
-    }
-
     pub fn randomly_edit<T>(
         &mut self,
         rng: &mut T,
@@ -2194,6 +3658,52 @@ impl BufferSnapshot {
     pub fn indent_size_for_line(&self, row: u32) -> IndentSize {
         indent_size_for_line(self, row)
     }
+
+    /// Returns the offset of the end of the given row, before its newline.
+    pub fn line_end_offset(&self, row: u32) -> usize {
+        self.point_to_offset(Point::new(row, self.line_len(row)))
+    }
+
+    /// Returns the range of the spaces and tabs at the end of the given row, excluding
+    /// its newline. For an empty row, this is an empty range at the start of the row.
+    pub fn trailing_whitespace_range(&self, row: u32) -> Range<usize> {
+        let end = self.line_end_offset(row);
+        let len = self
+            .reversed_chars_at(end)
+            .take_while(|c| matches!(c, ' ' | '\t'))
+            .map(char::len_utf8)
+            .sum::<usize>();
+        end - len..end
+    }
+
+    /// Returns the text of the given row in a form suitable for assistive technology,
+    /// such as screen readers. Tabs are expanded to spaces according to `tab_size`,
+    /// and control characters are replaced with their Unicode control pictures (or
+    /// with U+FFFD when there is none), so that they aren't silently dropped.
+    pub fn accessible_line(&self, row: u32, tab_size: NonZeroU32) -> String {
+        let tab_size = tab_size.get() as usize;
+        let start = Point::new(row, 0);
+        let end = Point::new(row, self.line_len(row));
+        let mut line = String::new();
+        let mut column = 0;
+        for c in self.chars_for_range(start..end) {
+            match c {
+                '\t' => {
+                    let len = tab_size - column % tab_size;
+                    line.extend(iter::repeat(' ').take(len));
+                    column += len;
+                    continue;
+                }
+                '\0'..='\x1f' => line.push(char::from_u32(0x2400 + c as u32).unwrap()),
+                '\x7f' => line.push('\u{2421}'),
+                c if c.is_control() => line.push(char::REPLACEMENT_CHARACTER),
+                c => line.push(c),
+            }
+            column += 1;
+        }
+        line
+    }
+
     /// Returns [`IndentSize`] for a given position that respects user settings
     /// and language preferences.
     pub fn language_indent_size_at<T: ToOffset>(&self, position: T, cx: &AppContext) -> IndentSize {
@@ -2238,6 +3748,27 @@ impl BufferSnapshot {
         result
     }
 
+    /// Returns the indentation column that the autoindenter would choose for the given
+    /// row, without modifying the buffer. The column is visual, with each tab of the
+    /// indentation spanning `tab_size` columns. The unit of indentation is passed in as
+    /// `single_indent_size`. Returns `None` if the language provides no suggestion for
+    /// the row.
+    pub fn suggested_indent_for_row(
+        &self,
+        row: u32,
+        single_indent_size: IndentSize,
+        tab_size: NonZeroU32,
+    ) -> Option<u32> {
+        let suggestion = self.suggest_autoindents(row..row + 1)?.next()??;
+        let indent = self
+            .indent_size_for_line(suggestion.basis_row)
+            .with_delta(suggestion.delta, single_indent_size);
+        Some(match indent.kind {
+            IndentKind::Space => indent.len,
+            IndentKind::Tab => indent.len * tab_size.get(),
+        })
+    }
+
     fn suggest_autoindents(
         &self,
         row_range: Range<u32>,
@@ -2458,21 +3989,37 @@ impl BufferSnapshot {
     /// returned in chunks where each chunk has a single syntax highlighting style and
     /// diagnostic status.
     pub fn chunks<T: ToOffset>(&self, range: Range<T>, language_aware: bool) -> BufferChunks {
+        self.chunks_with_min_severity(range, language_aware, DiagnosticSeverity::HINT)
+    }
+
+    /// Iterates over chunks of text in the given range of the buffer, like [`Self::chunks`],
+    /// but ignores any diagnostics that are less severe than `min_severity`, so that they
+    /// neither split chunks nor contribute to a chunk's diagnostic status.
+    pub fn chunks_with_min_severity<T: ToOffset>(
+        &self,
+        range: Range<T>,
+        language_aware: bool,
+        min_severity: DiagnosticSeverity,
+    ) -> BufferChunks {
         let range = range.start.to_offset(self)..range.end.to_offset(self);
 
         let mut syntax = None;
         let mut diagnostic_endpoints = Vec::new();
         if language_aware {
-            let captures = self.syntax.captures(range.clone(), &self.text, |grammar| {
-                grammar.highlights_query.as_ref()
+            let has_grammar = self
+                .language
+                .as_ref()
+                .map_or(false, |language| language.grammar().is_some());
+            syntax = Some(if self.fallback_highlighting && !has_grammar {
+                self.fallback_highlights(range.clone())
+            } else {
+                self.highlight_captures(range.clone())
             });
-            let highlight_maps = captures
-                .grammars()
-                .into_iter()
-                .map(|grammar| grammar.highlight_map())
-                .collect();
-            syntax = Some((captures, highlight_maps));
             for entry in self.diagnostics_in_range::<_, usize>(range.clone(), false) {
+                // Severities are ordered from most to least severe.
+                if entry.diagnostic.severity > min_severity {
+                    continue;
+                }
                 diagnostic_endpoints.push(DiagnosticEndpoint {
                     offset: entry.range.start,
                     is_start: true,
@@ -2486,11 +4033,235 @@ impl BufferSnapshot {
                     is_unnecessary: entry.diagnostic.is_unnecessary,
                 });
             }
-            diagnostic_endpoints
-                .sort_unstable_by_key(|endpoint| (endpoint.offset, !endpoint.is_start));
+            diagnostic_endpoints
+                .sort_unstable_by_key(|endpoint| (endpoint.offset, !endpoint.is_start));
+        }
+
+        BufferChunks::with_captures(self.text.as_rope(), range, syntax, diagnostic_endpoints)
+    }
+
+    /// Iterates over chunks of text in the given range of the buffer, like [`Self::chunks`],
+    /// but also splits the text at the boundaries of runs of spaces and tabs, so that each
+    /// run is returned as its own chunk, tagged with its [`WhitespaceKind`].
+    pub fn chunks_with_whitespace<T: ToOffset>(
+        &self,
+        range: Range<T>,
+        language_aware: bool,
+    ) -> BufferChunks {
+        let mut chunks = self.chunks(range, language_aware);
+        chunks.render_whitespace = true;
+        chunks
+    }
+
+    /// Iterates over chunks of text in the given range of the buffer, like [`Self::chunks`],
+    /// but also splits the text at the boundaries of the given overlay highlights, which
+    /// must be sorted by their start. Each chunk's [`Chunk::highlight_style`] is the
+    /// combination of the overlays containing it, to be layered over its syntax highlight.
+    pub fn chunks_with_highlights<T: ToOffset>(
+        &self,
+        range: Range<T>,
+        language_aware: bool,
+        highlights: &[(Range<usize>, HighlightStyle)],
+    ) -> BufferChunks {
+        let mut chunks = self.chunks(range, language_aware);
+        let range = chunks.range.clone();
+        chunks.overlay_highlights = highlights
+            .iter()
+            .filter(|(highlight_range, _)| {
+                highlight_range.start < range.end && highlight_range.end > range.start
+            })
+            .cloned()
+            .collect();
+        chunks
+    }
+
+    /// Returns the heuristic highlighter's captures for the given range, reusing the
+    /// captures from a previous call with the same range if the text hasn't changed.
+    fn fallback_highlights(&self, range: Range<usize>) -> (BufferChunkCaptures, Vec<HighlightMap>) {
+        let captures = if range.len() > MAX_CACHED_HIGHLIGHT_RANGE_LEN {
+            self.fallback_highlight_captures(range.clone())
+        } else if let Some(captures) = self.cached_highlight_captures(&range, true) {
+            captures
+        } else {
+            let captures = self.fallback_highlight_captures(range.clone());
+            self.cache_highlight_captures(range.clone(), true, captures.clone());
+            captures
+        };
+        (
+            BufferChunkCaptures::Cached {
+                captures,
+                range,
+                ix: 0,
+            },
+            vec![FALLBACK_HIGHLIGHT_MAP.read().clone()],
+        )
+    }
+
+    /// Finds comments, strings and numbers in the lines intersecting the given range
+    /// using [`FALLBACK_HIGHLIGHTS_REGEX`]. Block comments that begin before the
+    /// first of these lines aren't detected.
+    fn fallback_highlight_captures(&self, range: Range<usize>) -> Arc<[HighlightCapture]> {
+        let start = Point::new(range.start.to_point(self).row, 0).to_offset(self);
+        let end_row = range.end.to_point(self).row;
+        let end = Point::new(end_row, self.line_len(end_row)).to_offset(self);
+        let text = self.text_for_range(start..end).collect::<String>();
+        FALLBACK_HIGHLIGHTS_REGEX
+            .captures_iter(&text)
+            .filter_map(|captures| {
+                let (capture_index, token) = captures
+                    .iter()
+                    .skip(1)
+                    .enumerate()
+                    .find_map(|(ix, token)| Some((ix, token?)))?;
+                Some(HighlightCapture {
+                    range: start + token.start()..start + token.end(),
+                    grammar_index: 0,
+                    capture_index: capture_index as u32,
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the syntax highlight captures for the given range, reusing the captures
+    /// from a previous call with the same range if the syntax trees haven't changed since.
+    fn highlight_captures(&self, range: Range<usize>) -> (BufferChunkCaptures, Vec<HighlightMap>) {
+        let query: fn(&Grammar) -> Option<&tree_sitter::Query> =
+            |grammar| grammar.highlights_query.as_ref();
+        if range.len() > MAX_CACHED_HIGHLIGHT_RANGE_LEN {
+            let captures = self.syntax.captures(range, &self.text, query);
+            let highlight_maps = captures
+                .grammars()
+                .iter()
+                .map(|grammar| grammar.highlight_map())
+                .collect();
+            return (BufferChunkCaptures::Live(captures), highlight_maps);
+        }
+
+        // Mirror the order in which the captures iterator assigns grammar indices,
+        // so that cached captures can be resolved without running the query.
+        let mut grammars = Vec::<&Grammar>::new();
+        for layer in self.syntax.layers_for_range(range.clone(), &self.text) {
+            if let Some(grammar) = layer.language.grammar() {
+                if query(grammar).is_some() && grammars.iter().all(|g| g.id() != grammar.id()) {
+                    grammars.push(grammar);
+                }
+            }
+        }
+        let highlight_maps = grammars
+            .iter()
+            .map(|grammar| grammar.highlight_map())
+            .collect();
+
+        let captures = if let Some(captures) = self.cached_highlight_captures(&range, false) {
+            captures
+        } else {
+            let syntax_captures = self.syntax.captures(range.clone(), &self.text, query);
+            let grammar_indices = syntax_captures
+                .grammars()
+                .iter()
+                .map(|grammar| grammars.iter().position(|g| g.id() == grammar.id()))
+                .collect::<Option<Vec<_>>>();
+            let Some(grammar_indices) = grammar_indices else {
+                // The layers disagree with the captures iterator, so the cached grammar
+                // indices couldn't be resolved. Fall back to the live captures.
+                let highlight_maps = syntax_captures
+                    .grammars()
+                    .iter()
+                    .map(|grammar| grammar.highlight_map())
+                    .collect();
+                return (BufferChunkCaptures::Live(syntax_captures), highlight_maps);
+            };
+            let captures = syntax_captures
+                .map(|capture| HighlightCapture {
+                    range: capture.node.byte_range(),
+                    grammar_index: grammar_indices[capture.grammar_index],
+                    capture_index: capture.index,
+                })
+                .collect::<Arc<[_]>>();
+            self.cache_highlight_captures(range.clone(), false, captures.clone());
+            captures
+        };
+
+        (
+            BufferChunkCaptures::Cached {
+                captures,
+                range,
+                ix: 0,
+            },
+            highlight_maps,
+        )
+    }
+
+    /// Returns the cached captures for the given range, if any were stored for this
+    /// snapshot's text and syntax trees, marking them as most recently used.
+    fn cached_highlight_captures(
+        &self,
+        range: &Range<usize>,
+        fallback: bool,
+    ) -> Option<Arc<[HighlightCapture]>> {
+        let cache = self.highlight_cache.read();
+        let entry = cache
+            .entries
+            .iter()
+            .find(|entry| self.is_highlight_cache_entry_valid(entry, range, fallback))?;
+        let last_used = cache.last_used.fetch_add(1, atomic::Ordering::Relaxed) + 1;
+        entry.last_used.store(last_used, atomic::Ordering::Relaxed);
+        Some(entry.captures.clone())
+    }
+
+    /// Stores the captures for the given range, evicting the least recently used entry
+    /// if the cache is full.
+    fn cache_highlight_captures(
+        &self,
+        range: Range<usize>,
+        fallback: bool,
+        captures: Arc<[HighlightCapture]>,
+    ) {
+        let mut cache = self.highlight_cache.write();
+        cache
+            .entries
+            .retain(|entry| !self.is_highlight_cache_entry_valid(entry, &range, fallback));
+        if cache.entries.len() >= HIGHLIGHT_CACHE_CAPACITY {
+            let least_recently_used = cache
+                .entries
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, entry)| entry.last_used.load(atomic::Ordering::Relaxed))
+                .map(|(ix, _)| ix);
+            if let Some(ix) = least_recently_used {
+                cache.entries.swap_remove(ix);
+            }
         }
+        let last_used = *cache.last_used.get_mut() + 1;
+        *cache.last_used.get_mut() = last_used;
+        cache.entries.push(HighlightCacheEntry {
+            text_version: self.version().clone(),
+            parsed_version: self.syntax.parsed_version().clone(),
+            interpolated_version: self.syntax.interpolated_version().clone(),
+            language_registry_version: self.syntax.language_registry_version(),
+            non_text_state_update_count: self.non_text_state_update_count,
+            fallback,
+            range,
+            captures,
+            last_used: AtomicUsize::new(last_used),
+        });
+    }
 
-        BufferChunks::new(self.text.as_rope(), range, syntax, diagnostic_endpoints)
+    /// Whether the given cache entry holds the captures for the given range in this
+    /// snapshot's text and syntax trees.
+    fn is_highlight_cache_entry_valid(
+        &self,
+        entry: &HighlightCacheEntry,
+        range: &Range<usize>,
+        fallback: bool,
+    ) -> bool {
+        entry.range == *range
+            && entry.fallback == fallback
+            && entry.non_text_state_update_count == self.non_text_state_update_count
+            && entry.text_version == *self.version()
+            && entry.language_registry_version == self.syntax.language_registry_version()
+            && entry.parsed_version == *self.syntax.parsed_version()
+            && entry.interpolated_version == *self.syntax.interpolated_version()
     }
 
     /// Invokes the given callback for each line of text in the given range of the buffer.
@@ -2593,6 +4364,30 @@ impl BufferSnapshot {
         })
     }
 
+    /// Returns the range of the word containing the given offset. Word characters are
+    /// alphanumerics, underscores, and any of the language's additional
+    /// [word characters](LanguageScope::word_characters). The returned range is empty
+    /// if the offset isn't adjacent to any word characters.
+    pub fn word_range_at<T: ToOffset>(&self, offset: T) -> Range<usize> {
+        let offset = offset.to_offset(self);
+        let scope = self.language_scope_at(offset);
+        let is_word = |c: &char| char_kind(&scope, *c) == CharKind::Word;
+
+        let start = offset
+            - self
+                .reversed_chars_at(offset)
+                .take_while(is_word)
+                .map(char::len_utf8)
+                .sum::<usize>();
+        let end = offset
+            + self
+                .chars_at(offset)
+                .take_while(is_word)
+                .map(char::len_utf8)
+                .sum::<usize>();
+        start..end
+    }
+
     /// Returns a tuple of the range and character kind of the word
     /// surrounding the given position.
     pub fn surrounding_word<T: ToOffset>(&self, start: T) -> (Range<usize>, Option<CharKind>) {
@@ -2696,6 +4491,140 @@ impl BufferSnapshot {
         result
     }
 
+    /// Returns the range of the closest syntax node enclosing the given range, or the
+    /// range itself if no such node exists.
+    pub fn expand_selection(&self, range: Range<usize>) -> Range<usize> {
+        self.range_for_syntax_ancestor(range.clone())
+            .unwrap_or(range)
+    }
+
+    /// Returns the ranges of the items in the innermost list-like syntax node that
+    /// contains the given position, such as the arguments of a call, the elements
+    /// of an array, or the fields of a struct.
+    ///
+    /// A node is considered list-like if its named children are separated by commas.
+    pub fn enclosing_list_ranges<T: ToOffset>(&self, position: T) -> Option<Vec<Range<usize>>> {
+        let offset = position.to_offset(self);
+        let mut result: Option<(Range<usize>, Vec<Range<usize>>)> = None;
+        for layer in self.syntax.layers_for_range(offset..offset, &self.text) {
+            let mut node = layer.node().descendant_for_byte_range(offset, offset);
+            while let Some(candidate) = node {
+                let mut cursor = candidate.walk();
+                let mut items = Vec::new();
+                let mut has_separator = false;
+                for child in candidate.children(&mut cursor) {
+                    if child.kind() == "," {
+                        has_separator = true;
+                    } else if child.is_named() && !child.is_extra() {
+                        items.push(child.byte_range());
+                    }
+                }
+
+                if has_separator && !items.is_empty() {
+                    let node_range = candidate.byte_range();
+                    if result
+                        .as_ref()
+                        .map_or(true, |(range, _)| node_range.len() < range.len())
+                    {
+                        result = Some((node_range, items));
+                    }
+                    break;
+                }
+                node = candidate.parent();
+            }
+        }
+        result.map(|(_, items)| items)
+    }
+
+    /// Returns the range of the largest syntax node that lies within the given range,
+    /// is strictly smaller than it, and contains the given cursor offset. If there is
+    /// no such node, an empty range at the cursor is returned.
+    pub fn shrink_selection(&self, range: Range<usize>, cursor: usize) -> Range<usize> {
+        let mut result: Option<Range<usize>> = None;
+        for layer in self.syntax.layers_for_range(range.clone(), &self.text) {
+            let mut tree_cursor = layer.node().walk();
+            loop {
+                let node_range = tree_cursor.node().byte_range();
+                if node_range.start >= range.start
+                    && node_range.end <= range.end
+                    && node_range.len() < range.len()
+                {
+                    if result
+                        .as_ref()
+                        .map_or(true, |result| node_range.len() > result.len())
+                    {
+                        result = Some(node_range);
+                    }
+                    break;
+                }
+                if tree_cursor.goto_first_child_for_byte(cursor).is_none() {
+                    break;
+                }
+            }
+        }
+        result.unwrap_or(cursor..cursor)
+    }
+
+    /// Returns the range of the next named sibling of the syntax node at the given range.
+    /// If the range doesn't correspond to a named node, the smallest named node containing
+    /// the range is used instead.
+    pub fn next_sibling_range(&self, range: Range<usize>) -> Option<Range<usize>> {
+        self.named_sibling_range(range, |node| node.next_named_sibling())
+    }
+
+    /// Returns the range of the previous named sibling of the syntax node at the given range.
+    /// If the range doesn't correspond to a named node, the smallest named node containing
+    /// the range is used instead.
+    pub fn prev_sibling_range(&self, range: Range<usize>) -> Option<Range<usize>> {
+        self.named_sibling_range(range, |node| node.prev_named_sibling())
+    }
+
+    fn named_sibling_range<'a>(
+        &'a self,
+        range: Range<usize>,
+        sibling: impl Fn(tree_sitter::Node<'a>) -> Option<tree_sitter::Node<'a>>,
+    ) -> Option<Range<usize>> {
+        sibling(self.smallest_named_node(range)?).map(|node| node.byte_range())
+    }
+
+    /// Returns the kind of the smallest named syntax node containing the given offset.
+    pub fn scope_at<T: ToOffset>(&self, offset: T) -> Option<&'static str> {
+        let offset = offset.to_offset(self);
+        self.smallest_named_node(offset..offset)
+            .map(|node| node.kind())
+    }
+
+    /// Classifies the syntax at the given offset as being inside of a comment, inside of
+    /// a string, or neither, according to the `comment` and `string` scopes of the
+    /// language's overrides query. An offset is only considered to be inside of a
+    /// comment or string if it lies strictly between the start and end of the scope.
+    pub fn syntax_class_at<T: ToOffset>(&self, offset: T) -> SyntaxClass {
+        let scope = self.language_scope_at(offset);
+        match scope.as_ref().and_then(|scope| scope.override_name()) {
+            Some("comment") => SyntaxClass::Comment,
+            Some("string") => SyntaxClass::String,
+            _ => SyntaxClass::Code,
+        }
+    }
+
+    fn smallest_named_node(&self, range: Range<usize>) -> Option<tree_sitter::Node> {
+        let mut smallest_node: Option<tree_sitter::Node> = None;
+        for layer in self.syntax.layers_for_range(range.clone(), &self.text) {
+            let Some(node) = layer
+                .node()
+                .named_descendant_for_byte_range(range.start, range.end)
+            else {
+                continue;
+            };
+            if smallest_node.map_or(true, |smallest_node| {
+                node.byte_range().len() < smallest_node.byte_range().len()
+            }) {
+                smallest_node = Some(node);
+            }
+        }
+        smallest_node
+    }
+
     /// Returns the outline for the buffer.
     ///
     /// This method allows passing an optional [SyntaxTheme] to
@@ -2933,6 +4862,18 @@ impl BufferSnapshot {
             .filter(move |(open, close)| open.start <= range.start && close.end >= range.end)
     }
 
+    /// Returns all of the enclosing bracket ranges containing the given range, ordered
+    /// from the innermost pair to the outermost one.
+    pub fn all_enclosing_bracket_ranges<T: ToOffset>(
+        &self,
+        range: Range<T>,
+    ) -> Vec<(Range<usize>, Range<usize>)> {
+        let mut result = self.enclosing_bracket_ranges(range).collect::<Vec<_>>();
+        result.sort_by_key(|(open, close)| close.end - open.start);
+        result.dedup();
+        result
+    }
+
     /// Returns the smallest enclosing bracket ranges containing the given range or None if no brackets contain range
     ///
     /// Can optionally pass a range_filter to filter the ranges of brackets to consider
@@ -2941,31 +4882,164 @@ impl BufferSnapshot {
         range: Range<T>,
         range_filter: Option<&dyn Fn(Range<usize>, Range<usize>) -> bool>,
     ) -> Option<(Range<usize>, Range<usize>)> {
-        let range = range.start.to_offset(self)..range.end.to_offset(self);
+        self.all_enclosing_bracket_ranges(range)
+            .into_iter()
+            .find(|(open, close)| {
+                range_filter.map_or(true, |range_filter| {
+                    range_filter(open.clone(), close.clone())
+                })
+            })
+    }
+
+    /// Returns the innermost multi-line indentation block containing the given
+    /// position, as described by the language's indents query.
+    ///
+    /// This complements the bracket-based methods above for languages like
+    /// Python or YAML, whose blocks are delimited by indentation.
+    pub fn indentation_scope_at<T: ToOffset>(&self, position: T) -> Option<Range<usize>> {
+        let offset = position.to_offset(self);
+        let range = offset.saturating_sub(1)..self.len().min(offset + 1);
+        let mut matches = self.syntax.matches(range, &self.text, |grammar| {
+            Some(&grammar.indents_config.as_ref()?.query)
+        });
+        let indent_configs = matches
+            .grammars()
+            .iter()
+            .map(|grammar| grammar.indents_config.as_ref().unwrap())
+            .collect::<Vec<_>>();
 
-        // Get the ranges of the innermost pair of brackets.
-        let mut result: Option<(Range<usize>, Range<usize>)> = None;
+        let mut result: Option<Range<usize>> = None;
+        while let Some(mat) = matches.peek() {
+            let mut start = None;
+            let mut end = None;
 
-        for (open, close) in self.enclosing_bracket_ranges(range.clone()) {
-            if let Some(range_filter) = range_filter {
-                if !range_filter(open.clone(), close.clone()) {
-                    continue;
+            let config = &indent_configs[mat.grammar_index];
+            for capture in mat.captures {
+                if capture.index == config.indent_capture_ix {
+                    start.get_or_insert(capture.node.start_byte());
+                    end.get_or_insert(capture.node.end_byte());
+                } else if Some(capture.index) == config.start_capture_ix {
+                    start = Some(capture.node.end_byte());
+                } else if Some(capture.index) == config.end_capture_ix {
+                    end = Some(capture.node.start_byte());
                 }
             }
 
-            let len = close.end - open.start;
+            matches.advance();
+            let Some((start, end)) = start.zip(end) else {
+                continue;
+            };
+            if start > offset
+                || end < offset
+                || self.offset_to_point(start).row == self.offset_to_point(end).row
+            {
+                continue;
+            }
+            if result
+                .as_ref()
+                .map_or(true, |result| end - start < result.len())
+            {
+                result = Some(start..end);
+            }
+        }
+        result
+    }
 
-            if let Some((existing_open, existing_close)) = &result {
-                let existing_len = existing_close.end - existing_open.start;
-                if len > existing_len {
-                    continue;
+    /// Returns the indentation of the lines within the innermost indentation block
+    /// containing the given offset, or one level deeper than the line that opens the
+    /// block if it contains no other non-blank lines.
+    fn indent_for_scope_at(
+        &self,
+        offset: usize,
+        single_indent_size: IndentSize,
+    ) -> Option<IndentSize> {
+        let scope = self.indentation_scope_at(offset)?;
+        let start_row = self.offset_to_point(scope.start).row;
+        let end_row = self.offset_to_point(scope.end).row;
+        let row = self.offset_to_point(offset).row;
+        let indent = (start_row + 1..=end_row)
+            .filter(|scope_row| *scope_row != row && !self.is_line_blank(*scope_row))
+            .map(|scope_row| self.indent_size_for_line(scope_row))
+            .next()
+            .unwrap_or_else(|| {
+                self.indent_size_for_line(start_row)
+                    .with_delta(Ordering::Greater, single_indent_size)
+            });
+        Some(indent)
+    }
+
+    /// Returns every bracket pair that intersects the given range, along with the depth
+    /// at which it is nested within the other returned pairs, sorted by the position of
+    /// the opening bracket.
+    pub fn bracket_pairs_in_range<T: ToOffset>(
+        &self,
+        range: Range<T>,
+    ) -> Vec<(Range<usize>, Range<usize>, usize)> {
+        let mut pairs = self.bracket_ranges(range).collect::<Vec<_>>();
+        pairs.sort_by(|(open_a, close_a), (open_b, close_b)| {
+            open_a
+                .start
+                .cmp(&open_b.start)
+                .then_with(|| close_b.end.cmp(&close_a.end))
+        });
+        pairs.dedup();
+
+        let mut enclosing_pairs = Vec::<Range<usize>>::new();
+        pairs
+            .into_iter()
+            .map(|(open, close)| {
+                while enclosing_pairs
+                    .last()
+                    .map_or(false, |enclosing| enclosing.end <= open.start)
+                {
+                    enclosing_pairs.pop();
                 }
-            }
+                let depth = enclosing_pairs.len();
+                enclosing_pairs.push(open.start..close.end);
+                (open, close, depth)
+            })
+            .collect()
+    }
 
-            result = Some((open, close));
-        }
+    /// Returns the offset of the bracket matching the one at the given position, or the
+    /// one just before it if there is no bracket at the position. Returns `None` if neither
+    /// character is an opening or closing bracket.
+    pub fn matching_bracket<T: ToOffset>(&self, position: T) -> Option<usize> {
+        let offset = position.to_offset(self);
+        let pairs = self.bracket_ranges(offset..offset).collect::<Vec<_>>();
+        let partner = |contains: &dyn Fn(&Range<usize>) -> bool| {
+            pairs.iter().find_map(|(open, close)| {
+                if contains(open) {
+                    Some(close.start)
+                } else if contains(close) {
+                    Some(open.start)
+                } else {
+                    None
+                }
+            })
+        };
 
-        result
+        partner(&|bracket| bracket.contains(&offset))
+            .or_else(|| partner(&|bracket| offset > 0 && bracket.contains(&(offset - 1))))
+    }
+
+    /// Returns the range between the innermost pair of brackets enclosing the given
+    /// position, excluding the brackets themselves. A position on a bracket refers to
+    /// that bracket's pair.
+    pub fn range_inside_brackets<T: ToOffset>(&self, position: T) -> Option<Range<usize>> {
+        let (open, close) = self.innermost_brackets_at(position)?;
+        Some(open.end..close.start)
+    }
+
+    fn innermost_brackets_at<T: ToOffset>(
+        &self,
+        position: T,
+    ) -> Option<(Range<usize>, Range<usize>)> {
+        let offset = position.to_offset(self);
+        self.innermost_enclosing_bracket_ranges(
+            offset..offset,
+            Some(&|_, close: Range<usize>| offset < close.end),
+        )
     }
 
     /// Returns anchor ranges for any matches of the redaction query.
@@ -3385,6 +5459,28 @@ impl BufferSnapshot {
             })
     }
 
+    /// Returns the given replica's selections as anchor ranges, ordered by position.
+    ///
+    /// Unlike offsets or points, these remain valid across subsequent edits, so they
+    /// can be captured before an asynchronous operation and resolved once it completes.
+    pub fn selection_anchors(&self, replica_id: ReplicaId) -> Vec<Range<Anchor>> {
+        self.remote_selections
+            .get(&replica_id)
+            .map(|set| {
+                set.selections
+                    .iter()
+                    .map(|selection| selection.start..selection.end)
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Returns the text that the buffer's Git diff is computed against, as of the
+    /// time this snapshot was taken.
+    pub fn diff_base_text(&self) -> Option<&Rope> {
+        self.diff_base.as_ref()
+    }
+
     /// Whether the buffer contains any git changes.
     pub fn has_git_diff(&self) -> bool {
         !self.git_diff.is_empty()
@@ -3466,6 +5562,59 @@ impl BufferSnapshot {
         })
     }
 
+    /// Returns the diagnostics intersecting the given range, like [`Self::diagnostics_in_range`],
+    /// each paired with the range spanned by its whole group, including any entries of the
+    /// group outside of the search range. This avoids looking up each group separately when
+    /// rendering the visible diagnostics along with their groups.
+    pub fn diagnostics_with_group_ranges_in_range<'a, T, O>(
+        &'a self,
+        search_range: Range<T>,
+    ) -> Vec<(DiagnosticEntry<O>, Range<O>)>
+    where
+        T: 'a + Clone + ToOffset,
+        O: 'a + FromAnchor + Ord + Clone,
+    {
+        self.diagnostics_in_range::<T, O>(search_range, false)
+            .map(|entry| {
+                let group_range = self
+                    .diagnostics
+                    .iter()
+                    .find_map(|(_, set)| set.group_range(entry.diagnostic.group_id))
+                    .map(|range| {
+                        O::from_anchor(&range.start, self)..O::from_anchor(&range.end, self)
+                    })
+                    .unwrap_or_else(|| entry.range.clone());
+                (entry, group_range)
+            })
+            .collect()
+    }
+
+    /// Divides the buffer into the given number of equally-sized bands of rows, and
+    /// counts the diagnostics starting in each band, such as for rendering an
+    /// overview of the whole buffer.
+    pub fn diagnostic_density(&self, buckets: usize) -> Vec<DiagnosticCounts> {
+        let mut density = vec![DiagnosticCounts::default(); buckets];
+        if buckets == 0 {
+            return density;
+        }
+
+        let row_count = self.max_point().row as usize + 1;
+        for (_, diagnostics) in &self.diagnostics {
+            for entry in diagnostics.iter() {
+                let row = entry.range.start.to_point(self).row as usize;
+                let counts = &mut density[(row * buckets / row_count).min(buckets - 1)];
+                match entry.diagnostic.severity {
+                    DiagnosticSeverity::ERROR => counts.errors += 1,
+                    DiagnosticSeverity::WARNING => counts.warnings += 1,
+                    DiagnosticSeverity::INFORMATION => counts.infos += 1,
+                    DiagnosticSeverity::HINT => counts.hints += 1,
+                    _ => {}
+                }
+            }
+        }
+        density
+    }
+
     /// Returns all the diagnostic groups associated with the given
     /// language server id. If no language server id is provided,
     /// all diagnostics groups are returned.
@@ -3512,6 +5661,57 @@ impl BufferSnapshot {
             .flat_map(move |(_, set)| set.group(group_id, self))
     }
 
+    /// Returns the diagnostics for the given group, with the primary diagnostic first,
+    /// followed by the others in the order they appear in the buffer.
+    pub fn diagnostic_group_sorted<O>(&self, group_id: usize) -> Vec<(Range<O>, &Diagnostic)>
+    where
+        O: FromAnchor + Ord,
+    {
+        let mut entries = self
+            .diagnostics
+            .iter()
+            .flat_map(|(_, set)| set.iter())
+            .filter(|entry| entry.diagnostic.group_id == group_id)
+            .map(|entry| {
+                let range = O::from_anchor(&entry.range.start, self)
+                    ..O::from_anchor(&entry.range.end, self);
+                (range, &entry.diagnostic)
+            })
+            .collect::<Vec<_>>();
+        entries.sort_by(|(range_a, diagnostic_a), (range_b, diagnostic_b)| {
+            diagnostic_b
+                .is_primary
+                .cmp(&diagnostic_a.is_primary)
+                .then_with(|| range_a.start.cmp(&range_b.start))
+                .then_with(|| range_b.end.cmp(&range_a.end))
+        });
+        entries
+    }
+
+    /// Returns the data that the language server attached to the primary diagnostic
+    /// of the given group, if any.
+    pub fn diagnostic_data(&self, group_id: usize) -> Option<&serde_json::Value> {
+        self.diagnostics
+            .iter()
+            .flat_map(|(_, set)| set.iter())
+            .find(|entry| entry.diagnostic.group_id == group_id && entry.diagnostic.is_primary)?
+            .diagnostic
+            .data
+            .as_ref()
+    }
+
+    /// Returns whether code actions are available for the diagnostic group with
+    /// the given id, or `None` if this hasn't been checked yet.
+    pub fn diagnostic_is_fixable(&self, group_id: usize) -> Option<bool> {
+        self.fixable_diagnostic_groups.get(&group_id).copied()
+    }
+
+    /// Returns whether the message of the diagnostic group with the given id
+    /// is expanded inline.
+    pub fn is_diagnostic_group_active(&self, group_id: usize) -> bool {
+        self.active_diagnostic_group == Some(group_id)
+    }
+
     /// An integer version number that accounts for all updates besides
     /// the buffer's text itself (which is versioned via a version vector).
     pub fn non_text_state_update_count(&self) -> usize {
@@ -3537,6 +5737,19 @@ impl BufferSnapshot {
     }
 }
 
+/// Runs the given parse, measuring how long it takes when profiling is enabled.
+#[cfg(feature = "profile")]
+fn time_parse(parse: impl FnOnce()) -> ParseDuration {
+    let start = Instant::now();
+    parse();
+    start.elapsed()
+}
+
+#[cfg(not(feature = "profile"))]
+fn time_parse(parse: impl FnOnce()) -> ParseDuration {
+    parse()
+}
+
 fn indent_size_for_line(text: &text::BufferSnapshot, row: u32) -> IndentSize {
     indent_size_for_text(text.chars_at(Point::new(row, 0)))
 }
@@ -3557,17 +5770,32 @@ fn indent_size_for_text(text: impl Iterator<Item = char>) -> IndentSize {
     result
 }
 
+/// Returns the smallest indentation of any of the non-blank lines in the given text,
+/// which is the baseline that the text's indentation is relative to.
+fn common_leading_indent(text: &str) -> u32 {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| indent_size_for_text(line.chars()).len)
+        .min()
+        .unwrap_or(0)
+}
+
 impl Clone for BufferSnapshot {
     fn clone(&self) -> Self {
         Self {
             text: self.text.clone(),
+            diff_base: self.diff_base.clone(),
             git_diff: self.git_diff.clone(),
             syntax: self.syntax.clone(),
             file: self.file.clone(),
             remote_selections: self.remote_selections.clone(),
             diagnostics: self.diagnostics.clone(),
+            fixable_diagnostic_groups: self.fixable_diagnostic_groups.clone(),
+            active_diagnostic_group: self.active_diagnostic_group,
             language: self.language.clone(),
             non_text_state_update_count: self.non_text_state_update_count,
+            highlight_cache: self.highlight_cache.clone(),
+            fallback_highlighting: self.fallback_highlighting,
         }
     }
 }
@@ -3588,6 +5816,18 @@ impl<'a> BufferChunks<'a> {
         range: Range<usize>,
         syntax: Option<(SyntaxMapCaptures<'a>, Vec<HighlightMap>)>,
         diagnostic_endpoints: Vec<DiagnosticEndpoint>,
+    ) -> Self {
+        let syntax = syntax.map(|(captures, highlight_maps)| {
+            (BufferChunkCaptures::Live(captures), highlight_maps)
+        });
+        Self::with_captures(text, range, syntax, diagnostic_endpoints)
+    }
+
+    fn with_captures(
+        text: &'a Rope,
+        range: Range<usize>,
+        syntax: Option<(BufferChunkCaptures<'a>, Vec<HighlightMap>)>,
+        diagnostic_endpoints: Vec<DiagnosticEndpoint>,
     ) -> Self {
         let mut highlights = None;
         if let Some((captures, highlight_maps)) = syntax {
@@ -3612,24 +5852,38 @@ impl<'a> BufferChunks<'a> {
             hint_depth: 0,
             unnecessary_depth: 0,
             highlights,
+            overlay_highlights: Vec::new(),
+            overlay_highlight_ix: 0,
+            render_whitespace: false,
         }
     }
 
     /// Seeks to the given byte offset in the buffer.
     pub fn seek(&mut self, offset: usize) {
+        let is_backward = offset < self.range.start;
         self.range.start = offset;
         self.chunks.seek(self.range.start);
+        self.overlay_highlight_ix = 0;
         if let Some(highlights) = self.highlights.as_mut() {
+            // Captures that were already consumed can't be recovered from the stack,
+            // so when seeking backward, rebuild it by running the captures again.
+            if is_backward {
+                highlights.stack.clear();
+                highlights.next_capture = None;
+                highlights.captures.restart(self.range.clone());
+                return;
+            }
+
             highlights
                 .stack
                 .retain(|(end_offset, _)| *end_offset > offset);
             if let Some(capture) = &highlights.next_capture {
-                if offset >= capture.node.start_byte() {
-                    let next_capture_end = capture.node.end_byte();
-                    if offset < next_capture_end {
+                if offset >= capture.range.start {
+                    if offset < capture.range.end {
                         highlights.stack.push((
-                            next_capture_end,
-                            highlights.highlight_maps[capture.grammar_index].get(capture.index),
+                            capture.range.end,
+                            highlights.highlight_maps[capture.grammar_index]
+                                .get(capture.capture_index),
                         ));
                     }
                     highlights.next_capture.take();
@@ -3684,6 +5938,83 @@ impl<'a> BufferChunks<'a> {
     fn current_code_is_unnecessary(&self) -> bool {
         self.unnecessary_depth > 0
     }
+
+    /// Returns the combined style of the overlay highlights containing the current
+    /// offset, along with the offset at which that style next changes.
+    fn current_overlay_highlight(&mut self) -> (Option<HighlightStyle>, usize) {
+        let offset = self.range.start;
+        while self
+            .overlay_highlights
+            .get(self.overlay_highlight_ix)
+            .map_or(false, |(range, _)| range.end <= offset)
+        {
+            self.overlay_highlight_ix += 1;
+        }
+
+        let mut style = None::<HighlightStyle>;
+        let mut next_boundary = usize::MAX;
+        for (range, overlay_style) in &self.overlay_highlights[self.overlay_highlight_ix..] {
+            if range.start > offset {
+                next_boundary = next_boundary.min(range.start);
+                break;
+            } else if range.end > offset {
+                next_boundary = next_boundary.min(range.end);
+                match style.as_mut() {
+                    Some(style) => style.highlight(*overlay_style),
+                    None => style = Some(*overlay_style),
+                }
+            }
+        }
+        (style, next_boundary)
+    }
+}
+
+impl<'a> BufferChunkCaptures<'a> {
+    fn set_byte_range(&mut self, new_range: Range<usize>) {
+        match self {
+            BufferChunkCaptures::Live(captures) => captures.set_byte_range(new_range),
+            BufferChunkCaptures::Cached { range, .. } => *range = new_range,
+        }
+    }
+
+    fn restart(&mut self, new_range: Range<usize>) {
+        match self {
+            BufferChunkCaptures::Live(captures) => captures.restart(new_range),
+            BufferChunkCaptures::Cached { range, ix, .. } => {
+                *range = new_range;
+                *ix = 0;
+            }
+        }
+    }
+
+    fn next(&mut self) -> Option<HighlightCapture> {
+        match self {
+            BufferChunkCaptures::Live(captures) => {
+                let capture = captures.next()?;
+                Some(HighlightCapture {
+                    range: capture.node.byte_range(),
+                    grammar_index: capture.grammar_index,
+                    capture_index: capture.index,
+                })
+            }
+            BufferChunkCaptures::Cached {
+                captures,
+                range,
+                ix,
+            } => {
+                while let Some(capture) = captures.get(*ix) {
+                    if capture.range.start >= range.end {
+                        break;
+                    }
+                    *ix += 1;
+                    if capture.range.end > range.start {
+                        return Some(capture.clone());
+                    }
+                }
+                None
+            }
+        }
+    }
 }
 
 impl<'a> Iterator for BufferChunks<'a> {
@@ -3707,15 +6038,13 @@ impl<'a> Iterator for BufferChunks<'a> {
             }
 
             while let Some(capture) = highlights.next_capture.as_ref() {
-                if self.range.start < capture.node.start_byte() {
-                    next_capture_start = capture.node.start_byte();
+                if self.range.start < capture.range.start {
+                    next_capture_start = capture.range.start;
                     break;
                 } else {
                     let highlight_id =
-                        highlights.highlight_maps[capture.grammar_index].get(capture.index);
-                    highlights
-                        .stack
-                        .push((capture.node.end_byte(), highlight_id));
+                        highlights.highlight_maps[capture.grammar_index].get(capture.capture_index);
+                    highlights.stack.push((capture.range.end, highlight_id));
                     highlights.next_capture = highlights.captures.next();
                 }
             }
@@ -3731,11 +6060,14 @@ impl<'a> Iterator for BufferChunks<'a> {
             }
         }
 
+        let (highlight_style, next_overlay_boundary) = self.current_overlay_highlight();
+
         if let Some(chunk) = self.chunks.peek() {
             let chunk_start = self.range.start;
             let mut chunk_end = (self.chunks.offset() + chunk.len())
                 .min(next_capture_start)
-                .min(next_diagnostic_endpoint);
+                .min(next_diagnostic_endpoint)
+                .min(next_overlay_boundary);
             let mut highlight_id = None;
             if let Some(highlights) = self.highlights.as_ref() {
                 if let Some((parent_capture_end, parent_highlight_id)) = highlights.stack.last() {
@@ -3744,6 +6076,19 @@ impl<'a> Iterator for BufferChunks<'a> {
                 }
             }
 
+            let mut whitespace = None;
+            if self.render_whitespace {
+                let text =
+                    &chunk[chunk_start - self.chunks.offset()..chunk_end - self.chunks.offset()];
+                if let Some(first_char) = text.chars().next() {
+                    whitespace = WhitespaceKind::for_char(first_char);
+                    if let Some(run_len) = text.find(|c| WhitespaceKind::for_char(c) != whitespace)
+                    {
+                        chunk_end = chunk_start + run_len;
+                    }
+                }
+            }
+
             let slice =
                 &chunk[chunk_start - self.chunks.offset()..chunk_end - self.chunks.offset()];
             self.range.start = chunk_end;
@@ -3754,8 +6099,10 @@ impl<'a> Iterator for BufferChunks<'a> {
             Some(Chunk {
                 text: slice,
                 syntax_highlight_id: highlight_id,
+                highlight_style,
                 diagnostic_severity: self.current_diagnostic_severity(),
                 is_unnecessary: self.current_code_is_unnecessary(),
+                whitespace,
                 ..Default::default()
             })
         } else {
@@ -3778,6 +6125,9 @@ impl operation_queue::Operation for Operation {
             }
             | Operation::UpdateCompletionTriggers {
                 lamport_timestamp, ..
+            }
+            | Operation::UpdateLineEndingOverride {
+                lamport_timestamp, ..
             } => *lamport_timestamp,
         }
     }
@@ -3794,6 +6144,8 @@ impl Default for Diagnostic {
             is_primary: false,
             is_disk_based: false,
             is_unnecessary: false,
+            is_deprecated: false,
+            data: None,
         }
     }
 }
@@ -3946,6 +6298,81 @@ pub fn char_kind(scope: &Option<LanguageScope>, c: char) -> CharKind {
     CharKind::Punctuation
 }
 
+/// Adapts the case of `replacement` to match the case style of `matched`, so
+/// that replacing `foo` with `bar` turns `FOO` into `BAR` and `Foo` into `Bar`.
+///
+/// All-lowercase, all-uppercase and capitalized matches convert the whole
+/// replacement to that style. For any other mix of cases, only the first
+/// character of the replacement is adjusted to match.
+pub fn preserve_case(matched: &str, replacement: &str) -> String {
+    let letters = matched
+        .chars()
+        .filter(|c| c.is_alphabetic())
+        .collect::<Vec<_>>();
+    let Some((first, rest)) = letters.split_first() else {
+        return replacement.to_string();
+    };
+
+    let rest_is_lowercase = rest.iter().all(|c| c.is_lowercase());
+    if first.is_lowercase() && rest_is_lowercase {
+        return replacement.to_lowercase();
+    }
+    if first.is_uppercase() && !rest.is_empty() && rest.iter().all(|c| c.is_uppercase()) {
+        return replacement.to_uppercase();
+    }
+
+    let mut chars = replacement.chars();
+    let Some(replacement_first) = chars.next() else {
+        return String::new();
+    };
+    if first.is_uppercase() {
+        let mut result = replacement_first.to_uppercase().collect::<String>();
+        if rest_is_lowercase {
+            result.push_str(&chars.as_str().to_lowercase());
+        } else {
+            result.push_str(chars.as_str());
+        }
+        result
+    } else if first.is_lowercase() {
+        replacement_first.to_lowercase().chain(chars).collect()
+    } else {
+        replacement.to_string()
+    }
+}
+
+/// Merges each of the given edits into the previous one if they overlap or are adjacent.
+fn coalesce_edits(edits: Vec<(Range<usize>, Arc<str>)>) -> Vec<(Range<usize>, Arc<str>)> {
+    let mut coalesced_edits: Vec<(Range<usize>, Arc<str>)> = Vec::new();
+    for (range, new_text) in edits {
+        if let Some((prev_range, prev_text)) = coalesced_edits.last_mut() {
+            if prev_range.end >= range.start {
+                prev_range.end = cmp::max(prev_range.end, range.end);
+                *prev_text = format!("{prev_text}{new_text}").into();
+                continue;
+            }
+        }
+        coalesced_edits.push((range, new_text));
+    }
+    coalesced_edits
+}
+
+/// Returns edits that remove the trailing whitespace in the given snapshot, except
+/// for whitespace inside of string literals or on the given rows.
+fn removable_trailing_whitespace(
+    snapshot: &BufferSnapshot,
+    cursor_rows: &HashSet<u32>,
+) -> Vec<(Range<usize>, Arc<str>)> {
+    let empty = Arc::<str>::from("");
+    (0..=snapshot.max_point().row)
+        .filter(|row| !cursor_rows.contains(row))
+        .map(|row| snapshot.trailing_whitespace_range(row))
+        .filter(|range| {
+            !range.is_empty() && snapshot.syntax_class_at(range.start) != SyntaxClass::String
+        })
+        .map(|range| (range, empty.clone()))
+        .collect()
+}
+
 /// Find all of the ranges of whitespace that occur at the ends of lines
 /// in the given rope.
 ///
@@ -2,11 +2,13 @@ use super::*;
 use crate::language_settings::{
     AllLanguageSettings, AllLanguageSettingsContent, LanguageSettingsContent,
 };
-use crate::Buffer;
+use crate::{buffer::set_fallback_highlight_theme, Buffer};
 use clock::ReplicaId;
 use collections::BTreeMap;
 use futures::FutureExt as _;
-use gpui::{AppContext, BorrowAppContext, Model};
+use gpui::{
+    AppContext, BorrowAppContext, FontStyle, FontWeight, HighlightStyle, Model, ModelContext,
+};
 use gpui::{Context, TestAppContext};
 use indoc::indoc;
 use proto::deserialize_operation;
@@ -14,13 +16,16 @@ use rand::prelude::*;
 use regex::RegexBuilder;
 use settings::SettingsStore;
 use std::{
+    cmp::Reverse,
     env,
+    num::NonZeroU32,
     ops::Range,
     time::{Duration, Instant},
 };
 use text::network::Network;
 use text::{BufferId, LineEnding, LineIndent};
 use text::{Point, ToPoint};
+use theme::SyntaxTheme;
 use unindent::Unindent as _;
 use util::{assert_set_eq, post_inc, test::marked_text_ranges, RandomCharIter};
 
@@ -284,7 +289,7 @@ fn test_edit_events(cx: &mut gpui::AppContext) {
         |buffer, cx| {
             let buffer_1_events = buffer_1_events.clone();
             cx.subscribe(&buffer1, move |_, _, event, _| match event.clone() {
-                Event::Operation(op) => buffer1_ops.lock().push(op),
+                Event::Operations(ops) => buffer1_ops.lock().extend(ops),
                 event => buffer_1_events.lock().push(event),
             })
             .detach();
@@ -353,6 +358,402 @@ fn test_edit_events(cx: &mut gpui::AppContext) {
     );
 }
 
+#[gpui::test]
+fn test_edit_local_only(cx: &mut AppContext) {
+    let buffer = cx.new_model(|cx| Buffer::local("abc", cx));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    cx.subscribe(&buffer, {
+        let events = events.clone();
+        move |_, event, _| events.lock().push(event.clone())
+    })
+    .detach();
+
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit_local_only([(1..2, "BBB")], cx);
+        assert_eq!(buffer.text(), "aBBBc");
+    });
+    assert_eq!(
+        mem::take(&mut *events.lock()),
+        [Event::Edited, Event::DirtyChanged]
+    );
+
+    // Regular edits still produce operations.
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "_")], None, cx));
+    assert!(events
+        .lock()
+        .iter()
+        .any(|event| matches!(event, Event::Operations(_))));
+
+    // Local-only edits are refused once the buffer is shared with another replica.
+    let state = buffer.read(cx).to_proto();
+    let guest = cx.new_model(|cx| {
+        let mut guest = Buffer::from_proto(1, Capability::ReadWrite, state, None).unwrap();
+        assert_eq!(guest.edit_local_only([(0..0, "!")], cx), None);
+        guest.edit([(0..0, "?")], None, cx);
+        guest
+    });
+    let ops = guest.update(cx, |guest, cx| {
+        assert_eq!(guest.text(), "?abc");
+        cx.background_executor()
+            .block(guest.serialize_ops(None, cx))
+    });
+    buffer.update(cx, |buffer, cx| {
+        buffer
+            .apply_ops(
+                ops.into_iter()
+                    .map(|op| proto::deserialize_operation(op).unwrap()),
+                cx,
+            )
+            .unwrap();
+        assert_eq!(buffer.edit_local_only([(0..0, "!")], cx), None);
+    });
+}
+
+#[gpui::test]
+fn test_edit_offsets_within_characters(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        // "é" spans bytes 1..3 and "🦀" spans bytes 6..10.
+        let mut buffer = Buffer::local("héllo🦀", cx);
+
+        // An insertion inside a character moves to the start of that character.
+        buffer.edit([(2..2, "x")], None, cx);
+        assert_eq!(buffer.text(), "hxéllo🦀");
+
+        // A range whose ends fall inside characters is extended to cover them.
+        buffer.edit([(3..4, "e")], None, cx);
+        assert_eq!(buffer.text(), "hxello🦀");
+        buffer.edit([(7..8, "")], None, cx);
+        assert_eq!(buffer.text(), "hxello");
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_operations_are_batched_per_transaction(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    let buffer = cx.new_model(|cx| {
+        Buffer::local("fn a() {\n}\n", cx).with_language(Arc::new(rust_lang()), cx)
+    });
+    let sends = Arc::new(Mutex::new(Vec::new()));
+    cx.subscribe(&buffer, {
+        let sends = sends.clone();
+        move |_, event, _| {
+            if let Event::Operations(operations) = event {
+                sends.lock().push(operations.len());
+            }
+        }
+    })
+    .detach();
+
+    // The pasted text and the autoindent edits are sent together.
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit(
+            [(Point::new(1, 0)..Point::new(1, 0), "b(\nc\n);\n")],
+            Some(AutoindentMode::EachLine),
+            cx,
+        );
+        assert_eq!(buffer.text(), "fn a() {\n    b(\n        c\n    );\n}\n");
+    });
+    assert_eq!(mem::take(&mut *sends.lock()), [2]);
+
+    // So are all of the edits made within a transaction.
+    buffer.update(cx, |buffer, cx| {
+        buffer.transact(cx, |buffer, cx| {
+            for _ in 0..3 {
+                buffer.edit(
+                    [(Point::new(4, 0)..Point::new(4, 0), "d(\ne\n);\n")],
+                    Some(AutoindentMode::EachLine),
+                    cx,
+                );
+            }
+        });
+    });
+    assert_eq!(mem::take(&mut *sends.lock()), [4]);
+
+    // Operations produced outside of a transaction are sent immediately.
+    buffer.update(cx, |buffer, cx| {
+        buffer.set_active_selections(Vec::new().into(), false, CursorShape::default(), cx);
+    });
+    assert_eq!(mem::take(&mut *sends.lock()), [1]);
+}
+
+#[gpui::test]
+fn test_selection_anchors(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("one two three", cx);
+        let replica_id = buffer.replica_id();
+        assert_eq!(buffer.snapshot().selection_anchors(replica_id), []);
+
+        let selections = [4..7, 8..13]
+            .into_iter()
+            .enumerate()
+            .map(|(id, range)| Selection {
+                id,
+                start: buffer.anchor_before(range.start),
+                end: buffer.anchor_after(range.end),
+                reversed: false,
+                goal: SelectionGoal::None,
+            })
+            .collect::<Vec<_>>();
+        buffer.set_active_selections(selections.into(), false, CursorShape::default(), cx);
+        let anchors = buffer.snapshot().selection_anchors(replica_id);
+
+        // The captured anchors track the selected text across later edits.
+        buffer.edit([(0..0, "zero "), (1..2, "N")], None, cx);
+        assert_eq!(buffer.text(), "zero oNe two three");
+        let snapshot = buffer.snapshot();
+        assert_eq!(
+            anchors
+                .iter()
+                .map(|range| snapshot.text_for_range(range.clone()).collect::<String>())
+                .collect::<Vec<_>>(),
+            ["two", "three"]
+        );
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_select_lines(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("one\ntwo\nthree", cx);
+        let replica_id = buffer.replica_id();
+        let selected_ranges = |buffer: &Buffer| {
+            let snapshot = buffer.snapshot();
+            snapshot
+                .selection_anchors(replica_id)
+                .into_iter()
+                .map(|range| range.to_offset(&snapshot))
+                .collect::<Vec<_>>()
+        };
+        let set_cursors =
+            |buffer: &mut Buffer, offsets: &[usize], cx: &mut ModelContext<Buffer>| {
+                let selections = offsets
+                    .iter()
+                    .enumerate()
+                    .map(|(id, offset)| {
+                        let anchor = buffer.anchor_before(*offset);
+                        Selection {
+                            id,
+                            start: anchor,
+                            end: anchor,
+                            reversed: false,
+                            goal: SelectionGoal::None,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                buffer.set_active_selections(selections.into(), false, CursorShape::Block, cx);
+            };
+
+        // The current line includes its trailing newline.
+        set_cursors(&mut buffer, &[5], cx);
+        buffer.select_current_line(cx);
+        assert_eq!(selected_ranges(&buffer), [4..8]);
+
+        // Except for the last line, which has none.
+        set_cursors(&mut buffer, &[10], cx);
+        buffer.select_current_line(cx);
+        assert_eq!(selected_ranges(&buffer), [8..13]);
+
+        // Cursors on the same line are merged, and the cursor shape is preserved.
+        set_cursors(&mut buffer, &[0, 2, 6], cx);
+        buffer.select_current_line(cx);
+        assert_eq!(selected_ranges(&buffer), [0..8]);
+        set_cursors(&mut buffer, &[0, 2, 9], cx);
+        buffer.select_current_line(cx);
+        assert_eq!(selected_ranges(&buffer), [0..4, 8..13]);
+        assert_eq!(
+            buffer
+                .snapshot()
+                .selections_in_range(Anchor::MIN..Anchor::MAX, true)
+                .map(|(replica_id, _, cursor_shape, _)| (replica_id, cursor_shape))
+                .collect::<Vec<_>>(),
+            [(replica_id, CursorShape::Block)]
+        );
+
+        buffer.select_rows(0..2, cx);
+        assert_eq!(selected_ranges(&buffer), [0..8]);
+        buffer.select_rows(1..10, cx);
+        assert_eq!(selected_ranges(&buffer), [4..13]);
+
+        // Mapping each selection down one row moves every cursor, clipping to the buffer.
+        set_cursors(&mut buffer, &[1, 6], cx);
+        buffer.map_selections(
+            |selection| selection.map(|point| Point::new(point.row + 1, point.column)),
+            cx,
+        );
+        assert_eq!(selected_ranges(&buffer), [5..5, 10..10]);
+        buffer.map_selections(
+            |selection| selection.map(|point| Point::new(point.row + 1, point.column + 10)),
+            cx,
+        );
+        assert_eq!(selected_ranges(&buffer), [13..13]);
+
+        // Selections are sorted and merged when they overlap, and selections that
+        // end before they start are flipped.
+        set_cursors(&mut buffer, &[1, 6, 9], cx);
+        buffer.map_selections(
+            |selection| {
+                let start = selection.start;
+                Selection {
+                    start: Point::new(2 - start.row, 0),
+                    end: Point::new(1, 0),
+                    ..selection
+                }
+            },
+            cx,
+        );
+        assert_eq!(selected_ranges(&buffer), [0..8]);
+        assert_eq!(
+            buffer
+                .snapshot()
+                .selections_in_range(Anchor::MIN..Anchor::MAX, true)
+                .flat_map(|(_, _, _, selections)| selections.map(|s| s.reversed))
+                .collect::<Vec<_>>(),
+            [false]
+        );
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_transaction_summaries(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("one\ntwo\nthree\nfour\n", cx);
+        assert_eq!(buffer.transaction_summaries(), []);
+
+        buffer.edit([(Point::new(0, 0)..Point::new(0, 3), "ONE")], None, cx);
+        buffer.finalize_last_transaction();
+        buffer.edit(
+            [(Point::new(3, 0)..Point::new(3, 4), "FOUR\nFIVE")],
+            None,
+            cx,
+        );
+
+        let summaries = buffer.transaction_summaries();
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[0].rows, [0..1]);
+        assert_eq!(summaries[1].rows, [3..5]);
+        assert!(summaries[0].timestamp <= summaries[1].timestamp);
+
+        buffer.undo_transaction(summaries[0].id, cx);
+        assert_eq!(buffer.text(), "one\ntwo\nthree\nFOUR\nFIVE\n");
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_undo_to_transaction(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("", cx);
+        let mut transaction_ids = Vec::new();
+        for text in ["a", "b", "c"] {
+            buffer.start_transaction();
+            buffer.edit([(buffer.len()..buffer.len(), text)], None, cx);
+            transaction_ids.push(buffer.end_transaction(cx).unwrap());
+            buffer.finalize_last_transaction();
+        }
+        assert_eq!(buffer.text(), "abc");
+
+        // Reverting to the second transaction undoes it and everything after it.
+        assert!(buffer.undo_to_transaction(transaction_ids[1], cx));
+        assert_eq!(buffer.text(), "a");
+
+        // Transactions that were already undone are ignored.
+        assert!(!buffer.undo_to_transaction(transaction_ids[2], cx));
+        assert!(!buffer.undo_to_transaction(transaction_ids[1], cx));
+        assert_eq!(buffer.text(), "a");
+
+        // So are transactions newer than anything in the history.
+        let newer_id = clock::Lamport {
+            replica_id: transaction_ids[2].replica_id,
+            value: transaction_ids[2].value + 100,
+        };
+        assert!(!buffer.undo_to_transaction(newer_id, cx));
+        assert_eq!(buffer.text(), "a");
+
+        // The reverted transactions can be redone in order.
+        assert_eq!(buffer.redo(cx), Some(transaction_ids[1]));
+        assert_eq!(buffer.text(), "ab");
+        assert_eq!(buffer.redo(cx), Some(transaction_ids[2]));
+        assert_eq!(buffer.text(), "abc");
+        assert_eq!(buffer.redo(cx), None);
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_transact(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("abc", cx);
+        let transaction_id = buffer.transact(cx, |buffer, cx| {
+            buffer.edit([(0..0, "1")], None, cx);
+            buffer.transact(cx, |buffer, cx| {
+                buffer.edit([(4..4, "2")], None, cx);
+            });
+            buffer.edit([(2..2, "3")], None, cx);
+        });
+        assert!(transaction_id.is_some());
+        assert_eq!(buffer.text(), "1a3bc2");
+
+        assert_eq!(buffer.undo(cx), transaction_id);
+        assert_eq!(buffer.text(), "abc");
+        assert_eq!(buffer.redo(cx), transaction_id);
+        assert_eq!(buffer.text(), "1a3bc2");
+
+        // A panic inside the closure doesn't leave the transaction open.
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            buffer.transact(cx, |buffer, cx| {
+                buffer.edit([(0..0, "x")], None, cx);
+                panic!("oops");
+            })
+        }));
+        assert!(result.is_err());
+        assert_eq!(buffer.text(), "x1a3bc2");
+        let transaction_id = buffer.transact(cx, |buffer, cx| {
+            buffer.edit([(0..0, "y")], None, cx);
+        });
+        assert!(transaction_id.is_some());
+        assert_eq!(buffer.undo(cx), transaction_id);
+        assert_eq!(buffer.text(), "x1a3bc2");
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_transaction_grouping(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("", cx);
+        buffer.set_group_interval(Duration::from_millis(300));
+
+        let mut now = Instant::now();
+        for text in ["a", "b", "c"] {
+            buffer.start_transaction_at(now);
+            buffer.edit([(buffer.len()..buffer.len(), text)], None, cx);
+            buffer.end_transaction_at(now, cx);
+            now += Duration::from_millis(100);
+        }
+        assert_eq!(buffer.text(), "abc");
+
+        // A transaction outside the interval starts a new undo step.
+        now += Duration::from_millis(500);
+        buffer.start_transaction_at(now);
+        buffer.edit([(3..3, "d")], None, cx);
+        buffer.end_transaction_at(now, cx);
+
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), "abc");
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), "");
+        buffer.redo(cx);
+        assert_eq!(buffer.text(), "abc");
+        buffer
+    });
+}
+
 #[gpui::test]
 async fn test_apply_diff(cx: &mut TestAppContext) {
     let text = "a\nbb\nccc\ndddd\neeeee\nffffff\n";
@@ -376,6 +777,70 @@ async fn test_apply_diff(cx: &mut TestAppContext) {
     });
 }
 
+#[gpui::test]
+async fn test_merge_diff(cx: &mut TestAppContext) {
+    let base_text = "one\ntwo\nthree\nfour\n";
+    let buffer = cx.new_model(|cx| Buffer::local(base_text, cx));
+
+    // Edits that don't overlap the diff's hunks are preserved.
+    let diff = buffer
+        .update(cx, |buffer, cx| {
+            buffer.diff("one\nTWO\nthree\nfour\nfive\n".into(), cx)
+        })
+        .await;
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit([(0..0, "zero\n")], None, cx);
+        let (transaction_id, conflicts) = buffer.merge_diff(diff, cx);
+        assert!(transaction_id.is_some());
+        assert!(conflicts.is_empty());
+        assert_eq!(buffer.text(), "zero\none\nTWO\nthree\nfour\nfive\n");
+    });
+
+    // Hunks that overlap edits are discarded, and reported as conflicts.
+    buffer.update(cx, |buffer, cx| buffer.set_text(base_text, cx));
+    let diff = buffer
+        .update(cx, |buffer, cx| {
+            buffer.diff("one\nTWO\nthree\nFOUR\n".into(), cx)
+        })
+        .await;
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit([(4..7, "2")], None, cx);
+        let (_, conflicts) = buffer.merge_diff(diff, cx);
+        assert_eq!(buffer.text(), "one\n2\nthree\nFOUR\n");
+        assert_eq!(
+            conflicts
+                .iter()
+                .map(|range| range.to_point(&*buffer))
+                .collect::<Vec<_>>(),
+            [Point::new(1, 0)..Point::new(1, 1)]
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_apply_diff_with_small_change(cx: &mut TestAppContext) {
+    let text = "fn one() {}\nfn two() {}\nfn three() {}\n";
+    let buffer = cx.new_model(|cx| Buffer::local(text, cx));
+    let version = buffer.update(cx, |buffer, _| buffer.version());
+
+    // Changing a single character within a line only replaces that character.
+    let new_text = "fn one() {}\nfn tWo() {}\nfn three() {}\n";
+    let diff = buffer
+        .update(cx, |buffer, cx| buffer.diff(new_text.into(), cx))
+        .await;
+    buffer.update(cx, |buffer, cx| {
+        buffer.apply_diff(diff, cx).unwrap();
+        assert_eq!(buffer.text(), new_text);
+        assert_eq!(
+            buffer.edits_since::<usize>(&version).collect::<Vec<_>>(),
+            [Edit {
+                old: 16..17,
+                new: 16..17,
+            }]
+        );
+    });
+}
+
 #[gpui::test(iterations = 10)]
 async fn test_normalize_whitespace(cx: &mut gpui::TestAppContext) {
     let text = [
@@ -392,7 +857,9 @@ async fn test_normalize_whitespace(cx: &mut gpui::TestAppContext) {
 
     // Spawn a task to format the buffer's whitespace.
     // Pause so that the foratting task starts running.
-    let format = buffer.update(cx, |buffer, cx| buffer.remove_trailing_whitespace(cx));
+    let format = buffer.update(cx, |buffer, cx| {
+        buffer.remove_trailing_whitespace(false, cx)
+    });
     smol::future::yield_now().await;
 
     // Edit the buffer while the normalization task is running.
@@ -449,6 +916,91 @@ async fn test_normalize_whitespace(cx: &mut gpui::TestAppContext) {
     });
 }
 
+#[gpui::test]
+async fn test_remove_trailing_whitespace_on_cursor_lines(cx: &mut gpui::TestAppContext) {
+    let buffer = cx.new_model(|cx| Buffer::local("one  \ntwo  \nthree  ", cx));
+    buffer.update(cx, |buffer, cx| {
+        let cursor = buffer.anchor_before(Point::new(1, 5));
+        let selections = Arc::from([Selection {
+            id: 0,
+            start: cursor,
+            end: cursor,
+            reversed: false,
+            goal: SelectionGoal::None,
+        }]);
+        buffer.set_active_selections(selections, false, Default::default(), cx);
+    });
+
+    let diff = buffer
+        .update(cx, |buffer, cx| buffer.remove_trailing_whitespace(true, cx))
+        .await;
+    buffer.update(cx, |buffer, cx| {
+        buffer.apply_diff(diff, cx);
+        assert_eq!(buffer.text(), "one\ntwo  \nthree");
+    });
+
+    let diff = buffer
+        .update(cx, |buffer, cx| {
+            buffer.remove_trailing_whitespace(false, cx)
+        })
+        .await;
+    buffer.update(cx, |buffer, cx| {
+        buffer.apply_diff(diff, cx);
+        assert_eq!(buffer.text(), "one\ntwo\nthree");
+    });
+}
+
+#[gpui::test]
+async fn test_remove_trailing_whitespace_in_string_literals(cx: &mut gpui::TestAppContext) {
+    let text = "fn a() {  \n    let s = \"one  \n    two\";  \n}  ";
+    let language = Arc::new(
+        rust_lang()
+            .with_override_query("(string_literal) @string")
+            .unwrap(),
+    );
+    let buffer = cx.new_model(|cx| Buffer::local(text, cx).with_language(language, cx));
+    cx.executor().run_until_parked();
+
+    let diff = buffer
+        .update(cx, |buffer, cx| {
+            buffer.remove_trailing_whitespace(false, cx)
+        })
+        .await;
+    buffer.update(cx, |buffer, cx| {
+        buffer.apply_diff(diff, cx);
+        assert_eq!(
+            buffer.text(),
+            "fn a() {\n    let s = \"one  \n    two\";\n}"
+        );
+    });
+}
+
+#[cfg(feature = "profile")]
+#[gpui::test]
+async fn test_parse_metrics(cx: &mut gpui::TestAppContext) {
+    let buffer =
+        cx.new_model(|cx| Buffer::local("fn a() {}", cx).with_language(Arc::new(rust_lang()), cx));
+    cx.executor().run_until_parked();
+    let metrics = buffer.read_with(cx, |buffer, _| buffer.parse_metrics());
+    assert!(metrics.last_parse_duration.is_some());
+    assert_eq!(metrics.sync_parse_count + metrics.async_parse_count, 1);
+
+    // Parses that outlast the timeout are counted separately.
+    buffer.update(cx, |buffer, cx| {
+        buffer.set_sync_parse_timeout(Duration::ZERO);
+        buffer.edit([(8..8, "b")], None, cx);
+        assert!(buffer.is_parsing());
+    });
+    cx.executor().run_until_parked();
+    let new_metrics = buffer.read_with(cx, |buffer, _| buffer.parse_metrics());
+    assert_eq!(new_metrics.sync_parse_count, metrics.sync_parse_count);
+    assert_eq!(new_metrics.async_parse_count, metrics.async_parse_count + 1);
+    assert_eq!(
+        buffer.read_with(cx, |buffer, _| buffer.last_parse_duration()),
+        new_metrics.last_parse_duration
+    );
+}
+
 #[gpui::test]
 async fn test_reparse(cx: &mut gpui::TestAppContext) {
     let text = "fn a() {}";
@@ -599,6 +1151,43 @@ async fn test_resetting_language(cx: &mut gpui::TestAppContext) {
     assert_eq!(get_tree_sexp(&buffer, cx), "(document (object))");
 }
 
+#[gpui::test]
+async fn test_switching_languages_during_background_parse(cx: &mut gpui::TestAppContext) {
+    let buffer = cx.new_model(|cx| {
+        let mut buffer = Buffer::local("{}", cx);
+        buffer.set_sync_parse_timeout(Duration::ZERO);
+        buffer
+    });
+
+    buffer.update(cx, |buffer, cx| {
+        buffer.set_language(Some(Arc::new(rust_lang())), cx);
+        assert!(buffer.is_parsing());
+        buffer.set_language(Some(Arc::new(json_lang())), cx);
+        buffer.set_language(Some(Arc::new(rust_lang())), cx);
+        buffer.set_language(Some(Arc::new(json_lang())), cx);
+    });
+    cx.executor().run_until_parked();
+    assert!(!buffer.update(cx, |buffer, _| buffer.is_parsing()));
+    assert_eq!(get_tree_sexp(&buffer, cx), "(document (object))");
+    buffer.update(cx, |buffer, _| {
+        let snapshot = buffer.snapshot();
+        let layers = snapshot.syntax.layers(buffer.as_text_snapshot());
+        assert_eq!(layers.len(), 1);
+        assert_eq!(layers[0].language.name().as_ref(), "Json");
+    });
+
+    // Clearing the language mid-parse discards the pending parse.
+    buffer.update(cx, |buffer, cx| {
+        buffer.set_language(Some(Arc::new(rust_lang())), cx);
+        buffer.set_language(None, cx);
+    });
+    cx.executor().run_until_parked();
+    buffer.update(cx, |buffer, _| {
+        let snapshot = buffer.snapshot();
+        assert!(snapshot.syntax.layers(buffer.as_text_snapshot()).is_empty());
+    });
+}
+
 #[gpui::test]
 async fn test_outline(cx: &mut gpui::TestAppContext) {
     let text = r#"
@@ -776,33 +1365,97 @@ async fn test_outline_with_extra_context(cx: &mut gpui::TestAppContext) {
 }
 
 #[gpui::test]
-async fn test_symbols_containing(cx: &mut gpui::TestAppContext) {
+async fn test_outline_with_nested_modules_and_functions(cx: &mut gpui::TestAppContext) {
     let text = r#"
-        impl Person {
-            fn one() {
-                1
-            }
+        mod a {
+            fn one() {}
 
-            fn two() {
-                2
-            }fn three() {
-                3
+            mod b {
+                fn two() {}
             }
         }
+
+        fn three() {}
     "#
     .unindent();
 
     let buffer =
         cx.new_model(|cx| Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx));
     let snapshot = buffer.update(cx, |buffer, _| buffer.snapshot());
+    let outline = snapshot.outline(None).unwrap();
 
-    // point is at the start of an item
+    // Items are returned in document order, with their depth reflecting
+    // how deeply the corresponding syntax nodes are nested.
     assert_eq!(
-        symbols_containing(Point::new(1, 4), &snapshot),
-        vec![
-            (
-                "impl Person".to_string(),
-                Point::new(0, 0)..Point::new(10, 1)
+        outline
+            .items
+            .iter()
+            .map(|item| {
+                (
+                    item.text.as_str(),
+                    item.depth,
+                    item.range.to_point(&snapshot),
+                )
+            })
+            .collect::<Vec<_>>(),
+        &[
+            ("mod a", 0, Point::new(0, 0)..Point::new(6, 1)),
+            ("fn one", 1, Point::new(1, 4)..Point::new(1, 15)),
+            ("mod b", 1, Point::new(3, 4)..Point::new(5, 5)),
+            ("fn two", 2, Point::new(4, 8)..Point::new(4, 19)),
+            ("fn three", 0, Point::new(8, 0)..Point::new(8, 13)),
+        ]
+    );
+
+    // The name ranges only cover the captured names.
+    assert_eq!(
+        outline
+            .items
+            .iter()
+            .map(|item| item
+                .name_ranges
+                .iter()
+                .map(|range| &item.text[range.clone()])
+                .collect::<Vec<_>>())
+            .collect::<Vec<_>>(),
+        &[
+            vec!["a"],
+            vec!["one"],
+            vec!["b"],
+            vec!["two"],
+            vec!["three"]
+        ]
+    );
+}
+
+#[gpui::test]
+async fn test_symbols_containing(cx: &mut gpui::TestAppContext) {
+    let text = r#"
+        impl Person {
+            fn one() {
+                1
+            }
+
+            fn two() {
+                2
+            }fn three() {
+                3
+            }
+        }
+    "#
+    .unindent();
+
+    let buffer =
+        cx.new_model(|cx| Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx));
+    let snapshot = buffer.update(cx, |buffer, _| buffer.snapshot());
+
+    // point is at the start of an item
+    assert_eq!(
+        symbols_containing(Point::new(1, 4), &snapshot),
+        vec![
+            (
+                "impl Person".to_string(),
+                Point::new(0, 0)..Point::new(10, 1)
             ),
             ("fn one".to_string(), Point::new(1, 4)..Point::new(3, 5))
         ]
@@ -1010,6 +1663,503 @@ fn test_enclosing_bracket_ranges_where_brackets_are_not_outermost_children(cx: &
     );
 }
 
+#[gpui::test]
+fn test_all_enclosing_bracket_ranges(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let text = "a = ({ b: [ c ] });";
+        let buffer = Buffer::local(text, cx).with_language(Arc::new(javascript_lang()), cx);
+        let snapshot = buffer.snapshot();
+
+        let offset_of = |part| text.find(part).unwrap();
+        let pair = |open, close| {
+            (
+                offset_of(open)..offset_of(open) + 1,
+                offset_of(close)..offset_of(close) + 1,
+            )
+        };
+
+        let c = offset_of("c");
+        assert_eq!(
+            snapshot.all_enclosing_bracket_ranges(c..c + 1),
+            vec![pair("[", "]"), pair("{", "}"), pair("(", ")")]
+        );
+        assert_eq!(
+            snapshot.innermost_enclosing_bracket_ranges(c..c + 1, None),
+            Some(pair("[", "]"))
+        );
+        assert!(snapshot.all_enclosing_bracket_ranges(0..1).is_empty());
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_bracket_pairs_in_range(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let text = "a = ({ b: [c] }); d(e);";
+        let buffer = Buffer::local(text, cx).with_language(Arc::new(javascript_lang()), cx);
+        let snapshot = buffer.snapshot();
+
+        let pair = |open: usize, close: usize, depth| (open..open + 1, close..close + 1, depth);
+        let offset_of = |part| text.find(part).unwrap();
+        assert_eq!(
+            snapshot.bracket_pairs_in_range(0..text.len()),
+            vec![
+                pair(offset_of("("), offset_of(")"), 0),
+                pair(offset_of("{"), offset_of("}"), 1),
+                pair(offset_of("["), offset_of("]"), 2),
+                pair(text.rfind('(').unwrap(), text.rfind(')').unwrap(), 0),
+            ]
+        );
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_matching_bracket(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let text = "fn a() { if b { c } }";
+        let buffer = Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx);
+        let snapshot = buffer.snapshot();
+
+        let outer_open = text.find('{').unwrap();
+        let inner_open = text.rfind('{').unwrap();
+        let inner_close = text.find('}').unwrap();
+        let outer_close = text.rfind('}').unwrap();
+
+        // On an opening bracket.
+        assert_eq!(snapshot.matching_bracket(outer_open), Some(outer_close));
+        assert_eq!(snapshot.matching_bracket(inner_open), Some(inner_close));
+
+        // On a closing bracket.
+        assert_eq!(snapshot.matching_bracket(outer_close), Some(outer_open));
+        assert_eq!(snapshot.matching_bracket(inner_close), Some(inner_open));
+
+        // Just after a bracket.
+        assert_eq!(snapshot.matching_bracket(inner_open + 1), Some(inner_close));
+        assert_eq!(snapshot.matching_bracket(text.len()), Some(outer_open));
+
+        // Away from any bracket.
+        assert_eq!(snapshot.matching_bracket(text.find('b').unwrap()), None);
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_chunks_reuse_cached_highlights(cx: &mut AppContext) {
+    let language = Arc::new(
+        rust_lang()
+            .with_highlights_query(
+                r#"
+                "fn" @keyword
+                (identifier) @variable
+                "#,
+            )
+            .unwrap(),
+    );
+
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("fn a() { b }", cx).with_language(language, cx);
+        let query_cursor_count =
+            || crate::syntax_map::QUERY_CURSOR_HANDLE_COUNT.with(|count| count.get());
+        let chunks = |snapshot: &BufferSnapshot| {
+            snapshot
+                .chunks(0..snapshot.len(), true)
+                .map(|chunk| (chunk.text.to_string(), chunk.syntax_highlight_id))
+                .collect::<Vec<_>>()
+        };
+
+        let count = query_cursor_count();
+        let first_chunks = chunks(&buffer.snapshot());
+        assert!(query_cursor_count() > count);
+
+        // Requesting the same range again doesn't re-run the highlights query.
+        let count = query_cursor_count();
+        assert_eq!(chunks(&buffer.snapshot()), first_chunks);
+        assert_eq!(query_cursor_count(), count);
+
+        // Editing the buffer invalidates the cached highlights.
+        buffer.edit([(0..0, "// c\n")], None, cx);
+        let count = query_cursor_count();
+        let snapshot = buffer.snapshot();
+        let text = chunks(&snapshot)
+            .into_iter()
+            .map(|(text, _)| text)
+            .collect::<String>();
+        assert_eq!(text, snapshot.text());
+        assert!(query_cursor_count() > count);
+
+        buffer
+    });
+}
+
+#[gpui::test]
+async fn test_chunks_invalidate_cached_highlights_on_reparse(cx: &mut gpui::TestAppContext) {
+    let language = Arc::new(
+        rust_lang()
+            .with_highlights_query(
+                r#"
+                "fn" @keyword
+                (identifier) @variable
+                "#,
+            )
+            .unwrap(),
+    );
+    let buffer = cx.new_model(|cx| Buffer::local("fn a() { b }", cx).with_language(language, cx));
+    cx.executor().run_until_parked();
+
+    let query_cursor_count =
+        || crate::syntax_map::QUERY_CURSOR_HANDLE_COUNT.with(|count| count.get());
+    let chunks = |snapshot: &BufferSnapshot| {
+        snapshot
+            .chunks(0..snapshot.len(), true)
+            .map(|chunk| (chunk.text.to_string(), chunk.syntax_highlight_id))
+            .collect::<Vec<_>>()
+    };
+
+    // Highlight the buffer while the edit is still being parsed.
+    let version = buffer.update(cx, |buffer, cx| {
+        buffer.set_sync_parse_timeout(Duration::ZERO);
+        buffer.edit([(5..5, "c: D")], None, cx);
+        assert!(buffer.is_parsing());
+        chunks(&buffer.snapshot());
+        buffer.version()
+    });
+    cx.executor().run_until_parked();
+
+    // Once the reparse completes, the same range at the same version is highlighted anew.
+    buffer.update(cx, |buffer, _| {
+        assert!(!buffer.is_parsing());
+        assert_eq!(buffer.version(), version);
+        let count = query_cursor_count();
+        chunks(&buffer.snapshot());
+        assert!(query_cursor_count() > count);
+
+        let count = query_cursor_count();
+        chunks(&buffer.snapshot());
+        assert_eq!(query_cursor_count(), count);
+    });
+}
+
+#[gpui::test]
+fn test_chunk_highlight_font_styles(cx: &mut AppContext) {
+    let theme = SyntaxTheme {
+        highlights: vec![
+            (
+                "comment".into(),
+                HighlightStyle {
+                    font_style: Some(FontStyle::Italic),
+                    ..Default::default()
+                },
+            ),
+            (
+                "keyword".into(),
+                HighlightStyle {
+                    font_weight: Some(FontWeight::BOLD),
+                    ..Default::default()
+                },
+            ),
+        ],
+    };
+    let language = Arc::new(
+        rust_lang()
+            .with_highlights_query(
+                r#"
+                (line_comment) @comment
+                "fn" @keyword
+                "#,
+            )
+            .unwrap(),
+    );
+    language.set_theme(&theme);
+
+    cx.new_model(|cx| {
+        let buffer = Buffer::local("// a\nfn b() {}", cx).with_language(language, cx);
+        let snapshot = buffer.snapshot();
+        let styles = snapshot
+            .chunks(0..snapshot.len(), true)
+            .map(|chunk| {
+                let style = chunk
+                    .syntax_highlight_id
+                    .and_then(|id| id.style(&theme))
+                    .unwrap_or_default();
+                (chunk.text.to_string(), style.font_style, style.font_weight)
+            })
+            .collect::<Vec<_>>();
+
+        assert!(styles.iter().any(|(text, font_style, _)| {
+            text.starts_with("// a") && *font_style == Some(FontStyle::Italic)
+        }));
+        assert!(styles.contains(&("fn".into(), None, Some(FontWeight::BOLD))));
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_chunks_seek_backward(cx: &mut AppContext) {
+    let theme = SyntaxTheme {
+        highlights: ["function", "variable"]
+            .into_iter()
+            .map(|name| (name.into(), HighlightStyle::default()))
+            .collect(),
+    };
+    let language = Arc::new(
+        rust_lang()
+            .with_highlights_query(
+                r#"
+                (call_expression) @function
+                (identifier) @variable
+                "#,
+            )
+            .unwrap(),
+    );
+    language.set_theme(&theme);
+
+    // Short ranges use cached highlight captures, and long ones run the query live.
+    for prefix in ["", "// padding\n".repeat(2000).as_str()] {
+        cx.new_model(|cx| {
+            let text = format!("{prefix}fn a() {{ b(c) }}");
+            let buffer = Buffer::local(text.clone(), cx).with_language(language.clone(), cx);
+            let snapshot = buffer.snapshot();
+            let mut chunks = snapshot.chunks(0..snapshot.len(), true);
+            let next_chunk = |chunks: &mut BufferChunks| {
+                let chunk = chunks.next().unwrap();
+                let name = chunk.syntax_highlight_id.and_then(|id| id.name(&theme));
+                (chunk.text.to_string(), name)
+            };
+
+            while chunks.next().is_some() {}
+
+            // Seek backward into a capture nested within another one.
+            chunks.seek(text.rfind('c').unwrap());
+            assert_eq!(next_chunk(&mut chunks), ("c".to_string(), Some("variable")));
+            assert_eq!(next_chunk(&mut chunks), (")".to_string(), Some("function")));
+
+            // Seek backward to a position covered only by the enclosing capture.
+            chunks.seek(text.rfind('(').unwrap());
+            assert_eq!(next_chunk(&mut chunks), ("(".to_string(), Some("function")));
+            assert_eq!(next_chunk(&mut chunks), ("c".to_string(), Some("variable")));
+
+            buffer
+        });
+    }
+}
+
+#[gpui::test]
+fn test_fallback_highlighting(cx: &mut AppContext) {
+    let theme = |names: [&str; 3]| SyntaxTheme {
+        highlights: names
+            .into_iter()
+            .map(|name| (name.into(), HighlightStyle::default()))
+            .collect(),
+    };
+    let theme_1 = theme(["comment", "string", "number"]);
+    let theme_2 = theme(["number", "string", "comment"]);
+    set_fallback_highlight_theme(&theme_1);
+
+    cx.new_model(|cx| {
+        // A buffer for a file with an unrecognized extension has no language.
+        let mut buffer = Buffer::local("count = 42\nname = \"zed\" // the name\n# done\n", cx);
+        let highlights = |buffer: &Buffer, theme: &SyntaxTheme| {
+            let snapshot = buffer.snapshot();
+            snapshot
+                .chunks(0..snapshot.len(), true)
+                .filter_map(|chunk| {
+                    let name = chunk.syntax_highlight_id?.name(theme)?;
+                    Some((chunk.text.to_string(), name))
+                })
+                .collect::<Vec<_>>()
+        };
+        let expected = [
+            ("42".to_string(), "number"),
+            ("\"zed\"".to_string(), "string"),
+            ("// the name".to_string(), "comment"),
+            ("# done".to_string(), "comment"),
+        ];
+        assert!(highlights(&buffer, &theme_1).is_empty());
+
+        buffer.set_fallback_highlighting(true, cx);
+        assert_eq!(highlights(&buffer, &theme_1), expected);
+        // Captures are cached, and still reflect the text after an edit.
+        assert_eq!(highlights(&buffer, &theme_1), expected);
+        buffer.edit([(0..0, "// ")], None, cx);
+        assert_eq!(
+            highlights(&buffer, &theme_1)[0],
+            ("// count = 42".to_string(), "comment")
+        );
+        buffer.undo(cx);
+
+        // Changing the theme re-maps the captures without re-enabling the highlighter.
+        set_fallback_highlight_theme(&theme_2);
+        assert_eq!(highlights(&buffer, &theme_2), expected);
+
+        buffer.set_fallback_highlighting(false, cx);
+        assert!(highlights(&buffer, &theme_2).is_empty());
+
+        buffer
+    });
+}
+
+#[gpui::test]
+async fn test_highlights_query_override(cx: &mut TestAppContext) {
+    let theme = SyntaxTheme {
+        highlights: vec![
+            ("function".into(), HighlightStyle::default()),
+            ("type".into(), HighlightStyle::default()),
+        ],
+    };
+    let languages = Arc::new(LanguageRegistry::test(cx.executor()));
+    languages.register_native_grammars([("rust", tree_sitter_rust::language())]);
+    languages.register_language(
+        "Rust".into(),
+        Some("rust".into()),
+        Default::default(),
+        || {
+            Ok((
+                LanguageConfig {
+                    name: "Rust".into(),
+                    grammar: Some("rust".into()),
+                    ..Default::default()
+                },
+                LanguageQueries {
+                    highlights: Some("(function_item name: (identifier) @function)".into()),
+                    ..Default::default()
+                },
+                None,
+            ))
+        },
+    );
+
+    let highlight_names = |language: Arc<Language>| {
+        language.set_theme(&theme);
+        language
+            .highlight_text(&"fn a() {}".into(), 0..9)
+            .into_iter()
+            .map(|(range, id)| (range, id.name(&theme).unwrap().to_string()))
+            .collect::<Vec<_>>()
+    };
+
+    let language = languages.language_for_name("Rust").await.unwrap();
+    assert_eq!(highlight_names(language), [(3..4, "function".to_string())]);
+
+    languages.set_highlights_query_override("Rust", "(function_item name: (identifier) @type)");
+    let language = languages.language_for_name("Rust").await.unwrap();
+    assert_eq!(highlight_names(language), [(3..4, "type".to_string())]);
+
+    // An override that doesn't compile falls back to the built-in query.
+    languages.set_highlights_query_override("Rust", "(function_item");
+    let language = languages.language_for_name("Rust").await.unwrap();
+    assert_eq!(highlight_names(language), [(3..4, "function".to_string())]);
+}
+
+#[gpui::test]
+fn test_chunks_with_whitespace(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let buffer = Buffer::local("\tlet a = b;  \nc", cx);
+        let snapshot = buffer.snapshot();
+
+        let chunks = snapshot
+            .chunks_with_whitespace(0..snapshot.len(), false)
+            .map(|chunk| (chunk.text, chunk.whitespace))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chunks,
+            [
+                ("\t", Some(WhitespaceKind::Tab)),
+                ("let", None),
+                (" ", Some(WhitespaceKind::Space)),
+                ("a", None),
+                (" ", Some(WhitespaceKind::Space)),
+                ("=", None),
+                (" ", Some(WhitespaceKind::Space)),
+                ("b;", None),
+                ("  ", Some(WhitespaceKind::Space)),
+                ("\nc", None),
+            ]
+        );
+
+        // Without whitespace rendering, the text isn't split.
+        let chunks = snapshot
+            .chunks(0..snapshot.len(), false)
+            .map(|chunk| (chunk.text, chunk.whitespace))
+            .collect::<Vec<_>>();
+        assert_eq!(chunks, [("\tlet a = b;  \nc", None)]);
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_chunks_with_highlights(cx: &mut AppContext) {
+    let theme = SyntaxTheme {
+        highlights: vec![(
+            "keyword".into(),
+            HighlightStyle {
+                color: Some(gpui::red()),
+                ..Default::default()
+            },
+        )],
+    };
+    let language = Arc::new(
+        rust_lang()
+            .with_highlights_query(r#""fn" @keyword"#)
+            .unwrap(),
+    );
+    language.set_theme(&theme);
+
+    cx.new_model(|cx| {
+        let buffer = Buffer::local("fn abc() {}", cx).with_language(language, cx);
+        let snapshot = buffer.snapshot();
+        let search_match = HighlightStyle {
+            background_color: Some(gpui::yellow()),
+            font_weight: Some(FontWeight::BOLD),
+            ..Default::default()
+        };
+        let word = HighlightStyle {
+            background_color: Some(gpui::blue()),
+            ..Default::default()
+        };
+
+        let chunks = snapshot
+            .chunks_with_highlights(
+                0..snapshot.len(),
+                true,
+                &[(1..5, search_match), (3..6, word)],
+            )
+            .map(|chunk| {
+                let syntax_style = chunk.syntax_highlight_id.and_then(|id| id.style(&theme));
+                (chunk.text, syntax_style, chunk.highlight_style)
+            })
+            .collect::<Vec<_>>();
+        let keyword = theme.highlights[0].1;
+        let mut both = search_match;
+        both.highlight(word);
+        assert_eq!(
+            chunks,
+            [
+                ("f", Some(keyword), None),
+                ("n", Some(keyword), Some(search_match)),
+                (" ", None, Some(search_match)),
+                ("ab", None, Some(both)),
+                ("c", None, Some(word)),
+                ("() {}", None, None),
+            ]
+        );
+
+        // The overlay is layered over the syntax highlight.
+        let mut style = chunks[1].1.unwrap();
+        style.highlight(chunks[1].2.unwrap());
+        assert_eq!(style.color, Some(gpui::red()));
+        assert_eq!(style.background_color, Some(gpui::yellow()));
+        assert_eq!(style.font_weight, Some(FontWeight::BOLD));
+
+        buffer
+    });
+}
+
 #[gpui::test]
 fn test_range_for_syntax_ancestor(cx: &mut AppContext) {
     cx.new_model(|cx| {
@@ -1037,54 +2187,1340 @@ fn test_range_for_syntax_ancestor(cx: &mut AppContext) {
         buffer
     });
 
-    fn empty_range_at(text: &str, part: &str) -> Range<usize> {
-        let start = text.find(part).unwrap();
-        start..start
-    }
+    fn empty_range_at(text: &str, part: &str) -> Range<usize> {
+        let start = text.find(part).unwrap();
+        start..start
+    }
+}
+
+#[gpui::test]
+fn test_expand_and_shrink_syntax_selection(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let text = "fn a() { let b = c + d; }";
+        let mut buffer = Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx);
+        let cursor = text.find('c').unwrap();
+
+        // Expand from a cursor on an identifier up to the enclosing statement.
+        let mut range = cursor..cursor;
+        range = buffer.expand_syntax_selection(0, range);
+        assert_eq!(range, range_of(text, "c"));
+        range = buffer.expand_syntax_selection(0, range);
+        assert_eq!(range, range_of(text, "c + d"));
+        range = buffer.expand_syntax_selection(0, range);
+        assert_eq!(range, range_of(text, "let b = c + d;"));
+        assert_eq!(
+            buffer.syntax_selection_history(0),
+            vec![range_of(text, "c + d"), range_of(text, "c"), cursor..cursor]
+        );
+
+        // Shrinking retraces the expansions.
+        range = buffer.shrink_syntax_selection(0, range);
+        assert_eq!(range, range_of(text, "c + d"));
+        range = buffer.shrink_syntax_selection(0, range);
+        assert_eq!(range, range_of(text, "c"));
+        range = buffer.shrink_syntax_selection(0, range);
+        assert_eq!(range, cursor..cursor);
+        assert!(buffer.syntax_selection_history(0).is_empty());
+
+        // Without any history, shrinking descends towards the start of the selection.
+        let snapshot = buffer.snapshot();
+        assert_eq!(
+            snapshot.expand_selection(range_of(text, "c + d")),
+            range_of(text, "let b = c + d;")
+        );
+        assert_eq!(
+            snapshot.shrink_selection(range_of(text, "let b = c + d;"), cursor),
+            range_of(text, "c + d")
+        );
+        assert_eq!(
+            snapshot.shrink_selection(range_of(text, "c + d"), cursor),
+            range_of(text, "c")
+        );
+        assert_eq!(
+            snapshot.shrink_selection(range_of(text, "c"), cursor),
+            cursor..cursor
+        );
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_enclosing_list_ranges(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let text = "fn a() { b(c, d + 1, [e, f]); }";
+        let buffer = Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx);
+        let snapshot = buffer.snapshot();
+
+        let ranges_text = |position: usize| {
+            snapshot.enclosing_list_ranges(position).map(|ranges| {
+                ranges
+                    .into_iter()
+                    .map(|range| &text[range])
+                    .collect::<Vec<_>>()
+            })
+        };
+        assert_eq!(
+            ranges_text(text.find('d').unwrap()),
+            Some(vec!["c", "d + 1", "[e, f]"])
+        );
+        assert_eq!(ranges_text(text.find('e').unwrap()), Some(vec!["e", "f"]));
+        assert_eq!(ranges_text(text.find('a').unwrap()), None);
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_edit_reporting(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("abc def ghi", cx);
+        let ranges = buffer.edit_reporting(
+            [
+                (0..3, "one"),
+                (4..4, ""),
+                (4..7, "twenty two"),
+                (8..8, "three "),
+            ],
+            cx,
+        );
+        assert_eq!(buffer.text(), "one twenty two three ghi");
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|range| buffer.text_for_range(range.clone()).collect::<String>())
+                .collect::<Vec<_>>(),
+            ["one", "twenty two", "three "]
+        );
+
+        // The ranges keep tracking the inserted text after subsequent edits.
+        buffer.edit([(0..0, "zero ")], None, cx);
+        assert_eq!(
+            buffer.text_for_range(ranges[1].clone()).collect::<String>(),
+            "twenty two"
+        );
+
+        // Out-of-order edits are sorted, and overlapping ones are reported as the
+        // single edit they're coalesced into.
+        buffer.set_text("abc def ghi", cx);
+        let ranges = buffer.edit_reporting([(8..11, "GHI"), (0..3, "one"), (2..5, "X")], cx);
+        assert_eq!(buffer.text(), "oneXef GHI");
+        assert_eq!(
+            ranges
+                .iter()
+                .map(|range| buffer.text_for_range(range.clone()).collect::<String>())
+                .collect::<Vec<_>>(),
+            ["oneX", "GHI"]
+        );
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_metadata_store(cx: &mut AppContext) {
+    #[derive(Clone, Debug, PartialEq)]
+    struct Coverage {
+        hits: usize,
+    }
+
+    let buffer = cx.new_model(|cx| Buffer::local("fn a() {\n    b();\n}\n", cx));
+    let events = Arc::new(Mutex::new(Vec::new()));
+    cx.subscribe(&buffer, {
+        let events = events.clone();
+        move |_, event, _| events.lock().push(event.clone())
+    })
+    .detach();
+
+    buffer.update(cx, |buffer, cx| {
+        buffer.update_metadata_store::<Coverage, _>(cx, |store, snapshot| {
+            store.insert(
+                snapshot.anchor_before(Point::new(1, 4))..snapshot.anchor_after(Point::new(1, 8)),
+                Coverage { hits: 3 },
+                snapshot,
+            );
+            store.insert(
+                snapshot.anchor_before(Point::new(0, 0))..snapshot.anchor_after(Point::new(2, 1)),
+                Coverage { hits: 1 },
+                snapshot,
+            );
+        });
+    });
+    assert_eq!(events.lock().as_slice(), &[Event::MetadataChanged]);
+
+    // The ranges follow edits to the buffer.
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit(
+            [(Point::new(1, 0)..Point::new(1, 0), "    x();\n")],
+            None,
+            cx,
+        );
+
+        let store = buffer.metadata_store::<Coverage>();
+        assert_eq!(store.len(), 2);
+        assert_eq!(
+            store
+                .range::<_, Point>(Point::new(2, 4)..Point::new(2, 5), buffer, true)
+                .collect::<Vec<_>>(),
+            [
+                (Point::new(0, 0)..Point::new(3, 1), &Coverage { hits: 1 }),
+                (Point::new(2, 4)..Point::new(2, 8), &Coverage { hits: 3 }),
+            ]
+        );
+        assert_eq!(
+            store
+                .range::<_, Point>(Point::new(1, 0)..Point::new(1, 8), buffer, false)
+                .map(|(_, coverage)| coverage.hits)
+                .collect::<Vec<_>>(),
+            [1]
+        );
+
+        buffer.update_metadata_store::<Coverage, _>(cx, |store, snapshot| {
+            store.retain(snapshot, |entry| entry.value.hits > 1);
+        });
+        assert_eq!(buffer.metadata_store::<Coverage>().len(), 1);
+        assert!(buffer.metadata_store::<String>().is_empty());
+    });
+}
+
+#[gpui::test]
+fn test_annotation_sets(cx: &mut AppContext) {
+    struct SearchResults;
+    struct BlameHunks;
+
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("one two three two one", cx);
+        let ranges = [4..7, 14..17]
+            .into_iter()
+            .map(|range| buffer.anchor_after(range.start)..buffer.anchor_before(range.end))
+            .collect::<Vec<_>>();
+        let search_results = buffer.add_annotation_set(ranges, TypeId::of::<SearchResults>(), cx);
+        let hunk = buffer.anchor_after(0)..buffer.anchor_before(3);
+        let blame_hunks = buffer.add_annotation_set([hunk], TypeId::of::<BlameHunks>(), cx);
+        assert_ne!(search_results, blame_hunks);
+
+        let annotated_text = |buffer: &Buffer, key: TypeId| {
+            buffer
+                .annotations_in_range::<_, usize>(key, 0..buffer.len())
+                .map(|(range, id)| (buffer.text_for_range(range).collect::<String>(), id))
+                .collect::<Vec<_>>()
+        };
+
+        // Edit before and inside the annotated ranges.
+        buffer.edit([(0..0, "zero "), (5..6, "TW")], None, cx);
+        assert_eq!(buffer.text(), "zero one tTWo three two one");
+        assert_eq!(
+            annotated_text(&buffer, TypeId::of::<SearchResults>()),
+            [
+                ("tTWo".to_string(), search_results),
+                ("two".to_string(), search_results)
+            ]
+        );
+        assert_eq!(
+            annotated_text(&buffer, TypeId::of::<BlameHunks>()),
+            [("one".to_string(), blame_hunks)]
+        );
+
+        // Only annotations intersecting the queried range are returned.
+        assert_eq!(
+            buffer
+                .annotations_in_range::<_, usize>(TypeId::of::<SearchResults>(), 16..17)
+                .collect::<Vec<_>>(),
+            []
+        );
+        assert_eq!(
+            buffer
+                .annotations_in_range::<_, usize>(TypeId::of::<SearchResults>(), 20..22)
+                .collect::<Vec<_>>(),
+            [(20..23, search_results)]
+        );
+
+        buffer.remove_annotation_set(search_results, cx);
+        assert_eq!(annotated_text(&buffer, TypeId::of::<SearchResults>()), []);
+        assert_eq!(annotated_text(&buffer, TypeId::of::<BlameHunks>()).len(), 1);
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_transpose(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("abcd", cx);
+        assert_eq!(buffer.transpose_chars(&[2], cx), vec![3]);
+        assert_eq!(buffer.text(), "acbd");
+
+        // At the end of a line, the two preceding characters are swapped.
+        buffer.set_text("one\ntwo", cx);
+        assert_eq!(buffer.transpose_chars(&[3, 7], cx), vec![3, 7]);
+        assert_eq!(buffer.text(), "oen\ntow");
+
+        // Newlines are never transposed, and multi-byte characters stay intact.
+        buffer.set_text("aé\nb", cx);
+        assert_eq!(buffer.transpose_chars(&[0, 1, 4], cx), vec![0, 3, 4]);
+        assert_eq!(buffer.text(), "éa\nb");
+
+        // All transpositions are undone together.
+        buffer.set_text("ab cd", cx);
+        buffer.transpose_chars(&[1, 4], cx);
+        assert_eq!(buffer.text(), "ba dc");
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), "ab cd");
+
+        buffer.set_text("one two, three", cx);
+        assert_eq!(buffer.transpose_words(&[4], cx), vec![7]);
+        assert_eq!(buffer.text(), "two one, three");
+        assert_eq!(buffer.transpose_words(&[5], cx), vec![14]);
+        assert_eq!(buffer.text(), "two three, one");
+        assert_eq!(buffer.transpose_words(&[14], cx), vec![14]);
+        assert_eq!(buffer.text(), "two three, one");
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_chunks_with_min_severity(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("one two three", cx);
+        let diagnostics = DiagnosticSet::new(
+            [
+                (0..3, DiagnosticSeverity::WARNING),
+                (4..7, DiagnosticSeverity::HINT),
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(group_id, (columns, severity))| DiagnosticEntry {
+                range: PointUtf16::new(0, columns.start)..PointUtf16::new(0, columns.end),
+                diagnostic: Diagnostic {
+                    severity,
+                    group_id,
+                    is_primary: true,
+                    ..Default::default()
+                },
+            }),
+            &buffer,
+        );
+        buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+        let snapshot = buffer.snapshot();
+
+        let chunks = snapshot
+            .chunks(0..snapshot.len(), true)
+            .map(|chunk| (chunk.text, chunk.diagnostic_severity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chunks,
+            [
+                ("one", Some(DiagnosticSeverity::WARNING)),
+                (" ", None),
+                ("two", Some(DiagnosticSeverity::HINT)),
+                (" three", None),
+            ]
+        );
+
+        // Hints are ignored entirely, so they don't split chunks.
+        let chunks = snapshot
+            .chunks_with_min_severity(0..snapshot.len(), true, DiagnosticSeverity::WARNING)
+            .map(|chunk| (chunk.text, chunk.diagnostic_severity))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            chunks,
+            [
+                ("one", Some(DiagnosticSeverity::WARNING)),
+                (" two three", None),
+            ]
+        );
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_diagnostics_with_group_ranges(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("aaaa\nbbbb\ncccc\ndddd\neeee", cx);
+        // The two groups' ranges overlap.
+        let diagnostics = DiagnosticSet::new(
+            [(0, 0, true), (0, 3, false), (1, 2, true), (1, 4, false)]
+                .into_iter()
+                .map(|(group_id, row, is_primary)| DiagnosticEntry {
+                    range: PointUtf16::new(row, 0)..PointUtf16::new(row, 4),
+                    diagnostic: Diagnostic {
+                        group_id,
+                        is_primary,
+                        ..Default::default()
+                    },
+                }),
+            &buffer,
+        );
+        buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+        let snapshot = buffer.snapshot();
+
+        let entries = |range: Range<Point>| {
+            snapshot
+                .diagnostics_with_group_ranges_in_range::<_, Point>(range)
+                .into_iter()
+                .map(|(entry, group_range)| {
+                    (
+                        entry.range.start.row,
+                        entry.diagnostic.group_id,
+                        entry.diagnostic.is_primary,
+                        group_range,
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            entries(Point::new(2, 0)..Point::new(3, 2)),
+            [
+                (2, 1, true, Point::new(2, 0)..Point::new(4, 4)),
+                (3, 0, false, Point::new(0, 0)..Point::new(3, 4)),
+            ]
+        );
+        assert_eq!(
+            entries(Point::new(0, 1)..Point::new(0, 2)),
+            [(0, 0, true, Point::new(0, 0)..Point::new(3, 4))]
+        );
+        assert!(entries(Point::new(1, 1)..Point::new(1, 2)).is_empty());
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_diagnostic_group_sorted(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("one two three four", cx);
+        let diagnostics = DiagnosticSet::new(
+            [
+                (0, 14..18, "four", false),
+                (0, 4..7, "two", true),
+                (0, 0..3, "one", false),
+                (1, 8..13, "three", true),
+            ]
+            .into_iter()
+            .map(|(group_id, columns, message, is_primary)| DiagnosticEntry {
+                range: PointUtf16::new(0, columns.start)..PointUtf16::new(0, columns.end),
+                diagnostic: Diagnostic {
+                    message: message.to_string(),
+                    group_id,
+                    is_primary,
+                    ..Default::default()
+                },
+            }),
+            &buffer,
+        );
+        buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+        let snapshot = buffer.snapshot();
+
+        assert_eq!(
+            snapshot
+                .diagnostic_group_sorted::<usize>(0)
+                .into_iter()
+                .map(|(range, diagnostic)| (range, diagnostic.message.as_str()))
+                .collect::<Vec<_>>(),
+            [(4..7, "two"), (0..3, "one"), (14..18, "four")]
+        );
+        assert!(snapshot.diagnostic_group_sorted::<usize>(2).is_empty());
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_diagnostic_density(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let text = "x\n".repeat(99) + "x";
+        let mut buffer = Buffer::local(text, cx);
+        let diagnostics = DiagnosticSet::new(
+            [
+                (2, DiagnosticSeverity::ERROR),
+                (5, DiagnosticSeverity::ERROR),
+                (9, DiagnosticSeverity::ERROR),
+                (9, DiagnosticSeverity::HINT),
+                (95, DiagnosticSeverity::WARNING),
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(group_id, (row, severity))| DiagnosticEntry {
+                range: PointUtf16::new(row, 0)..PointUtf16::new(row, 1),
+                diagnostic: Diagnostic {
+                    severity,
+                    group_id,
+                    is_primary: true,
+                    ..Default::default()
+                },
+            }),
+            &buffer,
+        );
+        buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+        let snapshot = buffer.snapshot();
+
+        let density = snapshot.diagnostic_density(10);
+        assert_eq!(density.len(), 10);
+        assert_eq!(
+            density[0],
+            DiagnosticCounts {
+                errors: 3,
+                hints: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            density[9],
+            DiagnosticCounts {
+                warnings: 1,
+                ..Default::default()
+            }
+        );
+        assert!(density[1..9]
+            .iter()
+            .all(|counts| *counts == DiagnosticCounts::default()));
+
+        // With more buckets than rows, every diagnostic still lands in a bucket.
+        let density = snapshot.diagnostic_density(1000);
+        let total = density.iter().map(|counts| counts.errors).sum::<usize>();
+        assert_eq!(total, 3);
+
+        assert!(snapshot.diagnostic_density(0).is_empty());
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_active_diagnostic_group(cx: &mut AppContext) {
+    fn diagnostics(group_ids: &[usize], buffer: &Buffer) -> DiagnosticSet {
+        DiagnosticSet::new(
+            group_ids.iter().map(|&group_id| DiagnosticEntry {
+                range: PointUtf16::new(group_id as u32, 0)..PointUtf16::new(group_id as u32, 1),
+                diagnostic: Diagnostic {
+                    group_id,
+                    is_primary: true,
+                    ..Default::default()
+                },
+            }),
+            buffer,
+        )
+    }
+
+    let buffer = cx.new_model(|cx| {
+        let mut buffer = Buffer::local("a\nb\nc\n", cx);
+        let diagnostics = diagnostics(&[0, 1], &buffer);
+        buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+        buffer
+    });
+    let events = Arc::new(Mutex::new(Vec::new()));
+    cx.subscribe(&buffer, {
+        let events = events.clone();
+        move |_, event, _| {
+            if !matches!(event, Event::Operations(_)) {
+                events.lock().push(event.clone())
+            }
+        }
+    })
+    .detach();
+
+    buffer.update(cx, |buffer, cx| {
+        buffer.set_active_diagnostic_group(Some(1), cx);
+        buffer.set_active_diagnostic_group(Some(1), cx);
+        let snapshot = buffer.snapshot();
+        assert!(snapshot.is_diagnostic_group_active(1));
+        assert!(!snapshot.is_diagnostic_group_active(0));
+    });
+    assert_eq!(
+        mem::take(&mut *events.lock()),
+        [Event::ActiveDiagnosticGroupChanged]
+    );
+
+    // Updating the diagnostics keeps the active group while it still exists.
+    buffer.update(cx, |buffer, cx| {
+        let diagnostics = diagnostics(&[1, 2], buffer);
+        buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+        assert!(buffer.snapshot().is_diagnostic_group_active(1));
+    });
+    assert_eq!(
+        mem::take(&mut *events.lock()),
+        [Event::DiagnosticsUpdated {
+            removed_group_ids: vec![0]
+        }]
+    );
+
+    // Removing the active group clears it.
+    buffer.update(cx, |buffer, cx| {
+        let diagnostics = diagnostics(&[2], buffer);
+        buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+        assert!(!buffer.snapshot().is_diagnostic_group_active(1));
+    });
+    assert_eq!(
+        mem::take(&mut *events.lock()),
+        [
+            Event::ActiveDiagnosticGroupChanged,
+            Event::DiagnosticsUpdated {
+                removed_group_ids: vec![1]
+            }
+        ]
+    );
+}
+
+#[gpui::test]
+fn test_removed_diagnostic_groups(cx: &mut AppContext) {
+    fn diagnostics(group_ids: &[usize], buffer: &Buffer) -> DiagnosticSet {
+        DiagnosticSet::new(
+            group_ids.iter().map(|&group_id| DiagnosticEntry {
+                range: PointUtf16::new(0, 0)..PointUtf16::new(0, 1),
+                diagnostic: Diagnostic {
+                    group_id,
+                    is_primary: true,
+                    ..Default::default()
+                },
+            }),
+            buffer,
+        )
+    }
+
+    let buffer = cx.new_model(|cx| {
+        let mut buffer = Buffer::local("abc", cx);
+        let diagnostics = diagnostics(&[0, 1, 2], &buffer);
+        buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+        let diagnostics = diagnostics(&[3], &buffer);
+        buffer.update_diagnostics(LanguageServerId(1), diagnostics, cx);
+        buffer
+    });
+    let events = Arc::new(Mutex::new(Vec::new()));
+    cx.subscribe(&buffer, {
+        let events = events.clone();
+        move |_, event, _| {
+            if let Event::DiagnosticsUpdated { removed_group_ids } = event {
+                events.lock().push(removed_group_ids.clone())
+            }
+        }
+    })
+    .detach();
+
+    // Resolving a single group reports only that group as removed.
+    buffer.update(cx, |buffer, cx| {
+        let diagnostics = diagnostics(&[0, 2], buffer);
+        buffer.update_diagnostics(LanguageServerId(0), diagnostics, cx);
+    });
+    assert_eq!(mem::take(&mut *events.lock()), [vec![1]]);
+
+    // Clearing one server's diagnostics leaves the other server's groups intact.
+    buffer.update(cx, |buffer, cx| {
+        buffer.update_diagnostics(LanguageServerId(0), DiagnosticSet::default(), cx);
+    });
+    assert_eq!(mem::take(&mut *events.lock()), [vec![0, 2]]);
+}
+
+#[gpui::test]
+fn test_snapshot_diff_base_text(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("one\ntwo\n", cx);
+        let snapshot_before = buffer.snapshot();
+        assert!(snapshot_before.diff_base_text().is_none());
+
+        buffer.set_diff_base(Some("one\r\n".into()), cx);
+        let snapshot_after = buffer.snapshot();
+        assert_eq!(
+            snapshot_after.diff_base_text().map(|base| base.to_string()),
+            Some("one\n".into())
+        );
+        assert!(snapshot_before.diff_base_text().is_none());
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_syntax_class_at(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let language = rust_lang()
+            .with_override_query(
+                r#"
+                (string_literal) @string
+                (line_comment) @comment
+                "#,
+            )
+            .unwrap();
+        let text = r#"let a = "b c"; // d"#;
+        let buffer = Buffer::local(text, cx).with_language(Arc::new(language), cx);
+        let snapshot = buffer.snapshot();
+
+        let string_offset = text.find('c').unwrap();
+        assert_eq!(snapshot.syntax_class_at(string_offset), SyntaxClass::String);
+        assert!(snapshot
+            .scope_at(string_offset)
+            .map_or(false, |kind| kind.starts_with("string")));
+
+        let code_offset = text.find('a').unwrap();
+        assert_eq!(snapshot.syntax_class_at(code_offset), SyntaxClass::Code);
+        assert_eq!(snapshot.scope_at(code_offset), Some("identifier"));
+
+        // Just outside of the closing quote.
+        let after_string_offset = text.find(';').unwrap();
+        assert_eq!(
+            snapshot.syntax_class_at(after_string_offset),
+            SyntaxClass::Code
+        );
+
+        let comment_offset = text.find('d').unwrap();
+        assert_eq!(
+            snapshot.syntax_class_at(comment_offset),
+            SyntaxClass::Comment
+        );
+
+        buffer
+    });
+
+    // Code interpolated into a string is classified as code.
+    cx.new_model(|cx| {
+        let language = javascript_lang()
+            .with_override_query(
+                r#"
+                (template_string) @string
+                (template_substitution) @default
+                "#,
+            )
+            .unwrap();
+        let text = "let a = `b ${c} d`;";
+        let buffer = Buffer::local(text, cx).with_language(Arc::new(language), cx);
+        let snapshot = buffer.snapshot();
+
+        assert_eq!(
+            snapshot.syntax_class_at(text.find('b').unwrap()),
+            SyntaxClass::String
+        );
+        assert_eq!(
+            snapshot.syntax_class_at(text.find('c').unwrap()),
+            SyntaxClass::Code
+        );
+        assert_eq!(
+            snapshot.syntax_class_at(text.find('d').unwrap()),
+            SyntaxClass::String
+        );
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_handle_input_autoclose(cx: &mut AppContext) {
+    let language = Arc::new(
+        Language::new(
+            LanguageConfig {
+                name: "Rust".into(),
+                brackets: BracketPairConfig {
+                    pairs: vec![BracketPair {
+                        start: "(".into(),
+                        end: ")".into(),
+                        close: true,
+                        surround: true,
+                        newline: false,
+                    }],
+                    ..Default::default()
+                },
+                autoclose_before: ";)".into(),
+                ..Default::default()
+            },
+            Some(tree_sitter_rust::language()),
+        )
+        .with_override_query("(string_literal) @string")
+        .unwrap(),
+    );
+
+    cx.new_model(|cx| {
+        let text = r#"fn a() { b; let c = "d e)"; }"#;
+        let mut buffer = Buffer::local(text, cx).with_language(language, cx);
+
+        // Opening brackets are closed automatically.
+        let offset = text.find("b;").unwrap() + 1;
+        let position = buffer.anchor_before(offset);
+        assert!(buffer.handle_input(position, "(", cx));
+        assert_eq!(buffer.text(), r#"fn a() { b(); let c = "d e)"; }"#);
+
+        // Typing a closing bracket skips over an existing one.
+        let position = buffer.anchor_before(offset + 1);
+        assert!(buffer.handle_input(position, ")", cx));
+        assert_eq!(buffer.text(), r#"fn a() { b(); let c = "d e)"; }"#);
+
+        // Closing brackets that don't follow the position are inserted.
+        let position = buffer.anchor_before(offset + 2);
+        assert!(!buffer.handle_input(position, ")", cx));
+        assert_eq!(buffer.text(), r#"fn a() { b()); let c = "d e)"; }"#);
+
+        // Brackets aren't closed before a word character.
+        let position = buffer.anchor_before(offset - 1);
+        assert!(!buffer.handle_input(position, "(", cx));
+        assert_eq!(buffer.text(), r#"fn a() { (b()); let c = "d e)"; }"#);
+
+        // Inside of strings, brackets aren't closed, even before whitespace, and
+        // closing brackets are inserted rather than typed over.
+        let position = buffer.anchor_before(buffer.text().find(" e").unwrap());
+        assert!(!buffer.handle_input(position, "(", cx));
+        assert_eq!(buffer.text(), r#"fn a() { (b()); let c = "d( e)"; }"#);
+        let position = buffer.anchor_before(buffer.text().find(")\"").unwrap());
+        assert!(!buffer.handle_input(position, ")", cx));
+        assert_eq!(buffer.text(), r#"fn a() { (b()); let c = "d( e))"; }"#);
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_handle_newline_between_brackets(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("fn f() {}", cx).with_language(Arc::new(rust_lang()), cx);
+
+        let position = buffer.anchor_before(8);
+        assert!(buffer.handle_newline(position, cx));
+        assert_eq!(buffer.text(), "fn f() {\n    \n}");
+
+        // Outside of an empty bracket pair, a single line is inserted.
+        let position = buffer.anchor_before(Point::new(1, 4));
+        assert!(!buffer.handle_newline(position, cx));
+        assert_eq!(buffer.text(), "fn f() {\n    \n    \n}");
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_insert_tab(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("ab\n\tc\nd", cx);
+
+        // Spaces are inserted up to the next multiple of the tab size, with
+        // tabs expanded to the tab size.
+        let new_offsets = buffer.insert_tab(&[2, 5, 6], cx);
+        assert_eq!(buffer.text(), "ab  \n\tc   \n    d");
+        assert_eq!(new_offsets, [4, 10, 15]);
+
+        // All of the insertions are undone together.
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), "ab\n\tc\nd");
+
+        buffer
+    });
+
+    cx.update_global::<SettingsStore, _>(|settings, cx| {
+        settings.update_user_settings::<AllLanguageSettings>(cx, |settings| {
+            settings.defaults.hard_tabs = Some(true);
+        });
+    });
+    cx.new_model(|cx| {
+        let mut buffer = Buffer::local("ab", cx);
+        assert_eq!(buffer.insert_tab(&[2], cx), [3]);
+        assert_eq!(buffer.text(), "ab\t");
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_brackets_text_objects(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    let language = rust_lang()
+        .with_brackets_query(
+            r#"
+            ("(" @open ")" @close)
+            ("{" @open "}" @close)
+            "#,
+        )
+        .unwrap();
+    cx.new_model(|cx| {
+        let text = "fn a() { b(( foo )); }";
+        let mut buffer = Buffer::local(text, cx).with_language(Arc::new(language), cx);
+        let snapshot = buffer.snapshot();
+        let inside = |offset| {
+            snapshot
+                .range_inside_brackets(offset)
+                .map(|range| snapshot.text_for_range(range).collect::<String>())
+        };
+
+        let foo = text.find("foo").unwrap();
+        assert_eq!(inside(foo).as_deref(), Some(" foo "));
+
+        // On an opening or closing bracket, its own pair is used.
+        assert_eq!(inside(foo - 2).as_deref(), Some(" foo "));
+        assert_eq!(inside(foo + 4).as_deref(), Some(" foo "));
+        assert_eq!(inside(foo - 3).as_deref(), Some("( foo )"));
+
+        // Just past a closing bracket, the enclosing pair is used.
+        assert_eq!(inside(foo + 6).as_deref(), Some(" b(( foo )); "));
+        assert_eq!(inside(0), None);
+
+        assert!(buffer.delete_enclosing_brackets(foo, cx).is_some());
+        assert_eq!(buffer.text(), "fn a() { b( foo ); }");
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), text);
+
+        assert!(buffer.delete_enclosing_brackets(foo - 3, cx).is_some());
+        assert_eq!(buffer.text(), "fn a() { b( foo ); }");
+        assert_eq!(buffer.delete_enclosing_brackets(0, cx), None);
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_trailing_whitespace_range(cx: &mut AppContext) {
+    let text = "a b  \n\n \t\nc";
+    let buffer = cx.new_model(|cx| Buffer::local(text, cx));
+    let snapshot = buffer.read(cx).snapshot();
+
+    assert_eq!(snapshot.line_end_offset(0), 5);
+    assert_eq!(snapshot.trailing_whitespace_range(0), 3..5);
+
+    // An empty line.
+    assert_eq!(snapshot.line_end_offset(1), 6);
+    assert_eq!(snapshot.trailing_whitespace_range(1), 6..6);
+
+    // A line containing only whitespace.
+    assert_eq!(snapshot.line_end_offset(2), 9);
+    assert_eq!(snapshot.trailing_whitespace_range(2), 7..9);
+
+    // A line without trailing whitespace.
+    assert_eq!(snapshot.line_end_offset(3), 11);
+    assert_eq!(snapshot.trailing_whitespace_range(3), 11..11);
+}
+
+#[gpui::test]
+fn test_accessible_line(cx: &mut AppContext) {
+    let text = "a\tb\x07c\n\n\tx\u{7f}";
+    let buffer = cx.new_model(|cx| Buffer::local(text, cx));
+    let snapshot = buffer.read(cx).snapshot();
+    let tab_size = NonZeroU32::new(4).unwrap();
+
+    assert_eq!(snapshot.accessible_line(0, tab_size), "a   b\u{2407}c");
+    assert_eq!(snapshot.accessible_line(1, tab_size), "");
+    assert_eq!(snapshot.accessible_line(2, tab_size), "    x\u{2421}");
+    assert_eq!(
+        snapshot.accessible_line(0, NonZeroU32::new(2).unwrap()),
+        "a b\u{2407}c"
+    );
+}
+
+#[gpui::test]
+fn test_word_range_at(cx: &mut AppContext) {
+    let css = Arc::new(Language::new(
+        LanguageConfig {
+            name: "CSS".into(),
+            word_characters: ['-'].into_iter().collect(),
+            ..Default::default()
+        },
+        None,
+    ));
+
+    cx.new_model(|cx| {
+        let text = "a { font-size: 1em; }";
+        let mut buffer = Buffer::local(text, cx);
+        let snapshot = buffer.snapshot();
+
+        let offset = text.find("size").unwrap();
+        assert_eq!(
+            snapshot.word_range_at(offset),
+            offset..offset + "size".len()
+        );
+        assert_eq!(snapshot.word_range_at(offset - 1), range_of(text, "font"));
+        assert_eq!(snapshot.word_range_at(3), 3..3);
+        assert_eq!(
+            snapshot.word_range_at(text.find("1em").unwrap()),
+            range_of(text, "1em")
+        );
+        assert_eq!(snapshot.word_range_at(text.len()), text.len()..text.len());
+
+        // The language can treat additional characters as part of words.
+        buffer.set_language(Some(css), cx);
+        let snapshot = buffer.snapshot();
+        assert_eq!(snapshot.word_range_at(offset), range_of(text, "font-size"));
+
+        buffer
+    });
+}
+
+#[test]
+fn test_preserve_case() {
+    // Lowercase matches lowercase the whole replacement.
+    assert_eq!(preserve_case("foo", "bar"), "bar");
+    assert_eq!(preserve_case("foo", "Bar"), "bar");
+    assert_eq!(preserve_case("foo", "BAR"), "bar");
+    assert_eq!(preserve_case("f", "BazQux"), "bazqux");
+
+    // Uppercase matches uppercase the whole replacement.
+    assert_eq!(preserve_case("FOO", "bar"), "BAR");
+    assert_eq!(preserve_case("FOO", "BazQux"), "BAZQUX");
+    assert_eq!(preserve_case("FOO_BAR", "baz_qux"), "BAZ_QUX");
+
+    // Capitalized matches capitalize the replacement.
+    assert_eq!(preserve_case("Foo", "bar"), "Bar");
+    assert_eq!(preserve_case("Foo", "BAR"), "Bar");
+    assert_eq!(preserve_case("F", "bar"), "Bar");
+
+    // Mixed-case matches only adjust the replacement's first character.
+    assert_eq!(preserve_case("fooBar", "BazQux"), "bazQux");
+    assert_eq!(preserve_case("FooBar", "bazQux"), "BazQux");
+
+    // Matches without letters leave the replacement as written.
+    assert_eq!(preserve_case("_", "Bar"), "Bar");
+    assert_eq!(preserve_case("FOO", ""), "");
+}
+
+#[gpui::test]
+fn test_replace_ranges_preserving_case(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let text = "foo Foo FOO";
+        let mut buffer = Buffer::local(text, cx);
+        let replacements = [0..3, 4..7, 8..11]
+            .into_iter()
+            .map(|range| {
+                let range = buffer.anchor_before(range.start)..buffer.anchor_after(range.end);
+                (range, "bar".to_string())
+            })
+            .collect();
+        assert_eq!(buffer.replace_ranges(replacements, true, cx), 3);
+        assert_eq!(buffer.text(), "bar Bar BAR");
+
+        // All of the replacements are undone at once.
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), text);
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_sibling_ranges(cx: &mut AppContext) {
+    cx.new_model(|cx| {
+        let text = "fn a() { f(x, y + 1, z); }";
+        let buffer = Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx);
+        let snapshot = buffer.snapshot();
+
+        assert_eq!(
+            snapshot.next_sibling_range(range_of(text, "x")),
+            Some(range_of(text, "y + 1"))
+        );
+        assert_eq!(
+            snapshot.next_sibling_range(range_of(text, "y + 1")),
+            Some(range_of(text, "z"))
+        );
+        assert_eq!(snapshot.next_sibling_range(range_of(text, "z")), None);
+        assert_eq!(
+            snapshot.prev_sibling_range(range_of(text, "z")),
+            Some(range_of(text, "y + 1"))
+        );
+        assert_eq!(
+            snapshot.prev_sibling_range(range_of(text, "y + 1")),
+            Some(range_of(text, "x"))
+        );
+        assert_eq!(snapshot.prev_sibling_range(range_of(text, "x")), None);
+
+        // Ranges that don't correspond to a named node use the smallest
+        // named node that contains them.
+        let plus = text.find('+').unwrap();
+        assert_eq!(
+            snapshot.next_sibling_range(plus..plus),
+            Some(range_of(text, "z"))
+        );
+        assert_eq!(snapshot.next_sibling_range(range_of(text, "x, y")), None);
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_indentation_scope_at(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    cx.new_model(|cx| {
+        let text = r#"
+            def f(x):
+                y = x + 1
+                if y:
+                    return y
+
+            z = f(1)
+        "#
+        .unindent();
+        let buffer = Buffer::local(text.clone(), cx).with_language(Arc::new(python_lang()), cx);
+        let snapshot = buffer.snapshot();
+        let scope_text = |offset| {
+            snapshot
+                .indentation_scope_at(offset)
+                .map(|range| snapshot.text_for_range(range).collect::<String>())
+        };
+
+        assert_eq!(
+            scope_text(text.find("x + 1").unwrap()),
+            Some("\n    y = x + 1\n    if y:\n        return y".into())
+        );
+        assert_eq!(
+            scope_text(text.find("return").unwrap()),
+            Some("\n        return y".into())
+        );
+        assert_eq!(scope_text(text.find("z =").unwrap()), None);
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_autoindent_block_mode_by_indentation_scope(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    cx.new_model(|cx| {
+        let text = "def f(x):\n    y = x + 1\n    \n    return y\n";
+        let mut buffer = Buffer::local(text, cx).with_language(Arc::new(python_lang()), cx);
+
+        // This block was copied from a more deeply nested position, without the
+        // indentation of its first line.
+        let inserted_text = "if y:\n            z = y\n            y += z";
+        buffer.edit(
+            [(Point::new(2, 4)..Point::new(2, 4), inserted_text)],
+            Some(AutoindentMode::Block {
+                original_indent_columns: vec![8],
+            }),
+            cx,
+        );
+        assert_eq!(
+            buffer.text(),
+            "def f(x):\n    y = x + 1\n    if y:\n        z = y\n        y += z\n    return y\n"
+        );
+
+        // Pasting whole lines at column zero indents them to match the block.
+        buffer.edit(
+            [(Point::new(1, 0)..Point::new(1, 0), "print(x)\nif x:\n    x = 2\n")],
+            Some(AutoindentMode::Block {
+                original_indent_columns: vec![0],
+            }),
+            cx,
+        );
+        assert_eq!(
+            buffer.text(),
+            "def f(x):\n    print(x)\n    if x:\n        x = 2\n    y = x + 1\n    if y:\n        z = y\n        y += z\n    return y\n"
+        );
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_autoindent_with_soft_tabs(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    cx.new_model(|cx| {
+        let text = "fn a() {}";
+        let mut buffer = Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx);
+
+        buffer.edit([(8..8, "\n\n")], Some(AutoindentMode::EachLine), cx);
+        assert_eq!(buffer.text(), "fn a() {\n    \n}");
+
+        buffer.edit(
+            [(Point::new(1, 4)..Point::new(1, 4), "b()\n")],
+            Some(AutoindentMode::EachLine),
+            cx,
+        );
+        assert_eq!(buffer.text(), "fn a() {\n    b()\n    \n}");
+
+        // Create a field expression on a new line, causing that line
+        // to be indented.
+        buffer.edit(
+            [(Point::new(2, 4)..Point::new(2, 4), ".c")],
+            Some(AutoindentMode::EachLine),
+            cx,
+        );
+        assert_eq!(buffer.text(), "fn a() {\n    b()\n        .c\n}");
+
+        // Remove the dot so that the line is no longer a field expression,
+        // causing the line to be outdented.
+        buffer.edit(
+            [(Point::new(2, 8)..Point::new(2, 9), "")],
+            Some(AutoindentMode::EachLine),
+            cx,
+        );
+        assert_eq!(buffer.text(), "fn a() {\n    b()\n    c\n}");
+
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_suggested_indent_for_row(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    cx.new_model(|cx| {
+        let text = "fn a() {\nb();\n}\n";
+        let buffer = Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx);
+        let snapshot = buffer.snapshot();
+        let tab_size = NonZeroU32::new(4).unwrap();
+
+        assert_eq!(
+            snapshot.suggested_indent_for_row(1, IndentSize::spaces(4), tab_size),
+            Some(4)
+        );
+        assert_eq!(
+            snapshot.suggested_indent_for_row(2, IndentSize::spaces(4), tab_size),
+            Some(0)
+        );
+
+        // Tabs are measured in columns.
+        assert_eq!(
+            snapshot.suggested_indent_for_row(1, IndentSize::tab(), tab_size),
+            Some(4)
+        );
+        assert_eq!(
+            snapshot.suggested_indent_for_row(1, IndentSize::tab(), NonZeroU32::new(8).unwrap()),
+            Some(8)
+        );
+
+        // The buffer isn't modified.
+        assert_eq!(buffer.text(), text);
+
+        buffer
+    });
+
+    cx.new_model(|cx| {
+        let text = "fn a() {\n\tif b {\nc();\n\t}\n}\n";
+        let buffer = Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx);
+        let snapshot = buffer.snapshot();
+        assert_eq!(
+            snapshot.suggested_indent_for_row(2, IndentSize::tab(), NonZeroU32::new(4).unwrap()),
+            Some(8)
+        );
+        buffer
+    });
+}
+
+#[gpui::test]
+fn test_reindent_rows(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    cx.new_model(|cx| {
+        let text = "
+            fn a() {
+            b();
+                    if c {
+              d();
+
+                }
+                e();
+            }
+        "
+        .unindent();
+        let mut buffer = Buffer::local(text.clone(), cx).with_language(Arc::new(rust_lang()), cx);
+
+        buffer.reindent_rows(0..8, cx).unwrap();
+        assert_eq!(
+            buffer.text(),
+            "
+            fn a() {
+                b();
+                if c {
+                    d();
+
+                }
+                e();
+            }
+            "
+            .unindent()
+        );
+
+        // The rows are reindented in a single transaction.
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), text);
+        buffer.redo(cx);
+
+        // Correctly-indented rows are left alone.
+        assert_eq!(buffer.reindent_rows(0..8, cx), None);
+
+        buffer
+    });
+}
+
+#[gpui::test]
+async fn test_autoindent_with_timeout(cx: &mut gpui::TestAppContext) {
+    cx.update(|cx| init_settings(cx, |_| {}));
+
+    let buffer = cx.new_model(|cx| {
+        let mut buffer = Buffer::local("fn a() {}", cx).with_language(Arc::new(rust_lang()), cx);
+        buffer.set_autoindent_timeout(Duration::ZERO);
+        buffer
+    });
+    cx.executor().run_until_parked();
+
+    // Without any time to compute it synchronously, the indentation is applied in
+    // the background.
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit([(8..8, "\n\n")], Some(AutoindentMode::EachLine), cx);
+        assert_eq!(buffer.text(), "fn a() {\n\n}");
+    });
+    cx.executor().run_until_parked();
+    buffer.read_with(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "fn a() {\n    \n}");
+    });
 
-    fn range_of(text: &str, part: &str) -> Range<usize> {
-        let start = text.find(part).unwrap();
-        start..start + part.len()
-    }
+    buffer.update(cx, |buffer, cx| {
+        buffer.set_autoindent_timeout(Duration::from_secs(1));
+        buffer.edit([(13..13, "\n\n")], Some(AutoindentMode::EachLine), cx);
+        assert_eq!(buffer.text(), "fn a() {\n    \n    \n    \n}");
+    });
 }
 
 #[gpui::test]
-fn test_autoindent_with_soft_tabs(cx: &mut AppContext) {
-    init_settings(cx, |_| {});
+async fn test_autoindent_discards_stale_results(cx: &mut gpui::TestAppContext) {
+    cx.update(|cx| init_settings(cx, |_| {}));
 
-    cx.new_model(|cx| {
-        let text = "fn a() {}";
-        let mut buffer = Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx);
+    let buffer = cx.new_model(|cx| {
+        let mut buffer = Buffer::local("fn a() {}", cx).with_language(Arc::new(rust_lang()), cx);
+        buffer.set_autoindent_timeout(Duration::ZERO);
+        buffer
+    });
+    cx.executor().run_until_parked();
 
+    buffer.update(cx, |buffer, cx| {
         buffer.edit([(8..8, "\n\n")], Some(AutoindentMode::EachLine), cx);
-        assert_eq!(buffer.text(), "fn a() {\n    \n}");
+        assert_eq!(buffer.text(), "fn a() {\n\n}");
 
-        buffer.edit(
-            [(Point::new(1, 4)..Point::new(1, 4), "b()\n")],
-            Some(AutoindentMode::EachLine),
-            cx,
-        );
-        assert_eq!(buffer.text(), "fn a() {\n    b()\n    \n}");
+        // Move the autoindented lines down before their indentation is applied. The
+        // edit drops the pending autoindent task, which cancels it.
+        buffer.edit([(0..0, "\n\n")], None, cx);
+    });
+    cx.executor().run_until_parked();
 
-        // Create a field expression on a new line, causing that line
-        // to be indented.
-        buffer.edit(
-            [(Point::new(2, 4)..Point::new(2, 4), ".c")],
-            Some(AutoindentMode::EachLine),
-            cx,
-        );
-        assert_eq!(buffer.text(), "fn a() {\n    b()\n        .c\n}");
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "\n\nfn a() {\n    \n}");
+    });
+}
 
-        // Remove the dot so that the line is no longer a field expression,
-        // causing the line to be outdented.
+#[gpui::test]
+async fn test_autoindent_moves_cursors_within_lines(cx: &mut gpui::TestAppContext) {
+    cx.update(|cx| init_settings(cx, |_| {}));
+
+    let buffer = cx.new_model(|cx| {
+        let mut buffer = Buffer::local("fn a() {\n}", cx).with_language(Arc::new(rust_lang()), cx);
+        buffer.set_autoindent_timeout(Duration::ZERO);
+        buffer
+    });
+    cx.executor().run_until_parked();
+
+    // Place cursors in the middle of the inserted lines before they are indented.
+    let cursors = buffer.update(cx, |buffer, cx| {
         buffer.edit(
-            [(Point::new(2, 8)..Point::new(2, 9), "")],
+            [(8..8, "\nb();\ncc();")],
             Some(AutoindentMode::EachLine),
             cx,
         );
-        assert_eq!(buffer.text(), "fn a() {\n    b()\n    c\n}");
+        assert_eq!(buffer.text(), "fn a() {\nb();\ncc();\n}");
+        [Point::new(1, 2), Point::new(2, 1)].map(|point| buffer.anchor_before(point))
+    });
+    cx.executor().run_until_parked();
 
-        buffer
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "fn a() {\n    b();\n    cc();\n}");
+        assert_eq!(
+            cursors.map(|cursor| cursor.to_point(buffer)),
+            [Point::new(1, 6), Point::new(2, 5)]
+        );
     });
 }
 
@@ -1605,6 +4041,48 @@ fn test_autoindent_block_mode_without_original_indent_columns(cx: &mut AppContex
     });
 }
 
+#[gpui::test]
+fn test_autoindent_block_mode_with_common_leading_indent(cx: &mut AppContext) {
+    init_settings(cx, |_| {});
+
+    cx.new_model(|cx| {
+        let text = r#"
+            fn a() {
+                b();
+
+            }
+        "#
+        .unindent();
+        let mut buffer = Buffer::local(text, cx).with_language(Arc::new(rust_lang()), cx);
+
+        // A block copied from a deeply-nested context, whose lines all share
+        // eight spaces of indentation, is re-based to the shallower context.
+        let inserted_text = "        if c {\n\n            d();\n        }";
+        buffer.edit(
+            [(Point::new(2, 0)..Point::new(2, 0), inserted_text)],
+            Some(AutoindentMode::Block {
+                original_indent_columns: Vec::new(),
+            }),
+            cx,
+        );
+        assert_eq!(
+            buffer.text(),
+            r#"
+            fn a() {
+                b();
+                if c {
+
+                    d();
+                }
+            }
+            "#
+            .unindent()
+        );
+
+        buffer
+    });
+}
+
 #[gpui::test]
 fn test_autoindent_language_without_indents_query(cx: &mut AppContext) {
     init_settings(cx, |_| {});
@@ -2056,6 +4534,273 @@ fn test_serialization(cx: &mut gpui::AppContext) {
     assert_eq!(buffer2.read(cx).text(), "abcDF");
 }
 
+#[gpui::test]
+fn test_line_ending_override_replication(cx: &mut gpui::AppContext) {
+    let host = cx.new_model(|cx| {
+        let mut buffer = Buffer::local("a\nb\n", cx);
+        buffer.set_line_ending(LineEnding::Windows, cx);
+        buffer
+    });
+
+    // Replicas created from the buffer's state see the override.
+    let state = host.read(cx).to_proto();
+    let guest = cx.new_model(|cx| {
+        let buffer = Buffer::from_proto(1, Capability::ReadWrite, state, None).unwrap();
+        assert_eq!(buffer.line_ending(), LineEnding::Unix);
+        assert_eq!(buffer.line_ending_at_save(), LineEnding::Windows);
+        assert!(buffer.is_dirty());
+        buffer
+    });
+
+    // Changes to the override are sent as operations, so the host saves the buffer
+    // with the line ending chosen by the guest.
+    let guest_ops = Arc::new(Mutex::new(Vec::new()));
+    cx.subscribe(&guest, {
+        let guest_ops = guest_ops.clone();
+        move |_, event, _| {
+            if let Event::Operations(operations) = event {
+                guest_ops.lock().extend(operations.iter().cloned());
+            }
+        }
+    })
+    .detach();
+    guest.update(cx, |guest, cx| {
+        guest.set_line_ending(LineEnding::Unix, cx);
+        assert!(!guest.is_dirty());
+    });
+    host.update(cx, |host, cx| {
+        let operations = guest_ops.lock().drain(..).collect::<Vec<_>>();
+        assert_eq!(operations.len(), 1);
+        host.apply_ops(operations, cx).unwrap();
+        assert_eq!(host.line_ending_at_save(), LineEnding::Unix);
+        assert!(!host.is_dirty());
+    });
+}
+
+#[gpui::test]
+fn test_operations_since(cx: &mut gpui::AppContext) {
+    let host = cx.new_model(|cx| Buffer::local("abc", cx));
+    let state = host.read(cx).to_proto();
+    let guest =
+        cx.new_model(|cx| Buffer::from_proto(1, Capability::ReadWrite, state, None).unwrap());
+
+    // The guest misses a few edits and a selection update from the host.
+    host.update(cx, |host, cx| {
+        host.edit([(3..3, "def")], None, cx);
+        host.edit([(0..1, "A")], None, cx);
+        host.undo(cx);
+        host.edit([(1..1, "123")], None, cx);
+
+        let cursor = host.anchor_after(2);
+        let selections = Arc::from([Selection {
+            id: 0,
+            start: cursor,
+            end: cursor,
+            reversed: false,
+            goal: SelectionGoal::None,
+        }]);
+        host.set_active_selections(selections, false, Default::default(), cx);
+    });
+    assert_eq!(host.read(cx).text(), "a123bcdef");
+    assert_eq!(guest.read(cx).text(), "abc");
+
+    let ops = host.read(cx).operations_since(&guest.read(cx).version());
+    guest.update(cx, |guest, cx| guest.apply_ops(ops, cx).unwrap());
+    assert_eq!(guest.read(cx).text(), "a123bcdef");
+    assert_eq!(guest.read(cx).version(), host.read(cx).version());
+    assert_eq!(
+        guest
+            .read(cx)
+            .snapshot()
+            .selection_anchors(0)
+            .into_iter()
+            .map(|range| range.to_offset(&guest.read(cx).snapshot()))
+            .collect::<Vec<_>>(),
+        [2..2]
+    );
+
+    // Once caught up, only the non-text state is included.
+    let ops = host.read(cx).operations_since(&guest.read(cx).version());
+    assert!(ops
+        .iter()
+        .all(|operation| !matches!(operation, Operation::Buffer(_))));
+}
+
+#[gpui::test]
+fn test_local_diagnostics_coexist_with_remote_diagnostics(cx: &mut gpui::AppContext) {
+    let host = cx.new_model(|cx| Buffer::local("one two three", cx));
+    let state = host.read(cx).to_proto();
+    let guest =
+        cx.new_model(|cx| Buffer::from_proto(1, Capability::ReadWrite, state, None).unwrap());
+
+    let host_ops = Arc::new(Mutex::new(Vec::new()));
+    let guest_ops = Arc::new(Mutex::new(Vec::new()));
+    for (buffer, ops) in [(&host, host_ops.clone()), (&guest, guest_ops.clone())] {
+        cx.subscribe(buffer, move |_, event, _| {
+            if let Event::Operations(operations) = event {
+                ops.lock().extend(operations.iter().cloned());
+            }
+        })
+        .detach();
+    }
+
+    // The guest runs its own spell checker, whose diagnostics aren't shared.
+    let spell_checker_id = LanguageServerId(1);
+    guest.update(cx, |guest, cx| {
+        let diagnostics = diagnostic_set(guest, 4..7, "misspelled word");
+        guest.update_local_diagnostics(spell_checker_id, diagnostics, cx);
+    });
+    assert!(guest_ops.lock().is_empty());
+
+    // The host's language server diagnostics are received by the guest.
+    let language_server_id = LanguageServerId(0);
+    host.update(cx, |host, cx| {
+        let diagnostics = diagnostic_set(host, 0..3, "undefined variable");
+        host.update_diagnostics(language_server_id, diagnostics, cx);
+    });
+    guest.update(cx, |guest, cx| {
+        guest.apply_ops(host_ops.lock().drain(..), cx).unwrap();
+    });
+
+    let guest_diagnostics = guest
+        .read(cx)
+        .snapshot()
+        .diagnostic_groups(None)
+        .into_iter()
+        .map(|(server_id, group)| {
+            let primary = &group.entries[group.primary_ix];
+            (server_id, primary.diagnostic.message.clone())
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        guest_diagnostics,
+        [
+            (language_server_id, "undefined variable".to_string()),
+            (spell_checker_id, "misspelled word".to_string()),
+        ]
+    );
+
+    // Local diagnostics aren't included when replicating the guest's state.
+    let ops = cx
+        .background_executor()
+        .block(guest.read(cx).serialize_ops(None, cx));
+    let replicated_server_ids = ops
+        .into_iter()
+        .filter_map(|op| match proto::deserialize_operation(op).unwrap() {
+            Operation::UpdateDiagnostics { server_id, .. } => Some(server_id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(replicated_server_ids, [language_server_id]);
+
+    fn diagnostic_set(buffer: &Buffer, range: Range<u32>, message: &str) -> DiagnosticSet {
+        DiagnosticSet::new(
+            [DiagnosticEntry {
+                range: PointUtf16::new(0, range.start)..PointUtf16::new(0, range.end),
+                diagnostic: Diagnostic {
+                    message: message.to_string(),
+                    is_primary: true,
+                    ..Default::default()
+                },
+            }],
+            buffer,
+        )
+    }
+}
+
+#[gpui::test]
+fn test_clear_diagnostics(cx: &mut gpui::AppContext) {
+    let host = cx.new_model(|cx| Buffer::local("one two three", cx));
+    let state = host.read(cx).to_proto();
+    let guest =
+        cx.new_model(|cx| Buffer::from_proto(1, Capability::ReadWrite, state, None).unwrap());
+
+    let host_ops = Arc::new(Mutex::new(Vec::new()));
+    cx.subscribe(&host, {
+        let host_ops = host_ops.clone();
+        move |_, event, _| {
+            if let Event::Operations(operations) = event {
+                host_ops.lock().extend(operations.iter().cloned());
+            }
+        }
+    })
+    .detach();
+
+    host.update(cx, |host, cx| {
+        for (server_id, range) in [(0, 0..3), (1, 4..7)] {
+            let diagnostics = DiagnosticSet::new(
+                [DiagnosticEntry {
+                    range: PointUtf16::new(0, range.start)..PointUtf16::new(0, range.end),
+                    diagnostic: Diagnostic {
+                        is_primary: true,
+                        ..Default::default()
+                    },
+                }],
+                host,
+            );
+            host.update_diagnostics(LanguageServerId(server_id), diagnostics, cx);
+        }
+        let diagnostics = DiagnosticSet::new(
+            [DiagnosticEntry {
+                range: PointUtf16::new(0, 8)..PointUtf16::new(0, 13),
+                diagnostic: Diagnostic {
+                    is_primary: true,
+                    ..Default::default()
+                },
+            }],
+            host,
+        );
+        host.update_local_diagnostics(LanguageServerId(2), diagnostics, cx);
+    });
+    guest.update(cx, |guest, cx| {
+        guest.apply_ops(host_ops.lock().drain(..), cx).unwrap();
+    });
+    assert_eq!(
+        host.read(cx).snapshot().diagnostic_density(1),
+        [DiagnosticCounts {
+            errors: 3,
+            ..Default::default()
+        }]
+    );
+    assert!(guest.read(cx).snapshot().has_diagnostics());
+
+    let update_count = host.read(cx).non_text_state_update_count();
+    host.update(cx, |host, cx| host.clear_diagnostics(cx));
+    let host_snapshot = host.read(cx).snapshot();
+    assert!(!host_snapshot.has_diagnostics());
+    assert_eq!(
+        host_snapshot.diagnostic_density(1),
+        [DiagnosticCounts::default()]
+    );
+    assert!(host_snapshot.non_text_state_update_count() > update_count);
+    assert!(!host.read(cx).is_dirty());
+
+    // Only the shared diagnostics are cleared, through operations that empty each
+    // server's diagnostics on the remote replica.
+    let clear_ops = host_ops
+        .lock()
+        .drain(..)
+        .map(|op| proto::deserialize_operation(proto::serialize_operation(&op)).unwrap())
+        .collect::<Vec<_>>();
+    let cleared_server_ids = clear_ops
+        .iter()
+        .filter_map(|op| match op {
+            Operation::UpdateDiagnostics {
+                server_id,
+                diagnostics,
+                ..
+            } if diagnostics.is_empty() => Some(*server_id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        cleared_server_ids,
+        [LanguageServerId(0), LanguageServerId(1)]
+    );
+    guest.update(cx, |guest, cx| guest.apply_ops(clear_ops, cx).unwrap());
+    assert!(!guest.read(cx).snapshot().has_diagnostics());
+}
+
 #[gpui::test]
 async fn test_find_matching_indent(cx: &mut TestAppContext) {
     cx.update(|cx| init_settings(cx, |_| {}));
@@ -2142,6 +4887,168 @@ async fn test_find_matching_indent(cx: &mut TestAppContext) {
     );
 }
 
+#[gpui::test(iterations = 100)]
+fn test_random_anchor_range_multimap(cx: &mut AppContext, mut rng: StdRng) {
+    let text = RandomCharIter::new(&mut rng)
+        .take(rng.gen_range(0..50))
+        .collect::<String>();
+    let buffer = cx.new_model(|cx| Buffer::local(text, cx));
+    let snapshot = buffer.read(cx).text_snapshot();
+
+    let entries = |map: &AnchorRangeMultimap<usize>| {
+        map.iter()
+            .map(|entry| (entry.range.to_offset(&snapshot), entry.value))
+            .collect::<Vec<_>>()
+    };
+
+    let mut map = AnchorRangeMultimap::default();
+    let mut expected = Vec::<(Range<usize>, usize)>::new();
+    for value in 0..rng.gen_range(1..30) {
+        if expected.is_empty() || rng.gen_bool(0.7) {
+            let range = buffer.read(cx).random_byte_range(0, &mut rng);
+            log::info!("inserting {value} at {range:?}");
+            map.insert(
+                snapshot.anchor_before(range.start)..snapshot.anchor_before(range.end),
+                value,
+                &snapshot,
+            );
+            expected.push((range, value));
+        } else {
+            let modulus = rng.gen_range(2..5);
+            log::info!("removing values divisible by {modulus}");
+            let removed = map.remove_matching(&snapshot, |entry| entry.value % modulus == 0);
+            assert!(removed.iter().all(|entry| entry.value % modulus == 0));
+            expected.retain(|(_, value)| value % modulus != 0);
+        }
+
+        // Entries are kept ordered by start, and then by descending end.
+        let actual = entries(&map);
+        assert!(actual
+            .windows(2)
+            .all(|pair| (pair[0].0.start, Reverse(pair[0].0.end))
+                <= (pair[1].0.start, Reverse(pair[1].0.end))));
+        assert_eq!(map.len(), expected.len());
+
+        // They match a map built from scratch, up to the order of values with
+        // identical ranges.
+        let rebuilt = AnchorRangeMultimap::from_offset_ranges(expected.clone(), &snapshot);
+        let mut actual = actual;
+        let mut rebuilt = entries(&rebuilt);
+        actual.sort_by_key(|(range, value)| (range.start, Reverse(range.end), *value));
+        rebuilt.sort_by_key(|(range, value)| (range.start, Reverse(range.end), *value));
+        assert_eq!(actual, rebuilt);
+    }
+}
+
+#[gpui::test]
+fn test_anchor_range_multimap_range_as_anchors(cx: &mut AppContext) {
+    let buffer = cx.new_model(|cx| Buffer::local("one two three four", cx));
+    let snapshot = buffer.read(cx).text_snapshot();
+    let map =
+        AnchorRangeMultimap::from_offset_ranges([(4..7, 1), (8..13, 2), (14..18, 3)], &snapshot);
+
+    // Intersecting ranges can be retrieved as anchors, and stored for later.
+    let mut kept = AnchorRangeMultimap::default();
+    for (range, value) in map.range::<_, Anchor>(5..10, &snapshot, false) {
+        kept.insert(range, *value, &snapshot);
+    }
+
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "zero ")], None, cx));
+    let snapshot = buffer.read(cx).text_snapshot();
+    assert_eq!(
+        kept.range::<_, usize>(0..snapshot.len(), &snapshot, true)
+            .map(|(range, value)| (range, *value))
+            .collect::<Vec<_>>(),
+        [(9..12, 1), (13..18, 2)]
+    );
+}
+
+#[gpui::test]
+async fn test_anchor_range_multimap_serialization(cx: &mut TestAppContext) {
+    let host = cx.new_model(|cx| Buffer::local("one two three", cx));
+    let state = host.read_with(cx, |host, _| host.to_proto());
+    host.update(cx, |host, cx| host.edit([(4..7, "TWO")], None, cx));
+    let snapshot = host.read_with(cx, |host, _| host.text_snapshot());
+    let map = AnchorRangeMultimap::from_anchor_ranges(
+        [
+            (snapshot.anchor_before(8)..snapshot.anchor_after(13), 2),
+            (snapshot.anchor_after(4)..snapshot.anchor_before(7), 1),
+            (snapshot.anchor_before(0)..snapshot.anchor_after(13), 0),
+        ],
+        &snapshot,
+    );
+    let message =
+        proto::serialize_anchor_range_multimap(&map, |value: &u32| value.to_be_bytes().to_vec());
+
+    // The guest hasn't seen the edit yet, so it waits for it before resolving the anchors.
+    let guest =
+        cx.new_model(|_| Buffer::from_proto(1, Capability::ReadWrite, state, None).unwrap());
+    let guest_map = cx.spawn({
+        let guest = guest.clone();
+        |mut cx| async move {
+            proto::deserialize_anchor_range_multimap(
+                message,
+                |value| Some(u32::from_be_bytes(value.try_into().ok()?)),
+                &guest,
+                &mut cx,
+            )
+            .await
+        }
+    });
+    cx.run_until_parked();
+    let ops = host
+        .update(cx, |host, cx| host.serialize_ops(None, cx))
+        .await;
+    guest.update(cx, |guest, cx| {
+        guest
+            .apply_ops(
+                ops.into_iter()
+                    .map(|op| proto::deserialize_operation(op).unwrap()),
+                cx,
+            )
+            .unwrap()
+    });
+    let guest_map = guest_map.await.unwrap();
+    let guest_snapshot = guest.read_with(cx, |guest, _| guest.text_snapshot());
+
+    let entries = |map: &AnchorRangeMultimap<u32>, snapshot: &text::BufferSnapshot| {
+        map.iter()
+            .map(|entry| {
+                (
+                    entry.range.clone(),
+                    entry.range.to_offset(snapshot),
+                    entry.value,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(
+        entries(&guest_map, &guest_snapshot),
+        entries(&map, &snapshot)
+    );
+
+    // The anchors' biases are preserved, so edits on either side affect them alike.
+    let edits = [(4..4, "<"), (7..7, ">"), (13..13, "!")];
+    host.update(cx, |host, cx| host.edit(edits.clone(), None, cx));
+    guest.update(cx, |guest, cx| guest.edit(edits, None, cx));
+    let snapshot = host.read_with(cx, |host, _| host.text_snapshot());
+    let guest_snapshot = guest.read_with(cx, |guest, _| guest.text_snapshot());
+    assert_eq!(
+        entries(&guest_map, &guest_snapshot)
+            .into_iter()
+            .map(|(_, range, value)| (range, value))
+            .collect::<Vec<_>>(),
+        [(0..16, 0), (5..8, 1), (10..16, 2)]
+    );
+    assert_eq!(
+        entries(&map, &snapshot)
+            .into_iter()
+            .map(|(_, range, value)| (range, value))
+            .collect::<Vec<_>>(),
+        [(0..16, 0), (5..8, 1), (10..16, 2)]
+    );
+}
+
 #[gpui::test(iterations = 100)]
 fn test_random_collaboration(cx: &mut AppContext, mut rng: StdRng) {
     let min_peers = env::var("MIN_PEERS")
@@ -2181,10 +5088,11 @@ fn test_random_collaboration(cx: &mut AppContext, mut rng: StdRng) {
             buffer.set_group_interval(Duration::from_millis(rng.gen_range(0..=200)));
             let network = network.clone();
             cx.subscribe(&cx.handle(), move |buffer, _, event, _| {
-                if let Event::Operation(op) = event {
-                    network
-                        .lock()
-                        .broadcast(buffer.replica_id(), vec![proto::serialize_operation(op)]);
+                if let Event::Operations(ops) = event {
+                    network.lock().broadcast(
+                        buffer.replica_id(),
+                        ops.iter().map(proto::serialize_operation).collect(),
+                    );
                 }
             })
             .detach();
@@ -2309,10 +5217,10 @@ fn test_random_collaboration(cx: &mut AppContext, mut rng: StdRng) {
                     new_buffer.set_group_interval(Duration::from_millis(rng.gen_range(0..=200)));
                     let network = network.clone();
                     cx.subscribe(&cx.handle(), move |buffer, _, event, _| {
-                        if let Event::Operation(op) = event {
+                        if let Event::Operations(ops) = event {
                             network.lock().broadcast(
                                 buffer.replica_id(),
-                                vec![proto::serialize_operation(op)],
+                                ops.iter().map(proto::serialize_operation).collect(),
                             );
                         }
                     })
@@ -2514,6 +5422,30 @@ fn ruby_lang() -> Language {
     .unwrap()
 }
 
+fn python_lang() -> Language {
+    Language::new(
+        LanguageConfig {
+            name: "Python".into(),
+            matcher: LanguageMatcher {
+                path_suffixes: vec!["py".to_string()],
+                ..Default::default()
+            },
+            line_comments: vec!["# ".into()],
+            indent_pastes_by_scope: true,
+            ..Default::default()
+        },
+        Some(tree_sitter_python::language()),
+    )
+    .with_indents_query(
+        r#"
+            (function_definition ":" @start body: (block) @indent)
+            (if_statement ":" @start consequence: (block) @indent)
+            (_ "(" ")" @end) @indent
+        "#,
+    )
+    .unwrap()
+}
+
 fn html_lang() -> Language {
     Language::new(
         LanguageConfig {
@@ -2653,6 +5585,7 @@ fn javascript_lang() -> Language {
         r#"
         ("{" @open "}" @close)
         ("(" @open ")" @close)
+        ("[" @open "]" @close)
         "#,
     )
     .unwrap()
@@ -2672,6 +5605,12 @@ fn get_tree_sexp(buffer: &Model<Buffer>, cx: &mut gpui::TestAppContext) -> Strin
     })
 }
 
+/// Returns the range of the first occurrence of `part` in `text`.
+fn range_of(text: &str, part: &str) -> Range<usize> {
+    let start = text.find(part).unwrap();
+    start..start + part.len()
+}
+
 // Assert that the enclosing bracket ranges around the selection match the pairs indicated by the marked text in `range_markers`
 fn assert_bracket_pairs(
     selection_text: &'static str,
@@ -1,12 +1,12 @@
 use crate::{range_to_lsp, Diagnostic};
-use collections::HashMap;
+use collections::{hash_map, HashMap};
 use lsp::LanguageServerId;
 use std::{
     cmp::{Ordering, Reverse},
     iter,
     ops::Range,
 };
-use sum_tree::{self, Bias, SumTree};
+use sum_tree::{self, Bias, SumTree, TreeMap};
 use text::{Anchor, FromAnchor, PointUtf16, ToOffset};
 
 /// A set of diagnostics associated with a given buffer, provided
@@ -18,6 +18,8 @@ use text::{Anchor, FromAnchor, PointUtf16, ToOffset};
 #[derive(Clone, Debug, Default)]
 pub struct DiagnosticSet {
     diagnostics: SumTree<DiagnosticEntry<Anchor>>,
+    /// The range spanned by each group's entries, keyed by group id.
+    group_ranges: TreeMap<usize, Range<Anchor>>,
 }
 
 /// A single diagnostic in a set. Generic over its range type, because
@@ -69,6 +71,7 @@ impl DiagnosticEntry<PointUtf16> {
             severity: Some(self.diagnostic.severity),
             source: self.diagnostic.source.clone(),
             message: self.diagnostic.message.clone(),
+            data: self.diagnostic.data.clone(),
             ..Default::default()
         }
     }
@@ -81,8 +84,29 @@ impl DiagnosticSet {
     where
         I: IntoIterator<Item = DiagnosticEntry<Anchor>>,
     {
+        let entries = iter.into_iter().collect::<Vec<_>>();
+        let mut group_ranges = HashMap::<usize, Range<Anchor>>::default();
+        for entry in &entries {
+            match group_ranges.entry(entry.diagnostic.group_id) {
+                // The entries are ordered by their start, so a group's first entry
+                // starts the group's range.
+                hash_map::Entry::Vacant(group_range) => {
+                    group_range.insert(entry.range.clone());
+                }
+                hash_map::Entry::Occupied(mut group_range) => {
+                    let group_range = group_range.get_mut();
+                    if entry.range.end.cmp(&group_range.end, buffer).is_gt() {
+                        group_range.end = entry.range.end;
+                    }
+                }
+            }
+        }
+        let mut group_ranges = group_ranges.into_iter().collect::<Vec<_>>();
+        group_ranges.sort_unstable_by_key(|(group_id, _)| *group_id);
+
         Self {
-            diagnostics: SumTree::from_iter(iter, buffer),
+            diagnostics: SumTree::from_iter(entries, buffer),
+            group_ranges: TreeMap::from_ordered_entries(group_ranges),
         }
     }
 
@@ -93,16 +117,14 @@ impl DiagnosticSet {
     {
         let mut entries = iter.into_iter().collect::<Vec<_>>();
         entries.sort_unstable_by_key(|entry| (entry.range.start, Reverse(entry.range.end)));
-        Self {
-            diagnostics: SumTree::from_iter(
-                entries.into_iter().map(|entry| DiagnosticEntry {
-                    range: buffer.anchor_before(entry.range.start)
-                        ..buffer.anchor_before(entry.range.end),
-                    diagnostic: entry.diagnostic,
-                }),
-                buffer,
-            ),
-        }
+        Self::from_sorted_entries(
+            entries.into_iter().map(|entry| DiagnosticEntry {
+                range: buffer.anchor_before(entry.range.start)
+                    ..buffer.anchor_before(entry.range.end),
+                diagnostic: entry.diagnostic,
+            }),
+            buffer,
+        )
     }
 
     /// Returns the number of diagnostics in the set.
@@ -110,6 +132,12 @@ impl DiagnosticSet {
         self.diagnostics.summary().count
     }
 
+    /// Returns the range spanned by all of the entries in the given group, if the
+    /// group is in this set.
+    pub fn group_range(&self, group_id: usize) -> Option<&Range<Anchor>> {
+        self.group_ranges.get(&group_id)
+    }
+
     /// Returns an iterator over the diagnostic entries in the set.
     pub fn iter(&self) -> impl Iterator<Item = &DiagnosticEntry<Anchor>> {
         self.diagnostics.iter()
@@ -6,6 +6,7 @@
 //! - Exposes [`LanguageConfig`] that describes how constructs (like brackets or line comments) should be handled by the editor for a source file of a particular language.
 //!
 //! Notably we do *not* assign a single language to a single file; in real world a single file can consist of multiple programming languages - HTML is a good example of that - and `language` crate tends to reflect that status quo in its API.
+mod anchor_range_multimap;
 mod buffer;
 mod diagnostic_set;
 mod highlight_map;
@@ -63,6 +64,7 @@ use theme::SyntaxTheme;
 use tree_sitter::{self, wasmtime, Query, QueryCursor, WasmStore};
 use util::serde::default_true;
 
+pub use anchor_range_multimap::{AnchorRangeMultimap, AnchorRangeMultimapEntry};
 pub use buffer::Operation;
 pub use buffer::*;
 pub use diagnostic_set::DiagnosticEntry;
@@ -73,7 +75,7 @@ pub use language_registry::{
 pub use lsp::LanguageServerId;
 pub use outline::{render_item, Outline, OutlineItem};
 pub use syntax_map::{OwnedSyntaxLayer, SyntaxLayer};
-pub use text::{AnchorRangeExt, LineEnding};
+pub use text::{AnchorRangeExt, Encoding, LineEnding};
 pub use tree_sitter::{Node, Parser, Tree, TreeCursor};
 
 /// Initializes the `language` crate.
@@ -611,6 +613,12 @@ pub struct LanguageConfig {
     /// How to soft-wrap long lines of text.
     #[serde(default)]
     pub soft_wrap: Option<SoftWrap>,
+    /// If set to true, pasted blocks of text are reindented to match the lines of
+    /// the indentation block they're pasted into, as determined by the indents
+    /// query, rather than by the suggested indentation of their first line. This
+    /// suits languages whose blocks are delimited by indentation, like Python.
+    #[serde(default)]
+    pub indent_pastes_by_scope: bool,
     /// The name of a Prettier parser that will be used for this language when no file path is available.
     /// If there's a parser name in the language settings, that will be used instead.
     #[serde(default)]
@@ -700,6 +708,7 @@ impl Default for LanguageConfig {
             hard_tabs: None,
             tab_size: None,
             soft_wrap: None,
+            indent_pastes_by_scope: false,
             prettier_parser_name: None,
         }
     }
@@ -740,6 +749,7 @@ where
 pub struct FakeLspAdapter {
     pub name: &'static str,
     pub initialization_options: Option<Value>,
+    pub workspace_configuration: Option<Value>,
     pub capabilities: lsp::ServerCapabilities,
     pub initializer: Option<Box<dyn 'static + Send + Sync + Fn(&mut lsp::FakeLanguageServer)>>,
     pub disk_based_diagnostics_progress_token: Option<String>,
@@ -1464,6 +1474,15 @@ impl LanguageScope {
         }
     }
 
+    /// Returns the name of the innermost scope from the language's overrides query
+    /// that contains this scope's position, such as `string` or `comment`.
+    pub fn override_name(&self) -> Option<&str> {
+        let id = self.override_id?;
+        let grammar = self.language.grammar.as_ref()?;
+        let override_config = grammar.override_config.as_ref()?;
+        override_config.values.get(&id).map(|e| e.0.as_str())
+    }
+
     fn config_override(&self) -> Option<&LanguageConfigOverride> {
         let id = self.override_id?;
         let grammar = self.language.grammar.as_ref()?;
@@ -1591,6 +1610,7 @@ impl Default for FakeLspAdapter {
             initializer: None,
             disk_based_diagnostics_progress_token: None,
             initialization_options: None,
+            workspace_configuration: None,
             disk_based_diagnostics_sources: Vec::new(),
             prettier_plugins: Vec::new(),
             language_server_binary: LanguageServerBinary {
@@ -1665,6 +1685,17 @@ impl LspAdapter for FakeLspAdapter {
         Ok(self.initialization_options.clone())
     }
 
+    async fn workspace_configuration(
+        self: Arc<Self>,
+        _: &Arc<dyn LspAdapterDelegate>,
+        _: &mut AsyncAppContext,
+    ) -> Result<Value> {
+        Ok(self
+            .workspace_configuration
+            .clone()
+            .unwrap_or_else(|| serde_json::json!({})))
+    }
+
     fn as_fake(&self) -> Option<&FakeLspAdapter> {
         Some(self)
     }
@@ -46,6 +46,7 @@ struct LanguageRegistryState {
     language_settings: AllLanguageSettingsContent,
     available_languages: Vec<AvailableLanguage>,
     grammars: HashMap<Arc<str>, AvailableGrammar>,
+    highlights_query_overrides: HashMap<Arc<str>, Arc<str>>,
     lsp_adapters: HashMap<Arc<str>, Vec<Arc<CachedLspAdapter>>>,
     available_lsp_adapters:
         HashMap<LanguageServerName, Arc<dyn Fn() -> Arc<CachedLspAdapter> + 'static + Send + Sync>>,
@@ -155,6 +156,7 @@ impl LanguageRegistry {
                 languages: Vec::new(),
                 available_languages: Vec::new(),
                 grammars: Default::default(),
+                highlights_query_overrides: Default::default(),
                 language_settings: Default::default(),
                 loading_languages: Default::default(),
                 lsp_adapters: Default::default(),
@@ -188,6 +190,17 @@ impl LanguageRegistry {
         self.state.write().reload();
     }
 
+    /// Replaces the highlights query of the given language with a user-provided one.
+    ///
+    /// The language is reloaded so that open buffers pick up the new query. If the
+    /// override doesn't compile against the language's grammar, the built-in query
+    /// is used instead.
+    pub fn set_highlights_query_override(&self, language_name: &str, source: &str) {
+        self.state
+            .write()
+            .set_highlights_query_override(language_name.into(), source.into());
+    }
+
     /// Removes the specified languages and grammars from the registry.
     pub fn remove_languages(
         &self,
@@ -440,6 +453,7 @@ impl LanguageRegistry {
         for language in &state.languages {
             language.set_theme(theme.syntax());
         }
+        crate::buffer::set_fallback_highlight_theme(theme.syntax());
     }
 
     pub fn set_language_server_download_dir(&mut self, path: impl Into<Arc<Path>>) {
@@ -592,11 +606,27 @@ impl LanguageRegistry {
                         let id = language.id;
                         let name = language.name.clone();
                         let language = async {
-                            let (config, queries, provider) = (language.load)()?;
+                            let (config, mut queries, provider) = (language.load)()?;
 
                             if let Some(grammar) = config.grammar.clone() {
-                                let grammar = Some(this.get_or_load_grammar(grammar).await?);
-                                Language::new_with_id(id, config, grammar)
+                                let grammar = this.get_or_load_grammar(grammar).await?;
+                                let highlights_override = this
+                                    .state
+                                    .read()
+                                    .highlights_query_overrides
+                                    .get(&name)
+                                    .cloned();
+                                if let Some(source) = highlights_override {
+                                    match tree_sitter::Query::new(&grammar, &source) {
+                                        Ok(_) => {
+                                            queries.highlights = Some(source.to_string().into())
+                                        }
+                                        Err(error) => log::error!(
+                                            "invalid highlights query override for {name}: {error}"
+                                        ),
+                                    }
+                                }
+                                Language::new_with_id(id, config, Some(grammar))
                                     .with_context_provider(provider)
                                     .with_queries(queries)
                             } else {
@@ -920,6 +950,21 @@ impl LanguageRegistryState {
         *self.subscription.0.borrow_mut() = ();
     }
 
+    fn set_highlights_query_override(&mut self, language_name: Arc<str>, source: Arc<str>) {
+        self.languages
+            .retain(|language| language.name() != language_name);
+        for language in &mut self.available_languages {
+            if language.name == language_name {
+                language.loaded = false;
+            }
+        }
+        self.highlights_query_overrides
+            .insert(language_name, source);
+        self.version += 1;
+        self.reload_count += 1;
+        *self.subscription.0.borrow_mut() = ();
+    }
+
     fn remove_languages(
         &mut self,
         languages_to_remove: &[Arc<str>],
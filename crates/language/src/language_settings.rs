@@ -85,6 +85,9 @@ pub struct LanguageSettings {
     /// Whether or not to remove any trailing whitespace from lines of a buffer
     /// before saving it.
     pub remove_trailing_whitespace_on_save: bool,
+    /// Whether or not to keep trailing whitespace on the lines containing
+    /// cursors when removing it before saving.
+    pub preserve_trailing_whitespace_on_cursor_lines: bool,
     /// Whether or not to ensure there's a single newline at the end of a buffer
     /// when saving it.
     pub ensure_final_newline_on_save: bool,
@@ -120,6 +123,9 @@ pub struct LanguageSettings {
     pub code_actions_on_format: HashMap<String, bool>,
     /// Whether to perform linked edits
     pub linked_edits: bool,
+    /// Characters that trigger completions in addition to those advertised
+    /// by language servers.
+    pub completion_triggers: Vec<String>,
     /// Task configuration for this language.
     pub tasks: LanguageTaskConfig,
 }
@@ -264,6 +270,12 @@ pub struct LanguageSettingsContent {
     /// Default: true
     #[serde(default)]
     pub remove_trailing_whitespace_on_save: Option<bool>,
+    /// Whether or not to keep trailing whitespace on the lines containing
+    /// cursors when removing it before saving.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub preserve_trailing_whitespace_on_cursor_lines: Option<bool>,
     /// Whether or not to ensure there's a single newline at the end of a buffer
     /// when saving it.
     ///
@@ -342,6 +354,11 @@ pub struct LanguageSettingsContent {
     ///
     /// Default: true
     pub linked_edits: Option<bool>,
+    /// Additional characters that should trigger completions, alongside
+    /// the ones advertised by the language server.
+    ///
+    /// Default: []
+    pub completion_triggers: Option<Vec<String>>,
     /// Task configuration for this language.
     ///
     /// Default: {}
@@ -836,6 +853,10 @@ fn merge_settings(settings: &mut LanguageSettings, src: &LanguageSettingsContent
         src.code_actions_on_format.clone(),
     );
     merge(&mut settings.linked_edits, src.linked_edits);
+    merge(
+        &mut settings.completion_triggers,
+        src.completion_triggers.clone(),
+    );
     merge(&mut settings.tasks, src.tasks.clone());
 
     merge(
@@ -849,6 +870,10 @@ fn merge_settings(settings: &mut LanguageSettings, src: &LanguageSettingsContent
         &mut settings.remove_trailing_whitespace_on_save,
         src.remove_trailing_whitespace_on_save,
     );
+    merge(
+        &mut settings.preserve_trailing_whitespace_on_cursor_lines,
+        src.preserve_trailing_whitespace_on_cursor_lines,
+    );
     merge(
         &mut settings.ensure_final_newline_on_save,
         src.ensure_final_newline_on_save,
@@ -1,8 +1,11 @@
 //! Handles conversions of `language` items to and from the [`rpc`] protocol.
 
-use crate::{diagnostic_set::DiagnosticEntry, CursorShape, Diagnostic};
+use crate::{
+    diagnostic_set::DiagnosticEntry, AnchorRangeMultimap, Buffer, CursorShape, Diagnostic,
+};
 use anyhow::{anyhow, Result};
 use clock::ReplicaId;
+use gpui::{AsyncAppContext, Model};
 use lsp::{DiagnosticSeverity, LanguageServerId};
 use rpc::proto;
 use std::{ops::Range, sync::Arc};
@@ -85,6 +88,18 @@ pub fn serialize_operation(operation: &crate::Operation) -> proto::Operation {
                     triggers: triggers.clone(),
                 },
             ),
+
+            crate::Operation::UpdateLineEndingOverride {
+                line_ending,
+                lamport_timestamp,
+            } => proto::operation::Variant::UpdateLineEndingOverride(
+                proto::operation::UpdateLineEndingOverride {
+                    replica_id: lamport_timestamp.replica_id as u32,
+                    lamport_timestamp: lamport_timestamp.value,
+                    line_ending: line_ending
+                        .map(|line_ending| serialize_line_ending(line_ending) as i32),
+                },
+            ),
         }),
     }
 }
@@ -213,10 +228,29 @@ pub fn serialize_diagnostics<'a>(
             code: entry.diagnostic.code.clone(),
             is_disk_based: entry.diagnostic.is_disk_based,
             is_unnecessary: entry.diagnostic.is_unnecessary,
+            is_deprecated: entry.diagnostic.is_deprecated,
         })
         .collect()
 }
 
+/// Serializes an [`AnchorRangeMultimap`] to be sent over RPC, using the given
+/// function to serialize its values.
+pub fn serialize_anchor_range_multimap<T: Clone>(
+    map: &AnchorRangeMultimap<T>,
+    mut serialize_value: impl FnMut(&T) -> Vec<u8>,
+) -> proto::AnchorRangeMultimap {
+    proto::AnchorRangeMultimap {
+        entries: map
+            .iter()
+            .map(|entry| proto::anchor_range_multimap::Entry {
+                start: Some(serialize_anchor(&entry.range.start)),
+                end: Some(serialize_anchor(&entry.range.end)),
+                value: serialize_value(&entry.value),
+            })
+            .collect(),
+    }
+}
+
 /// Serializes an [`Anchor`] to be sent over RPC.
 pub fn serialize_anchor(anchor: &Anchor) -> proto::Anchor {
     proto::Anchor {
@@ -311,6 +345,22 @@ pub fn deserialize_operation(message: proto::Operation) -> Result<crate::Operati
                     },
                 }
             }
+            proto::operation::Variant::UpdateLineEndingOverride(message) => {
+                crate::Operation::UpdateLineEndingOverride {
+                    line_ending: message
+                        .line_ending
+                        .map(|line_ending| {
+                            proto::LineEnding::from_i32(line_ending)
+                                .map(deserialize_line_ending)
+                                .ok_or_else(|| anyhow!("invalid line ending"))
+                        })
+                        .transpose()?,
+                    lamport_timestamp: clock::Lamport {
+                        replica_id: message.replica_id as ReplicaId,
+                        value: message.lamport_timestamp,
+                    },
+                }
+            }
         },
     )
 }
@@ -398,12 +448,46 @@ pub fn deserialize_diagnostics(
                     is_primary: diagnostic.is_primary,
                     is_disk_based: diagnostic.is_disk_based,
                     is_unnecessary: diagnostic.is_unnecessary,
+                    is_deprecated: diagnostic.is_deprecated,
+                    data: None,
                 },
             })
         })
         .collect()
 }
 
+/// Deserializes an [`AnchorRangeMultimap`] from the RPC representation, skipping
+/// entries whose anchors or values are invalid.
+///
+/// Entries are ordered by resolving their anchors, so this waits for the given
+/// buffer to receive the operations that the anchors refer to.
+pub async fn deserialize_anchor_range_multimap<T: Clone>(
+    message: proto::AnchorRangeMultimap,
+    mut deserialize_value: impl FnMut(Vec<u8>) -> Option<T>,
+    buffer: &Model<Buffer>,
+    cx: &mut AsyncAppContext,
+) -> Result<AnchorRangeMultimap<T>> {
+    let entries = message
+        .entries
+        .into_iter()
+        .filter_map(|entry| {
+            let range = deserialize_anchor(entry.start?)?..deserialize_anchor(entry.end?)?;
+            Some((range, deserialize_value(entry.value)?))
+        })
+        .collect::<Vec<_>>();
+    buffer
+        .update(cx, |buffer, _| {
+            buffer.wait_for_anchors(
+                entries
+                    .iter()
+                    .flat_map(|(range, _)| [range.start, range.end]),
+            )
+        })?
+        .await?;
+    let snapshot = buffer.update(cx, |buffer, _| buffer.text_snapshot())?;
+    Ok(AnchorRangeMultimap::from_anchor_ranges(entries, &snapshot))
+}
+
 /// Deserializes an [`Anchor`] from the RPC representation.
 pub fn deserialize_anchor(anchor: proto::Anchor) -> Option<Anchor> {
     let buffer_id = if let Some(id) = anchor.buffer_id {
@@ -450,6 +534,10 @@ pub fn lamport_timestamp_for_operation(operation: &proto::Operation) -> Option<c
             replica_id = op.replica_id;
             value = op.lamport_timestamp;
         }
+        proto::operation::Variant::UpdateLineEndingOverride(op) => {
+            replica_id = op.replica_id;
+            value = op.lamport_timestamp;
+        }
     }
 
     Some(clock::Lamport {
@@ -67,6 +67,9 @@ struct SyntaxMapCapturesLayer<'a> {
     captures: QueryCaptures<'a, 'a, TextProvider<'a>, &'a [u8]>,
     next_capture: Option<QueryCapture<'a>>,
     grammar_index: usize,
+    node: Node<'a>,
+    query: &'a Query,
+    text: &'a Rope,
     _query_cursor: QueryCursorHandle,
 }
 
@@ -211,6 +214,12 @@ struct ByteChunks<'a>(text::Chunks<'a>);
 
 pub(crate) struct QueryCursorHandle(Option<QueryCursor>);
 
+#[cfg(test)]
+thread_local! {
+    /// The number of query cursors that have been checked out on this thread.
+    pub(crate) static QUERY_CURSOR_HANDLE_COUNT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 impl SyntaxMap {
     pub fn new() -> Self {
         Self::default()
@@ -857,6 +866,16 @@ impl SyntaxSnapshot {
     pub fn language_registry_version(&self) -> usize {
         self.language_registry_version
     }
+
+    /// The version of the text that the syntax trees were last parsed at.
+    pub fn parsed_version(&self) -> &clock::Global {
+        &self.parsed_version
+    }
+
+    /// The version of the text that the syntax trees have been edited to match.
+    pub fn interpolated_version(&self) -> &clock::Global {
+        &self.interpolated_version
+    }
 }
 
 impl<'a> SyntaxMapCaptures<'a> {
@@ -881,17 +900,6 @@ impl<'a> SyntaxMapCaptures<'a> {
                 None => continue,
             };
 
-            let mut query_cursor = QueryCursorHandle::new();
-
-            // TODO - add a Tree-sitter API to remove the need for this.
-            let cursor = unsafe {
-                std::mem::transmute::<&mut tree_sitter::QueryCursor, &'static mut QueryCursor>(
-                    query_cursor.deref_mut(),
-                )
-            };
-
-            cursor.set_byte_range(range.clone());
-            let captures = cursor.captures(query, layer.node(), TextProvider(text));
             let grammar_index = result
                 .grammars
                 .iter()
@@ -900,15 +908,15 @@ impl<'a> SyntaxMapCaptures<'a> {
                     result.grammars.push(grammar);
                     result.grammars.len() - 1
                 });
-            let mut layer = SyntaxMapCapturesLayer {
-                depth: layer.depth,
+            let layer = SyntaxMapCapturesLayer::new(
+                layer.depth,
                 grammar_index,
-                next_capture: None,
-                captures,
-                _query_cursor: query_cursor,
-            };
+                layer.node(),
+                query,
+                text,
+                range.clone(),
+            );
 
-            layer.advance();
             if layer.next_capture.is_some() {
                 let key = layer.sort_key();
                 let ix = match result.layers[..result.active_layer_count]
@@ -980,6 +988,30 @@ impl<'a> SyntaxMapCaptures<'a> {
             .position(|layer| layer.next_capture.is_none())
             .unwrap_or(self.layers.len());
     }
+
+    /// Restarts the captures from the beginning of the given range.
+    ///
+    /// Unlike [`Self::set_byte_range`], which can only skip ahead, this yields
+    /// every capture that intersects the range again, including any that were
+    /// already returned.
+    pub fn restart(&mut self, range: Range<usize>) {
+        for layer in &mut self.layers {
+            *layer = SyntaxMapCapturesLayer::new(
+                layer.depth,
+                layer.grammar_index,
+                layer.node,
+                layer.query,
+                layer.text,
+                range.clone(),
+            );
+        }
+        self.layers.sort_unstable_by_key(|layer| layer.sort_key());
+        self.active_layer_count = self
+            .layers
+            .iter()
+            .position(|layer| layer.next_capture.is_none())
+            .unwrap_or(self.layers.len());
+    }
 }
 
 impl<'a> SyntaxMapMatches<'a> {
@@ -1092,6 +1124,39 @@ impl<'a> SyntaxMapMatches<'a> {
 }
 
 impl<'a> SyntaxMapCapturesLayer<'a> {
+    fn new(
+        depth: usize,
+        grammar_index: usize,
+        node: Node<'a>,
+        query: &'a Query,
+        text: &'a Rope,
+        range: Range<usize>,
+    ) -> Self {
+        let mut query_cursor = QueryCursorHandle::new();
+
+        // TODO - add a Tree-sitter API to remove the need for this.
+        let cursor = unsafe {
+            std::mem::transmute::<&mut tree_sitter::QueryCursor, &'static mut QueryCursor>(
+                query_cursor.deref_mut(),
+            )
+        };
+
+        cursor.set_byte_range(range);
+        let captures = cursor.captures(query, node, TextProvider(text));
+        let mut layer = Self {
+            depth,
+            grammar_index,
+            next_capture: None,
+            captures,
+            node,
+            query,
+            text,
+            _query_cursor: query_cursor,
+        };
+        layer.advance();
+        layer
+    }
+
     fn advance(&mut self) {
         self.next_capture = self.captures.next().map(|(mat, ix)| mat.captures[ix]);
     }
@@ -1744,6 +1809,8 @@ impl<'a> Iterator for ByteChunks<'a> {
 
 impl QueryCursorHandle {
     pub fn new() -> Self {
+        #[cfg(test)]
+        QUERY_CURSOR_HANDLE_COUNT.with(|count| count.set(count.get() + 1));
         let mut cursor = QUERY_CURSORS.lock().pop().unwrap_or_else(QueryCursor::new);
         cursor.set_match_limit(64);
         QueryCursorHandle(Some(cursor))
@@ -278,9 +278,10 @@ fn python_module_name_from_relative_path(relative_path: &str) -> String {
 #[cfg(test)]
 mod tests {
     use gpui::{BorrowAppContext, Context, ModelContext, TestAppContext};
-    use language::{language_settings::AllLanguageSettings, AutoindentMode, Buffer};
+    use language::{language_settings::AllLanguageSettings, AutoindentMode, Buffer, Point};
     use settings::SettingsStore;
     use std::num::NonZeroU32;
+    use unindent::Unindent as _;
 
     #[gpui::test]
     async fn test_python_autoindent(cx: &mut TestAppContext) {
@@ -391,4 +392,82 @@ mod tests {
             buffer
         });
     }
+
+    #[gpui::test]
+    async fn test_python_indentation_scope(cx: &mut TestAppContext) {
+        let language = crate::language("python", tree_sitter_python::language());
+        cx.update(|cx| {
+            let test_settings = SettingsStore::test(cx);
+            cx.set_global(test_settings);
+            language::init(cx);
+        });
+
+        let text = r#"
+            class A:
+                def f(self, x):
+                    for y in x:
+                        pass
+                    try:
+                        return y
+                    except:
+                        return None
+
+            a = A()
+        "#
+        .unindent();
+        let buffer = cx.new_model(|cx| Buffer::local(text.clone(), cx).with_language(language, cx));
+        cx.executor().run_until_parked();
+        let snapshot = buffer.read_with(cx, |buffer, _| buffer.snapshot());
+        let scope_text = |part: &str| {
+            snapshot
+                .indentation_scope_at(text.find(part).unwrap())
+                .map(|range| snapshot.text_for_range(range).collect::<String>())
+        };
+
+        assert_eq!(
+            scope_text("def f"),
+            Some(text[text.find("\n    def").unwrap()..text.find("\n\n").unwrap()].into())
+        );
+        assert_eq!(
+            scope_text("for y"),
+            Some(text[text.find("\n        for").unwrap()..text.find("\n\n").unwrap()].into())
+        );
+        assert_eq!(scope_text("pass"), Some("\n            pass".into()));
+        assert_eq!(
+            scope_text("return None"),
+            Some("\n            return None".into())
+        );
+        assert_eq!(scope_text("a = A()"), None);
+    }
+
+    #[gpui::test]
+    async fn test_python_paste_by_indentation_scope(cx: &mut TestAppContext) {
+        cx.executor().set_block_on_ticks(usize::MAX..=usize::MAX);
+        let language = crate::language("python", tree_sitter_python::language());
+        cx.update(|cx| {
+            let test_settings = SettingsStore::test(cx);
+            cx.set_global(test_settings);
+            language::init(cx);
+        });
+
+        cx.new_model(|cx| {
+            let text = "class A:\n    def f(self):\n        x = 1\n\n        return x\n";
+            let mut buffer = Buffer::local(text, cx).with_language(language, cx);
+
+            // A block copied from the top level is indented like the lines of the
+            // method it's pasted into.
+            buffer.edit(
+                [(Point::new(3, 0)..Point::new(3, 0), "if x:\n    x += 1\n")],
+                Some(AutoindentMode::Block {
+                    original_indent_columns: vec![0],
+                }),
+                cx,
+            );
+            assert_eq!(
+                buffer.text(),
+                "class A:\n    def f(self):\n        x = 1\n        if x:\n            x += 1\n\n        return x\n"
+            );
+            buffer
+        });
+    }
 }
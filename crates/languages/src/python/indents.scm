@@ -1,3 +1,15 @@
 (_ "[" "]" @end) @indent
 (_ "{" "}" @end) @indent
 (_ "(" ")" @end) @indent
+
+(function_definition ":" @start body: (block) @indent)
+(class_definition ":" @start body: (block) @indent)
+(if_statement ":" @start consequence: (block) @indent)
+(elif_clause ":" @start consequence: (block) @indent)
+(else_clause ":" @start body: (block) @indent)
+(for_statement ":" @start body: (block) @indent)
+(while_statement ":" @start body: (block) @indent)
+(try_statement ":" @start body: (block) @indent)
+(except_clause ":" @start (block) @indent)
+(finally_clause ":" @start (block) @indent)
+(with_statement ":" @start body: (block) @indent)
@@ -0,0 +1 @@
+(block_mapping_pair ":" @start value: (block_node) @indent)
@@ -99,6 +99,7 @@ pub enum Event {
     Reparsed(BufferId),
     Saved,
     FileHandleChanged,
+    Conflict,
     Closed,
     DirtyChanged,
     DiagnosticsUpdated,
@@ -1626,12 +1627,14 @@ impl MultiBuffer {
             language::Event::DirtyChanged => Event::DirtyChanged,
             language::Event::Saved => Event::Saved,
             language::Event::FileHandleChanged => Event::FileHandleChanged,
+            language::Event::Conflict => Event::Conflict,
             language::Event::Reloaded => Event::Reloaded,
             language::Event::DiffBaseChanged => Event::DiffBaseChanged,
             language::Event::DiffUpdated => Event::DiffUpdated { buffer },
             language::Event::LanguageChanged => Event::LanguageChanged(buffer.read(cx).remote_id()),
             language::Event::Reparsed => Event::Reparsed(buffer.read(cx).remote_id()),
-            language::Event::DiagnosticsUpdated => Event::DiagnosticsUpdated,
+            language::Event::DiagnosticsUpdated { .. }
+            | language::Event::ActiveDiagnosticGroupChanged => Event::DiagnosticsUpdated,
             language::Event::Closed => Event::Closed,
             language::Event::CapabilityChanged => {
                 self.capability = buffer.read(cx).capability();
@@ -1639,7 +1642,7 @@ impl MultiBuffer {
             }
 
             //
-            language::Event::Operation(_) => return,
+            language::Event::Operations(_) | language::Event::MetadataChanged => return,
         });
     }
 
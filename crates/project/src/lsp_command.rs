@@ -1,7 +1,8 @@
 use crate::{
-    CodeAction, CoreCompletion, DocumentHighlight, Hover, HoverBlock, HoverBlockKind, InlayHint,
-    InlayHintLabel, InlayHintLabelPart, InlayHintLabelPartTooltip, InlayHintTooltip, Location,
-    LocationLink, MarkupContent, Project, ProjectTransaction, ResolveState,
+    CodeAction, Color, ColorPresentation, CoreCompletion, DocumentHighlight, Hover, HoverBlock,
+    HoverBlockKind, InlayHint, InlayHintLabel, InlayHintLabelPart, InlayHintLabelPartTooltip,
+    InlayHintTooltip, Location, LocationLink, MarkupContent, Project, ProjectTransaction,
+    ResolveState,
 };
 use anyhow::{anyhow, Context, Result};
 use async_trait::async_trait;
@@ -21,7 +22,7 @@ use lsp::{
     OneOf, ServerCapabilities,
 };
 use std::{cmp::Reverse, ops::Range, path::Path, sync::Arc};
-use text::{BufferId, LineEnding};
+use text::{BufferId, LineEnding, Patch};
 
 pub fn lsp_formatting_options(tab_size: u32) -> lsp::FormattingOptions {
     lsp::FormattingOptions {
@@ -103,14 +104,20 @@ pub(crate) struct PerformRename {
 
 pub struct GetDefinition {
     pub position: PointUtf16,
+    /// The version of the buffer that `position` refers to.
+    pub version: clock::Global,
 }
 
 pub(crate) struct GetTypeDefinition {
     pub position: PointUtf16,
+    /// The version of the buffer that `position` refers to.
+    pub version: clock::Global,
 }
 
 pub(crate) struct GetImplementation {
     pub position: PointUtf16,
+    /// The version of the buffer that `position` refers to.
+    pub version: clock::Global,
 }
 
 pub(crate) struct GetReferences {
@@ -124,11 +131,17 @@ pub(crate) struct GetDocumentHighlights {
 #[derive(Clone)]
 pub(crate) struct GetHover {
     pub position: PointUtf16,
+    /// The version of the buffer that `position` refers to.
+    pub version: clock::Global,
 }
 
 pub(crate) struct GetCompletions {
     pub position: PointUtf16,
     pub context: CompletionContext,
+    /// The version of the buffer that `position` refers to. The language server's
+    /// response is relative to this version, so it is translated through any edits
+    /// made to the buffer while the request was in flight.
+    pub version: clock::Global,
 }
 
 #[derive(Clone)]
@@ -164,6 +177,13 @@ pub(crate) struct LinkedEditingRange {
     pub position: Anchor,
 }
 
+pub(crate) struct GetDocumentColors;
+
+pub(crate) struct GetColorPresentations {
+    pub range: Range<Anchor>,
+    pub color: Color,
+}
+
 #[async_trait(?Send)]
 impl LspCommand for PrepareRename {
     type Response = Option<Range<Anchor>>;
@@ -447,7 +467,7 @@ impl LspCommand for GetDefinition {
         server_id: LanguageServerId,
         cx: AsyncAppContext,
     ) -> Result<Vec<LocationLink>> {
-        location_links_from_lsp(message, project, buffer, server_id, cx).await
+        location_links_from_lsp(message, &self.version, project, buffer, server_id, cx).await
     }
 
     fn to_proto(&self, project_id: u64, buffer: &Buffer) -> proto::GetDefinition {
@@ -476,8 +496,9 @@ impl LspCommand for GetDefinition {
                 buffer.wait_for_version(deserialize_version(&message.version))
             })?
             .await?;
-        Ok(Self {
-            position: buffer.update(&mut cx, |buffer, _| position.to_point_utf16(buffer))?,
+        buffer.update(&mut cx, |buffer, _| Self {
+            position: position.to_point_utf16(buffer),
+            version: buffer.version(),
         })
     }
 
@@ -540,7 +561,7 @@ impl LspCommand for GetImplementation {
         server_id: LanguageServerId,
         cx: AsyncAppContext,
     ) -> Result<Vec<LocationLink>> {
-        location_links_from_lsp(message, project, buffer, server_id, cx).await
+        location_links_from_lsp(message, &self.version, project, buffer, server_id, cx).await
     }
 
     fn to_proto(&self, project_id: u64, buffer: &Buffer) -> proto::GetImplementation {
@@ -569,8 +590,9 @@ impl LspCommand for GetImplementation {
                 buffer.wait_for_version(deserialize_version(&message.version))
             })?
             .await?;
-        Ok(Self {
-            position: buffer.update(&mut cx, |buffer, _| position.to_point_utf16(buffer))?,
+        buffer.update(&mut cx, |buffer, _| Self {
+            position: position.to_point_utf16(buffer),
+            version: buffer.version(),
         })
     }
 
@@ -641,7 +663,7 @@ impl LspCommand for GetTypeDefinition {
         server_id: LanguageServerId,
         cx: AsyncAppContext,
     ) -> Result<Vec<LocationLink>> {
-        location_links_from_lsp(message, project, buffer, server_id, cx).await
+        location_links_from_lsp(message, &self.version, project, buffer, server_id, cx).await
     }
 
     fn to_proto(&self, project_id: u64, buffer: &Buffer) -> proto::GetTypeDefinition {
@@ -670,8 +692,9 @@ impl LspCommand for GetTypeDefinition {
                 buffer.wait_for_version(deserialize_version(&message.version))
             })?
             .await?;
-        Ok(Self {
-            position: buffer.update(&mut cx, |buffer, _| position.to_point_utf16(buffer))?,
+        buffer.update(&mut cx, |buffer, _| Self {
+            position: position.to_point_utf16(buffer),
+            version: buffer.version(),
         })
     }
 
@@ -782,6 +805,7 @@ async fn location_links_from_proto(
 
 async fn location_links_from_lsp(
     message: Option<lsp::GotoDefinitionResponse>,
+    version: &clock::Global,
     project: Model<Project>,
     buffer: Model<Buffer>,
     server_id: LanguageServerId,
@@ -829,7 +853,10 @@ async fn location_links_from_lsp(
             .await?;
 
         cx.update(|cx| {
+            // Ranges in the requesting buffer refer to the version at the time of the request.
+            let edits_since_request = edits_since_request(buffer.read(cx), version);
             let origin_location = origin_range.map(|origin_range| {
+                let origin_range = translate_lsp_range(origin_range, &edits_since_request);
                 let origin_buffer = buffer.read(cx);
                 let origin_start =
                     origin_buffer.clip_point_utf16(point_from_lsp(origin_range.start), Bias::Left);
@@ -842,6 +869,11 @@ async fn location_links_from_lsp(
                 }
             });
 
+            let target_range = if target_buffer_handle == buffer {
+                translate_lsp_range(target_range, &edits_since_request)
+            } else {
+                target_range
+            };
             let target_buffer = target_buffer_handle.read(cx);
             let target_start =
                 target_buffer.clip_point_utf16(point_from_lsp(target_range.start), Bias::Left);
@@ -1265,6 +1297,8 @@ impl LspCommand for GetHover {
             (
                 buffer.language().cloned(),
                 hover.range.map(|range| {
+                    let range =
+                        translate_lsp_range(range, &edits_since_request(buffer, &self.version));
                     let token_start =
                         buffer.clip_point_utf16(point_from_lsp(range.start), Bias::Left);
                     let token_end = buffer.clip_point_utf16(point_from_lsp(range.end), Bias::Left);
@@ -1346,8 +1380,9 @@ impl LspCommand for GetHover {
                 buffer.wait_for_version(deserialize_version(&message.version))
             })?
             .await?;
-        Ok(Self {
-            position: buffer.update(&mut cx, |buffer, _| position.to_point_utf16(buffer))?,
+        buffer.update(&mut cx, |buffer, _| Self {
+            position: position.to_point_utf16(buffer),
+            version: buffer.version(),
         })
     }
 
@@ -1531,7 +1566,9 @@ impl LspCommand for GetCompletions {
         let mut completion_edits = Vec::new();
         buffer.update(&mut cx, |buffer, _cx| {
             let snapshot = buffer.snapshot();
-            let clipped_position = buffer.clip_point_utf16(Unclipped(self.position), Bias::Left);
+            let edits_since_request = edits_since_request(&snapshot, &self.version);
+            let position = edits_since_request.old_to_new(self.position);
+            let clipped_position = buffer.clip_point_utf16(Unclipped(position), Bias::Left);
 
             let mut range_for_token = None;
             completions.retain_mut(|lsp_completion| {
@@ -1539,7 +1576,11 @@ impl LspCommand for GetCompletions {
                     // If the language server provides a range to overwrite, then
                     // check that the range is valid.
                     Some(completion_text_edit) => {
-                        match parse_completion_text_edit(completion_text_edit, &snapshot) {
+                        let completion_text_edit = translate_completion_text_edit(
+                            completion_text_edit,
+                            &edits_since_request,
+                        );
+                        match parse_completion_text_edit(&completion_text_edit, &snapshot) {
                             Some(edit) => edit,
                             None => return false,
                         }
@@ -1548,7 +1589,7 @@ impl LspCommand for GetCompletions {
                     // If the language server does not provide a range, then infer
                     // the range based on the syntax tree.
                     None => {
-                        if self.position != clipped_position {
+                        if position != clipped_position {
                             log::info!("completion out of expected range");
                             return false;
                         }
@@ -1563,7 +1604,8 @@ impl LspCommand for GetCompletions {
                             });
 
                         let range = if let Some(range) = default_edit_range {
-                            let range = range_from_lsp(*range);
+                            let range =
+                                range_from_lsp(translate_lsp_range(*range, &edits_since_request));
                             let start = snapshot.clip_point_utf16(range.start, Bias::Left);
                             let end = snapshot.clip_point_utf16(range.end, Bias::Left);
                             if start != range.start.0 || end != range.end.0 {
@@ -1575,7 +1617,7 @@ impl LspCommand for GetCompletions {
                         } else {
                             range_for_token
                                 .get_or_insert_with(|| {
-                                    let offset = self.position.to_offset(&snapshot);
+                                    let offset = position.to_offset(&snapshot);
                                     let (range, kind) = snapshot.surrounding_word(offset);
                                     let range = if kind == Some(CharKind::Word) {
                                         range
@@ -1642,12 +1684,14 @@ impl LspCommand for GetCompletions {
         buffer
             .update(&mut cx, |buffer, _| buffer.wait_for_version(version))?
             .await?;
-        let position = message
+        let (position, version) = message
             .position
             .and_then(language::proto::deserialize_anchor)
             .map(|p| {
                 buffer.update(&mut cx, |buffer, _| {
-                    buffer.clip_point_utf16(Unclipped(p.to_point_utf16(buffer)), Bias::Left)
+                    let position =
+                        buffer.clip_point_utf16(Unclipped(p.to_point_utf16(buffer)), Bias::Left);
+                    (position, buffer.version())
                 })
             })
             .ok_or_else(|| anyhow!("invalid position"))??;
@@ -1657,6 +1701,7 @@ impl LspCommand for GetCompletions {
                 trigger_kind: CompletionTriggerKind::INVOKED,
                 trigger_character: None,
             },
+            version,
         })
     }
 
@@ -1701,6 +1746,42 @@ impl LspCommand for GetCompletions {
     }
 }
 
+/// Returns the edits made to the buffer since the version at which a request was made,
+/// through which the positions in the language server's response must be translated.
+fn edits_since_request(
+    buffer: &text::BufferSnapshot,
+    version: &clock::Global,
+) -> Patch<PointUtf16> {
+    Patch::new(buffer.edits_since::<PointUtf16>(version).collect())
+}
+
+fn translate_lsp_range(range: lsp::Range, edits: &Patch<PointUtf16>) -> lsp::Range {
+    let range = range_from_lsp(range);
+    lsp::Range::new(
+        point_to_lsp(edits.old_to_new(range.start.0)),
+        point_to_lsp(edits.old_to_new(range.end.0)),
+    )
+}
+
+fn translate_completion_text_edit(
+    edit: &lsp::CompletionTextEdit,
+    edits: &Patch<PointUtf16>,
+) -> lsp::CompletionTextEdit {
+    match edit {
+        lsp::CompletionTextEdit::Edit(edit) => lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+            range: translate_lsp_range(edit.range, edits),
+            new_text: edit.new_text.clone(),
+        }),
+        lsp::CompletionTextEdit::InsertAndReplace(edit) => {
+            lsp::CompletionTextEdit::InsertAndReplace(lsp::InsertReplaceEdit {
+                new_text: edit.new_text.clone(),
+                insert: translate_lsp_range(edit.insert, edits),
+                replace: translate_lsp_range(edit.replace, edits),
+            })
+        }
+    }
+}
+
 pub(crate) fn parse_completion_text_edit(
     edit: &lsp::CompletionTextEdit,
     snapshot: &BufferSnapshot,
@@ -2718,3 +2799,385 @@ impl LspCommand for LinkedEditingRange {
         BufferId::new(message.buffer_id)
     }
 }
+
+fn color_from_lsp(color: lsp::Color) -> Color {
+    Color {
+        red: color.red.clamp(0., 1.),
+        green: color.green.clamp(0., 1.),
+        blue: color.blue.clamp(0., 1.),
+        alpha: color.alpha.clamp(0., 1.),
+    }
+}
+
+fn color_to_lsp(color: Color) -> lsp::Color {
+    lsp::Color {
+        red: color.red,
+        green: color.green,
+        blue: color.blue,
+        alpha: color.alpha,
+    }
+}
+
+fn serialize_color(color: Color) -> proto::Color {
+    proto::Color {
+        red: color.red,
+        green: color.green,
+        blue: color.blue,
+        alpha: color.alpha,
+    }
+}
+
+fn deserialize_color(color: proto::Color) -> Color {
+    Color {
+        red: color.red,
+        green: color.green,
+        blue: color.blue,
+        alpha: color.alpha,
+    }
+}
+
+#[async_trait(?Send)]
+impl LspCommand for GetDocumentColors {
+    type Response = Vec<(Range<Anchor>, Color)>;
+    type LspRequest = lsp::request::DocumentColor;
+    type ProtoRequest = proto::GetDocumentColors;
+
+    fn check_capabilities(&self, capabilities: &ServerCapabilities) -> bool {
+        match &capabilities.color_provider {
+            None | Some(lsp::ColorProviderCapability::Simple(false)) => false,
+            Some(_) => true,
+        }
+    }
+
+    fn to_lsp(
+        &self,
+        path: &Path,
+        _: &Buffer,
+        _: &Arc<LanguageServer>,
+        _: &AppContext,
+    ) -> lsp::DocumentColorParams {
+        lsp::DocumentColorParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::from_file_path(path).unwrap(),
+            },
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }
+    }
+
+    async fn response_from_lsp(
+        self,
+        colors: Vec<lsp::ColorInformation>,
+        _: Model<Project>,
+        buffer: Model<Buffer>,
+        _: LanguageServerId,
+        mut cx: AsyncAppContext,
+    ) -> Result<Vec<(Range<Anchor>, Color)>> {
+        buffer.update(&mut cx, |buffer, _| {
+            let mut colors = colors
+                .into_iter()
+                .map(|information| {
+                    let range = range_from_lsp(information.range);
+                    let start = buffer.clip_point_utf16(range.start, Bias::Left);
+                    let end = buffer.clip_point_utf16(range.end, Bias::Left);
+                    (start..end, color_from_lsp(information.color))
+                })
+                .collect::<Vec<_>>();
+            colors.sort_by_key(|(range, _)| (range.start, Reverse(range.end)));
+            colors
+                .into_iter()
+                .map(|(range, color)| {
+                    (
+                        buffer.anchor_before(range.start)..buffer.anchor_after(range.end),
+                        color,
+                    )
+                })
+                .collect()
+        })
+    }
+
+    fn to_proto(&self, project_id: u64, buffer: &Buffer) -> proto::GetDocumentColors {
+        proto::GetDocumentColors {
+            project_id,
+            buffer_id: buffer.remote_id().into(),
+            version: serialize_version(&buffer.version()),
+        }
+    }
+
+    async fn from_proto(
+        message: proto::GetDocumentColors,
+        _: Model<Project>,
+        buffer: Model<Buffer>,
+        mut cx: AsyncAppContext,
+    ) -> Result<Self> {
+        buffer
+            .update(&mut cx, |buffer, _| {
+                buffer.wait_for_version(deserialize_version(&message.version))
+            })?
+            .await?;
+        Ok(Self)
+    }
+
+    fn response_to_proto(
+        response: Vec<(Range<Anchor>, Color)>,
+        _: &mut Project,
+        _: PeerId,
+        buffer_version: &clock::Global,
+        _: &mut AppContext,
+    ) -> proto::GetDocumentColorsResponse {
+        proto::GetDocumentColorsResponse {
+            colors: response
+                .into_iter()
+                .map(|(range, color)| proto::DocumentColor {
+                    start: Some(serialize_anchor(&range.start)),
+                    end: Some(serialize_anchor(&range.end)),
+                    color: Some(serialize_color(color)),
+                })
+                .collect(),
+            version: serialize_version(buffer_version),
+        }
+    }
+
+    async fn response_from_proto(
+        self,
+        message: proto::GetDocumentColorsResponse,
+        _: Model<Project>,
+        buffer: Model<Buffer>,
+        mut cx: AsyncAppContext,
+    ) -> Result<Vec<(Range<Anchor>, Color)>> {
+        buffer
+            .update(&mut cx, |buffer, _| {
+                buffer.wait_for_version(deserialize_version(&message.version))
+            })?
+            .await?;
+        let mut colors = Vec::new();
+        for document_color in message.colors {
+            let start = document_color
+                .start
+                .and_then(deserialize_anchor)
+                .ok_or_else(|| anyhow!("missing color start"))?;
+            let end = document_color
+                .end
+                .and_then(deserialize_anchor)
+                .ok_or_else(|| anyhow!("missing color end"))?;
+            let color = document_color
+                .color
+                .map(deserialize_color)
+                .ok_or_else(|| anyhow!("missing color"))?;
+            buffer
+                .update(&mut cx, |buffer, _| buffer.wait_for_anchors([start, end]))?
+                .await?;
+            colors.push((start..end, color));
+        }
+        Ok(colors)
+    }
+
+    fn buffer_id_from_proto(message: &proto::GetDocumentColors) -> Result<BufferId> {
+        BufferId::new(message.buffer_id)
+    }
+}
+
+fn color_presentation_edit_from_lsp(
+    edit: lsp::TextEdit,
+    buffer: &Buffer,
+) -> (Range<Anchor>, String) {
+    let range = range_from_lsp(edit.range);
+    let start = buffer.clip_point_utf16(range.start, Bias::Left);
+    let end = buffer.clip_point_utf16(range.end, Bias::Left);
+    (
+        buffer.anchor_before(start)..buffer.anchor_after(end),
+        edit.new_text,
+    )
+}
+
+fn serialize_color_presentation_edit(
+    (range, new_text): (Range<Anchor>, String),
+) -> proto::ColorPresentationEdit {
+    proto::ColorPresentationEdit {
+        start: Some(serialize_anchor(&range.start)),
+        end: Some(serialize_anchor(&range.end)),
+        new_text,
+    }
+}
+
+fn deserialize_color_presentation_edit(
+    edit: proto::ColorPresentationEdit,
+) -> Result<(Range<Anchor>, String)> {
+    let start = edit
+        .start
+        .and_then(deserialize_anchor)
+        .ok_or_else(|| anyhow!("missing edit start"))?;
+    let end = edit
+        .end
+        .and_then(deserialize_anchor)
+        .ok_or_else(|| anyhow!("missing edit end"))?;
+    Ok((start..end, edit.new_text))
+}
+
+#[async_trait(?Send)]
+impl LspCommand for GetColorPresentations {
+    type Response = Vec<ColorPresentation>;
+    type LspRequest = lsp::request::ColorPresentationRequest;
+    type ProtoRequest = proto::GetColorPresentations;
+
+    fn check_capabilities(&self, capabilities: &ServerCapabilities) -> bool {
+        match &capabilities.color_provider {
+            None | Some(lsp::ColorProviderCapability::Simple(false)) => false,
+            Some(_) => true,
+        }
+    }
+
+    fn to_lsp(
+        &self,
+        path: &Path,
+        buffer: &Buffer,
+        _: &Arc<LanguageServer>,
+        _: &AppContext,
+    ) -> lsp::ColorPresentationParams {
+        lsp::ColorPresentationParams {
+            text_document: lsp::TextDocumentIdentifier {
+                uri: lsp::Url::from_file_path(path).unwrap(),
+            },
+            color: color_to_lsp(self.color),
+            range: range_to_lsp(self.range.to_point_utf16(buffer)),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        }
+    }
+
+    async fn response_from_lsp(
+        self,
+        presentations: Vec<lsp::ColorPresentation>,
+        _: Model<Project>,
+        buffer: Model<Buffer>,
+        _: LanguageServerId,
+        mut cx: AsyncAppContext,
+    ) -> Result<Vec<ColorPresentation>> {
+        buffer.update(&mut cx, |buffer, _| {
+            presentations
+                .into_iter()
+                .map(|presentation| ColorPresentation {
+                    label: presentation.label,
+                    text_edit: presentation
+                        .text_edit
+                        .map(|edit| color_presentation_edit_from_lsp(edit, buffer)),
+                    additional_text_edits: presentation
+                        .additional_text_edits
+                        .unwrap_or_default()
+                        .into_iter()
+                        .map(|edit| color_presentation_edit_from_lsp(edit, buffer))
+                        .collect(),
+                })
+                .collect()
+        })
+    }
+
+    fn to_proto(&self, project_id: u64, buffer: &Buffer) -> proto::GetColorPresentations {
+        proto::GetColorPresentations {
+            project_id,
+            buffer_id: buffer.remote_id().into(),
+            start: Some(serialize_anchor(&self.range.start)),
+            end: Some(serialize_anchor(&self.range.end)),
+            color: Some(serialize_color(self.color)),
+            version: serialize_version(&buffer.version()),
+        }
+    }
+
+    async fn from_proto(
+        message: proto::GetColorPresentations,
+        _: Model<Project>,
+        buffer: Model<Buffer>,
+        mut cx: AsyncAppContext,
+    ) -> Result<Self> {
+        let start = message
+            .start
+            .and_then(deserialize_anchor)
+            .ok_or_else(|| anyhow!("invalid start"))?;
+        let end = message
+            .end
+            .and_then(deserialize_anchor)
+            .ok_or_else(|| anyhow!("invalid end"))?;
+        let color = message
+            .color
+            .map(deserialize_color)
+            .ok_or_else(|| anyhow!("invalid color"))?;
+        buffer
+            .update(&mut cx, |buffer, _| {
+                buffer.wait_for_version(deserialize_version(&message.version))
+            })?
+            .await?;
+        Ok(Self {
+            range: start..end,
+            color,
+        })
+    }
+
+    fn response_to_proto(
+        response: Vec<ColorPresentation>,
+        _: &mut Project,
+        _: PeerId,
+        buffer_version: &clock::Global,
+        _: &mut AppContext,
+    ) -> proto::GetColorPresentationsResponse {
+        proto::GetColorPresentationsResponse {
+            presentations: response
+                .into_iter()
+                .map(|presentation| proto::ColorPresentation {
+                    label: presentation.label,
+                    text_edit: presentation
+                        .text_edit
+                        .map(serialize_color_presentation_edit),
+                    additional_text_edits: presentation
+                        .additional_text_edits
+                        .into_iter()
+                        .map(serialize_color_presentation_edit)
+                        .collect(),
+                })
+                .collect(),
+            version: serialize_version(buffer_version),
+        }
+    }
+
+    async fn response_from_proto(
+        self,
+        message: proto::GetColorPresentationsResponse,
+        _: Model<Project>,
+        buffer: Model<Buffer>,
+        mut cx: AsyncAppContext,
+    ) -> Result<Vec<ColorPresentation>> {
+        buffer
+            .update(&mut cx, |buffer, _| {
+                buffer.wait_for_version(deserialize_version(&message.version))
+            })?
+            .await?;
+        let mut presentations = Vec::new();
+        for presentation in message.presentations {
+            let text_edit = presentation
+                .text_edit
+                .map(deserialize_color_presentation_edit)
+                .transpose()?;
+            let additional_text_edits = presentation
+                .additional_text_edits
+                .into_iter()
+                .map(deserialize_color_presentation_edit)
+                .collect::<Result<Vec<_>>>()?;
+            for (range, _) in text_edit.iter().chain(&additional_text_edits) {
+                buffer
+                    .update(&mut cx, |buffer, _| {
+                        buffer.wait_for_anchors([range.start, range.end])
+                    })?
+                    .await?;
+            }
+            presentations.push(ColorPresentation {
+                label: presentation.label,
+                text_edit,
+                additional_text_edits,
+            });
+        }
+        Ok(presentations)
+    }
+
+    fn buffer_id_from_proto(message: &proto::GetColorPresentations) -> Result<BufferId> {
+        BufferId::new(message.buffer_id)
+    }
+}
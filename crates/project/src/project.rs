@@ -19,7 +19,7 @@ use client::{
     TypedEnvelope, UserStore,
 };
 use clock::ReplicaId;
-use collections::{btree_map, hash_map, BTreeMap, HashMap, HashSet, VecDeque};
+use collections::{btree_map, hash_map, BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 use debounced_delay::DebouncedDelay;
 use futures::{
     channel::{
@@ -53,8 +53,8 @@ use language::{
     range_from_lsp, Bias, Buffer, BufferSnapshot, CachedLspAdapter, Capability, CodeLabel,
     ContextProvider, Diagnostic, DiagnosticEntry, DiagnosticSet, Diff, Documentation,
     Event as BufferEvent, File as _, Language, LanguageRegistry, LanguageServerName, LocalFile,
-    LspAdapterDelegate, Operation, Patch, PendingLanguageServer, PointUtf16, TextBufferSnapshot,
-    ToOffset, ToPointUtf16, Transaction, Unclipped,
+    LspAdapterDelegate, OffsetRangeExt, Operation, Patch, PendingLanguageServer, PointUtf16,
+    TextBufferSnapshot, ToOffset, ToPointUtf16, Transaction, TransactionId, Unclipped,
 };
 use log::error;
 use lsp::{
@@ -72,7 +72,7 @@ use paths::{
 };
 use postage::watch;
 use prettier_support::{DefaultPrettier, PrettierInstance};
-use project_settings::{LspSettings, ProjectSettings};
+use project_settings::{DiagnosticGrouping, LspSettings, ProjectSettings};
 use rand::prelude::*;
 use rpc::{ErrorCode, ErrorExt as _};
 use search::SearchQuery;
@@ -133,6 +133,7 @@ const MAX_SERVER_REINSTALL_ATTEMPT_COUNT: u64 = 4;
 const SERVER_REINSTALL_DEBOUNCE_TIMEOUT: Duration = Duration::from_secs(1);
 const SERVER_LAUNCHING_BEFORE_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
 pub const SERVER_PROGRESS_THROTTLE_TIMEOUT: Duration = Duration::from_millis(100);
+const WILL_SAVE_WAIT_UNTIL_TIMEOUT: Duration = Duration::from_secs(1);
 
 const MAX_PROJECT_SEARCH_HISTORY_SIZE: usize = 500;
 
@@ -213,6 +214,18 @@ pub struct Project {
     local_buffer_ids_by_path: HashMap<ProjectPath, BufferId>,
     local_buffer_ids_by_entry_id: HashMap<ProjectEntryId, BufferId>,
     buffer_snapshots: HashMap<BufferId, HashMap<LanguageServerId, Vec<LspBufferSnapshot>>>, // buffer_id -> server_id -> vec of snapshots
+    /// The latest version of each buffer sent to a language server for which
+    /// that server hasn't yet published diagnostics.
+    pending_diagnostics_versions: HashMap<(BufferId, LanguageServerId), i32>,
+    /// Language servers that have published diagnostics, whose pending versions are
+    /// tracked even if they don't advertise that they report diagnostics.
+    diagnostic_publishing_server_ids: HashSet<LanguageServerId>,
+    /// The version of each buffer that the diagnostics most recently published
+    /// by each language server referred to.
+    applied_diagnostics_versions: HashMap<(BufferId, LanguageServerId), i32>,
+    /// For each buffer, the edits made since it was last saved, which are used to
+    /// translate the ranges of disk-based diagnostics.
+    edits_since_save: HashMap<BufferId, EditsSinceSave>,
     buffers_being_formatted: HashSet<BufferId>,
     buffers_needing_diff: HashSet<WeakModel<Buffer>>,
     git_diff_debouncer: DebouncedDelay,
@@ -241,6 +254,19 @@ struct LspBufferSnapshot {
     snapshot: TextBufferSnapshot,
 }
 
+/// The edits made to a buffer since it was saved, as of a particular version.
+struct EditsSinceSave {
+    version: clock::Global,
+    saved_version: clock::Global,
+    edits: Arc<Patch<Unclipped<PointUtf16>>>,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// The number of times that a buffer's edits since its last save were computed on this thread.
+    static EDITS_SINCE_SAVE_COMPUTATION_COUNT: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
 /// Message ordered with respect to buffer operations
 #[derive(Debug)]
 enum BufferOrderedMessage {
@@ -323,6 +349,9 @@ pub enum Event {
     LanguageServerAdded(LanguageServerId),
     LanguageServerRemoved(LanguageServerId),
     LanguageServerLog(LanguageServerId, String),
+    /// A message about a buffer could not be sent to a language server, for
+    /// example because the server has stopped.
+    LanguageServerError(LanguageServerId, String),
     Notification(String),
     LanguageServerPrompt(LanguageServerPromptRequest),
     LanguageNotFound(Model<Buffer>),
@@ -343,6 +372,20 @@ pub enum Event {
         path: ProjectPath,
         language_server_id: LanguageServerId,
     },
+    /// A buffer's contents were sent to a language server, which hasn't yet
+    /// published diagnostics for them. Only emitted for servers that advertise
+    /// diagnostics or have published them before, and never resolved if the server
+    /// is stopped in the meantime.
+    DiagnosticsPending {
+        buffer_id: BufferId,
+        language_server_id: LanguageServerId,
+    },
+    /// A language server published diagnostics for the latest contents of a buffer
+    /// that were sent to it.
+    DiagnosticsCaughtUp {
+        buffer_id: BufferId,
+        language_server_id: LanguageServerId,
+    },
     RemoteIdChanged(Option<u64>),
     DisconnectedFromHost,
     Closed,
@@ -583,6 +626,26 @@ impl Hover {
     }
 }
 
+/// A color reported by a language server, with each RGBA component in the range `[0, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Color {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+    pub alpha: f32,
+}
+
+/// A way of writing a [`Color`] in the buffer, offered by a language server
+/// as a replacement for an existing color.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ColorPresentation {
+    pub label: String,
+    /// The edit that replaces the color's text with this presentation. When
+    /// absent, the label is inserted in place of the color.
+    pub text_edit: Option<(Range<language::Anchor>, String)>,
+    pub additional_text_edits: Vec<(Range<language::Anchor>, String)>,
+}
+
 #[derive(Default)]
 pub struct ProjectTransaction(pub HashMap<Model<Buffer>, language::Transaction>);
 
@@ -611,6 +674,62 @@ impl FormatTrigger {
     }
 }
 
+/// A language server feature that can be queried via [`Project::server_supports`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerCapability {
+    Hover,
+    Completion,
+    SignatureHelp,
+    Definition,
+    TypeDefinition,
+    Implementation,
+    References,
+    DocumentHighlight,
+    CodeAction,
+    Rename,
+    Format,
+    RangeFormat,
+}
+
+impl ServerCapability {
+    fn is_supported_by(self, capabilities: &lsp::ServerCapabilities) -> bool {
+        fn enabled<T>(provider: &Option<OneOf<bool, T>>) -> bool {
+            matches!(provider, Some(p) if !matches!(p, OneOf::Left(false)))
+        }
+
+        match self {
+            ServerCapability::Hover => !matches!(
+                capabilities.hover_provider,
+                None | Some(lsp::HoverProviderCapability::Simple(false))
+            ),
+            ServerCapability::Completion => capabilities.completion_provider.is_some(),
+            ServerCapability::SignatureHelp => capabilities.signature_help_provider.is_some(),
+            ServerCapability::Definition => enabled(&capabilities.definition_provider),
+            ServerCapability::TypeDefinition => !matches!(
+                capabilities.type_definition_provider,
+                None | Some(lsp::TypeDefinitionProviderCapability::Simple(false))
+            ),
+            ServerCapability::Implementation => !matches!(
+                capabilities.implementation_provider,
+                None | Some(lsp::ImplementationProviderCapability::Simple(false))
+            ),
+            ServerCapability::References => enabled(&capabilities.references_provider),
+            ServerCapability::DocumentHighlight => {
+                enabled(&capabilities.document_highlight_provider)
+            }
+            ServerCapability::CodeAction => !matches!(
+                capabilities.code_action_provider,
+                None | Some(lsp::CodeActionProviderCapability::Simple(false))
+            ),
+            ServerCapability::Rename => enabled(&capabilities.rename_provider),
+            ServerCapability::Format => enabled(&capabilities.document_formatting_provider),
+            ServerCapability::RangeFormat => {
+                enabled(&capabilities.document_range_formatting_provider)
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq)]
 enum SearchMatchCandidate {
     OpenBuffer {
@@ -686,6 +805,8 @@ impl Project {
         client.add_model_request_handler(Self::handle_lsp_command::<GetDefinition>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetTypeDefinition>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetDocumentHighlights>);
+        client.add_model_request_handler(Self::handle_lsp_command::<GetDocumentColors>);
+        client.add_model_request_handler(Self::handle_lsp_command::<GetColorPresentations>);
         client.add_model_request_handler(Self::handle_lsp_command::<GetReferences>);
         client.add_model_request_handler(Self::handle_lsp_command::<PrepareRename>);
         client.add_model_request_handler(Self::handle_lsp_command::<PerformRename>);
@@ -732,6 +853,10 @@ impl Project {
                 local_buffer_ids_by_path: Default::default(),
                 local_buffer_ids_by_entry_id: Default::default(),
                 buffer_snapshots: Default::default(),
+                pending_diagnostics_versions: Default::default(),
+                diagnostic_publishing_server_ids: Default::default(),
+                applied_diagnostics_versions: Default::default(),
+                edits_since_save: Default::default(),
                 join_project_response_message_id: 0,
                 client_state: ProjectClientState::Local,
                 loading_buffers: HashMap::default(),
@@ -923,6 +1048,10 @@ impl Project {
                 buffers_needing_diff: Default::default(),
                 git_diff_debouncer: DebouncedDelay::new(),
                 buffer_snapshots: Default::default(),
+                pending_diagnostics_versions: Default::default(),
+                diagnostic_publishing_server_ids: Default::default(),
+                applied_diagnostics_versions: Default::default(),
+                edits_since_save: Default::default(),
                 nonce: StdRng::from_entropy().gen(),
                 terminals: Terminals {
                     local_handles: Vec::new(),
@@ -2069,13 +2198,15 @@ impl Project {
                     .background_executor()
                     .spawn(async move { text::Buffer::new(0, buffer_id, loaded.text) })
                     .await;
-                cx.insert_model(reservation, |_| {
-                    Buffer::build(
+                cx.insert_model(reservation, |cx| {
+                    let mut buffer = Buffer::build(
                         text_buffer,
                         loaded.diff_base,
                         Some(loaded.file),
                         Capability::ReadWrite,
-                    )
+                    );
+                    buffer.set_encoding(loaded.encoding, cx);
+                    buffer
                 })
             })
         });
@@ -2208,11 +2339,12 @@ impl Project {
     pub fn save_buffers(
         &self,
         buffers: HashSet<Model<Buffer>>,
+        force: bool,
         cx: &mut ModelContext<Self>,
     ) -> Task<Result<()>> {
         cx.spawn(move |this, mut cx| async move {
             let save_tasks = buffers.into_iter().filter_map(|buffer| {
-                this.update(&mut cx, |this, cx| this.save_buffer(buffer, cx))
+                this.update(&mut cx, |this, cx| this.save_buffer(buffer, force, cx))
                     .ok()
             });
             try_join_all(save_tasks).await?;
@@ -2220,14 +2352,23 @@ impl Project {
         })
     }
 
+    /// Saves the buffer to its file.
+    ///
+    /// Unless `force` is true, this fails with [`SaveError::Conflict`] when the
+    /// file has changed on disk since the buffer was last saved or loaded, so
+    /// that the caller can ask whether to overwrite it.
     pub fn save_buffer(
         &self,
         buffer: Model<Buffer>,
+        force: bool,
         cx: &mut ModelContext<Self>,
     ) -> Task<Result<()>> {
         let Some(file) = File::from_dyn(buffer.read(cx).file()) else {
             return Task::ready(Err(anyhow!("buffer doesn't have a file")));
         };
+        if !force && buffer.read(cx).has_conflict() {
+            return Task::ready(Err(SaveError::Conflict.into()));
+        }
         let worktree = file.worktree.clone();
         let path = file.path.clone();
         if self.is_local() {
@@ -2270,6 +2411,116 @@ impl Project {
     }
 
     pub fn save_local_buffer(
+        &self,
+        worktree: Model<Worktree>,
+        buffer_handle: Model<Buffer>,
+        path: Arc<Path>,
+        has_changed_file: bool,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<()>> {
+        let pre_save_transaction =
+            buffer_handle.update(cx, |buffer, cx| buffer.run_pre_save_hooks(cx));
+
+        let buffer = buffer_handle.read(cx);
+        let will_save_servers = self
+            .language_servers_for_buffer(buffer, cx)
+            .filter(|(_, server)| will_save_wait_until(server))
+            .map(|(_, server)| server.clone())
+            .collect::<Vec<_>>();
+        let abs_path = File::from_dyn(buffer.file())
+            .map(|file| file.abs_path(cx))
+            .filter(|_| !will_save_servers.is_empty());
+        let Some(abs_path) = abs_path else {
+            return self.write_local_buffer(worktree, buffer_handle, path, has_changed_file, cx);
+        };
+
+        // Give language servers a chance to edit the buffer before it is written,
+        // applying their edits as part of the same transaction as the pre-save hooks.
+        cx.spawn(move |this, mut cx| async move {
+            let mut save_transaction = pre_save_transaction;
+            for server in will_save_servers {
+                let edits = Self::will_save_wait_until_edits(
+                    &this,
+                    &buffer_handle,
+                    &abs_path,
+                    &server,
+                    &mut cx,
+                )
+                .await;
+                let edits = match edits {
+                    Ok(edits) if !edits.is_empty() => edits,
+                    Ok(_) => continue,
+                    Err(error) => {
+                        log::warn!("willSaveWaitUntil failed for {}: {error:#}", server.name());
+                        continue;
+                    }
+                };
+                buffer_handle.update(&mut cx, |buffer, cx| {
+                    buffer.start_transaction();
+                    buffer.edit(edits, None, cx);
+                    if let Some(transaction_id) = buffer.end_transaction(cx) {
+                        if let Some(save_transaction) = save_transaction {
+                            buffer.merge_transactions(transaction_id, save_transaction);
+                        } else {
+                            save_transaction = Some(transaction_id);
+                        }
+                    }
+                })?;
+            }
+
+            this.update(&mut cx, |this, cx| {
+                this.write_local_buffer(worktree, buffer_handle, path, has_changed_file, cx)
+            })?
+            .await
+        })
+    }
+
+    async fn will_save_wait_until_edits(
+        this: &WeakModel<Self>,
+        buffer: &Model<Buffer>,
+        abs_path: &Path,
+        language_server: &Arc<LanguageServer>,
+        cx: &mut AsyncAppContext,
+    ) -> Result<Vec<(Range<Anchor>, String)>> {
+        let uri = lsp::Url::from_file_path(abs_path)
+            .map_err(|_| anyhow!("failed to convert abs path to uri"))?;
+        // The server's edits refer to the latest version of the document that it
+        // was sent, which the buffer may have moved past by the time they arrive.
+        let version = this.update(cx, |this, cx| {
+            this.buffer_snapshots
+                .get(&buffer.read(cx).remote_id())
+                .and_then(|snapshots| snapshots.get(&language_server.server_id()))
+                .and_then(|snapshots| snapshots.last())
+                .map(|snapshot| snapshot.version)
+        })?;
+        let request = language_server.request::<lsp::request::WillSaveWaitUntil>(
+            lsp::WillSaveTextDocumentParams {
+                text_document: lsp::TextDocumentIdentifier::new(uri),
+                reason: lsp::TextDocumentSaveReason::MANUAL,
+            },
+        );
+        let mut timeout = cx
+            .background_executor()
+            .timer(WILL_SAVE_WAIT_UNTIL_TIMEOUT)
+            .fuse();
+        let lsp_edits = futures::select! {
+            lsp_edits = request.fuse() => lsp_edits?,
+            _ = timeout => {
+                return Err(anyhow!("timed out after {WILL_SAVE_WAIT_UNTIL_TIMEOUT:?}"));
+            }
+        };
+
+        if let Some(lsp_edits) = lsp_edits {
+            this.update(cx, |this, cx| {
+                this.edits_from_lsp(buffer, lsp_edits, language_server.server_id(), version, cx)
+            })?
+            .await
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    fn write_local_buffer(
         &self,
         worktree: Model<Worktree>,
         buffer_handle: Model<Buffer>,
@@ -2280,14 +2531,15 @@ impl Project {
         let buffer = buffer_handle.read(cx);
         let buffer_id = buffer.remote_id();
         let text = buffer.as_rope().clone();
-        let line_ending = buffer.line_ending();
+        let line_ending = buffer.line_ending_at_save();
+        let encoding = buffer.encoding();
         let version = buffer.version();
         if buffer.file().is_some_and(|file| !file.is_created()) {
             has_changed_file = true;
         }
 
         let save = worktree.update(cx, |worktree, cx| {
-            worktree.write_file(path.as_ref(), text, line_ending, cx)
+            worktree.write_file(path.as_ref(), text, line_ending, encoding, cx)
         });
 
         let client = self.client.clone();
@@ -2527,15 +2779,8 @@ impl Project {
                         .log_err();
 
                     buffer_handle.update(cx, |buffer, cx| {
-                        buffer.set_completion_triggers(
-                            server
-                                .capabilities()
-                                .completion_provider
-                                .as_ref()
-                                .and_then(|provider| provider.trigger_characters.clone())
-                                .unwrap_or_default(),
-                            cx,
-                        );
+                        let triggers = completion_triggers(buffer, &server, cx);
+                        buffer.set_completion_triggers(triggers, cx);
                     });
 
                     let snapshot = LspBufferSnapshot {
@@ -2546,6 +2791,8 @@ impl Project {
                         .entry(buffer_id)
                         .or_default()
                         .insert(server.server_id(), vec![snapshot]);
+                    self.applied_diagnostics_versions
+                        .remove(&(buffer_id, server.server_id()));
                 }
             }
         }
@@ -2575,6 +2822,12 @@ impl Project {
             }
 
             self.buffer_snapshots.remove(&buffer.remote_id());
+            let buffer_id = buffer.remote_id();
+            self.edits_since_save.remove(&buffer_id);
+            self.pending_diagnostics_versions
+                .retain(|(pending_buffer_id, _), _| *pending_buffer_id != buffer_id);
+            self.applied_diagnostics_versions
+                .retain(|(applied_buffer_id, _), _| *applied_buffer_id != buffer_id);
             let file_url = lsp::Url::from_file_path(old_path).unwrap();
             for (_, language_server) in self.language_servers_for_buffer(buffer, cx) {
                 language_server
@@ -2710,12 +2963,15 @@ impl Project {
         }
 
         match event {
-            BufferEvent::Operation(operation) => {
-                self.enqueue_buffer_ordered_message(BufferOrderedMessage::Operation {
-                    buffer_id: buffer.read(cx).remote_id(),
-                    operation: language::proto::serialize_operation(operation),
-                })
-                .ok();
+            BufferEvent::Operations(operations) => {
+                let buffer_id = buffer.read(cx).remote_id();
+                for operation in operations {
+                    self.enqueue_buffer_ordered_message(BufferOrderedMessage::Operation {
+                        buffer_id,
+                        operation: language::proto::serialize_operation(operation),
+                    })
+                    .ok();
+                }
             }
 
             BufferEvent::Reloaded => {
@@ -2744,12 +3000,13 @@ impl Project {
 
                 let language_servers: Vec<_> = self
                     .language_servers_for_buffer(buffer, cx)
-                    .map(|i| i.1.clone())
+                    .map(|(adapter, server)| (adapter.clone(), server.clone()))
                     .collect();
+                let buffer_id = buffer.remote_id();
+                let mut newly_pending_server_ids = Vec::new();
+                let mut errors = Vec::new();
 
-                for language_server in language_servers {
-                    let language_server = language_server.clone();
-
+                for (adapter, language_server) in language_servers {
                     let buffer_snapshots = self
                         .buffer_snapshots
                         .get_mut(&buffer.remote_id())
@@ -2815,8 +3072,20 @@ impl Project {
                         version: next_version,
                         snapshot: next_snapshot.clone(),
                     });
+                    let server_id = language_server.server_id();
+                    let provides_diagnostics =
+                        language_server_provides_diagnostics(&adapter, &language_server)
+                            || self.diagnostic_publishing_server_ids.contains(&server_id);
+                    if provides_diagnostics
+                        && self
+                            .pending_diagnostics_versions
+                            .insert((buffer_id, server_id), next_version)
+                            .is_none()
+                    {
+                        newly_pending_server_ids.push(server_id);
+                    }
 
-                    language_server
+                    if let Err(error) = language_server
                         .notify::<lsp::notification::DidChangeTextDocument>(
                             lsp::DidChangeTextDocumentParams {
                                 text_document: lsp::VersionedTextDocumentIdentifier::new(
@@ -2826,7 +3095,20 @@ impl Project {
                                 content_changes,
                             },
                         )
-                        .log_err();
+                    {
+                        log::error!("failed to send didChange to language server: {error:#}");
+                        errors.push((server_id, format!("{error:#}")));
+                    }
+                }
+
+                for language_server_id in newly_pending_server_ids {
+                    cx.emit(Event::DiagnosticsPending {
+                        buffer_id,
+                        language_server_id,
+                    });
+                }
+                for (language_server_id, error) in errors {
+                    cx.emit(Event::LanguageServerError(language_server_id, error));
                 }
             }
 
@@ -2838,16 +3120,21 @@ impl Project {
                     uri: lsp::Url::from_file_path(abs_path).unwrap(),
                 };
 
+                let mut errors = Vec::new();
                 for (_, _, server) in self.language_servers_for_worktree(worktree_id) {
                     let text = include_text(server.as_ref()).then(|| buffer.read(cx).text());
-                    server
-                        .notify::<lsp::notification::DidSaveTextDocument>(
-                            lsp::DidSaveTextDocumentParams {
-                                text_document: text_document.clone(),
-                                text,
-                            },
-                        )
-                        .log_err();
+                    if let Err(error) = server.notify::<lsp::notification::DidSaveTextDocument>(
+                        lsp::DidSaveTextDocumentParams {
+                            text_document: text_document.clone(),
+                            text,
+                        },
+                    ) {
+                        log::error!("failed to send didSave to language server: {error:#}");
+                        errors.push((server.server_id(), format!("{error:#}")));
+                    }
+                }
+                for (language_server_id, error) in errors {
+                    cx.emit(Event::LanguageServerError(language_server_id, error));
                 }
 
                 for language_server_id in self.language_server_ids_for_buffer(buffer.read(cx), cx) {
@@ -3536,8 +3823,17 @@ impl Project {
                             .into_iter()
                             .map(|item| {
                                 if let Some(section) = &item.section {
+                                    // Servers may request nested settings using dotted
+                                    // section names, such as `rust-analyzer.cargo`.
                                     workspace_config
                                         .get(section)
+                                        .or_else(|| {
+                                            section
+                                                .split('.')
+                                                .try_fold(&workspace_config, |value, key| {
+                                                    value.get(key)
+                                                })
+                                        })
                                         .cloned()
                                         .unwrap_or(serde_json::Value::Null)
                                 } else {
@@ -3888,15 +4184,8 @@ impl Project {
                 )?;
 
                 buffer_handle.update(cx, |buffer, cx| {
-                    buffer.set_completion_triggers(
-                        language_server
-                            .capabilities()
-                            .completion_provider
-                            .as_ref()
-                            .and_then(|provider| provider.trigger_characters.clone())
-                            .unwrap_or_default(),
-                        cx,
-                    )
+                    let triggers = completion_triggers(buffer, &language_server, cx);
+                    buffer.set_completion_triggers(triggers, cx)
                 });
             }
         }
@@ -3974,6 +4263,9 @@ impl Project {
                 });
             }
 
+            self.pending_diagnostics_versions
+                .retain(|(_, pending_server_id), _| *pending_server_id != server_id);
+            self.diagnostic_publishing_server_ids.remove(&server_id);
             self.language_server_watched_paths.remove(&server_id);
             self.language_server_statuses.remove(&server_id);
             cx.notify();
@@ -4610,6 +4902,8 @@ impl Project {
         disk_based_sources: &[String],
         cx: &mut ModelContext<Self>,
     ) -> Result<()> {
+        self.diagnostic_publishing_server_ids
+            .insert(language_server_id);
         let abs_path = params
             .uri
             .to_file_path()
@@ -4618,6 +4912,9 @@ impl Project {
         let mut primary_diagnostic_group_ids = HashMap::default();
         let mut sources_by_group_id = HashMap::default();
         let mut supporting_diagnostics = HashMap::default();
+        let grouping = ProjectSettings::get_global(cx)
+            .diagnostic_grouping
+            .unwrap_or_default();
 
         // Ensure that primary diagnostics are always the most severe
         params.diagnostics.sort_by_key(|item| item.severity);
@@ -4629,27 +4926,33 @@ impl Project {
                 lsp::NumberOrString::String(code) => code.clone(),
             });
             let range = range_from_lsp(diagnostic.range);
-            let is_supporting = diagnostic
-                .related_information
-                .as_ref()
-                .map_or(false, |infos| {
-                    infos.iter().any(|info| {
-                        primary_diagnostic_group_ids.contains_key(&(
-                            source,
-                            code.clone(),
-                            range_from_lsp(info.location.range),
-                        ))
-                    })
-                });
+            let is_supporting = grouping == DiagnosticGrouping::BySourceCodeRange
+                && diagnostic
+                    .related_information
+                    .as_ref()
+                    .map_or(false, |infos| {
+                        infos.iter().any(|info| {
+                            primary_diagnostic_group_ids.contains_key(&(
+                                source,
+                                code.clone(),
+                                range_from_lsp(info.location.range),
+                            ))
+                        })
+                    });
 
-            let is_unnecessary = diagnostic.tags.as_ref().map_or(false, |tags| {
-                tags.iter().any(|tag| *tag == DiagnosticTag::UNNECESSARY)
-            });
+            let has_tag = |tag| {
+                diagnostic
+                    .tags
+                    .as_ref()
+                    .map_or(false, |tags| tags.contains(&tag))
+            };
+            let is_unnecessary = has_tag(DiagnosticTag::UNNECESSARY);
+            let is_deprecated = has_tag(DiagnosticTag::DEPRECATED);
 
             if is_supporting {
                 supporting_diagnostics.insert(
                     (source, code.clone(), range),
-                    (diagnostic.severity, is_unnecessary),
+                    (diagnostic.severity, is_unnecessary, is_deprecated),
                 );
             } else {
                 let group_id = post_inc(&mut self.next_diagnostic_group_id);
@@ -4671,9 +4974,15 @@ impl Project {
                         is_primary: true,
                         is_disk_based,
                         is_unnecessary,
+                        is_deprecated,
+                        data: diagnostic.data.clone(),
                     },
                 });
-                if let Some(infos) = &diagnostic.related_information {
+                if let Some(infos) = diagnostic
+                    .related_information
+                    .as_ref()
+                    .filter(|_| grouping != DiagnosticGrouping::None)
+                {
                     for info in infos {
                         if info.location.uri == params.uri && !info.message.is_empty() {
                             let range = range_from_lsp(info.location.range);
@@ -4688,6 +4997,8 @@ impl Project {
                                     is_primary: false,
                                     is_disk_based,
                                     is_unnecessary: false,
+                                    is_deprecated: false,
+                                    data: None,
                                 },
                             });
                         }
@@ -4700,15 +5011,14 @@ impl Project {
             let diagnostic = &mut entry.diagnostic;
             if !diagnostic.is_primary {
                 let source = *sources_by_group_id.get(&diagnostic.group_id).unwrap();
-                if let Some(&(severity, is_unnecessary)) = supporting_diagnostics.get(&(
-                    source,
-                    diagnostic.code.clone(),
-                    entry.range.clone(),
-                )) {
+                if let Some(&(severity, is_unnecessary, is_deprecated)) = supporting_diagnostics
+                    .get(&(source, diagnostic.code.clone(), entry.range.clone()))
+                {
                     if let Some(severity) = severity {
                         diagnostic.severity = severity;
                     }
                     diagnostic.is_unnecessary = is_unnecessary;
+                    diagnostic.is_deprecated = is_deprecated;
                 }
             }
         }
@@ -4839,7 +5149,64 @@ impl Project {
                 .then_with(|| a.message.cmp(&b.message))
         }
 
-        let snapshot = self.buffer_snapshot_for_lsp_version(buffer, server_id, version, cx)?;
+        // Servers may publish diagnostics out of order. Ignore any that refer to an
+        // older version of the buffer than diagnostics that have already been applied.
+        let buffer_id = buffer.read(cx).remote_id();
+        if let Some(version) = version {
+            if let Some(applied_version) = self
+                .applied_diagnostics_versions
+                .get(&(buffer_id, server_id))
+            {
+                if version < *applied_version {
+                    log::debug!(
+                        "ignoring diagnostics for version {version}, which is older than {applied_version}"
+                    );
+                    return Ok(());
+                }
+            }
+        }
+
+        let snapshot = match self.buffer_snapshot_for_lsp_version(buffer, server_id, version, cx) {
+            Ok(snapshot) => snapshot,
+            Err(error) => {
+                // Losing the diagnostics is worse than showing them slightly misplaced,
+                // so if the snapshot they refer to has already been discarded, interpret
+                // them against the buffer's current contents instead.
+                let oldest_version = self
+                    .buffer_snapshots
+                    .get(&buffer.read(cx).remote_id())
+                    .and_then(|snapshots| snapshots.get(&server_id))
+                    .and_then(|snapshots| snapshots.first())
+                    .map(|snapshot| snapshot.version);
+                match version.zip(oldest_version) {
+                    Some((version, oldest_version)) if version < oldest_version => {
+                        log::warn!("{error:#}, using the current buffer contents instead");
+                        buffer.read(cx).text_snapshot()
+                    }
+                    _ => return Err(error),
+                }
+            }
+        };
+
+        if let Some(version) = version {
+            self.applied_diagnostics_versions
+                .insert((buffer_id, server_id), version);
+        }
+
+        // Diagnostics without a version are interpreted against the latest snapshot,
+        // so they also resolve any pending version.
+        if let hash_map::Entry::Occupied(entry) = self
+            .pending_diagnostics_versions
+            .entry((buffer_id, server_id))
+        {
+            if version.map_or(true, |version| version >= *entry.get()) {
+                entry.remove();
+                cx.emit(Event::DiagnosticsCaughtUp {
+                    buffer_id,
+                    language_server_id: server_id,
+                });
+            }
+        }
 
         diagnostics.sort_unstable_by(|a, b| {
             Ordering::Equal
@@ -4849,11 +5216,8 @@ impl Project {
         });
 
         let mut sanitized_diagnostics = Vec::new();
-        let edits_since_save = Patch::new(
-            snapshot
-                .edits_since::<Unclipped<PointUtf16>>(buffer.read(cx).saved_version())
-                .collect(),
-        );
+        let saved_version = buffer.read(cx).saved_version().clone();
+        let mut edits_since_save = None;
         for entry in diagnostics {
             let start;
             let end;
@@ -4861,6 +5225,9 @@ impl Project {
                 // Some diagnostics are based on files on disk instead of buffers'
                 // current contents. Adjust these diagnostics' ranges to reflect
                 // any unsaved edits.
+                let edits_since_save = edits_since_save.get_or_insert_with(|| {
+                    self.edits_since_save(buffer_id, &snapshot, &saved_version)
+                });
                 start = edits_since_save.old_to_new(entry.range.start);
                 end = edits_since_save.old_to_new(entry.range.end);
             } else {
@@ -4887,15 +5254,49 @@ impl Project {
                 diagnostic: entry.diagnostic,
             });
         }
-        drop(edits_since_save);
 
-        let set = DiagnosticSet::new(sanitized_diagnostics, &snapshot);
+        let mut set = DiagnosticSet::new(sanitized_diagnostics, &snapshot);
+        if ProjectSettings::get_global(cx).deduplicate_diagnostics {
+            set = remove_duplicate_diagnostics(set, server_id, &buffer.read(cx).snapshot());
+        }
         buffer.update(cx, |buffer, cx| {
             buffer.update_diagnostics(server_id, set, cx)
         });
         Ok(())
     }
 
+    /// Returns the edits made to a buffer between its saved version and the given
+    /// snapshot, reusing the edits computed by a previous call for the same versions.
+    fn edits_since_save(
+        &mut self,
+        buffer_id: BufferId,
+        snapshot: &TextBufferSnapshot,
+        saved_version: &clock::Global,
+    ) -> Arc<Patch<Unclipped<PointUtf16>>> {
+        if let Some(cached) = self.edits_since_save.get(&buffer_id) {
+            if cached.version == *snapshot.version() && cached.saved_version == *saved_version {
+                return cached.edits.clone();
+            }
+        }
+
+        #[cfg(test)]
+        EDITS_SINCE_SAVE_COMPUTATION_COUNT.with(|count| count.set(count.get() + 1));
+        let edits = Arc::new(Patch::new(
+            snapshot
+                .edits_since::<Unclipped<PointUtf16>>(saved_version)
+                .collect(),
+        ));
+        self.edits_since_save.insert(
+            buffer_id,
+            EditsSinceSave {
+                version: snapshot.version().clone(),
+                saved_version: saved_version.clone(),
+                edits: edits.clone(),
+            },
+        );
+        edits
+    }
+
     pub fn reload_buffers(
         &self,
         buffers: HashSet<Model<Buffer>>,
@@ -4948,7 +5349,7 @@ impl Project {
 
             for buffer in local_buffers {
                 let transaction = buffer
-                    .update(&mut cx, |buffer, cx| buffer.reload(cx))?
+                    .update(&mut cx, |buffer, cx| buffer.reload_discarding_changes(cx))?
                     .await?;
                 buffer.update(&mut cx, |buffer, cx| {
                     if let Some(transaction) = transaction {
@@ -5085,29 +5486,14 @@ impl Project {
                 language_settings(buffer.language(), buffer.file(), cx).clone()
             })?;
 
-            let remove_trailing_whitespace = settings.remove_trailing_whitespace_on_save;
-            let ensure_final_newline = settings.ensure_final_newline_on_save;
             let tab_size = settings.tab_size;
 
-            // First, format buffer's whitespace according to the settings.
-            let trailing_whitespace_diff = if remove_trailing_whitespace {
-                Some(
-                    buffer
-                        .update(&mut cx, |b, cx| b.remove_trailing_whitespace(cx))?
-                        .await,
-                )
-            } else {
-                None
-            };
+            // First, format buffer's whitespace according to the settings, the same
+            // way it's done when the buffer is saved.
             let whitespace_transaction_id = buffer.update(&mut cx, |buffer, cx| {
                 buffer.finalize_last_transaction();
                 buffer.start_transaction();
-                if let Some(diff) = trailing_whitespace_diff {
-                    buffer.apply_diff(diff, cx);
-                }
-                if ensure_final_newline {
-                    buffer.ensure_final_newline(cx);
-                }
+                buffer.format_whitespace(cx);
                 buffer.end_transaction(cx)
             })?;
 
@@ -5410,7 +5796,10 @@ impl Project {
         self.request_lsp(
             buffer.clone(),
             LanguageServerToQuery::Primary,
-            GetDefinition { position },
+            GetDefinition {
+                position,
+                version: buffer.read(cx).version(),
+            },
             cx,
         )
     }
@@ -5433,7 +5822,10 @@ impl Project {
         self.request_lsp(
             buffer.clone(),
             LanguageServerToQuery::Primary,
-            GetTypeDefinition { position },
+            GetTypeDefinition {
+                position,
+                version: buffer.read(cx).version(),
+            },
             cx,
         )
     }
@@ -5457,7 +5849,10 @@ impl Project {
         self.request_lsp(
             buffer.clone(),
             LanguageServerToQuery::Primary,
-            GetImplementation { position },
+            GetImplementation {
+                position,
+                version: buffer.read(cx).version(),
+            },
             cx,
         )
     }
@@ -5519,6 +5914,38 @@ impl Project {
         self.document_highlights_impl(buffer, position, cx)
     }
 
+    /// Returns the colors that the buffer's language server finds in the buffer,
+    /// such as color literals in a stylesheet, along with their ranges.
+    pub fn document_colors(
+        &self,
+        buffer: &Model<Buffer>,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<(Range<Anchor>, Color)>>> {
+        self.request_lsp(
+            buffer.clone(),
+            LanguageServerToQuery::Primary,
+            GetDocumentColors,
+            cx,
+        )
+    }
+
+    /// Returns the ways in which the given color could be written in place of the
+    /// color at the given range, for picking a replacement.
+    pub fn color_presentations(
+        &self,
+        buffer: &Model<Buffer>,
+        range: Range<Anchor>,
+        color: Color,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Vec<ColorPresentation>>> {
+        self.request_lsp(
+            buffer.clone(),
+            LanguageServerToQuery::Primary,
+            GetColorPresentations { range, color },
+            cx,
+        )
+    }
+
     pub fn symbols(&self, query: &str, cx: &mut ModelContext<Self>) -> Task<Result<Vec<Symbol>>> {
         let language_registry = self.languages.clone();
 
@@ -5739,6 +6166,10 @@ impl Project {
         position: PointUtf16,
         cx: &mut ModelContext<Self>,
     ) -> Task<Vec<Hover>> {
+        let request = GetHover {
+            position,
+            version: buffer.read(cx).version(),
+        };
         if self.is_local() {
             let all_actions_task = self.request_multiple_lsp_locally(
                 &buffer,
@@ -5748,7 +6179,7 @@ impl Project {
                     Some(lsp::HoverProviderCapability::Options(_)) => true,
                     None => false,
                 },
-                GetHover { position },
+                request,
                 cx,
             );
             cx.spawn(|_, _| async move {
@@ -5767,7 +6198,7 @@ impl Project {
                     proto::AllLanguageServers {},
                 )),
                 request: Some(proto::multi_lsp_query::Request::GetHover(
-                    GetHover { position }.to_proto(project_id, buffer.read(cx)),
+                    request.to_proto(project_id, buffer.read(cx)),
                 )),
             });
             let buffer = buffer.clone();
@@ -5792,7 +6223,7 @@ impl Project {
                             }
                         })
                         .map(|hover_response| {
-                            let response = GetHover { position }.response_from_proto(
+                            let response = request.clone().response_from_proto(
                                 hover_response,
                                 project.clone(),
                                 buffer.clone(),
@@ -5898,6 +6329,7 @@ impl Project {
             let snapshot = buffer.read(cx).snapshot();
             let offset = position.to_offset(&snapshot);
             let scope = snapshot.language_scope_at(offset);
+            let version = snapshot.version().clone();
             let language = snapshot.language().cloned();
 
             let server_ids: Vec<_> = self
@@ -5926,6 +6358,7 @@ impl Project {
                                 GetCompletions {
                                     position,
                                     context: context.clone(),
+                                    version: version.clone(),
                                 },
                                 cx,
                             ),
@@ -5953,7 +6386,11 @@ impl Project {
             let task = self.send_lsp_proto_request(
                 buffer.clone(),
                 project_id,
-                GetCompletions { position, context },
+                GetCompletions {
+                    position,
+                    context,
+                    version: buffer.read(cx).version(),
+                },
                 cx,
             );
             let language = buffer.read(cx).language().cloned();
@@ -6085,6 +6522,28 @@ impl Project {
         })
     }
 
+    /// Resolves a single completion with its language server, filling in fields
+    /// like its documentation and additional text edits that servers may omit from
+    /// the initial completion list. If the server doesn't support resolving
+    /// completions, the completion is returned unchanged.
+    pub fn resolve_completion(
+        &self,
+        buffer: Model<Buffer>,
+        completion: Completion,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<Result<Completion>> {
+        let completions = Arc::new(RwLock::new(Box::new([completion]) as Box<[Completion]>));
+        let resolve = self.resolve_completions(buffer, vec![0], completions.clone(), cx);
+        cx.background_executor().spawn(async move {
+            resolve.await?;
+            let completions = mem::take(&mut *completions.write());
+            completions
+                .into_vec()
+                .pop()
+                .ok_or_else(|| anyhow!("completion was not returned after resolving"))
+        })
+    }
+
     async fn resolve_completion_local(
         server: Arc<lsp::LanguageServer>,
         snapshot: &BufferSnapshot,
@@ -6125,6 +6584,13 @@ impl Project {
             completion.documentation = Some(Documentation::Undocumented);
         }
 
+        if completion_item.additional_text_edits.is_some() {
+            let mut completions = completions.write();
+            let completion = &mut completions[completion_index];
+            completion.lsp_completion.additional_text_edits =
+                completion_item.additional_text_edits.clone();
+        }
+
         if let Some(text_edit) = completion_item.text_edit.as_ref() {
             // Technically we don't have to parse the whole `text_edit`, since the only
             // language server we currently use that does update `text_edit` in `completionItem/resolve`
@@ -6414,6 +6880,43 @@ impl Project {
         self.code_actions_impl(buffer_handle, range, cx)
     }
 
+    /// Returns whether any code actions are available for the primary range of the
+    /// given diagnostic group. The result is cached on the buffer for as long as the
+    /// group exists, so that code actions are only requested once per group.
+    pub fn diagnostic_group_is_fixable(
+        &mut self,
+        buffer_handle: &Model<Buffer>,
+        group_id: usize,
+        cx: &mut ModelContext<Self>,
+    ) -> Task<bool> {
+        let snapshot = buffer_handle.read(cx).snapshot();
+        if let Some(fixable) = snapshot.diagnostic_is_fixable(group_id) {
+            return Task::ready(fixable);
+        }
+
+        let mut group_range = None;
+        for entry in snapshot.diagnostic_group::<usize>(group_id) {
+            if entry.diagnostic.is_primary || group_range.is_none() {
+                group_range = Some(entry.range);
+            }
+        }
+        let Some(group_range) = group_range else {
+            return Task::ready(false);
+        };
+
+        let actions = self.code_actions(buffer_handle, group_range, cx);
+        let buffer = buffer_handle.downgrade();
+        cx.spawn(move |_, mut cx| async move {
+            let fixable = !actions.await.is_empty();
+            buffer
+                .update(&mut cx, |buffer, cx| {
+                    buffer.set_diagnostic_group_fixable(group_id, fixable, cx)
+                })
+                .ok();
+            fixable
+        })
+    }
+
     pub fn apply_code_action(
         &self,
         buffer_handle: Model<Buffer>,
@@ -7444,16 +7947,26 @@ impl Project {
                         None
                     };
 
-                    let result = lsp_request.await;
-
-                    let response = result.map_err(|err| {
-                        log::warn!(
-                            "Generic lsp request to {} failed: {}",
-                            language_server.name(),
-                            err
-                        );
-                        err
-                    })?;
+                    let response = match lsp_request.await {
+                        Ok(response) => response,
+                        Err(err) => {
+                            log::warn!(
+                                "Generic lsp request to {} failed: {}",
+                                language_server.name(),
+                                err
+                            );
+                            cx.update(|cx| {
+                                this.update(cx, |_, cx| {
+                                    cx.emit(Event::LanguageServerError(
+                                        language_server.server_id(),
+                                        format!("{err:#}"),
+                                    ));
+                                })
+                            })
+                            .ok();
+                            return Err(err);
+                        }
+                    };
 
                     request
                         .response_from_lsp(
@@ -9267,8 +9780,11 @@ impl Project {
             })?
             .await?;
         } else {
-            this.update(&mut cx, |this, cx| this.save_buffer(buffer.clone(), cx))?
-                .await?;
+            // The guest has already decided to overwrite any conflicting changes.
+            this.update(&mut cx, |this, cx| {
+                this.save_buffer(buffer.clone(), true, cx)
+            })?
+            .await?;
         }
 
         buffer.update(&mut cx, |buffer, _| proto::BufferSaved {
@@ -10750,6 +11266,30 @@ impl Project {
             })
     }
 
+    pub fn has_language_server(&self, buffer: &Model<Buffer>, cx: &AppContext) -> bool {
+        self.language_servers_for_buffer(buffer.read(cx), cx)
+            .next()
+            .is_some()
+    }
+
+    /// Returns whether any running language server for the buffer advertised
+    /// the given capability in its `initialize` response.
+    ///
+    /// Remote projects don't know the host's server capabilities, so this
+    /// always returns `true` for them and leaves the decision to the host.
+    pub fn server_supports(
+        &self,
+        buffer: &Model<Buffer>,
+        capability: ServerCapability,
+        cx: &AppContext,
+    ) -> bool {
+        if self.is_remote() {
+            return true;
+        }
+        self.language_servers_for_buffer(buffer.read(cx), cx)
+            .any(|(_, server)| capability.is_supported_by(&server.capabilities()))
+    }
+
     fn primary_language_server_for_buffer(
         &self,
         buffer: &Buffer,
@@ -11653,6 +12193,50 @@ impl Completion {
     pub fn is_snippet(&self) -> bool {
         self.lsp_completion.insert_text_format == Some(lsp::InsertTextFormat::SNIPPET)
     }
+
+    /// Replaces the given range with this completion's text and applies its
+    /// additional text edits, such as an automatic import, in a single transaction,
+    /// so that undoing the completion reverts all of its edits.
+    ///
+    /// The additional edits' ranges are anchored in `request_snapshot`, the contents
+    /// of the buffer when the completion was requested, so that they account for any
+    /// edits made since. Any additional edit that overlaps the replaced range is skipped.
+    pub fn apply(
+        &self,
+        buffer: &mut Buffer,
+        replace_range: Range<Anchor>,
+        request_snapshot: &TextBufferSnapshot,
+        cx: &mut ModelContext<Buffer>,
+    ) -> Option<TransactionId> {
+        let snapshot = buffer.snapshot();
+        let replace_range = replace_range.to_offset(&snapshot);
+        let mut edits = vec![(replace_range.clone(), self.new_text.clone())];
+        for edit in self.lsp_completion.additional_text_edits.iter().flatten() {
+            let range = range_from_lsp(edit.range);
+            let range = request_snapshot.clip_point_utf16(range.start, Bias::Left)
+                ..request_snapshot.clip_point_utf16(range.end, Bias::Left);
+            let start = request_snapshot.anchor_after(range.start);
+            let end = if range.is_empty() {
+                start
+            } else {
+                request_snapshot.anchor_before(range.end)
+            };
+            let range = (start..end).to_offset(&snapshot);
+            if range.start < replace_range.end && range.end > replace_range.start {
+                continue;
+            }
+
+            let mut new_text = edit.new_text.clone();
+            LineEnding::normalize(&mut new_text);
+            edits.push((range, new_text));
+        }
+        edits.sort_by_key(|(range, _)| (range.start, range.end));
+
+        buffer.finalize_last_transaction();
+        buffer.start_transaction();
+        buffer.edit(edits, None, cx);
+        buffer.end_transaction(cx)
+    }
 }
 
 async fn wait_for_loading_buffer(
@@ -11692,6 +12276,89 @@ fn include_text(server: &lsp::LanguageServer) -> bool {
         .unwrap_or(false)
 }
 
+/// The characters that trigger completions in the given buffer: those advertised
+/// by the language server, plus any configured in the buffer's language settings.
+fn completion_triggers(
+    buffer: &Buffer,
+    server: &lsp::LanguageServer,
+    cx: &AppContext,
+) -> Vec<String> {
+    let mut triggers = server
+        .capabilities()
+        .completion_provider
+        .as_ref()
+        .and_then(|provider| provider.trigger_characters.clone())
+        .unwrap_or_default();
+    let settings = language_settings(buffer.language(), buffer.file(), cx);
+    for trigger in &settings.completion_triggers {
+        if !triggers.contains(trigger) {
+            triggers.push(trigger.clone());
+        }
+    }
+    triggers
+}
+
+fn will_save_wait_until(server: &lsp::LanguageServer) -> bool {
+    server
+        .capabilities()
+        .text_document_sync
+        .as_ref()
+        .and_then(|sync| match sync {
+            lsp::TextDocumentSyncCapability::Kind(_) => None,
+            lsp::TextDocumentSyncCapability::Options(options) => options.will_save_wait_until,
+        })
+        .unwrap_or(false)
+}
+
+/// Whether the given language server advertises that it reports diagnostics, either
+/// through the diagnostic provider capability or the adapter's disk-based sources.
+fn language_server_provides_diagnostics(
+    adapter: &CachedLspAdapter,
+    server: &lsp::LanguageServer,
+) -> bool {
+    server.capabilities().diagnostic_provider.is_some()
+        || !adapter.disk_based_diagnostic_sources.is_empty()
+}
+
+/// Removes the diagnostics from the given server's set that another server has already
+/// reported with the same range, severity, message and source. Diagnostics with a
+/// different source at the same range are kept.
+fn remove_duplicate_diagnostics(
+    set: DiagnosticSet,
+    server_id: LanguageServerId,
+    buffer: &BufferSnapshot,
+) -> DiagnosticSet {
+    fn key<'a>(
+        entry: &'a DiagnosticEntry<Anchor>,
+        buffer: &BufferSnapshot,
+    ) -> (usize, usize, DiagnosticSeverity, &'a str, Option<&'a str>) {
+        (
+            entry.range.start.to_offset(buffer),
+            entry.range.end.to_offset(buffer),
+            entry.diagnostic.severity,
+            &entry.diagnostic.message,
+            entry.diagnostic.source.as_deref(),
+        )
+    }
+
+    let groups = buffer.diagnostic_groups(None);
+    let reported_by_other_servers = groups
+        .iter()
+        .filter(|(id, _)| *id != server_id)
+        .flat_map(|(_, group)| &group.entries)
+        .map(|entry| key(entry, buffer))
+        .collect::<BTreeSet<_>>();
+    if reported_by_other_servers.is_empty() {
+        return set;
+    }
+    DiagnosticSet::from_sorted_entries(
+        set.iter()
+            .filter(|entry| !reported_by_other_servers.contains(&key(entry, buffer)))
+            .cloned(),
+        buffer,
+    )
+}
+
 async fn load_shell_environment(dir: &Path) -> Result<HashMap<String, String>> {
     let marker = "ZED_SHELL_START";
     let shell = env::var("SHELL").context(
@@ -11878,6 +12545,22 @@ impl std::fmt::Display for NoRepositoryError {
 
 impl std::error::Error for NoRepositoryError {}
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SaveError {
+    /// The file was modified on disk after the buffer was last saved or loaded.
+    Conflict,
+}
+
+impl std::fmt::Display for SaveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SaveError::Conflict => write!(f, "file has changed on disk since it was last saved"),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
 fn serialize_location(location: &Location, cx: &AppContext) -> proto::Location {
     proto::Location {
         buffer_id: location.buffer.read(cx).remote_id().into(),
@@ -20,6 +20,34 @@ pub struct ProjectSettings {
     /// Configuration for Git-related features
     #[serde(default)]
     pub git: GitSettings,
+
+    /// How diagnostics reported by language servers are grouped together.
+    ///
+    /// Default: by_source_code_range
+    #[serde(default)]
+    pub diagnostic_grouping: Option<DiagnosticGrouping>,
+
+    /// Whether to hide diagnostics that another language server has already
+    /// reported with the same range, severity, message and source.
+    ///
+    /// Default: false
+    #[serde(default)]
+    pub deduplicate_diagnostics: bool,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DiagnosticGrouping {
+    /// Group each diagnostic with its related information, and merge diagnostics
+    /// whose related information refers to another diagnostic with the same
+    /// source, code and range into that diagnostic's group.
+    #[default]
+    BySourceCodeRange,
+    /// Group each diagnostic with its related information, but never merge
+    /// separately reported diagnostics.
+    ByRelatedInfo,
+    /// Put each diagnostic in its own group, ignoring related information.
+    None,
 }
 
 #[derive(Copy, Clone, Debug, Default, Serialize, Deserialize, JsonSchema)]
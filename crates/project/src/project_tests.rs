@@ -4,7 +4,7 @@ use futures::{future, StreamExt};
 use gpui::{AppContext, SemanticVersion, UpdateGlobal};
 use language::{
     language_settings::{AllLanguageSettings, LanguageSettingsContent},
-    tree_sitter_rust, tree_sitter_typescript, Diagnostic, FakeLspAdapter, LanguageConfig,
+    tree_sitter_rust, tree_sitter_typescript, Diagnostic, Encoding, FakeLspAdapter, LanguageConfig,
     LanguageMatcher, LineEnding, OffsetRangeExt, Point, ToPoint,
 };
 use lsp::NumberOrString;
@@ -471,7 +471,9 @@ async fn test_managing_language_servers(cx: &mut gpui::TestAppContext) {
 
     // Save notifications are reported to all servers.
     project
-        .update(cx, |project, cx| project.save_buffer(toml_buffer, cx))
+        .update(cx, |project, cx| {
+            project.save_buffer(toml_buffer, false, cx)
+        })
         .await
         .unwrap();
     assert_eq!(
@@ -665,6 +667,60 @@ async fn test_managing_language_servers(cx: &mut gpui::TestAppContext) {
     );
 }
 
+#[gpui::test]
+async fn test_configured_completion_triggers(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |settings, cx| {
+            settings.update_user_settings::<AllLanguageSettings>(cx, |settings| {
+                settings.languages.insert(
+                    Arc::from("Rust"),
+                    LanguageSettingsContent {
+                        completion_triggers: Some(vec!["/".into(), ".".into()]),
+                        ..Default::default()
+                    },
+                );
+            });
+        })
+    });
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": "fn main() {}\n" }))
+        .await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(rust_lang());
+    let mut fake_servers = language_registry.register_fake_lsp_adapter(
+        "Rust",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                completion_provider: Some(lsp::CompletionOptions {
+                    trigger_characters: Some(vec![".".to_string(), "::".to_string()]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+    fake_servers.next().await.unwrap();
+    cx.executor().run_until_parked();
+
+    // The server's triggers are combined with the configured ones.
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(
+            buffer.completion_triggers(),
+            &[".".to_string(), "::".to_string(), "/".to_string()]
+        );
+    });
+}
+
 #[gpui::test]
 async fn test_reporting_fs_changes_to_language_servers(cx: &mut gpui::TestAppContext) {
     init_test(cx);
@@ -1418,6 +1474,154 @@ async fn test_restarted_server_reporting_invalid_buffer_version(cx: &mut gpui::T
     assert_eq!(notification.version, 0);
 }
 
+#[gpui::test]
+async fn test_diagnostics_for_discarded_buffer_version(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": "fn a() { A }" }))
+        .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+
+    language_registry.add(rust_lang());
+    let mut fake_servers =
+        language_registry.register_fake_lsp_adapter("Rust", FakeLspAdapter::default());
+
+    let buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+
+    let mut fake_server = fake_servers.next().await.unwrap();
+    let open_version = fake_server
+        .receive_notification::<lsp::notification::DidOpenTextDocument>()
+        .await
+        .text_document
+        .version;
+
+    // Edit the buffer many times, and resolve edits from the server against the
+    // latest version, which discards the snapshots of the oldest versions.
+    let mut latest_version = open_version;
+    for _ in 0..20 {
+        buffer.update(cx, |buffer, cx| {
+            let len = buffer.len();
+            buffer.edit([(len..len, "\n")], None, cx)
+        });
+        latest_version = fake_server
+            .receive_notification::<lsp::notification::DidChangeTextDocument>()
+            .await
+            .text_document
+            .version;
+    }
+    let server_id = fake_server.server.server_id();
+    project
+        .update(cx, |project, cx| {
+            project.edits_from_lsp(&buffer, Vec::new(), server_id, Some(latest_version), cx)
+        })
+        .await
+        .unwrap();
+
+    // Diagnostics for a discarded version are interpreted against the current contents.
+    fake_server.notify::<lsp::notification::PublishDiagnostics>(lsp::PublishDiagnosticsParams {
+        uri: lsp::Url::from_file_path("/dir/a.rs").unwrap(),
+        version: Some(open_version),
+        diagnostics: vec![lsp::Diagnostic {
+            range: lsp::Range::new(lsp::Position::new(0, 9), lsp::Position::new(0, 10)),
+            severity: Some(DiagnosticSeverity::ERROR),
+            message: "undefined variable 'A'".to_string(),
+            ..Default::default()
+        }],
+    });
+    cx.executor().run_until_parked();
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(
+            buffer
+                .snapshot()
+                .diagnostics_in_range::<_, Point>(0..buffer.len(), false)
+                .map(|entry| (entry.range, entry.diagnostic.message))
+                .collect::<Vec<_>>(),
+            [(
+                Point::new(0, 9)..Point::new(0, 10),
+                "undefined variable 'A'".to_string()
+            )]
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_diagnostics_for_stale_buffer_version(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": "fn a() { A }" }))
+        .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+
+    language_registry.add(rust_lang());
+    let mut fake_servers =
+        language_registry.register_fake_lsp_adapter("Rust", FakeLspAdapter::default());
+
+    let buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+
+    let mut fake_server = fake_servers.next().await.unwrap();
+    let open_version = fake_server
+        .receive_notification::<lsp::notification::DidOpenTextDocument>()
+        .await
+        .text_document
+        .version;
+
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    let latest_version = fake_server
+        .receive_notification::<lsp::notification::DidChangeTextDocument>()
+        .await
+        .text_document
+        .version;
+
+    // The server publishes diagnostics for the latest version before those for
+    // the original version, which are then ignored.
+    fake_server.notify::<lsp::notification::PublishDiagnostics>(lsp::PublishDiagnosticsParams {
+        uri: lsp::Url::from_file_path("/dir/a.rs").unwrap(),
+        version: Some(latest_version),
+        diagnostics: vec![lsp::Diagnostic {
+            range: lsp::Range::new(lsp::Position::new(1, 9), lsp::Position::new(1, 10)),
+            severity: Some(DiagnosticSeverity::ERROR),
+            message: "undefined variable 'A'".to_string(),
+            ..Default::default()
+        }],
+    });
+    fake_server.notify::<lsp::notification::PublishDiagnostics>(lsp::PublishDiagnosticsParams {
+        uri: lsp::Url::from_file_path("/dir/a.rs").unwrap(),
+        version: Some(open_version),
+        diagnostics: vec![lsp::Diagnostic {
+            range: lsp::Range::new(lsp::Position::new(0, 3), lsp::Position::new(0, 4)),
+            severity: Some(DiagnosticSeverity::WARNING),
+            message: "unused function 'a'".to_string(),
+            ..Default::default()
+        }],
+    });
+    cx.executor().run_until_parked();
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(
+            buffer
+                .snapshot()
+                .diagnostics_in_range::<_, Point>(0..buffer.len(), false)
+                .map(|entry| (entry.range, entry.diagnostic.message))
+                .collect::<Vec<_>>(),
+            [(
+                Point::new(1, 9)..Point::new(1, 10),
+                "undefined variable 'A'".to_string()
+            )]
+        );
+    });
+}
+
 #[gpui::test]
 async fn test_cancel_language_server_work(cx: &mut gpui::TestAppContext) {
     init_test(cx);
@@ -1595,19 +1799,13 @@ async fn test_toggling_enable_language_server(cx: &mut gpui::TestAppContext) {
         .await;
 }
 
-#[gpui::test(iterations = 3)]
-async fn test_transforming_diagnostics(cx: &mut gpui::TestAppContext) {
+#[gpui::test]
+async fn test_pending_diagnostics_events(cx: &mut gpui::TestAppContext) {
     init_test(cx);
 
-    let text = "
-        fn a() { A }
-        fn b() { BB }
-        fn c() { CCC }
-    "
-    .unindent();
-
     let fs = FakeFs::new(cx.executor());
-    fs.insert_tree("/dir", json!({ "a.rs": text })).await;
+    fs.insert_tree("/dir", json!({ "a.rs": "fn a() { A }" }))
+        .await;
 
     let project = Project::test(fs, ["/dir".as_ref()], cx).await;
     let language_registry = project.read_with(cx, |project, _| project.languages().clone());
@@ -1625,62 +1823,394 @@ async fn test_transforming_diagnostics(cx: &mut gpui::TestAppContext) {
         .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
         .await
         .unwrap();
+    let buffer_id = buffer.read_with(cx, |buffer, _| buffer.remote_id());
 
     let mut fake_server = fake_servers.next().await.unwrap();
+    let server_id = fake_server.server.server_id();
     let open_notification = fake_server
         .receive_notification::<lsp::notification::DidOpenTextDocument>()
         .await;
 
-    // Edit the buffer, moving the content down
-    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n\n")], None, cx));
-    let change_notification_1 = fake_server
+    let events = Arc::new(Mutex::new(Vec::new()));
+    cx.update(|cx| {
+        let events = events.clone();
+        cx.subscribe(&project, move |_, event, _| match event {
+            Event::DiagnosticsPending { .. } | Event::DiagnosticsCaughtUp { .. } => {
+                events.lock().push(event.clone())
+            }
+            _ => {}
+        })
+        .detach();
+    });
+
+    // Editing the buffer sends a new version to the server.
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    fake_server
         .receive_notification::<lsp::notification::DidChangeTextDocument>()
         .await;
-    assert!(change_notification_1.text_document.version > open_notification.text_document.version);
+    assert_eq!(
+        mem::take(&mut *events.lock()),
+        [Event::DiagnosticsPending {
+            buffer_id,
+            language_server_id: server_id,
+        }]
+    );
+
+    // Further edits don't emit another event while diagnostics are pending.
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    let change_notification = fake_server
+        .receive_notification::<lsp::notification::DidChangeTextDocument>()
+        .await;
+    assert!(events.lock().is_empty());
+
+    // Diagnostics for older versions don't resolve the pending version.
+    let latest_version = change_notification.text_document.version;
+    for version in [open_notification.text_document.version, latest_version - 1] {
+        fake_server.notify::<lsp::notification::PublishDiagnostics>(
+            lsp::PublishDiagnosticsParams {
+                uri: lsp::Url::from_file_path("/dir/a.rs").unwrap(),
+                version: Some(version),
+                diagnostics: Vec::new(),
+            },
+        );
+        cx.executor().run_until_parked();
+        assert!(events.lock().is_empty());
+    }
 
-    // Report some diagnostics for the initial version of the buffer
     fake_server.notify::<lsp::notification::PublishDiagnostics>(lsp::PublishDiagnosticsParams {
         uri: lsp::Url::from_file_path("/dir/a.rs").unwrap(),
-        version: Some(open_notification.text_document.version),
-        diagnostics: vec![
-            lsp::Diagnostic {
-                range: lsp::Range::new(lsp::Position::new(0, 9), lsp::Position::new(0, 10)),
-                severity: Some(DiagnosticSeverity::ERROR),
-                message: "undefined variable 'A'".to_string(),
-                source: Some("disk".to_string()),
-                ..Default::default()
-            },
-            lsp::Diagnostic {
-                range: lsp::Range::new(lsp::Position::new(1, 9), lsp::Position::new(1, 11)),
-                severity: Some(DiagnosticSeverity::ERROR),
-                message: "undefined variable 'BB'".to_string(),
-                source: Some("disk".to_string()),
-                ..Default::default()
-            },
-            lsp::Diagnostic {
-                range: lsp::Range::new(lsp::Position::new(2, 9), lsp::Position::new(2, 12)),
-                severity: Some(DiagnosticSeverity::ERROR),
-                source: Some("disk".to_string()),
-                message: "undefined variable 'CCC'".to_string(),
+        version: Some(latest_version),
+        diagnostics: Vec::new(),
+    });
+    cx.executor().run_until_parked();
+    assert_eq!(
+        mem::take(&mut *events.lock()),
+        [Event::DiagnosticsCaughtUp {
+            buffer_id,
+            language_server_id: server_id,
+        }]
+    );
+}
+
+#[gpui::test]
+async fn test_language_server_error_events(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": "fn a() { A }" }))
+        .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+
+    language_registry.add(rust_lang());
+    let mut fake_servers = language_registry.register_fake_lsp_adapter(
+        "Rust",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                definition_provider: Some(lsp::OneOf::Left(true)),
                 ..Default::default()
             },
-        ],
-    });
+            ..Default::default()
+        },
+    );
 
-    // The diagnostics have moved down since they were created.
-    cx.executor().run_until_parked();
-    buffer.update(cx, |buffer, _| {
-        assert_eq!(
-            buffer
-                .snapshot()
-                .diagnostics_in_range::<_, Point>(Point::new(3, 0)..Point::new(5, 0), false)
-                .collect::<Vec<_>>(),
-            &[
-                DiagnosticEntry {
-                    range: Point::new(3, 9)..Point::new(3, 11),
-                    diagnostic: Diagnostic {
-                        source: Some("disk".into()),
-                        severity: DiagnosticSeverity::ERROR,
+    let buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+
+    let mut fake_server = fake_servers.next().await.unwrap();
+    let server_id = fake_server.server.server_id();
+    fake_server
+        .receive_notification::<lsp::notification::DidOpenTextDocument>()
+        .await;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    cx.update(|cx| {
+        let events = events.clone();
+        cx.subscribe(&project, move |_, event, _| {
+            if let Event::LanguageServerError(..) = event {
+                events.lock().push(event.clone())
+            }
+        })
+        .detach();
+    });
+
+    // Edits are sent to a running server without errors.
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    fake_server
+        .receive_notification::<lsp::notification::DidChangeTextDocument>()
+        .await;
+    assert!(events.lock().is_empty());
+
+    // Requests that the server fails are reported.
+    fake_server.handle_request::<lsp::request::GotoDefinition, _, _>(|_, _| async move {
+        Err(anyhow!("no definition"))
+    });
+    let definitions = project
+        .update(cx, |project, cx| project.definition(&buffer, 0, cx))
+        .await;
+    assert!(definitions.is_err());
+    let request_errors = mem::take(&mut *events.lock());
+    assert_eq!(request_errors.len(), 1);
+    assert!(matches!(
+        &request_errors[0],
+        Event::LanguageServerError(id, _) if *id == server_id
+    ));
+
+    // Once the connection to the server is closed, sending an edit fails.
+    let server = project.read_with(cx, |project, cx| {
+        let (_, server) = project
+            .language_server_for_buffer(buffer.read(cx), server_id, cx)
+            .unwrap();
+        server.clone()
+    });
+    drop(server.shutdown());
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    let events = mem::take(&mut *events.lock());
+    assert_eq!(events.len(), 1);
+    assert!(matches!(
+        &events[0],
+        Event::LanguageServerError(id, _) if *id == server_id
+    ));
+}
+
+#[gpui::test]
+async fn test_pending_diagnostics_cleared_when_server_stops(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": "fn a() { A }" }))
+        .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+
+    language_registry.add(rust_lang());
+    let mut fake_servers = language_registry.register_fake_lsp_adapter(
+        "Rust",
+        FakeLspAdapter {
+            disk_based_diagnostics_sources: vec!["disk".into()],
+            ..Default::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+    let buffer_id = buffer.read_with(cx, |buffer, _| buffer.remote_id());
+
+    let mut fake_server = fake_servers.next().await.unwrap();
+    let server_id = fake_server.server.server_id();
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    fake_server
+        .receive_notification::<lsp::notification::DidChangeTextDocument>()
+        .await;
+    project.read_with(cx, |project, _| {
+        assert!(project
+            .pending_diagnostics_versions
+            .contains_key(&(buffer_id, server_id)));
+    });
+
+    // Restarting the server drops the version it never published diagnostics for.
+    project.update(cx, |project, cx| {
+        project.restart_language_servers_for_buffers([buffer.clone()], cx);
+    });
+    let mut fake_server = fake_servers.next().await.unwrap();
+    let new_server_id = fake_server.server.server_id();
+    cx.executor().run_until_parked();
+    project.read_with(cx, |project, _| {
+        assert!(project.pending_diagnostics_versions.is_empty());
+    });
+
+    // The restarted server starts tracking pending versions afresh.
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    fake_server
+        .receive_notification::<lsp::notification::DidChangeTextDocument>()
+        .await;
+    project.read_with(cx, |project, _| {
+        assert_eq!(
+            project
+                .pending_diagnostics_versions
+                .keys()
+                .copied()
+                .collect::<Vec<_>>(),
+            [(buffer_id, new_server_id)]
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_pending_diagnostics_for_servers_that_only_push_diagnostics(
+    cx: &mut gpui::TestAppContext,
+) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": "fn a() { A }" }))
+        .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+
+    language_registry.add(rust_lang());
+    let mut fake_servers = language_registry.register_fake_lsp_adapter("Rust", Default::default());
+
+    let buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+    let buffer_id = buffer.read_with(cx, |buffer, _| buffer.remote_id());
+    let mut fake_server = fake_servers.next().await.unwrap();
+    let server_id = fake_server.server.server_id();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    cx.update(|cx| {
+        let events = events.clone();
+        cx.subscribe(&project, move |_, event, _| match event {
+            Event::DiagnosticsPending { .. } | Event::DiagnosticsCaughtUp { .. } => {
+                events.lock().push(event.clone())
+            }
+            _ => {}
+        })
+        .detach();
+    });
+
+    // The server doesn't advertise diagnostics, so it may never publish any.
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    let change_notification = fake_server
+        .receive_notification::<lsp::notification::DidChangeTextDocument>()
+        .await;
+    cx.executor().run_until_parked();
+    assert!(events.lock().is_empty());
+    project.read_with(cx, |project, _| {
+        assert!(project.pending_diagnostics_versions.is_empty());
+    });
+
+    // Once it has published diagnostics, its pending versions are tracked.
+    fake_server.notify::<lsp::notification::PublishDiagnostics>(lsp::PublishDiagnosticsParams {
+        uri: lsp::Url::from_file_path("/dir/a.rs").unwrap(),
+        version: Some(change_notification.text_document.version),
+        diagnostics: Vec::new(),
+    });
+    cx.executor().run_until_parked();
+    assert!(events.lock().is_empty());
+
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    let change_notification = fake_server
+        .receive_notification::<lsp::notification::DidChangeTextDocument>()
+        .await;
+    assert_eq!(
+        mem::take(&mut *events.lock()),
+        [Event::DiagnosticsPending {
+            buffer_id,
+            language_server_id: server_id,
+        }]
+    );
+
+    fake_server.notify::<lsp::notification::PublishDiagnostics>(lsp::PublishDiagnosticsParams {
+        uri: lsp::Url::from_file_path("/dir/a.rs").unwrap(),
+        version: Some(change_notification.text_document.version),
+        diagnostics: Vec::new(),
+    });
+    cx.executor().run_until_parked();
+    assert_eq!(
+        mem::take(&mut *events.lock()),
+        [Event::DiagnosticsCaughtUp {
+            buffer_id,
+            language_server_id: server_id,
+        }]
+    );
+}
+
+#[gpui::test(iterations = 3)]
+async fn test_transforming_diagnostics(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let text = "
+        fn a() { A }
+        fn b() { BB }
+        fn c() { CCC }
+    "
+    .unindent();
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": text })).await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+
+    language_registry.add(rust_lang());
+    let mut fake_servers = language_registry.register_fake_lsp_adapter(
+        "Rust",
+        FakeLspAdapter {
+            disk_based_diagnostics_sources: vec!["disk".into()],
+            ..Default::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+
+    let mut fake_server = fake_servers.next().await.unwrap();
+    let open_notification = fake_server
+        .receive_notification::<lsp::notification::DidOpenTextDocument>()
+        .await;
+
+    // Edit the buffer, moving the content down
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n\n")], None, cx));
+    let change_notification_1 = fake_server
+        .receive_notification::<lsp::notification::DidChangeTextDocument>()
+        .await;
+    assert!(change_notification_1.text_document.version > open_notification.text_document.version);
+
+    // Report some diagnostics for the initial version of the buffer
+    fake_server.notify::<lsp::notification::PublishDiagnostics>(lsp::PublishDiagnosticsParams {
+        uri: lsp::Url::from_file_path("/dir/a.rs").unwrap(),
+        version: Some(open_notification.text_document.version),
+        diagnostics: vec![
+            lsp::Diagnostic {
+                range: lsp::Range::new(lsp::Position::new(0, 9), lsp::Position::new(0, 10)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                message: "undefined variable 'A'".to_string(),
+                source: Some("disk".to_string()),
+                ..Default::default()
+            },
+            lsp::Diagnostic {
+                range: lsp::Range::new(lsp::Position::new(1, 9), lsp::Position::new(1, 11)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                message: "undefined variable 'BB'".to_string(),
+                source: Some("disk".to_string()),
+                ..Default::default()
+            },
+            lsp::Diagnostic {
+                range: lsp::Range::new(lsp::Position::new(2, 9), lsp::Position::new(2, 12)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("disk".to_string()),
+                message: "undefined variable 'CCC'".to_string(),
+                ..Default::default()
+            },
+        ],
+    });
+
+    // The diagnostics have moved down since they were created.
+    cx.executor().run_until_parked();
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(
+            buffer
+                .snapshot()
+                .diagnostics_in_range::<_, Point>(Point::new(3, 0)..Point::new(5, 0), false)
+                .collect::<Vec<_>>(),
+            &[
+                DiagnosticEntry {
+                    range: Point::new(3, 9)..Point::new(3, 11),
+                    diagnostic: Diagnostic {
+                        source: Some("disk".into()),
+                        severity: DiagnosticSeverity::ERROR,
                         message: "undefined variable 'BB'".to_string(),
                         is_disk_based: true,
                         group_id: 1,
@@ -2478,14 +3008,15 @@ async fn test_definition(cx: &mut gpui::TestAppContext) {
 }
 
 #[gpui::test]
-async fn test_completions_without_edit_ranges(cx: &mut gpui::TestAppContext) {
+async fn test_type_definition_and_implementation(cx: &mut gpui::TestAppContext) {
     init_test(cx);
 
     let fs = FakeFs::new(cx.executor());
     fs.insert_tree(
         "/dir",
         json!({
-            "a.ts": "",
+            "a.rs": "struct A; impl Tr for A {}",
+            "b.rs": "fn b(a: crate::A) { a.f() }",
         }),
     )
     .await;
@@ -2493,43 +3024,271 @@ async fn test_completions_without_edit_ranges(cx: &mut gpui::TestAppContext) {
     let project = Project::test(fs, ["/dir".as_ref()], cx).await;
 
     let language_registry = project.read_with(cx, |project, _| project.languages().clone());
-    language_registry.add(typescript_lang());
-    let mut fake_language_servers = language_registry.register_fake_lsp_adapter(
-        "TypeScript",
-        FakeLspAdapter {
-            capabilities: lsp::ServerCapabilities {
-                completion_provider: Some(lsp::CompletionOptions {
-                    trigger_characters: Some(vec![":".to_string()]),
-                    ..Default::default()
-                }),
-                ..Default::default()
-            },
-            ..Default::default()
-        },
-    );
+    language_registry.add(rust_lang());
+    let mut fake_servers =
+        language_registry.register_fake_lsp_adapter("Rust", FakeLspAdapter::default());
 
     let buffer = project
-        .update(cx, |p, cx| p.open_local_buffer("/dir/a.ts", cx))
+        .update(cx, |project, cx| project.open_local_buffer("/dir/b.rs", cx))
         .await
         .unwrap();
 
-    let fake_server = fake_language_servers.next().await.unwrap();
+    let fake_server = fake_servers.next().await.unwrap();
+    fake_server.handle_request::<lsp::request::GotoTypeDefinition, _, _>(|params, _| async move {
+        let params = params.text_document_position_params;
+        assert_eq!(
+            params.text_document.uri.to_file_path().unwrap(),
+            Path::new("/dir/b.rs"),
+        );
+        assert_eq!(params.position, lsp::Position::new(0, 20));
 
-    let text = "let a = b.fqn";
-    buffer.update(cx, |buffer, cx| buffer.set_text(text, cx));
-    let completions = project.update(cx, |project, cx| {
-        project.completions(&buffer, text.len(), DEFAULT_COMPLETION_CONTEXT, cx)
+        Ok(Some(lsp::GotoDefinitionResponse::Scalar(
+            lsp::Location::new(
+                lsp::Url::from_file_path("/dir/a.rs").unwrap(),
+                lsp::Range::new(lsp::Position::new(0, 7), lsp::Position::new(0, 8)),
+            ),
+        )))
     });
+    fake_server.handle_request::<lsp::request::GotoImplementation, _, _>(|params, _| async move {
+        let params = params.text_document_position_params;
+        assert_eq!(params.position, lsp::Position::new(0, 20));
 
-    fake_server
-        .handle_request::<lsp::request::Completion, _, _>(|_, _| async move {
-            Ok(Some(lsp::CompletionResponse::Array(vec![
-                lsp::CompletionItem {
-                    label: "fullyQualifiedName?".into(),
-                    insert_text: Some("fullyQualifiedName".into()),
-                    ..Default::default()
-                },
-            ])))
+        Ok(Some(lsp::GotoDefinitionResponse::Array(vec![
+            lsp::Location::new(
+                lsp::Url::from_file_path("/dir/a.rs").unwrap(),
+                lsp::Range::new(lsp::Position::new(0, 10), lsp::Position::new(0, 26)),
+            ),
+        ])))
+    });
+
+    let type_definitions = project
+        .update(cx, |project, cx| project.type_definition(&buffer, 20, cx))
+        .await
+        .unwrap();
+    let implementations = project
+        .update(cx, |project, cx| project.implementation(&buffer, 20, cx))
+        .await
+        .unwrap();
+
+    cx.update(|cx| {
+        for (locations, expected_range) in [(type_definitions, 7..8), (implementations, 10..26)] {
+            assert_eq!(locations.len(), 1);
+            let target_buffer = locations[0].target.buffer.read(cx);
+            assert_eq!(
+                target_buffer
+                    .file()
+                    .unwrap()
+                    .as_local()
+                    .unwrap()
+                    .abs_path(cx),
+                Path::new("/dir/a.rs"),
+            );
+            assert_eq!(
+                locations[0].target.range.to_offset(target_buffer),
+                expected_range
+            );
+        }
+    });
+}
+
+#[gpui::test]
+async fn test_workspace_configuration_request(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": "fn a() {}" })).await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(rust_lang());
+    let mut fake_servers = language_registry.register_fake_lsp_adapter(
+        "Rust",
+        FakeLspAdapter {
+            workspace_configuration: Some(json!({
+                "rust-analyzer": {
+                    "cargo": { "features": "all" },
+                },
+            })),
+            ..Default::default()
+        },
+    );
+
+    let _buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+    let fake_server = fake_servers.next().await.unwrap();
+
+    let section = |section: Option<&str>| lsp::ConfigurationItem {
+        scope_uri: None,
+        section: section.map(ToString::to_string),
+    };
+    let response = fake_server
+        .request::<lsp::request::WorkspaceConfiguration>(lsp::ConfigurationParams {
+            items: vec![
+                section(Some("rust-analyzer")),
+                section(Some("rust-analyzer.cargo.features")),
+                section(Some("rust-analyzer.missing")),
+            ],
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        response,
+        [
+            json!({ "cargo": { "features": "all" } }),
+            json!("all"),
+            serde_json::Value::Null,
+        ]
+    );
+}
+
+#[gpui::test]
+async fn test_server_supports(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": "fn a() {}" })).await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(rust_lang());
+    let mut fake_servers = language_registry.register_fake_lsp_adapter(
+        "Rust",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                document_formatting_provider: Some(lsp::OneOf::Left(true)),
+                rename_provider: Some(lsp::OneOf::Left(false)),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+
+    let _fake_server = fake_servers.next().await.unwrap();
+    cx.executor().run_until_parked();
+
+    project.read_with(cx, |project, cx| {
+        assert!(project.has_language_server(&buffer, cx));
+        assert!(project.server_supports(&buffer, ServerCapability::Format, cx));
+        assert!(!project.server_supports(&buffer, ServerCapability::RangeFormat, cx));
+        assert!(!project.server_supports(&buffer, ServerCapability::Rename, cx));
+    });
+}
+
+#[gpui::test]
+async fn test_format_without_formatting_capability(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": "fn a()   {}\n" }))
+        .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(rust_lang());
+    let mut fake_servers = language_registry.register_fake_lsp_adapter(
+        "Rust",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                hover_provider: Some(lsp::HoverProviderCapability::Simple(true)),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+
+    let fake_server = fake_servers.next().await.unwrap();
+    fake_server.handle_request::<lsp::request::Formatting, _, _>(|_, _| async move {
+        panic!("formatting request sent to a server that doesn't support it")
+    });
+    cx.executor().run_until_parked();
+
+    project.read_with(cx, |project, cx| {
+        assert!(project.server_supports(&buffer, ServerCapability::Hover, cx));
+        assert!(!project.server_supports(&buffer, ServerCapability::Format, cx));
+    });
+
+    project
+        .update(cx, |project, cx| {
+            project.format(
+                HashSet::from_iter([buffer.clone()]),
+                true,
+                FormatTrigger::Manual,
+                cx,
+            )
+        })
+        .await
+        .unwrap();
+    buffer.read_with(cx, |buffer, _| assert_eq!(buffer.text(), "fn a()   {}\n"));
+}
+
+#[gpui::test]
+async fn test_completions_without_edit_ranges(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            "a.ts": "",
+        }),
+    )
+    .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(typescript_lang());
+    let mut fake_language_servers = language_registry.register_fake_lsp_adapter(
+        "TypeScript",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                completion_provider: Some(lsp::CompletionOptions {
+                    trigger_characters: Some(vec![":".to_string()]),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/a.ts", cx))
+        .await
+        .unwrap();
+
+    let fake_server = fake_language_servers.next().await.unwrap();
+
+    let text = "let a = b.fqn";
+    buffer.update(cx, |buffer, cx| buffer.set_text(text, cx));
+    let completions = project.update(cx, |project, cx| {
+        project.completions(&buffer, text.len(), DEFAULT_COMPLETION_CONTEXT, cx)
+    });
+
+    fake_server
+        .handle_request::<lsp::request::Completion, _, _>(|_, _| async move {
+            Ok(Some(lsp::CompletionResponse::Array(vec![
+                lsp::CompletionItem {
+                    label: "fullyQualifiedName?".into(),
+                    insert_text: Some("fullyQualifiedName".into()),
+                    ..Default::default()
+                },
+            ])))
         })
         .next()
         .await;
@@ -2545,27 +3304,385 @@ async fn test_completions_without_edit_ranges(cx: &mut gpui::TestAppContext) {
     let text = "let a = \"atoms/cmp\"";
     buffer.update(cx, |buffer, cx| buffer.set_text(text, cx));
     let completions = project.update(cx, |project, cx| {
-        project.completions(&buffer, text.len() - 1, DEFAULT_COMPLETION_CONTEXT, cx)
+        project.completions(&buffer, text.len() - 1, DEFAULT_COMPLETION_CONTEXT, cx)
+    });
+
+    fake_server
+        .handle_request::<lsp::request::Completion, _, _>(|_, _| async move {
+            Ok(Some(lsp::CompletionResponse::Array(vec![
+                lsp::CompletionItem {
+                    label: "component".into(),
+                    ..Default::default()
+                },
+            ])))
+        })
+        .next()
+        .await;
+    let completions = completions.await.unwrap();
+    let snapshot = buffer.update(cx, |buffer, _| buffer.snapshot());
+    assert_eq!(completions.len(), 1);
+    assert_eq!(completions[0].new_text, "component");
+    assert_eq!(
+        completions[0].old_range.to_offset(&snapshot),
+        text.len() - 4..text.len() - 1
+    );
+}
+
+#[gpui::test]
+async fn test_completions_after_intervening_edits(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            "a.ts": "",
+        }),
+    )
+    .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(typescript_lang());
+    let mut fake_language_servers = language_registry.register_fake_lsp_adapter(
+        "TypeScript",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                completion_provider: Some(lsp::CompletionOptions::default()),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/a.ts", cx))
+        .await
+        .unwrap();
+
+    let fake_server = fake_language_servers.next().await.unwrap();
+
+    let text = "let a = b.fqn";
+    buffer.update(cx, |buffer, cx| buffer.set_text(text, cx));
+
+    // Hold the response until the buffer has been edited above the cursor.
+    let (response_tx, response_rx) = futures::channel::oneshot::channel::<()>();
+    let response_rx = Arc::new(Mutex::new(Some(response_rx)));
+    let _requests = fake_server.handle_request::<lsp::request::Completion, _, _>(move |_, _| {
+        let response_rx = response_rx.lock().take().unwrap();
+        async move {
+            response_rx.await.ok();
+            Ok(Some(lsp::CompletionResponse::Array(vec![
+                lsp::CompletionItem {
+                    label: "fullyQualifiedName".into(),
+                    text_edit: Some(lsp::CompletionTextEdit::Edit(lsp::TextEdit {
+                        range: lsp::Range::new(
+                            lsp::Position::new(0, 10),
+                            lsp::Position::new(0, 13),
+                        ),
+                        new_text: "fullyQualifiedName".into(),
+                    })),
+                    ..Default::default()
+                },
+            ])))
+        }
+    });
+
+    let completions = project.update(cx, |project, cx| {
+        project.completions(&buffer, text.len(), DEFAULT_COMPLETION_CONTEXT, cx)
+    });
+    cx.executor().run_until_parked();
+
+    let prefix = "// comment\n";
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, prefix)], None, cx));
+    response_tx.send(()).unwrap();
+
+    let completions = completions.await.unwrap();
+    let snapshot = buffer.update(cx, |buffer, _| buffer.snapshot());
+    assert_eq!(completions.len(), 1);
+    assert_eq!(
+        completions[0].old_range.to_offset(&snapshot),
+        prefix.len() + text.len() - 3..prefix.len() + text.len()
+    );
+}
+
+#[gpui::test]
+async fn test_resolve_completion(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            "a.ts": "",
+        }),
+    )
+    .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(typescript_lang());
+    let mut fake_language_servers = language_registry.register_fake_lsp_adapter(
+        "TypeScript",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                completion_provider: Some(lsp::CompletionOptions {
+                    resolve_provider: Some(true),
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/a.ts", cx))
+        .await
+        .unwrap();
+
+    let fake_server = fake_language_servers.next().await.unwrap();
+
+    let text = "let a = fqn";
+    buffer.update(cx, |buffer, cx| buffer.set_text(text, cx));
+
+    let completions = project.update(cx, |project, cx| {
+        project.completions(&buffer, text.len(), DEFAULT_COMPLETION_CONTEXT, cx)
     });
-
     fake_server
         .handle_request::<lsp::request::Completion, _, _>(|_, _| async move {
             Ok(Some(lsp::CompletionResponse::Array(vec![
                 lsp::CompletionItem {
-                    label: "component".into(),
+                    label: "fullyQualifiedName".into(),
                     ..Default::default()
                 },
             ])))
         })
         .next()
         .await;
-    let completions = completions.await.unwrap();
-    let snapshot = buffer.update(cx, |buffer, _| buffer.snapshot());
+    let mut completions = completions.await.unwrap();
     assert_eq!(completions.len(), 1);
-    assert_eq!(completions[0].new_text, "component");
+    assert_eq!(completions[0].lsp_completion.additional_text_edits, None);
+
+    let import_edit = lsp::TextEdit {
+        range: lsp::Range::new(lsp::Position::new(0, 0), lsp::Position::new(0, 0)),
+        new_text: "import { fullyQualifiedName } from 'b';\n".into(),
+    };
+    let resolve = project.update(cx, |project, cx| {
+        project.resolve_completion(buffer.clone(), completions.pop().unwrap(), cx)
+    });
+    fake_server
+        .handle_request::<lsp::request::ResolveCompletionItem, _, _>({
+            let import_edit = import_edit.clone();
+            move |item, _| {
+                let import_edit = import_edit.clone();
+                async move {
+                    Ok(lsp::CompletionItem {
+                        additional_text_edits: Some(vec![import_edit]),
+                        ..item
+                    })
+                }
+            }
+        })
+        .next()
+        .await;
+    let completion = resolve.await.unwrap();
     assert_eq!(
-        completions[0].old_range.to_offset(&snapshot),
-        text.len() - 4..text.len() - 1
+        completion.lsp_completion.additional_text_edits,
+        Some(vec![import_edit])
+    );
+    assert_eq!(completion.label.text, "fullyQualifiedName");
+}
+
+#[gpui::test]
+fn test_apply_completion_with_additional_edits(cx: &mut gpui::AppContext) {
+    let text = "use a::b;\nfn main() {\n    fqn\n}";
+    let buffer = cx.new_model(|cx| Buffer::local(text, cx));
+    buffer.update(cx, |buffer, cx| {
+        let request_snapshot = buffer.text_snapshot();
+        let completion_start = text.find("fqn").unwrap();
+        let replace_range = buffer.anchor_before(completion_start)
+            ..buffer.anchor_after(completion_start + "fqn".len());
+        let text_edit = |start: (u32, u32), end: (u32, u32), new_text: &str| lsp::TextEdit {
+            range: lsp::Range::new(
+                lsp::Position::new(start.0, start.1),
+                lsp::Position::new(end.0, end.1),
+            ),
+            new_text: new_text.into(),
+        };
+        let completion = Completion {
+            old_range: replace_range.clone(),
+            new_text: "fully_qualified_name()".into(),
+            label: CodeLabel::plain("fully_qualified_name".into(), None),
+            server_id: LanguageServerId(0),
+            documentation: None,
+            lsp_completion: lsp::CompletionItem {
+                label: "fully_qualified_name".into(),
+                additional_text_edits: Some(vec![
+                    text_edit((1, 0), (1, 0), "use a::fully_qualified_name;\r\n"),
+                    // Edits adjacent to the replaced range are applied...
+                    text_edit((2, 4), (2, 4), "self::"),
+                    // ...but edits overlapping it are skipped.
+                    text_edit((2, 5), (2, 6), "Q"),
+                ]),
+                ..Default::default()
+            },
+            confirm: None,
+            show_new_completions_on_confirm: false,
+        };
+
+        // Edits made after the completion was requested shift its additional edits.
+        buffer.edit([(0..0, "use c::d;\n")], None, cx);
+        assert!(completion
+            .apply(buffer, replace_range, &request_snapshot, cx)
+            .is_some());
+        assert_eq!(
+            buffer.text(),
+            "use c::d;\nuse a::b;\nuse a::fully_qualified_name;\nfn main() {\n    self::fully_qualified_name()\n}"
+        );
+
+        // A single undo reverts both the completion and its additional edits.
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), format!("use c::d;\n{text}"));
+    });
+}
+
+#[gpui::test]
+async fn test_hover_after_intervening_edits(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.ts": "let a = b.fqn" }))
+        .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(typescript_lang());
+    let mut fake_language_servers = language_registry.register_fake_lsp_adapter(
+        "TypeScript",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                hover_provider: Some(lsp::HoverProviderCapability::Simple(true)),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/a.ts", cx))
+        .await
+        .unwrap();
+
+    let fake_server = fake_language_servers.next().await.unwrap();
+
+    // Hold the response until the buffer has been edited above the hovered token.
+    let (response_tx, response_rx) = futures::channel::oneshot::channel::<()>();
+    let response_rx = Arc::new(Mutex::new(Some(response_rx)));
+    let _requests = fake_server.handle_request::<lsp::request::HoverRequest, _, _>(move |_, _| {
+        let response_rx = response_rx.lock().take().unwrap();
+        async move {
+            response_rx.await.ok();
+            Ok(Some(lsp::Hover {
+                contents: lsp::HoverContents::Scalar(lsp::MarkedString::String("fqn".into())),
+                range: Some(lsp::Range::new(
+                    lsp::Position::new(0, 10),
+                    lsp::Position::new(0, 13),
+                )),
+            }))
+        }
+    });
+
+    let hovers = project.update(cx, |project, cx| project.hover(&buffer, 11, cx));
+    cx.executor().run_until_parked();
+
+    let prefix = "// comment\n";
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, prefix)], None, cx));
+    response_tx.send(()).unwrap();
+
+    let hovers = hovers.await;
+    let snapshot = buffer.update(cx, |buffer, _| buffer.snapshot());
+    assert_eq!(hovers.len(), 1);
+    assert_eq!(
+        hovers[0].range.as_ref().unwrap().to_offset(&snapshot),
+        prefix.len() + 10..prefix.len() + 13
+    );
+}
+
+#[gpui::test]
+async fn test_definition_after_intervening_edits(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.ts": "let a = 1; a" }))
+        .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(typescript_lang());
+    let mut fake_language_servers =
+        language_registry.register_fake_lsp_adapter("TypeScript", FakeLspAdapter::default());
+
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/a.ts", cx))
+        .await
+        .unwrap();
+
+    let fake_server = fake_language_servers.next().await.unwrap();
+
+    // Hold the response until the buffer has been edited above both the origin and
+    // the target of the definition.
+    let (response_tx, response_rx) = futures::channel::oneshot::channel::<()>();
+    let response_rx = Arc::new(Mutex::new(Some(response_rx)));
+    let _requests =
+        fake_server.handle_request::<lsp::request::GotoDefinition, _, _>(move |params, _| {
+            let response_rx = response_rx.lock().take().unwrap();
+            let uri = params.text_document_position_params.text_document.uri;
+            async move {
+                response_rx.await.ok();
+                Ok(Some(lsp::GotoDefinitionResponse::Link(vec![
+                    lsp::LocationLink {
+                        origin_selection_range: Some(lsp::Range::new(
+                            lsp::Position::new(0, 11),
+                            lsp::Position::new(0, 12),
+                        )),
+                        target_uri: uri,
+                        target_range: lsp::Range::new(
+                            lsp::Position::new(0, 0),
+                            lsp::Position::new(0, 10),
+                        ),
+                        target_selection_range: lsp::Range::new(
+                            lsp::Position::new(0, 4),
+                            lsp::Position::new(0, 5),
+                        ),
+                    },
+                ])))
+            }
+        });
+
+    let definitions = project.update(cx, |project, cx| project.definition(&buffer, 11, cx));
+    cx.executor().run_until_parked();
+
+    let prefix = "// comment\n";
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, prefix)], None, cx));
+    response_tx.send(()).unwrap();
+
+    let definitions = definitions.await.unwrap();
+    let snapshot = buffer.update(cx, |buffer, _| buffer.snapshot());
+    assert_eq!(definitions.len(), 1);
+    let origin = definitions[0].origin.as_ref().unwrap();
+    assert_eq!(
+        origin.range.to_offset(&snapshot),
+        prefix.len() + 11..prefix.len() + 12
+    );
+    assert_eq!(definitions[0].target.buffer, buffer);
+    assert_eq!(
+        definitions[0].target.range.to_offset(&snapshot),
+        prefix.len() + 4..prefix.len() + 5
     );
 }
 
@@ -2752,26 +3869,242 @@ async fn test_apply_code_actions_with_commands(cx: &mut gpui::TestAppContext) {
         .next()
         .await;
 
-    // Applying the code action returns a project transaction containing the edits
-    // sent by the language server in its `workspaceEdit` request.
-    let transaction = apply.await.unwrap();
-    assert!(transaction.0.contains_key(&buffer));
-    buffer.update(cx, |buffer, cx| {
-        assert_eq!(buffer.text(), "Xa");
-        buffer.undo(cx);
-        assert_eq!(buffer.text(), "a");
+    // Applying the code action returns a project transaction containing the edits
+    // sent by the language server in its `workspaceEdit` request.
+    let transaction = apply.await.unwrap();
+    assert!(transaction.0.contains_key(&buffer));
+    buffer.update(cx, |buffer, cx| {
+        assert_eq!(buffer.text(), "Xa");
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), "a");
+    });
+}
+
+#[gpui::test]
+async fn test_diagnostic_group_is_fixable(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            "a.ts": "let a = b;",
+        }),
+    )
+    .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(typescript_lang());
+    let mut fake_language_servers = language_registry.register_fake_lsp_adapter(
+        "TypeScript",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                code_action_provider: Some(lsp::CodeActionProviderCapability::Simple(true)),
+                ..lsp::ServerCapabilities::default()
+            },
+            ..FakeLspAdapter::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/a.ts", cx))
+        .await
+        .unwrap();
+
+    let fake_server = fake_language_servers.next().await.unwrap();
+    fake_server.notify::<lsp::notification::PublishDiagnostics>(lsp::PublishDiagnosticsParams {
+        uri: Url::from_file_path("/dir/a.ts").unwrap(),
+        version: None,
+        diagnostics: vec![lsp::Diagnostic {
+            range: lsp::Range::new(lsp::Position::new(0, 8), lsp::Position::new(0, 9)),
+            severity: Some(lsp::DiagnosticSeverity::ERROR),
+            message: "cannot find name 'b'".to_string(),
+            ..Default::default()
+        }],
+    });
+    cx.executor().run_until_parked();
+
+    let group_id = buffer.update(cx, |buffer, _| {
+        let snapshot = buffer.snapshot();
+        let groups = snapshot.diagnostic_groups(None);
+        assert_eq!(groups.len(), 1);
+        let group_id = groups[0].1.entries[0].diagnostic.group_id;
+        assert_eq!(snapshot.diagnostic_is_fixable(group_id), None);
+        group_id
+    });
+
+    let fixable = project.update(cx, |project, cx| {
+        project.diagnostic_group_is_fixable(&buffer, group_id, cx)
+    });
+    fake_server
+        .handle_request::<lsp::request::CodeActionRequest, _, _>(|params, _| async move {
+            assert_eq!(
+                params.range,
+                lsp::Range::new(lsp::Position::new(0, 8), lsp::Position::new(0, 9))
+            );
+            Ok(Some(vec![lsp::CodeActionOrCommand::CodeAction(
+                lsp::CodeAction {
+                    title: "Declare 'b'".into(),
+                    ..lsp::CodeAction::default()
+                },
+            )]))
+        })
+        .next()
+        .await;
+    assert!(fixable.await);
+
+    // The result is cached on the buffer, so no further requests are made.
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(
+            buffer.snapshot().diagnostic_is_fixable(group_id),
+            Some(true)
+        );
+    });
+    let fixable = project.update(cx, |project, cx| {
+        project.diagnostic_group_is_fixable(&buffer, group_id, cx)
+    });
+    assert!(fixable.await);
+}
+
+#[gpui::test(iterations = 10)]
+async fn test_save_file(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            "file1": "the old contents",
+        }),
+    )
+    .await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/file1", cx))
+        .await
+        .unwrap();
+    buffer.update(cx, |buffer, cx| {
+        assert_eq!(buffer.text(), "the old contents");
+        buffer.edit([(0..0, "a line of text.\n".repeat(10 * 1024))], None, cx);
+    });
+
+    project
+        .update(cx, |project, cx| {
+            project.save_buffer(buffer.clone(), false, cx)
+        })
+        .await
+        .unwrap();
+
+    let new_text = fs.load(Path::new("/dir/file1")).await.unwrap();
+    assert_eq!(new_text, buffer.update(cx, |buffer, _| buffer.text()));
+}
+
+#[gpui::test]
+async fn test_save_file_preserves_encoding(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({})).await;
+    fs.insert_file("/dir/latin1.txt", b"caf\xe9\n".to_vec())
+        .await;
+    fs.insert_file("/dir/utf16.txt", b"\xff\xfeh\0i\0\n\0".to_vec())
+        .await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let latin1_buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/latin1.txt", cx))
+        .await
+        .unwrap();
+    latin1_buffer.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "café\n");
+        assert_eq!(buffer.encoding(), Encoding::Latin1);
+    });
+
+    // Saving an unmodified buffer writes back the exact same bytes.
+    project
+        .update(cx, |project, cx| {
+            project.save_buffer(latin1_buffer.clone(), false, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        fs.load_bytes(Path::new("/dir/latin1.txt")).await.unwrap(),
+        b"caf\xe9\n"
+    );
+
+    latin1_buffer.update(cx, |buffer, cx| {
+        buffer.edit([(buffer.len()..buffer.len(), "crème\n")], None, cx)
+    });
+    project
+        .update(cx, |project, cx| {
+            project.save_buffer(latin1_buffer.clone(), false, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        fs.load_bytes(Path::new("/dir/latin1.txt")).await.unwrap(),
+        b"caf\xe9\ncr\xe8me\n"
+    );
+
+    let utf16_buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/utf16.txt", cx))
+        .await
+        .unwrap();
+    utf16_buffer.update(cx, |buffer, cx| {
+        assert_eq!(buffer.text(), "hi\n");
+        assert_eq!(buffer.encoding(), Encoding::Utf16Le);
+        buffer.edit([(2..2, "!")], None, cx);
+    });
+    project
+        .update(cx, |project, cx| {
+            project.save_buffer(utf16_buffer.clone(), false, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        fs.load_bytes(Path::new("/dir/utf16.txt")).await.unwrap(),
+        b"\xff\xfeh\0i\0!\0\n\0"
+    );
+
+    // The encoding is detected again when the file changes on disk.
+    fs.insert_file("/dir/latin1.txt", "café\ncrème\n".as_bytes().to_vec())
+        .await;
+    cx.executor().run_until_parked();
+    latin1_buffer.update(cx, |buffer, cx| {
+        assert_eq!(buffer.text(), "café\ncrème\n");
+        assert_eq!(buffer.encoding(), Encoding::Utf8);
+        assert!(!buffer.is_dirty());
+
+        buffer.choose_encoding(Encoding::Latin1, cx);
+        buffer.edit([(buffer.len()..buffer.len(), "π\n")], None, cx);
     });
+
+    // Characters that the encoding can't represent fail the save instead of
+    // being replaced.
+    assert!(project
+        .update(cx, |project, cx| {
+            project.save_buffer(latin1_buffer.clone(), false, cx)
+        })
+        .await
+        .is_err());
+    assert_eq!(
+        fs.load_bytes(Path::new("/dir/latin1.txt")).await.unwrap(),
+        "café\ncrème\n".as_bytes()
+    );
 }
 
-#[gpui::test(iterations = 10)]
-async fn test_save_file(cx: &mut gpui::TestAppContext) {
+#[gpui::test]
+async fn test_save_file_runs_pre_save_hooks(cx: &mut gpui::TestAppContext) {
     init_test(cx);
 
     let fs = FakeFs::new(cx.executor());
     fs.insert_tree(
         "/dir",
         json!({
-            "file1": "the old contents",
+            "file1": "the first line\nthe second line\n",
         }),
     )
     .await;
@@ -2782,17 +4115,152 @@ async fn test_save_file(cx: &mut gpui::TestAppContext) {
         .await
         .unwrap();
     buffer.update(cx, |buffer, cx| {
-        assert_eq!(buffer.text(), "the old contents");
-        buffer.edit([(0..0, "a line of text.\n".repeat(10 * 1024))], None, cx);
+        buffer.add_pre_save_hook(Box::new(|buffer, cx| {
+            let first_line_len = buffer.line_len(0) as usize;
+            let first_line = buffer.text_for_range(0..first_line_len).collect::<String>();
+            buffer.edit([(0..first_line_len, first_line.to_uppercase())], None, cx);
+        }));
     });
 
     project
-        .update(cx, |project, cx| project.save_buffer(buffer.clone(), cx))
+        .update(cx, |project, cx| {
+            project.save_buffer(buffer.clone(), false, cx)
+        })
         .await
         .unwrap();
 
     let new_text = fs.load(Path::new("/dir/file1")).await.unwrap();
-    assert_eq!(new_text, buffer.update(cx, |buffer, _| buffer.text()));
+    assert_eq!(new_text, "THE FIRST LINE\nthe second line\n");
+    buffer.update(cx, |buffer, cx| {
+        assert_eq!(buffer.text(), new_text);
+        assert!(!buffer.is_dirty());
+
+        // The hook's edits are undone as a single transaction.
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), "the first line\nthe second line\n");
+    });
+}
+
+#[gpui::test]
+async fn test_save_file_formats_whitespace(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "file1": "one  \ntwo" }))
+        .await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/file1", cx))
+        .await
+        .unwrap();
+    buffer.update(cx, |buffer, cx| {
+        buffer.add_pre_save_hook(Box::new(|buffer, cx| {
+            buffer.edit([(buffer.len()..buffer.len(), "three ")], None, cx);
+        }));
+    });
+
+    // The built-in hook runs before the registered one, even when the buffer
+    // isn't formatted.
+    project
+        .update(cx, |project, cx| {
+            project.save_buffer(buffer.clone(), false, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        fs.load(Path::new("/dir/file1")).await.unwrap(),
+        "one\ntwo\nthree "
+    );
+}
+
+#[gpui::test]
+async fn test_save_file_applies_will_save_wait_until_edits(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "a.rs": "fn main() {}\n" }))
+        .await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(rust_lang());
+    let mut fake_servers = language_registry.register_fake_lsp_adapter(
+        "Rust",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                text_document_sync: Some(lsp::TextDocumentSyncCapability::Options(
+                    lsp::TextDocumentSyncOptions {
+                        will_save_wait_until: Some(true),
+                        ..Default::default()
+                    },
+                )),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |project, cx| project.open_local_buffer("/dir/a.rs", cx))
+        .await
+        .unwrap();
+    let fake_server = fake_servers.next().await.unwrap();
+    cx.executor().run_until_parked();
+
+    buffer.update(cx, |buffer, _| {
+        buffer.add_pre_save_hook(Box::new(|buffer, cx| {
+            let first_line_len = buffer.line_len(0) as usize;
+            let first_line = buffer.text_for_range(0..first_line_len).collect::<String>();
+            buffer.edit([(0..first_line_len, first_line.to_uppercase())], None, cx);
+        }));
+    });
+    fake_server.handle_request::<lsp::request::WillSaveWaitUntil, _, _>(|params, _| async move {
+        assert_eq!(params.reason, lsp::TextDocumentSaveReason::MANUAL);
+        assert_eq!(
+            params.text_document.uri,
+            lsp::Url::from_file_path("/dir/a.rs").unwrap()
+        );
+        Ok(Some(vec![lsp::TextEdit::new(
+            lsp::Range::new(lsp::Position::new(0, 0), lsp::Position::new(0, 0)),
+            "use std::io;\n".into(),
+        )]))
+    });
+
+    project
+        .update(cx, |project, cx| {
+            project.save_buffer(buffer.clone(), false, cx)
+        })
+        .await
+        .unwrap();
+
+    // The server's edits are applied after the pre-save hooks, before the file is written.
+    let new_text = fs.load(Path::new("/dir/a.rs")).await.unwrap();
+    assert_eq!(new_text, "use std::io;\nFN MAIN() {}\n");
+    buffer.update(cx, |buffer, cx| {
+        assert_eq!(buffer.text(), new_text);
+        assert!(!buffer.is_dirty());
+
+        // The server's edits are undone along with the pre-save hook's edits.
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), "fn main() {}\n");
+        buffer.redo(cx);
+    });
+
+    // If the server doesn't respond in time, the file is saved anyway.
+    fake_server.handle_request::<lsp::request::WillSaveWaitUntil, _, _>(|_, _| async move {
+        future::pending::<()>().await;
+        Ok(None)
+    });
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "// ")], None, cx));
+    let save = project.update(cx, |project, cx| {
+        project.save_buffer(buffer.clone(), false, cx)
+    });
+    cx.executor().run_until_parked();
+    cx.executor().advance_clock(WILL_SAVE_WAIT_UNTIL_TIMEOUT);
+    save.await.unwrap();
+    let new_text = fs.load(Path::new("/dir/a.rs")).await.unwrap();
+    assert_eq!(new_text, "// USE STD::IO;\nFN MAIN() {}\n");
 }
 
 #[gpui::test(iterations = 30)]
@@ -2914,6 +4382,60 @@ async fn test_edit_buffer_while_it_reloads(cx: &mut gpui::TestAppContext) {
     });
 }
 
+#[gpui::test]
+async fn test_reload_discards_unsaved_changes(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            "file1": "the original contents\n",
+        }),
+    )
+    .await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/file1", cx))
+        .await
+        .unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    buffer.update(cx, |_, cx| {
+        cx.subscribe(&buffer, {
+            let events = events.clone();
+            move |_, _, event, _| {
+                if let BufferEvent::Reloaded = event {
+                    events.lock().push(event.clone());
+                }
+            }
+        })
+        .detach();
+    });
+
+    buffer.update(cx, |buffer, cx| {
+        buffer.edit([(0..3, "some"), (12..12, " and edited")], None, cx);
+        buffer.set_line_ending(LineEnding::Windows, cx);
+        assert_eq!(buffer.text(), "some original and edited contents\n");
+        assert!(buffer.is_dirty());
+    });
+
+    let reload = buffer.update(cx, |buffer, cx| buffer.reload_discarding_changes(cx));
+    let transaction = reload.await.unwrap();
+    assert!(transaction.is_some());
+    buffer.update(cx, |buffer, cx| {
+        assert_eq!(buffer.text(), "the original contents\n");
+        assert_eq!(buffer.line_ending_at_save(), LineEnding::Unix);
+        assert!(!buffer.is_dirty());
+        assert!(!buffer.has_conflict());
+
+        // Reloading is undoable, restoring the discarded edits.
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), "some original and edited contents\n");
+    });
+    assert_eq!(events.lock().as_slice(), &[BufferEvent::Reloaded]);
+}
+
 #[gpui::test]
 async fn test_save_in_single_file_worktree(cx: &mut gpui::TestAppContext) {
     init_test(cx);
@@ -2937,7 +4459,9 @@ async fn test_save_in_single_file_worktree(cx: &mut gpui::TestAppContext) {
     });
 
     project
-        .update(cx, |project, cx| project.save_buffer(buffer.clone(), cx))
+        .update(cx, |project, cx| {
+            project.save_buffer(buffer.clone(), false, cx)
+        })
         .await
         .unwrap();
 
@@ -2975,7 +4499,8 @@ async fn test_save_as(cx: &mut gpui::TestAppContext) {
         })
         .await
         .unwrap();
-    assert_eq!(fs.load(Path::new("/dir/file1.rs")).await.unwrap(), "abc");
+    // The built-in pre-save hooks add a final newline.
+    assert_eq!(fs.load(Path::new("/dir/file1.rs")).await.unwrap(), "abc\n");
 
     cx.executor().run_until_parked();
     buffer.update(cx, |buffer, cx| {
@@ -3278,7 +4803,7 @@ async fn test_buffer_is_dirty(cx: &mut gpui::TestAppContext) {
         cx.subscribe(&buffer1, {
             let events = events.clone();
             move |_, _, event, _| match event {
-                BufferEvent::Operation(_) => {}
+                BufferEvent::Operations(_) => {}
                 _ => events.lock().push(event.clone()),
             }
         })
@@ -3464,12 +4989,181 @@ async fn test_buffer_file_changes_on_disk(cx: &mut gpui::TestAppContext) {
     .await
     .unwrap();
 
-    // Because the buffer is modified, it doesn't reload from disk, but is
-    // marked as having a conflict.
+    // Because the new lines overlap the buffer's unsaved edit, they can't be
+    // merged, and the buffer is marked as having a conflict.
+    cx.executor().run_until_parked();
+    buffer.update(cx, |buffer, _| {
+        assert!(buffer.has_conflict());
+    });
+}
+
+#[gpui::test]
+async fn test_reload_merges_unsaved_changes(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "the-file": "one\ntwo\nthree\nfour\n" }))
+        .await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/the-file", cx))
+        .await
+        .unwrap();
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..3, "ONE")], None, cx));
+
+    // Changes on disk that don't overlap the unsaved edits are merged into the
+    // buffer, which stays dirty.
+    fs.save(
+        "/dir/the-file".as_ref(),
+        &"one\ntwo\nthree\nFOUR\n".into(),
+        LineEnding::Unix,
+    )
+    .await
+    .unwrap();
+    cx.executor().run_until_parked();
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "ONE\ntwo\nthree\nFOUR\n");
+        assert!(buffer.is_dirty());
+        assert!(!buffer.has_conflict());
+    });
+
+    // Changes that were already merged don't conflict, but changes that overlap
+    // the unsaved edits do.
+    fs.save(
+        "/dir/the-file".as_ref(),
+        &"uno\ntwo\nthree\nFOUR\nfive\n".into(),
+        LineEnding::Unix,
+    )
+    .await
+    .unwrap();
+    cx.executor().run_until_parked();
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "ONE\ntwo\nthree\nFOUR\nfive\n");
+        assert!(buffer.is_dirty());
+        assert!(buffer.has_conflict());
+    });
+
+    // Once the buffer matches the file on disk, it's no longer dirty.
+    fs.save(
+        "/dir/the-file".as_ref(),
+        &"ONE\ntwo\nthree\nFOUR\nfive\n".into(),
+        LineEnding::Unix,
+    )
+    .await
+    .unwrap();
     cx.executor().run_until_parked();
     buffer.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "ONE\ntwo\nthree\nFOUR\nfive\n");
+        assert!(!buffer.is_dirty());
+        assert!(!buffer.has_conflict());
+    });
+}
+
+#[gpui::test]
+async fn test_buffer_conflict_event(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "the-file": "abc\n" })).await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/the-file", cx))
+        .await
+        .unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    buffer.update(cx, |_, cx| {
+        cx.subscribe(&buffer, {
+            let events = events.clone();
+            move |_, _, event, _| {
+                if let BufferEvent::Conflict = event {
+                    events.lock().push(event.clone());
+                }
+            }
+        })
+        .detach();
+    });
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "x")], None, cx));
+
+    // Change the file on disk twice while the buffer is dirty. The conflict
+    // is only reported once.
+    for contents in ["def\n", "ghi\n"] {
+        fs.save("/dir/the-file".as_ref(), &contents.into(), LineEnding::Unix)
+            .await
+            .unwrap();
+        cx.executor().run_until_parked();
+    }
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "xabc\n");
         assert!(buffer.has_conflict());
     });
+    assert_eq!(events.lock().as_slice(), &[BufferEvent::Conflict]);
+
+    // After saving, the buffer no longer conflicts, so a subsequent change
+    // on disk while it's dirty is reported again.
+    project
+        .update(cx, |project, cx| {
+            project.save_buffer(buffer.clone(), true, cx)
+        })
+        .await
+        .unwrap();
+    buffer.update(cx, |buffer, cx| {
+        assert!(!buffer.has_conflict());
+        buffer.edit([(0..0, "y")], None, cx);
+    });
+    fs.save("/dir/the-file".as_ref(), &"jkl\n".into(), LineEnding::Unix)
+        .await
+        .unwrap();
+    cx.executor().run_until_parked();
+    assert_eq!(
+        events.lock().as_slice(),
+        &[BufferEvent::Conflict, BufferEvent::Conflict]
+    );
+}
+
+#[gpui::test]
+async fn test_save_conflicting_buffer(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "the-file": "abc\n" })).await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/the-file", cx))
+        .await
+        .unwrap();
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "x")], None, cx));
+    fs.save("/dir/the-file".as_ref(), &"def\n".into(), LineEnding::Unix)
+        .await
+        .unwrap();
+    cx.executor().run_until_parked();
+    assert!(buffer.read_with(cx, |buffer, _| buffer.has_conflict()));
+
+    // Without forcing, the save is rejected and the file is left untouched.
+    let error = project
+        .update(cx, |project, cx| {
+            project.save_buffer(buffer.clone(), false, cx)
+        })
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.downcast_ref::<SaveError>(),
+        Some(&SaveError::Conflict)
+    );
+    assert_eq!(fs.load("/dir/the-file".as_ref()).await.unwrap(), "def\n");
+    assert!(buffer.read_with(cx, |buffer, _| buffer.has_conflict()));
+
+    // Forcing the save overwrites the file.
+    project
+        .update(cx, |project, cx| {
+            project.save_buffer(buffer.clone(), true, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(fs.load("/dir/the-file".as_ref()).await.unwrap(), "xabc\n");
+    assert!(buffer.read_with(cx, |buffer, _| !buffer.has_conflict()));
 }
 
 #[gpui::test]
@@ -3525,7 +5219,7 @@ async fn test_buffer_line_endings(cx: &mut gpui::TestAppContext) {
         buffer.set_text("one\ntwo\nthree\nfour\n", cx);
     });
     project
-        .update(cx, |project, cx| project.save_buffer(buffer2, cx))
+        .update(cx, |project, cx| project.save_buffer(buffer2, false, cx))
         .await
         .unwrap();
     assert_eq!(
@@ -3534,6 +5228,124 @@ async fn test_buffer_line_endings(cx: &mut gpui::TestAppContext) {
     );
 }
 
+#[gpui::test]
+async fn test_save_buffer_with_line_ending_override(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/dir", json!({ "file1": "a\nb\nc\n" }))
+        .await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/file1", cx))
+        .await
+        .unwrap();
+
+    buffer.update(cx, |buffer, cx| {
+        assert_eq!(buffer.line_ending(), LineEnding::Unix);
+        buffer.set_line_ending(LineEnding::Windows, cx);
+        assert_eq!(buffer.line_ending_at_save(), LineEnding::Windows);
+        assert!(buffer.is_dirty());
+
+        // Choosing the current line ending again clears the override.
+        buffer.set_line_ending(LineEnding::Unix, cx);
+        assert!(!buffer.is_dirty());
+        buffer.set_line_ending(LineEnding::Windows, cx);
+    });
+
+    project
+        .update(cx, |project, cx| {
+            project.save_buffer(buffer.clone(), false, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        fs.load("/dir/file1".as_ref()).await.unwrap(),
+        "a\r\nb\r\nc\r\n",
+    );
+
+    cx.executor().run_until_parked();
+    buffer.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "a\nb\nc\n");
+        assert_eq!(buffer.line_ending(), LineEnding::Windows);
+        assert_eq!(buffer.line_ending_at_save(), LineEnding::Windows);
+        assert!(!buffer.is_dirty());
+    });
+}
+
+#[gpui::test]
+async fn test_ensure_final_newline_on_save(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            "without_newline": "a\nb",
+            "with_newline": "a\nb\n",
+        }),
+    )
+    .await;
+
+    let project = Project::test(fs.clone(), ["/dir".as_ref()], cx).await;
+    let without_newline = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/without_newline", cx))
+        .await
+        .unwrap();
+    let with_newline = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/with_newline", cx))
+        .await
+        .unwrap();
+
+    let set_ensure_final_newline = |enabled, cx: &mut gpui::TestAppContext| {
+        cx.update(|cx| {
+            SettingsStore::update_global(cx, |settings, cx| {
+                settings.update_user_settings::<AllLanguageSettings>(cx, |settings| {
+                    settings.defaults.ensure_final_newline_on_save = Some(enabled);
+                });
+            })
+        });
+    };
+    let format_on_save = |cx: &mut gpui::TestAppContext| {
+        project.update(cx, |project, cx| {
+            let buffers = HashSet::from_iter([without_newline.clone(), with_newline.clone()]);
+            project.format(buffers, true, FormatTrigger::Save, cx)
+        })
+    };
+
+    // When the setting is disabled, a file without a final newline is left alone.
+    set_ensure_final_newline(false, cx);
+    format_on_save(cx).await.unwrap();
+    without_newline.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "a\nb");
+        assert!(!buffer.is_dirty());
+    });
+
+    // When it's enabled, a newline is added only where it's missing.
+    set_ensure_final_newline(true, cx);
+    format_on_save(cx).await.unwrap();
+    without_newline.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "a\nb\n");
+        assert!(buffer.is_dirty());
+    });
+    with_newline.update(cx, |buffer, _| {
+        assert_eq!(buffer.text(), "a\nb\n");
+        assert!(!buffer.is_dirty());
+    });
+
+    project
+        .update(cx, |project, cx| {
+            project.save_buffer(without_newline, false, cx)
+        })
+        .await
+        .unwrap();
+    assert_eq!(
+        fs.load("/dir/without_newline".as_ref()).await.unwrap(),
+        "a\nb\n"
+    );
+}
+
 #[gpui::test]
 async fn test_grouped_diagnostics(cx: &mut gpui::TestAppContext) {
     init_test(cx);
@@ -3713,67 +5525,412 @@ async fn test_grouped_diagnostics(cx: &mut gpui::TestAppContext) {
         ]
     );
 
-    assert_eq!(
-        buffer.diagnostic_group::<Point>(0).collect::<Vec<_>>(),
-        &[
-            DiagnosticEntry {
-                range: Point::new(1, 13)..Point::new(1, 15),
-                diagnostic: Diagnostic {
-                    severity: DiagnosticSeverity::HINT,
-                    message: "error 2 hint 1".to_string(),
-                    group_id: 0,
-                    is_primary: false,
-                    ..Default::default()
-                }
+    assert_eq!(
+        buffer.diagnostic_group::<Point>(0).collect::<Vec<_>>(),
+        &[
+            DiagnosticEntry {
+                range: Point::new(1, 13)..Point::new(1, 15),
+                diagnostic: Diagnostic {
+                    severity: DiagnosticSeverity::HINT,
+                    message: "error 2 hint 1".to_string(),
+                    group_id: 0,
+                    is_primary: false,
+                    ..Default::default()
+                }
+            },
+            DiagnosticEntry {
+                range: Point::new(1, 13)..Point::new(1, 15),
+                diagnostic: Diagnostic {
+                    severity: DiagnosticSeverity::HINT,
+                    message: "error 2 hint 2".to_string(),
+                    group_id: 0,
+                    is_primary: false,
+                    ..Default::default()
+                }
+            },
+            DiagnosticEntry {
+                range: Point::new(2, 8)..Point::new(2, 17),
+                diagnostic: Diagnostic {
+                    severity: DiagnosticSeverity::ERROR,
+                    message: "error 2".to_string(),
+                    group_id: 0,
+                    is_primary: true,
+                    ..Default::default()
+                }
+            }
+        ]
+    );
+
+    assert_eq!(
+        buffer.diagnostic_group::<Point>(1).collect::<Vec<_>>(),
+        &[
+            DiagnosticEntry {
+                range: Point::new(1, 8)..Point::new(1, 9),
+                diagnostic: Diagnostic {
+                    severity: DiagnosticSeverity::WARNING,
+                    message: "error 1".to_string(),
+                    group_id: 1,
+                    is_primary: true,
+                    ..Default::default()
+                }
+            },
+            DiagnosticEntry {
+                range: Point::new(1, 8)..Point::new(1, 9),
+                diagnostic: Diagnostic {
+                    severity: DiagnosticSeverity::HINT,
+                    message: "error 1 hint 1".to_string(),
+                    group_id: 1,
+                    is_primary: false,
+                    ..Default::default()
+                }
+            },
+        ]
+    );
+}
+
+#[gpui::test]
+async fn test_ungrouped_diagnostics(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |settings, cx| {
+            settings.update_user_settings::<ProjectSettings>(cx, |settings| {
+                settings.diagnostic_grouping = Some(DiagnosticGrouping::None);
+            });
+        })
+    });
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/the-dir", json!({ "a.rs": "let one = two;" }))
+        .await;
+
+    let project = Project::test(fs.clone(), ["/the-dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/the-dir/a.rs", cx))
+        .await
+        .unwrap();
+
+    // The hint refers to the error, which would usually merge them into one group.
+    let buffer_uri = Url::from_file_path("/the-dir/a.rs").unwrap();
+    let error_range = lsp::Range::new(lsp::Position::new(0, 10), lsp::Position::new(0, 13));
+    let hint_range = lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7));
+    let message = lsp::PublishDiagnosticsParams {
+        uri: buffer_uri.clone(),
+        diagnostics: vec![
+            lsp::Diagnostic {
+                range: error_range,
+                severity: Some(DiagnosticSeverity::ERROR),
+                message: "undefined variable".to_string(),
+                related_information: Some(vec![lsp::DiagnosticRelatedInformation {
+                    location: lsp::Location {
+                        uri: buffer_uri.clone(),
+                        range: hint_range,
+                    },
+                    message: "unused variable".to_string(),
+                }]),
+                ..Default::default()
+            },
+            lsp::Diagnostic {
+                range: hint_range,
+                severity: Some(DiagnosticSeverity::HINT),
+                message: "unused variable".to_string(),
+                related_information: Some(vec![lsp::DiagnosticRelatedInformation {
+                    location: lsp::Location {
+                        uri: buffer_uri,
+                        range: error_range,
+                    },
+                    message: "original diagnostic".to_string(),
+                }]),
+                ..Default::default()
+            },
+        ],
+        version: None,
+    };
+
+    project
+        .update(cx, |p, cx| {
+            p.update_diagnostics(LanguageServerId(0), message, &[], cx)
+        })
+        .unwrap();
+    let buffer = buffer.update(cx, |buffer, _| buffer.snapshot());
+
+    assert_eq!(
+        buffer
+            .diagnostics_in_range::<_, Point>(0..buffer.len(), false)
+            .map(|entry| (
+                entry.range,
+                entry.diagnostic.message,
+                entry.diagnostic.group_id,
+                entry.diagnostic.is_primary
+            ))
+            .collect::<Vec<_>>(),
+        &[
+            (
+                Point::new(0, 4)..Point::new(0, 7),
+                "unused variable".to_string(),
+                1,
+                true
+            ),
+            (
+                Point::new(0, 10)..Point::new(0, 13),
+                "undefined variable".to_string(),
+                0,
+                true
+            ),
+        ]
+    );
+}
+
+#[gpui::test]
+async fn test_diagnostic_tags(cx: &mut gpui::TestAppContext) {
+    let (buffer, entries) = publish_single_line_diagnostics(
+        vec![
+            lsp::Diagnostic {
+                range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7)),
+                severity: Some(DiagnosticSeverity::HINT),
+                message: "unused variable".to_string(),
+                tags: Some(vec![lsp::DiagnosticTag::UNNECESSARY]),
+                ..Default::default()
+            },
+            lsp::Diagnostic {
+                range: lsp::Range::new(lsp::Position::new(0, 10), lsp::Position::new(0, 13)),
+                severity: Some(DiagnosticSeverity::WARNING),
+                message: "use of deprecated function".to_string(),
+                tags: Some(vec![lsp::DiagnosticTag::DEPRECATED]),
+                ..Default::default()
+            },
+        ],
+        cx,
+    )
+    .await;
+    let tags = |entries: &[DiagnosticEntry<Anchor>]| {
+        entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.range.to_offset(&buffer),
+                    entry.diagnostic.is_unnecessary,
+                    entry.diagnostic.is_deprecated,
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(tags(&entries), [(4..7, true, false), (10..13, false, true)]);
+
+    // The tags are preserved when the diagnostics are sent to collaborators.
+    let deserialized =
+        language::proto::deserialize_diagnostics(language::proto::serialize_diagnostics(&entries));
+    assert_eq!(
+        tags(&deserialized),
+        [(4..7, true, false), (10..13, false, true)]
+    );
+}
+
+#[gpui::test]
+async fn test_diagnostic_source_and_code(cx: &mut gpui::TestAppContext) {
+    let (_, entries) = publish_single_line_diagnostics(
+        vec![
+            lsp::Diagnostic {
+                range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7)),
+                severity: Some(DiagnosticSeverity::WARNING),
+                source: Some("clippy".to_string()),
+                code: Some(NumberOrString::String("E0277".to_string())),
+                message: "the trait bound is not satisfied".to_string(),
+                ..Default::default()
+            },
+            lsp::Diagnostic {
+                range: lsp::Range::new(lsp::Position::new(0, 10), lsp::Position::new(0, 13)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                code: Some(NumberOrString::Number(425)),
+                message: "cannot find function".to_string(),
+                ..Default::default()
+            },
+        ],
+        cx,
+    )
+    .await;
+    let sources_and_codes = |entries: &[DiagnosticEntry<Anchor>]| {
+        entries
+            .iter()
+            .map(|entry| {
+                (
+                    entry.diagnostic.source.clone(),
+                    entry.diagnostic.code.clone(),
+                )
+            })
+            .collect::<Vec<_>>()
+    };
+    let expected = [
+        (Some("clippy".to_string()), Some("E0277".to_string())),
+        (None, Some("425".to_string())),
+    ];
+    assert_eq!(sources_and_codes(&entries), expected);
+
+    let deserialized =
+        language::proto::deserialize_diagnostics(language::proto::serialize_diagnostics(&entries));
+    assert_eq!(sources_and_codes(&deserialized), expected);
+}
+
+#[gpui::test]
+async fn test_diagnostic_data(cx: &mut gpui::TestAppContext) {
+    let data = json!({ "rendered": "error: cannot find function `two`" });
+    let (buffer, _) = publish_single_line_diagnostics(
+        vec![
+            lsp::Diagnostic {
+                range: lsp::Range::new(lsp::Position::new(0, 10), lsp::Position::new(0, 13)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                message: "cannot find function".to_string(),
+                data: Some(data.clone()),
+                ..Default::default()
             },
-            DiagnosticEntry {
-                range: Point::new(1, 13)..Point::new(1, 15),
-                diagnostic: Diagnostic {
-                    severity: DiagnosticSeverity::HINT,
-                    message: "error 2 hint 2".to_string(),
-                    group_id: 0,
-                    is_primary: false,
-                    ..Default::default()
-                }
+            lsp::Diagnostic {
+                range: lsp::Range::new(lsp::Position::new(0, 4), lsp::Position::new(0, 7)),
+                severity: Some(DiagnosticSeverity::WARNING),
+                message: "unused variable".to_string(),
+                ..Default::default()
             },
-            DiagnosticEntry {
-                range: Point::new(2, 8)..Point::new(2, 17),
-                diagnostic: Diagnostic {
-                    severity: DiagnosticSeverity::ERROR,
-                    message: "error 2".to_string(),
-                    group_id: 0,
-                    is_primary: true,
-                    ..Default::default()
-                }
-            }
-        ]
-    );
+        ],
+        cx,
+    )
+    .await;
 
+    let group_ids = buffer
+        .diagnostics_in_range::<_, PointUtf16>(0..buffer.len(), false)
+        .map(|entry| {
+            // The data is passed back to the server when requesting code actions.
+            assert_eq!(entry.to_lsp_diagnostic_stub().data, entry.diagnostic.data);
+            (entry.diagnostic.message, entry.diagnostic.group_id)
+        })
+        .collect::<HashMap<_, _>>();
     assert_eq!(
-        buffer.diagnostic_group::<Point>(1).collect::<Vec<_>>(),
-        &[
-            DiagnosticEntry {
-                range: Point::new(1, 8)..Point::new(1, 9),
-                diagnostic: Diagnostic {
-                    severity: DiagnosticSeverity::WARNING,
-                    message: "error 1".to_string(),
-                    group_id: 1,
-                    is_primary: true,
-                    ..Default::default()
-                }
-            },
-            DiagnosticEntry {
-                range: Point::new(1, 8)..Point::new(1, 9),
-                diagnostic: Diagnostic {
-                    severity: DiagnosticSeverity::HINT,
-                    message: "error 1 hint 1".to_string(),
-                    group_id: 1,
-                    is_primary: false,
-                    ..Default::default()
-                }
-            },
-        ]
+        buffer.diagnostic_data(group_ids["cannot find function"]),
+        Some(&data)
     );
+    assert_eq!(buffer.diagnostic_data(group_ids["unused variable"]), None);
+}
+
+#[gpui::test]
+async fn test_deduplicate_diagnostics(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+    cx.update(|cx| {
+        SettingsStore::update_global(cx, |settings, cx| {
+            settings.update_user_settings::<ProjectSettings>(cx, |settings| {
+                settings.deduplicate_diagnostics = true;
+            });
+        })
+    });
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/the-dir", json!({ "a.rs": "let x = y;" }))
+        .await;
+
+    let project = Project::test(fs.clone(), ["/the-dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/the-dir/a.rs", cx))
+        .await
+        .unwrap();
+
+    let message = |sources: &[&str]| lsp::PublishDiagnosticsParams {
+        uri: Url::from_file_path("/the-dir/a.rs").unwrap(),
+        diagnostics: sources
+            .iter()
+            .map(|source| lsp::Diagnostic {
+                range: lsp::Range::new(lsp::Position::new(0, 8), lsp::Position::new(0, 9)),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some(source.to_string()),
+                message: "unresolved name `y`".to_string(),
+                ..Default::default()
+            })
+            .collect(),
+        version: None,
+    };
+
+    // Both servers report the same diagnostic, and the second one also reports
+    // a diagnostic from a different source at the same range.
+    project
+        .update(cx, |p, cx| {
+            p.update_diagnostics(LanguageServerId(0), message(&["rustc"]), &[], cx)?;
+            p.update_diagnostics(LanguageServerId(1), message(&["rustc", "clippy"]), &[], cx)
+        })
+        .unwrap();
+    buffer.update(cx, |buffer, _| {
+        let mut sources = buffer
+            .snapshot()
+            .diagnostics_in_range::<_, usize>(0..buffer.len(), false)
+            .map(|entry| (entry.range, entry.diagnostic.source.unwrap()))
+            .collect::<Vec<_>>();
+        sources.sort();
+        assert_eq!(
+            sources,
+            [(8..9, "clippy".to_string()), (8..9, "rustc".to_string())]
+        );
+    });
+}
+
+#[gpui::test]
+async fn test_disk_based_diagnostics_reuse_edits_since_save(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    let text = "let x = 1;\n".repeat(100);
+    fs.insert_tree("/the-dir", json!({ "a.rs": text })).await;
+
+    let project = Project::test(fs.clone(), ["/the-dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/the-dir/a.rs", cx))
+        .await
+        .unwrap();
+
+    // Make many unsaved edits, which disk-based diagnostics need to be translated through.
+    buffer.update(cx, |buffer, cx| {
+        for row in 0..100 {
+            buffer.edit([(Point::new(row, 0)..Point::new(row, 0), "  ")], None, cx);
+        }
+    });
+
+    let publish_diagnostics = |cx: &mut gpui::TestAppContext| {
+        let message = lsp::PublishDiagnosticsParams {
+            uri: Url::from_file_path("/the-dir/a.rs").unwrap(),
+            diagnostics: (0..100)
+                .map(|row| lsp::Diagnostic {
+                    range: lsp::Range::new(lsp::Position::new(row, 4), lsp::Position::new(row, 5)),
+                    severity: Some(DiagnosticSeverity::WARNING),
+                    source: Some("disk".to_string()),
+                    message: "unused variable".to_string(),
+                    ..Default::default()
+                })
+                .collect(),
+            version: None,
+        };
+        project
+            .update(cx, |p, cx| {
+                p.update_diagnostics(LanguageServerId(0), message, &["disk".to_string()], cx)
+            })
+            .unwrap();
+    };
+    let computation_count = || EDITS_SINCE_SAVE_COMPUTATION_COUNT.with(|count| count.get());
+
+    let count = computation_count();
+    publish_diagnostics(cx);
+    assert_eq!(computation_count(), count + 1);
+    buffer.update(cx, |buffer, _| {
+        let ranges = buffer
+            .snapshot()
+            .diagnostics_in_range::<_, Point>(0..buffer.len(), false)
+            .map(|entry| entry.range)
+            .collect::<Vec<_>>();
+        assert_eq!(ranges.len(), 100);
+        assert!(ranges
+            .iter()
+            .enumerate()
+            .all(|(row, range)| *range == Point::new(row as u32, 6)..Point::new(row as u32, 7)));
+    });
+
+    // The edits are reused until the buffer changes.
+    publish_diagnostics(cx);
+    assert_eq!(computation_count(), count + 1);
+    buffer.update(cx, |buffer, cx| buffer.edit([(0..0, "\n")], None, cx));
+    publish_diagnostics(cx);
+    assert_eq!(computation_count(), count + 2);
 }
 
 #[gpui::test]
@@ -4516,6 +6673,88 @@ async fn test_search_in_gitignored_dirs(cx: &mut gpui::TestAppContext) {
     );
 }
 
+#[gpui::test]
+fn test_search_query_find_all(cx: &mut gpui::AppContext) {
+    let buffer = cx.new_model(|cx| Buffer::local("Foo foo FOO food foo_bar", cx));
+    let snapshot = buffer.read(cx).snapshot();
+    let find_all = |whole_word, case_sensitive| {
+        let query = SearchQuery::text(
+            "foo",
+            whole_word,
+            case_sensitive,
+            false,
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+        query
+            .find_all(&snapshot)
+            .into_iter()
+            .map(|range| snapshot.text_for_range(range).collect::<String>())
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(find_all(false, true), ["foo", "foo", "foo"]);
+    assert_eq!(find_all(false, false), ["Foo", "foo", "FOO", "foo", "foo"]);
+    assert_eq!(find_all(true, false), ["Foo", "foo", "FOO"]);
+    assert_eq!(find_all(true, true), ["foo"]);
+}
+
+#[gpui::test]
+fn test_search_query_replace_all(cx: &mut gpui::AppContext) {
+    let text = "foo food foo_bar foo";
+    let buffer = cx.new_model(|cx| Buffer::local(text, cx));
+    buffer.update(cx, |buffer, cx| {
+        let query = SearchQuery::text(
+            "foo",
+            true,
+            true,
+            false,
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(query.replace_all(buffer, "baz", false, cx), 2);
+        assert_eq!(buffer.text(), "baz food foo_bar baz");
+
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), text);
+
+        let query = SearchQuery::regex(
+            r"foo(\w+)",
+            false,
+            true,
+            false,
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(query.replace_all(buffer, "$1-foo", false, cx), 2);
+        assert_eq!(buffer.text(), "foo d-foo _bar-foo foo");
+
+        buffer.undo(cx);
+        assert_eq!(buffer.text(), text);
+    });
+}
+
+#[gpui::test]
+fn test_search_query_replace_all_preserving_case(cx: &mut gpui::AppContext) {
+    let buffer = cx.new_model(|cx| Buffer::local("foo Foo FOO fooBar", cx));
+    buffer.update(cx, |buffer, cx| {
+        let query = SearchQuery::text(
+            "foo",
+            false,
+            false,
+            false,
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+        assert_eq!(query.replace_all(buffer, "bar", true, cx), 4);
+        assert_eq!(buffer.text(), "bar Bar BAR barBar");
+    });
+}
+
 #[test]
 fn test_glob_literal_prefix() {
     assert_eq!(glob_literal_prefix("**/*.js"), "");
@@ -4818,6 +7057,113 @@ async fn test_hovers_with_empty_parts(cx: &mut gpui::TestAppContext) {
     );
 }
 
+#[gpui::test]
+async fn test_document_colors(cx: &mut gpui::TestAppContext) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree(
+        "/dir",
+        json!({
+            "a.ts": r#"let red = "#f00"; let blue = "#0000ff80";"#,
+        }),
+    )
+    .await;
+
+    let project = Project::test(fs, ["/dir".as_ref()], cx).await;
+
+    let language_registry = project.read_with(cx, |project, _| project.languages().clone());
+    language_registry.add(typescript_lang());
+    let mut fake_language_servers = language_registry.register_fake_lsp_adapter(
+        "TypeScript",
+        FakeLspAdapter {
+            capabilities: lsp::ServerCapabilities {
+                color_provider: Some(lsp::ColorProviderCapability::Simple(true)),
+                ..lsp::ServerCapabilities::default()
+            },
+            ..FakeLspAdapter::default()
+        },
+    );
+
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/dir/a.ts", cx))
+        .await
+        .unwrap();
+    cx.executor().run_until_parked();
+
+    let fake_server = fake_language_servers
+        .next()
+        .await
+        .expect("failed to get the language server");
+
+    // The server reports the colors out of order.
+    let mut request_handled = fake_server.handle_request::<lsp::request::DocumentColor, _, _>(
+        move |params, _| async move {
+            assert_eq!(
+                params.text_document.uri,
+                lsp::Url::from_file_path("/dir/a.ts").unwrap()
+            );
+            Ok(vec![
+                lsp::ColorInformation {
+                    range: lsp::Range::new(lsp::Position::new(0, 30), lsp::Position::new(0, 39)),
+                    color: lsp::Color {
+                        red: 0.,
+                        green: 0.,
+                        blue: 1.,
+                        alpha: 0.5,
+                    },
+                },
+                lsp::ColorInformation {
+                    range: lsp::Range::new(lsp::Position::new(0, 11), lsp::Position::new(0, 15)),
+                    color: lsp::Color {
+                        red: 1.,
+                        green: 0.,
+                        blue: 0.,
+                        alpha: 1.,
+                    },
+                },
+            ])
+        },
+    );
+
+    let colors_task = project.update(cx, |project, cx| project.document_colors(&buffer, cx));
+    request_handled
+        .next()
+        .await
+        .expect("document color request should have been handled");
+    let colors = colors_task.await.unwrap();
+
+    buffer.update(cx, |buffer, _| {
+        let colors = colors
+            .into_iter()
+            .map(|(range, color)| (range.to_offset(buffer), color))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            colors,
+            [
+                (
+                    11..15,
+                    Color {
+                        red: 1.,
+                        green: 0.,
+                        blue: 0.,
+                        alpha: 1.,
+                    }
+                ),
+                (
+                    30..39,
+                    Color {
+                        red: 0.,
+                        green: 0.,
+                        blue: 1.,
+                        alpha: 0.5,
+                    }
+                ),
+            ]
+        );
+    });
+}
+
 #[gpui::test]
 async fn test_multiple_language_server_actions(cx: &mut gpui::TestAppContext) {
     init_test(cx);
@@ -5206,6 +7552,47 @@ async fn search(
         .collect())
 }
 
+/// Publishes the given diagnostics for a buffer containing `let one = two();`,
+/// returning a snapshot of the buffer along with the diagnostics it contains.
+async fn publish_single_line_diagnostics(
+    diagnostics: Vec<lsp::Diagnostic>,
+    cx: &mut gpui::TestAppContext,
+) -> (BufferSnapshot, Vec<DiagnosticEntry<Anchor>>) {
+    init_test(cx);
+
+    let fs = FakeFs::new(cx.executor());
+    fs.insert_tree("/the-dir", json!({ "a.rs": "let one = two();" }))
+        .await;
+
+    let project = Project::test(fs.clone(), ["/the-dir".as_ref()], cx).await;
+    let buffer = project
+        .update(cx, |p, cx| p.open_local_buffer("/the-dir/a.rs", cx))
+        .await
+        .unwrap();
+
+    let message = lsp::PublishDiagnosticsParams {
+        uri: Url::from_file_path("/the-dir/a.rs").unwrap(),
+        diagnostics,
+        version: None,
+    };
+
+    project
+        .update(cx, |p, cx| {
+            p.update_diagnostics(LanguageServerId(0), message, &[], cx)
+        })
+        .unwrap();
+    let buffer = buffer.update(cx, |buffer, _| buffer.snapshot());
+
+    let entries = buffer
+        .diagnostics_in_range::<_, usize>(0..buffer.len(), false)
+        .map(|entry| DiagnosticEntry {
+            range: buffer.anchor_before(entry.range.start)..buffer.anchor_after(entry.range.end),
+            diagnostic: entry.diagnostic,
+        })
+        .collect::<Vec<_>>();
+    (buffer, entries)
+}
+
 fn init_test(cx: &mut gpui::TestAppContext) {
     if std::env::var("RUST_LOG").is_ok() {
         env_logger::try_init().ok();
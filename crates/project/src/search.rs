@@ -1,7 +1,8 @@
 use aho_corasick::{AhoCorasick, AhoCorasickBuilder};
 use anyhow::Result;
 use client::proto;
-use language::{char_kind, BufferSnapshot};
+use gpui::ModelContext;
+use language::{char_kind, Anchor, Buffer, BufferSnapshot, Rope};
 use regex::{Captures, Regex, RegexBuilder};
 use smol::future::yield_now;
 use std::{
@@ -261,20 +262,50 @@ impl SearchQuery {
         };
 
         let mut matches = Vec::new();
+        for (ix, mat) in self
+            .matches_in_rope(buffer, &rope, range_offset)
+            .enumerate()
+        {
+            if (ix + 1) % YIELD_INTERVAL == 0 {
+                yield_now().await;
+            }
+            matches.push(mat);
+        }
+        matches
+    }
+
+    /// Synchronously finds every match in the buffer, returning anchor ranges
+    /// that keep tracking the matched text across subsequent edits.
+    pub fn find_all(&self, buffer: &BufferSnapshot) -> Vec<Range<Anchor>> {
+        if self.as_str().is_empty() {
+            return Default::default();
+        }
+
+        self.matches_in_rope(buffer, buffer.as_rope(), 0)
+            .map(|range| buffer.anchor_before(range.start)..buffer.anchor_after(range.end))
+            .collect()
+    }
+
+    /// Lazily finds the matches in the given rope, which starts at `range_offset`
+    /// within the buffer.
+    fn matches_in_rope<'a>(
+        &'a self,
+        buffer: &'a BufferSnapshot,
+        rope: &'a Rope,
+        range_offset: usize,
+    ) -> Box<dyn 'a + Iterator<Item = Range<usize>>> {
         match self {
             Self::Text {
                 search, whole_word, ..
-            } => {
-                for (ix, mat) in search
+            } => Box::new(
+                search
                     .stream_find_iter(rope.bytes_in_range(0..rope.len()))
-                    .enumerate()
-                {
-                    if (ix + 1) % YIELD_INTERVAL == 0 {
-                        yield_now().await;
-                    }
+                    .map(|mat| mat.unwrap())
+                    .filter(move |mat| {
+                        if !*whole_word {
+                            return true;
+                        }
 
-                    let mat = mat.unwrap();
-                    if *whole_word {
                         let scope = buffer.language_scope_at(range_offset + mat.start());
                         let kind = |c| char_kind(&scope, c);
 
@@ -282,34 +313,26 @@ impl SearchQuery {
                         let start_kind = kind(rope.chars_at(mat.start()).next().unwrap());
                         let end_kind = kind(rope.reversed_chars_at(mat.end()).next().unwrap());
                         let next_kind = rope.chars_at(mat.end()).next().map(kind);
-                        if Some(start_kind) == prev_kind || Some(end_kind) == next_kind {
-                            continue;
-                        }
-                    }
-                    matches.push(mat.start()..mat.end())
-                }
-            }
+                        Some(start_kind) != prev_kind && Some(end_kind) != next_kind
+                    })
+                    .map(|mat| mat.start()..mat.end()),
+            ),
 
             Self::Regex {
                 regex, multiline, ..
             } => {
                 if *multiline {
                     let text = rope.to_string();
-                    for (ix, mat) in regex.find_iter(&text).enumerate() {
-                        if (ix + 1) % YIELD_INTERVAL == 0 {
-                            yield_now().await;
-                        }
-
-                        matches.push(mat.start()..mat.end());
-                    }
+                    let matches = regex
+                        .find_iter(&text)
+                        .map(|mat| mat.start()..mat.end())
+                        .collect::<Vec<_>>();
+                    Box::new(matches.into_iter())
                 } else {
                     let mut line = String::new();
                     let mut line_offset = 0;
-                    for (chunk_ix, chunk) in rope.chunks().chain(["\n"]).enumerate() {
-                        if (chunk_ix + 1) % YIELD_INTERVAL == 0 {
-                            yield_now().await;
-                        }
-
+                    Box::new(rope.chunks().chain(["\n"]).flat_map(move |chunk| {
+                        let mut matches = Vec::new();
                         for (newline_ix, text) in chunk.split('\n').enumerate() {
                             if newline_ix > 0 {
                                 for mat in regex.find_iter(&line) {
@@ -323,12 +346,36 @@ impl SearchQuery {
                             }
                             line.push_str(text);
                         }
-                    }
+                        matches
+                    }))
                 }
             }
         }
+    }
 
-        matches
+    /// Replaces every match in the buffer as a single undoable transaction,
+    /// returning the number of replaced matches. For regex queries, the
+    /// replacement may refer to capture groups, e.g. `$1`. When `preserve_case`
+    /// is set, each replacement is adapted to the case style of its match.
+    pub fn replace_all(
+        &self,
+        buffer: &mut Buffer,
+        replacement: &str,
+        preserve_case: bool,
+        cx: &mut ModelContext<Buffer>,
+    ) -> usize {
+        let query = self.clone().with_replacement(replacement.to_string());
+        let snapshot = buffer.snapshot();
+        let replacements = query
+            .find_all(&snapshot)
+            .into_iter()
+            .filter_map(|range| {
+                let text = snapshot.text_for_range(range.clone()).collect::<String>();
+                let new_text = query.replacement_for(&text)?.into_owned();
+                Some((range, new_text))
+            })
+            .collect::<Vec<_>>();
+        buffer.replace_ranges(replacements, preserve_case, cx)
     }
 
     pub fn is_empty(&self) -> bool {
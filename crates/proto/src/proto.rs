@@ -337,7 +337,11 @@ messages!(
     (OpenNewBuffer, Foreground),
     (RestartLanguageServers, Foreground),
     (LinkedEditingRange, Background),
-    (LinkedEditingRangeResponse, Background)
+    (LinkedEditingRangeResponse, Background),
+    (GetDocumentColors, Background),
+    (GetDocumentColorsResponse, Background),
+    (GetColorPresentations, Background),
+    (GetColorPresentationsResponse, Background)
 );
 
 request_messages!(
@@ -371,6 +375,8 @@ request_messages!(
     (GetDefinition, GetDefinitionResponse),
     (GetImplementation, GetImplementationResponse),
     (GetDocumentHighlights, GetDocumentHighlightsResponse),
+    (GetDocumentColors, GetDocumentColorsResponse),
+    (GetColorPresentations, GetColorPresentationsResponse),
     (GetHover, GetHoverResponse),
     (GetNotifications, GetNotificationsResponse),
     (GetPrivateUserInfo, GetPrivateUserInfoResponse),
@@ -471,6 +477,8 @@ entity_messages!(
     GetDefinition,
     GetImplementation,
     GetDocumentHighlights,
+    GetDocumentColors,
+    GetColorPresentations,
     GetHover,
     GetProjectSymbols,
     GetReferences,
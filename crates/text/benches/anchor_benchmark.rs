@@ -0,0 +1,70 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::prelude::*;
+use rand::rngs::StdRng;
+use text::{Anchor, Buffer, BufferId, ToOffset};
+use util::RandomCharIter;
+
+fn generate_edited_buffer(mut rng: StdRng, text_len: usize, edit_count: usize) -> Buffer {
+    let text = RandomCharIter::new(&mut rng)
+        .take(text_len)
+        .collect::<String>();
+    let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), text);
+
+    // Split the text into many fragments, as a buffer accumulates them when edited.
+    for _ in 0..edit_count {
+        let offset = buffer.clip_offset(rng.gen_range(0..=buffer.len()), text::Bias::Left);
+        buffer.edit([(offset..offset, "x")]);
+    }
+    buffer
+}
+
+fn generate_sorted_anchors(buffer: &Buffer, anchor_count: usize) -> Vec<Anchor> {
+    let step = (buffer.len() / anchor_count).max(1);
+    (0..anchor_count)
+        .map(|ix| buffer.anchor_before(buffer.clip_offset(ix * step, text::Bias::Left)))
+        .collect()
+}
+
+fn anchor_benchmarks(c: &mut Criterion) {
+    static SEED: u64 = 9999;
+    let anchor_counts = [1_000, 10_000];
+
+    let buffer = generate_edited_buffer(StdRng::seed_from_u64(SEED), 256 * 1024, 1000);
+
+    let mut group = c.benchmark_group("summaries_for_sorted_anchors");
+    for anchor_count in anchor_counts {
+        let anchors = generate_sorted_anchors(&buffer, anchor_count);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(anchor_count),
+            &anchors,
+            |b, anchors| {
+                b.iter(|| {
+                    for offset in buffer.summaries_for_sorted_anchors::<usize, _>(anchors) {
+                        black_box(offset);
+                    }
+                });
+            },
+        );
+    }
+    group.finish();
+
+    let mut group = c.benchmark_group("summary_for_anchor");
+    for anchor_count in anchor_counts {
+        let anchors = generate_sorted_anchors(&buffer, anchor_count);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(anchor_count),
+            &anchors,
+            |b, anchors| {
+                b.iter(|| {
+                    for anchor in anchors {
+                        black_box(anchor.to_offset(&buffer));
+                    }
+                });
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, anchor_benchmarks);
+criterion_main!(benches);
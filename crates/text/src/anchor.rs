@@ -31,6 +31,12 @@ impl Anchor {
         buffer_id: None,
     };
 
+    /// Compares the positions of two anchors in the given buffer.
+    ///
+    /// This is infallible as long as the buffer has observed the insertions that both
+    /// anchors refer to, which holds for any anchors created from this buffer or its
+    /// earlier snapshots, regardless of the version they were created at. Comparing an
+    /// anchor from a replica whose edits haven't been applied yet is a bug, and panics.
     pub fn cmp(&self, other: &Anchor, buffer: &BufferSnapshot) -> Ordering {
         let fragment_id_comparison = if self.timestamp == other.timestamp {
             Ordering::Equal
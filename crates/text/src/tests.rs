@@ -32,6 +32,19 @@ fn test_edit() {
     assert_eq!(buffer.text(), "ghiamnoef");
 }
 
+#[test]
+fn test_snapshot_version() {
+    let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), "abc".into());
+    let snapshot = buffer.snapshot();
+    buffer.edit([(3..3, "def")]);
+    let new_snapshot = buffer.snapshot();
+
+    assert!(new_snapshot.version().changed_since(snapshot.version()));
+    assert!(new_snapshot.version().observed_all(snapshot.version()));
+    assert!(!snapshot.version().observed_all(new_snapshot.version()));
+    assert_eq!(new_snapshot.version(), &buffer.version());
+}
+
 #[gpui::test(iterations = 100)]
 fn test_random_edits(mut rng: StdRng) {
     let operations = env::var("OPERATIONS")
@@ -176,6 +189,10 @@ fn test_line_endings() {
         LineEnding::detect(&"abcd\r\n".repeat(1000)),
         LineEnding::Windows
     );
+    assert_eq!(LineEnding::detect("a\nb\r\nc\r\nd"), LineEnding::Windows);
+    assert_eq!(LineEnding::detect("a\r\nb\nc\nd"), LineEnding::Unix);
+    assert_eq!(LineEnding::detect("a\r\nb\nc"), LineEnding::Windows);
+    assert_eq!(LineEnding::detect("abc"), LineEnding::default());
 
     let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), "one\r\ntwo\rthree".into());
     assert_eq!(buffer.text(), "one\ntwo\nthree");
@@ -189,6 +206,39 @@ fn test_line_endings() {
     buffer.check_invariants();
 }
 
+#[test]
+fn test_encodings() {
+    let latin1 = b"caf\xe9 cr\xe8me".to_vec();
+    let (encoding, text) = Encoding::detect_and_decode(latin1.clone()).unwrap();
+    assert_eq!(encoding, Encoding::Latin1);
+    assert_eq!(text, "café crème");
+    assert_eq!(Encoding::Latin1.encode(&text).unwrap(), latin1.as_slice());
+    assert!(Encoding::Latin1.encode("π").is_err());
+
+    let utf8 = "café 🍐".as_bytes().to_vec();
+    assert_eq!(
+        Encoding::detect_and_decode(utf8.clone()).unwrap(),
+        (Encoding::Utf8, "café 🍐".to_string())
+    );
+    assert!(matches!(
+        Encoding::Utf8.encode("café 🍐").unwrap(),
+        Cow::Borrowed(_)
+    ));
+
+    // Binary files and UTF-8 files with invalid bytes aren't mistaken for Latin-1.
+    assert!(Encoding::detect_and_decode(b"\x7fELF\x02\x01\x00\x00".to_vec()).is_err());
+    assert!(Encoding::detect_and_decode(b"caf\xc3\xa9 \xff".to_vec()).is_err());
+    assert!(Encoding::Utf8.decode(b"caf\xe9".to_vec()).is_err());
+
+    for encoding in [Encoding::Utf16Le, Encoding::Utf16Be] {
+        let bytes = encoding.encode("café 🍐").unwrap().into_owned();
+        let (detected_encoding, text) = Encoding::detect_and_decode(bytes.clone()).unwrap();
+        assert_eq!(detected_encoding, encoding);
+        assert_eq!(text, "café 🍐");
+        assert_eq!(encoding.encode(&text).unwrap(), bytes);
+    }
+}
+
 #[test]
 fn test_line_len() {
     let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), "".into());
@@ -205,6 +255,43 @@ fn test_line_len() {
     assert_eq!(buffer.line_len(5), 0);
 }
 
+#[test]
+fn test_row_infos() {
+    let text = (0..120)
+        .map(|row| {
+            let indent = if row % 3 == 0 { "\t" } else { "  " }.repeat(row % 4);
+            let body = if row % 5 == 0 { "" } else { "fn αβ 🏀()" };
+            format!("{indent}{body}")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    let buffer = Buffer::new(0, BufferId::new(1).unwrap(), text);
+
+    let infos = buffer.row_infos(10..110);
+    assert_eq!(infos.len(), 100);
+    for (row, info) in (10..110).zip(infos) {
+        let line_end = Point::new(row, buffer.line_len(row));
+        assert_eq!(info.offset, Point::new(row, 0).to_offset(&buffer));
+        assert_eq!(info.len_bytes, buffer.line_len(row));
+        assert_eq!(
+            info.len_utf16,
+            buffer.point_to_point_utf16(line_end).column,
+            "row {row}"
+        );
+        assert_eq!(info.indent, buffer.line_indent_for_row(row), "row {row}");
+    }
+
+    assert_eq!(buffer.row_infos(115..200).len(), 5);
+    assert_eq!(buffer.row_infos(200..210), []);
+}
+
+#[test]
+fn test_max_point_utf16() {
+    let buffer = Buffer::new(0, BufferId::new(1).unwrap(), "abc\nδα 🏀".into());
+    assert_eq!(buffer.max_point(), Point::new(1, 9));
+    assert_eq!(buffer.max_point_utf16(), PointUtf16::new(1, 5));
+}
+
 #[test]
 fn test_common_prefix_at_position() {
     let text = "a = str; b = δα";
@@ -478,6 +565,50 @@ fn test_anchors() {
     );
 }
 
+#[test]
+fn test_anchor_comparison_across_versions() {
+    let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), "abcdef".into());
+    let old_anchor = buffer.anchor_before(4);
+    buffer.edit([(2..2, "123")]);
+    let new_anchor = buffer.anchor_after(3);
+    let other_new_anchor = buffer.anchor_before(7);
+    assert_eq!(buffer.text(), "ab123cdef");
+
+    // An anchor created at an older version is ordered by its current position.
+    assert_eq!(new_anchor.cmp(&old_anchor, &buffer), Ordering::Less);
+    assert_eq!(old_anchor.cmp(&new_anchor, &buffer), Ordering::Greater);
+    assert_eq!(old_anchor.cmp(&other_new_anchor, &buffer), Ordering::Equal);
+    assert_eq!(
+        old_anchor.cmp(&buffer.anchor_after(7), &buffer),
+        Ordering::Less
+    );
+
+    // Anchors in concurrent insertions are ordered identically on every replica.
+    let mut buffer1 = Buffer::new(1, BufferId::new(1).unwrap(), "abc".into());
+    let mut buffer2 = Buffer::new(2, BufferId::new(1).unwrap(), "abc".into());
+    let op1 = buffer1.edit([(1..1, "xx")]);
+    let op2 = buffer2.edit([(1..1, "yy")]);
+    let anchor1 = buffer1.anchor_before(2);
+    let anchor2 = buffer2.anchor_before(2);
+    buffer1.apply_op(op2).unwrap();
+    buffer2.apply_op(op1).unwrap();
+    assert_eq!(buffer1.text(), buffer2.text());
+    assert_eq!(
+        anchor1.cmp(&anchor2, &buffer1),
+        anchor1.cmp(&anchor2, &buffer2)
+    );
+    assert_eq!(
+        anchor1.cmp(&anchor2, &buffer1),
+        anchor1
+            .to_offset(&buffer1)
+            .cmp(&anchor2.to_offset(&buffer1))
+    );
+    assert_eq!(
+        anchor2.cmp(&anchor1, &buffer1),
+        anchor1.cmp(&anchor2, &buffer1).reverse()
+    );
+}
+
 #[test]
 fn test_anchors_at_start_and_end() {
     let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), "".into());
@@ -501,6 +632,63 @@ fn test_anchors_at_start_and_end() {
     assert_eq!(after_end_anchor.to_offset(&buffer), 9);
 }
 
+#[gpui::test(iterations = 100)]
+fn test_random_summaries_for_anchors(mut rng: StdRng) {
+    let text = RandomCharIter::new(&mut rng)
+        .take(rng.gen_range(0..20))
+        .collect::<String>();
+    let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), text);
+
+    // Create anchors at different versions of the buffer, some of which end up in
+    // deleted text.
+    let mut anchors = Vec::new();
+    for _ in 0..10 {
+        for _ in 0..rng.gen_range(0..5) {
+            let offset = buffer.clip_offset(rng.gen_range(0..=buffer.len()), Bias::Left);
+            let bias = if rng.gen() { Bias::Left } else { Bias::Right };
+            anchors.push(buffer.anchor_at(offset, bias));
+        }
+        buffer.randomly_edit(&mut rng, 3);
+    }
+    anchors.push(Anchor::MIN);
+    anchors.push(Anchor::MAX);
+    anchors.sort_by(|a, b| a.cmp(b, &buffer));
+
+    assert_eq!(
+        buffer
+            .summaries_for_sorted_anchors::<Point, _>(&anchors)
+            .collect::<Vec<_>>(),
+        anchors
+            .iter()
+            .map(|anchor| anchor.to_point(&buffer))
+            .collect::<Vec<_>>()
+    );
+
+    // Callers may sort the anchors by offset, leaving anchors at the same offset
+    // in any order.
+    anchors.shuffle(&mut rng);
+    anchors.sort_by_key(|anchor| anchor.to_offset(&buffer));
+    assert_eq!(
+        buffer
+            .summaries_for_sorted_anchors::<Point, _>(&anchors)
+            .collect::<Vec<_>>(),
+        anchors
+            .iter()
+            .map(|anchor| anchor.to_point(&buffer))
+            .collect::<Vec<_>>()
+    );
+}
+
+#[test]
+#[should_panic(expected = "anchors must be sorted by offset")]
+fn test_summaries_for_unsorted_anchors() {
+    let buffer = Buffer::new(0, BufferId::new(1).unwrap(), "abcdef".into());
+    let anchors = [buffer.anchor_before(4), buffer.anchor_before(2)];
+    buffer
+        .summaries_for_sorted_anchors::<usize, _>(&anchors)
+        .for_each(drop);
+}
+
 #[test]
 fn test_undo_redo() {
     let mut buffer = Buffer::new(0, BufferId::new(1).unwrap(), "1234".into());
@@ -136,6 +136,19 @@ impl HistoryEntry {
     }
 }
 
+/// A description of a transaction in a buffer's undo history,
+/// as returned by [`Buffer::transaction_summaries`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TransactionSummary {
+    /// The id of the transaction, which can be passed to
+    /// [`Buffer::undo_transaction`] or [`Buffer::undo_to_transaction`].
+    pub id: TransactionId,
+    /// When the last edit in the transaction was made.
+    pub timestamp: Instant,
+    /// The rows currently spanned by the text that the transaction edited.
+    pub rows: Vec<Range<u32>>,
+}
+
 struct History {
     base_text: Rope,
     operations: TreeMap<clock::Lamport, Operation>,
@@ -533,6 +546,19 @@ pub struct UndoOperation {
     pub counts: HashMap<clock::Lamport, u32>,
 }
 
+/// Layout-related metadata about a single row, as returned by
+/// [`BufferSnapshot::row_infos`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct RowInfo {
+    /// The offset of the start of the row.
+    pub offset: usize,
+    /// The length of the row in bytes, excluding the newline.
+    pub len_bytes: u32,
+    /// The length of the row in UTF-16 code units, excluding the newline.
+    pub len_utf16: u32,
+    pub indent: LineIndent,
+}
+
 /// Stores information about the indentation of a line (tabs and spaces).
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 pub struct LineIndent {
@@ -676,6 +702,7 @@ impl Buffer {
         }
     }
 
+    /// Returns the buffer's current version. See [`BufferSnapshot::version`].
     pub fn version(&self) -> clock::Global {
         self.version.clone()
     }
@@ -700,6 +727,12 @@ impl Buffer {
         self.history.group_interval
     }
 
+    /// Sets the maximum delay between consecutive transactions for them to be
+    /// grouped into a single undo step.
+    pub fn set_group_interval(&mut self, group_interval: Duration) {
+        self.history.group_interval = group_interval;
+    }
+
     pub fn edit<R, I, S, T>(&mut self, edits: R) -> Operation
     where
         R: IntoIterator<IntoIter = I>,
@@ -739,7 +772,15 @@ impl Buffer {
         let mut insertion_slices = Vec::new();
 
         let mut edits = edits
-            .map(|(range, new_text)| (range.to_offset(&*self), new_text))
+            .map(|(range, new_text)| {
+                let range = range.to_offset(&*self);
+                debug_assert!(
+                    self.visible_text.clip_offset(range.start, Bias::Left) == range.start
+                        && self.visible_text.clip_offset(range.end, Bias::Left) == range.end,
+                    "edited range {range:?} splits a character"
+                );
+                (range, new_text)
+            })
             .peekable();
 
         let mut new_ropes =
@@ -1273,6 +1314,33 @@ impl Buffer {
         self.history.redo_stack.last()
     }
 
+    /// Summarizes the transactions that can currently be undone, from oldest
+    /// to most recent. Edits that were grouped together are summarized as a
+    /// single transaction.
+    pub fn transaction_summaries(&self) -> Vec<TransactionSummary> {
+        self.history
+            .undo_stack
+            .iter()
+            .map(|entry| {
+                let mut rows: Vec<Range<u32>> = Vec::new();
+                for range in self.edited_ranges_for_transaction::<Point>(&entry.transaction) {
+                    let row_range = range.start.row..range.end.row + 1;
+                    match rows.last_mut() {
+                        Some(last) if last.end >= row_range.start => {
+                            last.end = last.end.max(row_range.end);
+                        }
+                        _ => rows.push(row_range),
+                    }
+                }
+                TransactionSummary {
+                    id: entry.transaction.id,
+                    timestamp: entry.last_edit_at,
+                    rows,
+                }
+            })
+            .collect()
+    }
+
     pub fn start_transaction(&mut self) -> Option<TransactionId> {
         self.start_transaction_at(Instant::now())
     }
@@ -1654,10 +1722,6 @@ impl Buffer {
         assert!(!self.text().contains("\r\n"));
     }
 
-    pub fn set_group_interval(&mut self, group_interval: Duration) {
-        self.history.group_interval = group_interval;
-    }
-
     pub fn random_byte_range(&self, start_offset: usize, rng: &mut impl rand::Rng) -> Range<usize> {
         let end = self.clip_offset(rng.gen_range(start_offset..=self.len()), Bias::Right);
         let start = self.clip_offset(rng.gen_range(start_offset..=end), Bias::Right);
@@ -1927,6 +1991,12 @@ impl BufferSnapshot {
         self.visible_text.point_to_point_utf16(point)
     }
 
+    /// Returns the version of the buffer that this snapshot was taken at.
+    ///
+    /// A version records the operations from each replica that have been applied
+    /// to the buffer. Versions are only partially ordered: when operations are made
+    /// concurrently on different replicas, neither version contains the other. Compare
+    /// them with [`clock::Global::observed_all`] and [`clock::Global::changed_since`].
     pub fn version(&self) -> &clock::Global {
         &self.version
     }
@@ -1974,6 +2044,30 @@ impl BufferSnapshot {
         (row_end_offset - row_start_offset) as u32
     }
 
+    /// Returns metadata for each row in the given range, computed in a single
+    /// pass over the text. Rows past the end of the buffer are ignored.
+    pub fn row_infos(&self, rows: Range<u32>) -> Vec<RowInfo> {
+        let end_row = rows.end.min(self.max_point().row + 1);
+        if rows.start >= end_row {
+            return Vec::new();
+        }
+
+        let mut offset = Point::new(rows.start, 0).to_offset(self);
+        let end = Point::new(end_row - 1, self.line_len(end_row - 1)).to_offset(self);
+        let mut lines = self.visible_text.chunks_in_range(offset..end).lines();
+        let mut infos = Vec::with_capacity((end_row - rows.start) as usize);
+        while let Some(line) = lines.next() {
+            infos.push(RowInfo {
+                offset,
+                len_bytes: line.len() as u32,
+                len_utf16: line.encode_utf16().count() as u32,
+                indent: LineIndent::from(line),
+            });
+            offset += line.len() + 1;
+        }
+        infos
+    }
+
     pub fn line_indents_in_row_range(
         &self,
         row_range: Range<u32>,
@@ -2034,7 +2128,16 @@ impl BufferSnapshot {
             .summary(range.end.to_offset(self))
     }
 
-    pub fn summaries_for_anchors<'a, D, A>(&'a self, anchors: A) -> impl 'a + Iterator<Item = D>
+    /// Resolves the given anchors to the given dimension in a single forward pass over
+    /// the buffer, which is much cheaper than resolving each anchor on its own.
+    ///
+    /// The anchors must be sorted by the offsets they resolve to, as they are when
+    /// sorted by [`Anchor::cmp`]. Anchors that resolve to the same offset may come
+    /// in any order.
+    pub fn summaries_for_sorted_anchors<'a, D, A>(
+        &'a self,
+        anchors: A,
+    ) -> impl 'a + Iterator<Item = D>
     where
         D: 'a + TextDimension,
         A: 'a + IntoIterator<Item = &'a Anchor>,
@@ -2044,6 +2147,17 @@ impl BufferSnapshot {
             .map(|d| d.0)
     }
 
+    /// Same as [`Self::summaries_for_sorted_anchors`].
+    pub fn summaries_for_anchors<'a, D, A>(&'a self, anchors: A) -> impl 'a + Iterator<Item = D>
+    where
+        D: 'a + TextDimension,
+        A: 'a + IntoIterator<Item = &'a Anchor>,
+    {
+        self.summaries_for_sorted_anchors(anchors)
+    }
+
+    /// Like [`Self::summaries_for_sorted_anchors`], but passes along a payload with each
+    /// anchor.
     pub fn summaries_for_anchors_with_payload<'a, D, A, T>(
         &'a self,
         anchors: A,
@@ -2057,6 +2171,7 @@ impl BufferSnapshot {
         let mut fragment_cursor = self.fragments.cursor::<(Option<&Locator>, usize)>();
         let mut text_cursor = self.visible_text.cursor(0);
         let mut position = D::default();
+        let mut last_offset = 0;
 
         anchors.map(move |(anchor, payload)| {
             if *anchor == Anchor::MIN {
@@ -2086,11 +2201,25 @@ impl BufferSnapshot {
             assert_eq!(insertion.timestamp, anchor.timestamp, "invalid insertion");
 
             fragment_cursor.seek_forward(&Some(&insertion.fragment_id), Bias::Left, &None);
+            if fragment_cursor
+                .item()
+                .map_or(true, |fragment| fragment.id != insertion.fragment_id)
+            {
+                // The fragment comes before the previous anchor's. This only happens when
+                // both anchors resolve to the same offset, which is checked below.
+                fragment_cursor.seek(&Some(&insertion.fragment_id), Bias::Left, &None);
+            }
             let fragment = fragment_cursor.item().unwrap();
+            debug_assert_eq!(fragment.id, insertion.fragment_id);
             let mut fragment_offset = fragment_cursor.start().1;
             if fragment.visible {
                 fragment_offset += anchor.offset - insertion.split_offset;
             }
+            debug_assert!(
+                fragment_offset >= last_offset,
+                "anchors must be sorted by offset"
+            );
+            last_offset = fragment_offset;
 
             position.add_assign(&text_cursor.summary(fragment_offset));
             (position.clone(), payload)
@@ -2912,6 +3041,12 @@ pub trait FromAnchor {
     fn from_anchor(anchor: &Anchor, snapshot: &BufferSnapshot) -> Self;
 }
 
+impl FromAnchor for Anchor {
+    fn from_anchor(anchor: &Anchor, _snapshot: &BufferSnapshot) -> Self {
+        *anchor
+    }
+}
+
 impl FromAnchor for Point {
     fn from_anchor(anchor: &Anchor, snapshot: &BufferSnapshot) -> Self {
         snapshot.summary_for_anchor(anchor)
@@ -2960,14 +3095,29 @@ impl LineEnding {
             max_ix -= 1;
         }
 
-        if let Some(ix) = text[..max_ix].find(['\n']) {
-            if ix > 0 && text.as_bytes()[ix - 1] == b'\r' {
-                Self::Windows
+        // Use the dominant line ending, breaking ties in favor of the first one.
+        let sample = &text[..max_ix];
+        let mut newlines = sample
+            .match_indices('\n')
+            .map(|(ix, _)| ix > 0 && sample.as_bytes()[ix - 1] == b'\r');
+        let Some(first_is_windows) = newlines.next() else {
+            return Self::default();
+        };
+        let mut windows_count = first_is_windows as usize;
+        let mut unix_count = !first_is_windows as usize;
+        for is_windows in newlines {
+            if is_windows {
+                windows_count += 1;
             } else {
-                Self::Unix
+                unix_count += 1;
             }
-        } else {
-            Self::default()
+        }
+
+        match windows_count.cmp(&unix_count) {
+            Ordering::Greater => Self::Windows,
+            Ordering::Less => Self::Unix,
+            Ordering::Equal if first_is_windows => Self::Windows,
+            Ordering::Equal => Self::Unix,
         }
     }
 
@@ -2985,3 +3135,115 @@ impl LineEnding {
         }
     }
 }
+
+/// The character encoding of a file on disk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Latin1,
+}
+
+impl Encoding {
+    /// Detects the encoding of the given file contents and decodes them.
+    ///
+    /// UTF-16 is detected from a byte order mark. Other content must be valid UTF-8,
+    /// unless it looks like Latin-1 text: it contains no control characters, and
+    /// none of its non-ASCII bytes form UTF-8 sequences. Anything else, such as a
+    /// binary file, can't be decoded.
+    pub fn detect_and_decode(bytes: Vec<u8>) -> Result<(Self, String)> {
+        let encoding = match bytes.as_slice() {
+            [0xFF, 0xFE, ..] => Self::Utf16Le,
+            [0xFE, 0xFF, ..] => Self::Utf16Be,
+            _ => {
+                return match String::from_utf8(bytes) {
+                    Ok(text) => Ok((Self::Utf8, text)),
+                    Err(error) if looks_like_latin1(error.as_bytes()) => {
+                        Ok((Self::Latin1, Self::Latin1.decode(error.into_bytes())?))
+                    }
+                    Err(_) => Err(anyhow!("file is not valid UTF-8")),
+                }
+            }
+        };
+        Ok((encoding, encoding.decode(bytes)?))
+    }
+
+    /// Decodes the given bytes using this encoding, stripping any UTF-16 byte
+    /// order mark. Fails if the bytes aren't valid in this encoding.
+    pub fn decode(self, bytes: Vec<u8>) -> Result<String> {
+        match self {
+            Self::Utf8 => String::from_utf8(bytes).context("file is not valid UTF-8"),
+            Self::Utf16Le | Self::Utf16Be => {
+                let bytes = match bytes.as_slice() {
+                    [0xFF, 0xFE, rest @ ..] | [0xFE, 0xFF, rest @ ..] => rest,
+                    bytes => bytes,
+                };
+                if bytes.len() % 2 != 0 {
+                    return Err(anyhow!("file is not valid UTF-16"));
+                }
+                let units = bytes.chunks(2).map(|pair| {
+                    let pair = [pair[0], pair[1]];
+                    if self == Self::Utf16Le {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                });
+                char::decode_utf16(units)
+                    .collect::<Result<String, _>>()
+                    .context("file is not valid UTF-16")
+            }
+            Self::Latin1 => Ok(bytes.into_iter().map(char::from).collect()),
+        }
+    }
+
+    /// Encodes the given text using this encoding, writing a byte order mark for
+    /// UTF-16. Fails if the text contains characters that Latin-1 can't represent.
+    pub fn encode(self, text: &str) -> Result<Cow<'_, [u8]>> {
+        Ok(match self {
+            Self::Utf8 => Cow::Borrowed(text.as_bytes()),
+            Self::Utf16Le => Cow::Owned(
+                [0xFF, 0xFE]
+                    .into_iter()
+                    .chain(text.encode_utf16().flat_map(u16::to_le_bytes))
+                    .collect(),
+            ),
+            Self::Utf16Be => Cow::Owned(
+                [0xFE, 0xFF]
+                    .into_iter()
+                    .chain(text.encode_utf16().flat_map(u16::to_be_bytes))
+                    .collect(),
+            ),
+            Self::Latin1 => Cow::Owned(
+                text.chars()
+                    .map(|c| {
+                        u8::try_from(c)
+                            .map_err(|_| anyhow!("{c:?} can't be represented in Latin-1"))
+                    })
+                    .collect::<Result<_>>()?,
+            ),
+        })
+    }
+}
+
+/// Whether the given bytes, which aren't valid UTF-8, look like Latin-1 text
+/// rather than binary data or UTF-8 text with a few invalid bytes.
+fn looks_like_latin1(bytes: &[u8]) -> bool {
+    let has_control_characters = bytes
+        .iter()
+        .any(|byte| matches!(byte, 0x00..=0x08 | 0x0E..=0x1F | 0x7F..=0x9F));
+    let has_utf8_sequences = bytes.iter().enumerate().any(|(ix, byte)| {
+        let len = match byte {
+            0xC2..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            0xF0..=0xF4 => 4,
+            _ => return false,
+        };
+        bytes
+            .get(ix..ix + len)
+            .map_or(false, |sequence| std::str::from_utf8(sequence).is_ok())
+    });
+    !has_control_characters && !has_utf8_sequences
+}